@@ -0,0 +1,17 @@
+use std::process::Command;
+
+/// Embed the commit the tool is built from as `GIT_HASH`, when building from a git checkout,
+/// so the manifest of an output can name the exact build which produced it
+fn main() {
+    println!("cargo:rerun-if-changed=.git/HEAD");
+    println!("cargo:rerun-if-changed=.git/refs");
+    let hash = Command::new("git")
+        .args(["rev-parse", "--short", "HEAD"])
+        .output()
+        .ok()
+        .filter(|output| output.status.success())
+        .map(|output| String::from_utf8_lossy(&output.stdout).trim().to_owned());
+    if let Some(hash) = hash {
+        println!("cargo:rustc-env=GIT_HASH={}", hash);
+    }
+}
@@ -0,0 +1,151 @@
+use std::{
+    path::{Path, PathBuf},
+    sync::OnceLock,
+};
+
+use crate::vdf::Vdf;
+
+/// Difference between a 64 bit Steam ID and the account ID naming the `userdata` folder
+const STEAM_ID_BASE: u64 = 76561197960265728;
+
+/// Account selected with `--steam-user`
+static SELECTED: OnceLock<String> = OnceLock::new();
+/// Steam ID of the account chosen at the prompt of `current()`, asked at most once per run
+static CHOSEN: OnceLock<Option<u64>> = OnceLock::new();
+
+/// Use only the Steam account `name` (account name, profile name or Steam ID) for
+/// account-specific settings, instead of every account on this machine
+pub(crate) fn select(name: &str) {
+    let _ = SELECTED.set(name.to_owned());
+}
+
+/// The account selected with `--steam-user`, if any
+pub(crate) fn selected() -> Option<&'static str> {
+    SELECTED.get().map(String::as_str)
+}
+
+/// A Steam account which has logged in on this machine, from `config/loginusers.vdf`
+/// # Properties
+/// - `steam_id: u64` = 64 bit Steam ID
+/// - `account_name: String` = Login name
+/// - `persona_name: String` = Profile name shown to friends
+/// - `most_recent: bool` = Whether this is the account which logged in last
+/// - `timestamp: u64` = When the account last logged in, in seconds since the Unix epoch
+pub(crate) struct SteamUser {
+    pub(crate) steam_id: u64,
+    pub(crate) account_name: String,
+    pub(crate) persona_name: String,
+    most_recent: bool,
+    timestamp: u64,
+}
+
+impl SteamUser {
+    /// Whether `name` is the account name, profile name or Steam ID of the account
+    fn matches(&self, name: &str) -> bool {
+        self.account_name.eq_ignore_ascii_case(name)
+            || self.persona_name.eq_ignore_ascii_case(name)
+            || self.steam_id.to_string() == name
+    }
+
+    /// Folder holding the account's settings, `userdata/<account ID>` in the Steam folder
+    pub(crate) fn userdata_dir(&self, steam_path: &Path) -> PathBuf {
+        steam_path
+            .join("userdata")
+            .join((self.steam_id - STEAM_ID_BASE).to_string())
+    }
+}
+
+/// The accounts listed in `config/loginusers.vdf`. Empty if the file cannot be read.
+pub(crate) fn login_users(steam_path: &Path) -> Vec<SteamUser> {
+    let Some(vdf) = std::fs::read_to_string(steam_path.join("config").join("loginusers.vdf"))
+        .ok()
+        .and_then(|text| Vdf::parse(&text))
+    else {
+        return Vec::new();
+    };
+    let Some(Vdf::Object(users)) = vdf.get("users") else {
+        return Vec::new();
+    };
+    users
+        .iter()
+        .filter_map(|(steam_id, user)| {
+            let text = |key: &str| user.get(key).and_then(Vdf::as_str).unwrap_or_default();
+            Some(SteamUser {
+                steam_id: steam_id.parse().ok().filter(|id| *id >= STEAM_ID_BASE)?,
+                account_name: text("AccountName").to_owned(),
+                persona_name: text("PersonaName").to_owned(),
+                most_recent: text("MostRecent") == "1",
+                timestamp: text("Timestamp").parse().unwrap_or(0),
+            })
+        })
+        .collect()
+}
+
+/// Find the account selected with `--steam-user` among the accounts of the Steam
+/// installation at `steam_path`. Returns `Ok(None)` if no account was selected, and the
+/// names of the known accounts if the selected one does not exist.
+pub(crate) fn find_selected(steam_path: &Path) -> Result<Option<SteamUser>, Vec<String>> {
+    let Some(name) = selected() else {
+        return Ok(None);
+    };
+    let users = login_users(steam_path);
+    let names = users.iter().map(|user| user.account_name.clone()).collect();
+    match users.into_iter().find(|user| user.matches(name)) {
+        Some(user) => Ok(Some(user)),
+        None => Err(names),
+    }
+}
+
+/// The account whose settings are used: the one selected with `--steam-user`, or else the
+/// one Steam marks as having logged in most recently. When several accounts are present
+/// without such a mark, the user is asked to choose one if `interactive`. Returns `None` if
+/// there are no accounts or the question was not asked or answered.
+pub(crate) fn current(steam_path: &Path, interactive: bool) -> Option<SteamUser> {
+    if selected().is_some() {
+        return find_selected(steam_path).ok().flatten();
+    }
+    let mut users = login_users(steam_path);
+    if let Some(i) = users.iter().position(|user| user.most_recent) {
+        return Some(users.swap_remove(i));
+    }
+    if users.len() <= 1 {
+        return users.pop();
+    }
+    if !interactive {
+        return None;
+    }
+
+    users.sort_by_key(|user| std::cmp::Reverse(user.timestamp));
+    let chosen = CHOSEN.get_or_init(|| {
+        println!("Several Steam accounts use this machine. Which one plays Dota 2?");
+        for (i, user) in users.iter().enumerate() {
+            println!("{}: {} ({})", i + 1, user.persona_name, user.account_name);
+        }
+        println!("Enter a number, or pass --steam-user <name> to skip this question:");
+        let mut answer = String::new();
+        match std::io::stdin().read_line(&mut answer) {
+            Ok(n) if n > 0 => answer
+                .trim()
+                .parse::<usize>()
+                .ok()
+                .and_then(|n| users.get(n.checked_sub(1)?))
+                .map(|user| user.steam_id),
+            _ => None,
+        }
+    });
+    users
+        .into_iter()
+        .find(|user| Some(user.steam_id) == *chosen)
+}
+
+/// The `userdata` folders of the accounts whose settings are read: the current account, see
+/// `current()`, or every account on this machine if it is not known
+pub(crate) fn userdata_dirs(steam_path: &Path, interactive: bool) -> Vec<PathBuf> {
+    if let Some(user) = current(steam_path, interactive) {
+        return vec![user.userdata_dir(steam_path)];
+    }
+    match std::fs::read_dir(steam_path.join("userdata")) {
+        Ok(dir) => dir.flatten().map(|account| account.path()).collect(),
+        Err(_) => Vec::new(),
+    }
+}
@@ -0,0 +1,846 @@
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    time::Instant,
+};
+
+use crate::cli::Args;
+use crate::config::Config;
+use crate::conflicts::Policy;
+use crate::hashing::HashAlgorithm;
+use crate::history::{self, HistoryEntry};
+use crate::install::{self, InstallMethod};
+use crate::manifest::{self, BuildStats, Manifest, Source};
+use crate::pipeline::Options;
+use crate::rules::{self, Rule};
+use crate::terrain_settings::TerrainSettings;
+use crate::texture::Recolor;
+use crate::utils::{self, DotaInstall, TMError};
+use crate::vpk::{PatchOptions, VpkOptions, VPK};
+use crate::{
+    conflicts, crash, game, integrity, journal, launch, lzma, menu, minimap, pins, references,
+    resource, signature, steam_state, sync, tree_size, vpk,
+};
+
+/// Settings for building a terrain, taken from the command line and the configuration
+/// # Properties
+/// - `base: String` = Name of the map the terrain replaces (`--base`, by default `dota`)
+/// - `overrides: HashMap<String, Vec<u8>>` = Files added on top of the terrain (`--overrides`)
+/// - `override_layers: Vec<PathBuf>` = Directories and VPKs the overrides were loaded from,
+///   later ones replacing files of earlier ones
+/// - `rules: Vec<Rule>` = Rename rules applied to the terrain (`--rules` or the configuration)
+/// - `rules_path: Option<PathBuf>` = File the rules were loaded from, unless they came from
+///   the configuration
+/// - `terrain_minimap: bool` = Use the terrain's own minimap (`--terrain-minimap`)
+/// - `compress: bool` = Store the output LZMA-compressed (`--compress`), only for VPKs
+/// - `method: InstallMethod` = How the terrain is installed (`--method`)
+/// - `outputs: Vec<PathBuf>` = Additional paths the output is written to (`--output`)
+/// - `map_aliases: Vec<String>` = Additional names the map is written under (`--map-alias` or
+///   the configuration)
+/// - `low_end: bool` = Strip or swap heavyweight assets for weak GPUs, see
+///   `rules::low_end_rules()` (`--low-end`)
+/// - `strip_editor_files: bool` = Leave out editor leftovers and uncompiled assets, see
+///   `rules::editor_file_rules()` (`--strip-editor-files`)
+/// - `downscale: Option<u16>` = Largest texture size kept from the terrain (`--downscale`)
+/// - `recolor: Option<Recolor>` = Shift applied to the colors of ground textures (`--recolor`)
+/// - `trusted_keys: Vec<String>` = Public keys third-party terrains may be signed with (the
+///   configuration)
+/// - `hash: HashAlgorithm` = Hash function of pinned terrains and the history (the
+///   configuration)
+/// - `assume_yes: bool` = Overwrite files which were not written by this program without
+///   asking (`--yes` or `--force`)
+/// - `interactive: bool` = Ask the questions `assume_yes` does not answer on the console,
+///   instead of answering them with no, unset for the library
+/// - `allow_maps_dir: bool` = Allow additional outputs in the maps folder of the game
+///   (`--i-know-what-im-doing`), see `DotaInstall::check_output()`
+/// - `allow_updating: bool` = Build even while Steam is updating the game
+///   (`--ignore-steam-state`), see `steam_state::defer_reason()`
+/// - `limits: bool` = Refuse a base map or terrain claiming implausible sizes, unset by
+///   `--no-limits`, see `VpkOptions`
+pub(crate) struct ApplyOptions {
+    base: String,
+    overrides: HashMap<String, Vec<u8>>,
+    override_layers: Vec<PathBuf>,
+    rules: Vec<Rule>,
+    rules_path: Option<PathBuf>,
+    terrain_minimap: bool,
+    compress: bool,
+    method: InstallMethod,
+    outputs: Vec<PathBuf>,
+    map_aliases: Vec<String>,
+    low_end: bool,
+    strip_editor_files: bool,
+    downscale: Option<u16>,
+    recolor: Option<Recolor>,
+    trusted_keys: Vec<String>,
+    hash: HashAlgorithm,
+    assume_yes: bool,
+    interactive: bool,
+    allow_maps_dir: bool,
+    allow_updating: bool,
+    limits: bool,
+}
+
+impl ApplyOptions {
+    /// Gather the options for building the terrain in `terrain_file` from the command line,
+    /// falling back to the settings of the terrain and then the rest of the configuration, see
+    /// `from_options()`. With `--resolve-conflicts`, which layer wins files several layers add
+    /// is asked first, see `conflicts::resolve()`.
+    pub(crate) fn from_args(
+        args: &Args,
+        config: &Config,
+        terrain_file: &str,
+    ) -> Result<ApplyOptions, TMError> {
+        let downscale = match args.option("downscale").map(str::parse::<u16>) {
+            None => None,
+            Some(Ok(size)) => Some(size),
+            Some(Err(_)) => return Err(downscale_error()),
+        };
+        let recolor = match args.option("recolor") {
+            None => None,
+            Some(recolor) => {
+                let recolor = Recolor::parse(recolor).ok_or_else(recolor_error)?;
+                Some((recolor.hue, recolor.saturation))
+            }
+        };
+        let options = Options {
+            terrain: terrain_file.to_owned(),
+            base: args.option("base").map(str::to_owned),
+            method: args.option("method").map(str::to_owned),
+            overrides: args
+                .options("overrides")
+                .iter()
+                .map(PathBuf::from)
+                .collect(),
+            rules: args.option("rules").map(PathBuf::from),
+            outputs: args.options("output").iter().map(PathBuf::from).collect(),
+            conflicts_policy: args.option("conflicts-policy").map(PathBuf::from),
+            map_aliases: args.options("map-alias").to_vec(),
+            terrain_minimap: args.flag("terrain-minimap"),
+            compress: args.flag("compress"),
+            low_end: args.flag("low-end"),
+            strip_editor_files: args.flag("strip-editor-files"),
+            downscale,
+            recolor,
+            assume_yes: args.assume_yes(),
+            allow_maps_dir: args.flag("i-know-what-im-doing"),
+            ignore_steam_state: args.flag("ignore-steam-state"),
+            no_limits: args.flag("no-limits"),
+            steam_path: args.option("steam-path").map(PathBuf::from),
+        };
+        ApplyOptions::gather(
+            &options,
+            config,
+            terrain_file,
+            args.flag("resolve-conflicts"),
+            true,
+        )
+    }
+
+    /// Gather the options for building the terrain in `terrain_file` from the options of the
+    /// library, without asking anything on the console, see `gather()`
+    pub(crate) fn from_options(
+        options: &Options,
+        config: &Config,
+        terrain_file: &str,
+    ) -> Result<ApplyOptions, TMError> {
+        ApplyOptions::gather(options, config, terrain_file, false, false)
+    }
+
+    /// Gather the options for building the terrain in `terrain_file` from `options`, falling
+    /// back to the settings of the terrain and then the rest of the configuration. Override
+    /// layers of the terrain's settings are applied below those of `options`. With
+    /// `resolve_conflicts`, which layer wins files several layers add is asked first, see
+    /// `conflicts::resolve()`. A conflicts policy given replaces the configuration's policy.
+    fn gather(
+        options: &Options,
+        config: &Config,
+        terrain_file: &str,
+        resolve_conflicts: bool,
+        interactive: bool,
+    ) -> Result<ApplyOptions, TMError> {
+        let default = TerrainSettings::default();
+        let settings = config.terrain_settings(terrain_file).unwrap_or(&default);
+        let base = options
+            .base
+            .as_deref()
+            .unwrap_or(game::current().default_base);
+        let mut apply_options = ApplyOptions::new(
+            base,
+            settings
+                .overrides
+                .iter()
+                .chain(&options.overrides)
+                .cloned()
+                .collect(),
+            options.rules.clone(),
+            options
+                .outputs
+                .iter()
+                .map(|output| output_path(output, base))
+                .collect(),
+            options
+                .conflicts_policy
+                .clone()
+                .or_else(|| config.conflicts_policy.clone()),
+            resolve_conflicts,
+            config,
+        )?;
+        apply_options.terrain_minimap =
+            options.terrain_minimap || settings.terrain_minimap.unwrap_or(false);
+        apply_options.compress = options.compress || settings.compress.unwrap_or(false);
+        lzma::check_compress(apply_options.compress).map_err(TMError::ConfigError)?;
+        if let Some(method) = &options.method {
+            apply_options.method = InstallMethod::from_name(method).ok_or_else(|| {
+                TMError::ConfigError(format!("--method expects {}", InstallMethod::names()))
+            })?;
+        }
+        if !apply_options.method.is_packed() && !apply_options.outputs.is_empty() {
+            return Err(TMError::ConfigError(format!(
+                "--output cannot be combined with --method {}, loose files are only \
+                installed into the game folder",
+                apply_options.method.name()
+            )));
+        }
+        apply_options.map_aliases = match (options.map_aliases.as_slice(), &settings.map_aliases) {
+            ([], Some(aliases)) => aliases.clone(),
+            ([], None) => config.map_aliases.clone(),
+            (aliases, _) => aliases.to_vec(),
+        };
+        apply_options.set_low_end(options.low_end || settings.low_end.unwrap_or(false));
+        apply_options.set_strip_editor_files(
+            options.strip_editor_files || settings.strip_editor_files.unwrap_or(false),
+        );
+        apply_options.downscale = match options.downscale {
+            None => settings.downscale,
+            Some(0) => return Err(downscale_error()),
+            size => size,
+        };
+        apply_options.recolor = match options.recolor {
+            None => settings.recolor,
+            Some((hue, saturation)) => {
+                Some(Recolor::new(hue, saturation).ok_or_else(recolor_error)?)
+            }
+        };
+        apply_options.assume_yes = options.assume_yes;
+        apply_options.interactive = interactive;
+        apply_options.allow_maps_dir = options.allow_maps_dir;
+        apply_options.allow_updating = options.ignore_steam_state;
+        apply_options.limits = !options.no_limits;
+        Ok(apply_options)
+    }
+
+    /// Repeat the options of an entry of the history. Rules which came from the
+    /// configuration are taken from the current configuration, `--yes` from the command line.
+    pub(crate) fn from_history(
+        entry: &HistoryEntry,
+        args: &Args,
+        config: &Config,
+    ) -> Result<ApplyOptions, TMError> {
+        let mut options = ApplyOptions::new(
+            &entry.base,
+            entry.overrides.clone(),
+            entry.rules.clone(),
+            entry.outputs.clone(),
+            config.conflicts_policy.clone(),
+            false,
+            config,
+        )?;
+        options.terrain_minimap = entry.terrain_minimap;
+        options.compress = entry.compress;
+        lzma::check_compress(options.compress).map_err(TMError::ConfigError)?;
+        options.method = entry.method;
+        options.map_aliases = entry.map_aliases.clone();
+        options.set_low_end(entry.low_end);
+        options.set_strip_editor_files(entry.strip_editor_files);
+        options.downscale = entry.downscale;
+        options.recolor = entry.recolor;
+        options.assume_yes = args.assume_yes();
+        options.allow_maps_dir = args.flag("i-know-what-im-doing");
+        options.allow_updating = args.flag("ignore-steam-state");
+        options.limits = !args.flag("no-limits");
+        Ok(options)
+    }
+
+    fn new(
+        base: &str,
+        override_layers: Vec<PathBuf>,
+        rules_path: Option<PathBuf>,
+        outputs: Vec<PathBuf>,
+        conflicts_policy: Option<PathBuf>,
+        resolve_conflicts: bool,
+        config: &Config,
+    ) -> Result<ApplyOptions, TMError> {
+        let loaded = utils::load_override_layers(&override_layers)?;
+        let policy = conflicts_policy
+            .map(|path| Policy::load(&path))
+            .transpose()
+            .map_err(TMError::ConfigError)?;
+        let decisions = if resolve_conflicts {
+            conflicts::resolve(
+                &loaded,
+                &override_layers,
+                &config.conflicts,
+                policy.as_ref(),
+            )?
+        } else {
+            config.conflicts.clone()
+        };
+        let overrides = conflicts::merge(loaded, &override_layers, &decisions, policy.as_ref());
+        let mut paths: Vec<&String> = overrides.keys().collect();
+        paths.sort();
+        for warning in paths
+            .into_iter()
+            .filter_map(|path| resource::check_location(path))
+        {
+            eprintln!("Warning: {}", warning);
+        }
+        let rules = match &rules_path {
+            Some(path) => rules::load(path)?,
+            None => config
+                .rules
+                .clone()
+                .unwrap_or_else(|| rules::default_rules(base)),
+        };
+        Ok(ApplyOptions {
+            base: base.to_owned(),
+            overrides,
+            override_layers,
+            rules,
+            rules_path,
+            terrain_minimap: false,
+            compress: false,
+            method: InstallMethod::default(),
+            outputs,
+            map_aliases: Vec::new(),
+            low_end: false,
+            strip_editor_files: false,
+            downscale: None,
+            recolor: None,
+            trusted_keys: config.trusted_keys.clone(),
+            hash: config.hash,
+            assume_yes: false,
+            interactive: true,
+            allow_maps_dir: false,
+            allow_updating: false,
+            limits: true,
+        })
+    }
+
+    /// Add the low-end rules after the other rules if `low_end` is set
+    fn set_low_end(&mut self, low_end: bool) {
+        self.low_end = low_end;
+        if low_end {
+            self.rules.extend(rules::low_end_rules());
+        }
+    }
+
+    /// Add the rules leaving out editor leftovers after the other rules if `strip` is set,
+    /// and remove matching overrides
+    fn set_strip_editor_files(&mut self, strip: bool) {
+        self.strip_editor_files = strip;
+        if strip {
+            let editor_rules = rules::editor_file_rules();
+            self.overrides
+                .retain(|path, _| !editor_rules.iter().any(|rule| rule.matches(path)));
+            self.rules.extend(editor_rules);
+        }
+    }
+
+    /// Name of the map the terrain replaces, `dota` unless `--base` is given
+    pub(crate) fn base(&self) -> &str {
+        &self.base
+    }
+
+    /// How the terrain is installed, a VPK unless `--method` is given
+    pub(crate) fn method(&self) -> InstallMethod {
+        self.method
+    }
+
+    /// Files added on top of the terrain, keyed by their path
+    pub(crate) fn overrides(&self) -> &HashMap<String, Vec<u8>> {
+        &self.overrides
+    }
+
+    /// Rename rules applied to the terrain
+    pub(crate) fn rules(&self) -> &[Rule] {
+        &self.rules
+    }
+
+    /// Hash function of pinned terrains and the history
+    pub(crate) fn hash(&self) -> HashAlgorithm {
+        self.hash
+    }
+
+    /// The options affecting the contents of the output, as they are given on the command
+    /// line. Map aliases and rules from the configuration are included as if given there.
+    pub(crate) fn to_args(&self) -> Vec<String> {
+        let mut args = Vec::new();
+        let game = game::current();
+        if !game.is_default() {
+            args.push(format!("--game {}", game.id));
+        }
+        if self.base != game.default_base {
+            args.push(format!("--base {}", self.base));
+        }
+        for layer in &self.override_layers {
+            args.push(format!("--overrides {}", layer.display()));
+        }
+        if let Some(path) = &self.rules_path {
+            args.push(format!("--rules {}", path.display()));
+        }
+        for (flag, set) in [
+            ("--terrain-minimap", self.terrain_minimap),
+            ("--compress", self.compress),
+            ("--low-end", self.low_end),
+            ("--strip-editor-files", self.strip_editor_files),
+        ] {
+            if set {
+                args.push(flag.to_owned());
+            }
+        }
+        for alias in &self.map_aliases {
+            args.push(format!("--map-alias {}", alias));
+        }
+        if let Some(size) = self.downscale {
+            args.push(format!("--downscale {}", size));
+        }
+        if let Some(recolor) = &self.recolor {
+            args.push(format!("--recolor {}", recolor));
+        }
+        if self.method != InstallMethod::default() {
+            args.push(format!("--method {}", self.method.name()));
+        }
+        args
+    }
+}
+
+/// Error of a texture size for `--downscale` which is not a number of pixels
+fn downscale_error() -> TMError {
+    TMError::ConfigError(String::from(
+        "--downscale expects a texture size in pixels, e.g. 1024",
+    ))
+}
+
+/// Error of a color shift for `--recolor` which cannot be applied
+fn recolor_error() -> TMError {
+    TMError::ConfigError(String::from(
+        "--recolor expects a hue shift in degrees and optionally a saturation factor, e.g. 40 \
+        or 40:1.3",
+    ))
+}
+
+/// Path an additional output given with `--output` is written to. Directories (existing ones,
+/// or paths ending in a separator) receive a file named after the base map, e.g. `dota.vpk`.
+fn output_path(output: &Path, base: &str) -> PathBuf {
+    if output.is_dir() || output.to_string_lossy().ends_with(['/', '\\']) {
+        output.join(format!("{}.vpk", base))
+    } else {
+        output.to_path_buf()
+    }
+}
+
+/// Report the editor leftovers and uncompiled assets in the terrain VPK at `path`, see
+/// `rules::editor_file_rules()`: as stripped if `strip` is set, or else with a hint at
+/// `--strip-editor-files`
+fn report_editor_files(path: &Path, strip: bool) {
+    let Ok(vpk) = VPK::open(path.to_path_buf()) else {
+        return;
+    };
+    let editor_rules = rules::editor_file_rules();
+    let (count, bytes) = vpk
+        .entries()
+        .filter(|(path, _)| editor_rules.iter().any(|rule| rule.matches(path)))
+        .fold((0, 0), |(count, bytes), (_, entry)| {
+            (count + 1, bytes + entry.entry_length())
+        });
+    if count == 0 {
+        return;
+    }
+    if strip {
+        println!(
+            "Stripped {} editor-only file(s) ({}) from the terrain",
+            count,
+            tree_size::format_size(bytes)
+        );
+    } else {
+        println!(
+            "Note: The terrain holds {} editor-only file(s) ({}) the game never loads. Pass \
+            --strip-editor-files to leave them out.",
+            count,
+            tree_size::format_size(bytes)
+        );
+    }
+}
+
+/// Merge the terrain in `terrain_file` with the base map without writing anything.
+/// Third-party terrains have their signature checked and are compared with their pinned hash
+/// first, see `signature::check()` and `pins::check()`. Fails while Steam is updating the
+/// game, see `steam_state::defer_reason()`, unless `--ignore-steam-state` is given. For
+/// hybrid installs, the overrides are installed loose, so they are only recorded in the
+/// manifest.
+/// Returns the merged files, keyed by their path, and their manifest.
+pub(crate) fn merge(
+    dota: &DotaInstall,
+    terrain_file: &str,
+    options: &ApplyOptions,
+) -> Result<(HashMap<String, Vec<u8>>, Manifest), TMError> {
+    crash::phase(&format!("building {}", terrain_file));
+    let base_path = dota.base_vpk(&options.base);
+    // A shipped terrain may have been renamed by a game update
+    let target_path = menu::terrain_vpk(dota, terrain_file);
+    if !base_path.exists() {
+        return Err(TMError::BaseNotFound(
+            options.base.clone(),
+            dota.available_bases(),
+        ));
+    }
+    if !options.allow_updating {
+        if let Some(reason) = steam_state::defer_reason(dota) {
+            return Err(TMError::GameUpdating(reason));
+        }
+    }
+    let mut stats = BuildStats::default();
+    let started = Instant::now();
+    if !menu::is_shipped(terrain_file) {
+        let data = std::fs::read(&target_path).map_err(TMError::InternalError)?;
+        let status = signature::check(&target_path, &data, &options.trusted_keys);
+        if let Some(warning) = signature::warning(terrain_file, &status, &options.trusted_keys) {
+            eprintln!("{}", warning);
+        }
+        pins::check(
+            terrain_file,
+            &data,
+            options.hash,
+            options.assume_yes,
+            options.interactive,
+        )?;
+        report_editor_files(&target_path, options.strip_editor_files);
+        stats.add("verify", started.elapsed());
+    }
+    let file_size = |path: &Path| std::fs::metadata(path).map_or(0, |metadata| metadata.len());
+    stats.input_bytes = file_size(&base_path)
+        + file_size(&target_path)
+        + options
+            .overrides
+            .values()
+            .map(|data| data.len() as u64)
+            .sum::<u64>();
+    let building = Instant::now();
+    vpk::merge_terrain(
+        base_path,
+        target_path,
+        PatchOptions {
+            overrides: match options.method {
+                InstallMethod::Hybrid => HashMap::new(),
+                _ => options.overrides.clone(),
+            },
+            rules: &options.rules,
+            map_aliases: &options.map_aliases,
+            terrain_minimap: options.terrain_minimap,
+            downscale: options.downscale,
+            recolor: options.recolor,
+            limits: options.limits,
+        },
+    )
+    .map(|(files, mut manifest)| {
+        manifest.options = options.to_args();
+        manifest.build = dota.build_id().unwrap_or_default();
+        manifest.method = options.method;
+        manifest.loose = match options.method {
+            InstallMethod::Vpk | InstallMethod::Gameinfo => Vec::new(),
+            InstallMethod::Loose => files.keys().cloned().collect(),
+            InstallMethod::Hybrid => {
+                for path in options.overrides.keys() {
+                    manifest.add(path, Source::Overrides);
+                }
+                options.overrides.keys().cloned().collect()
+            }
+        };
+        manifest.loose.sort();
+        stats.add("build", building.elapsed());
+        stats.output_bytes = files.values().map(|data| data.len() as u64).sum();
+        manifest.stats = stats;
+        (files, manifest)
+    })
+    .map_err(TMError::InternalError)
+}
+
+/// Build the terrain in `terrain_file` on top of the base map without writing anything, see
+/// `merge()`. Returns the contents of the output VPK and its manifest.
+pub(crate) fn build(
+    dota: &DotaInstall,
+    terrain_file: &str,
+    options: &ApplyOptions,
+) -> Result<(Vec<u8>, Manifest), TMError> {
+    let (files, mut manifest) = merge(dota, terrain_file, options)?;
+    let packing = Instant::now();
+    let out_file = vpk::create_vpk(
+        files,
+        &VpkOptions {
+            compress: options.compress,
+            ..VpkOptions::default()
+        },
+    )
+    .map_err(TMError::InternalError)?;
+    manifest.stats.add("pack", packing.elapsed());
+    manifest.stats.output_bytes = out_file.len() as u64;
+    Ok((out_file, manifest))
+}
+
+/// Build the terrain in `terrain_file` on top of the base map and install it with the method
+/// given by `--method`, see `install::Installer`, write it to every additional output, and
+/// record it in the history. What the previous install leaves over is removed afterwards,
+/// such as loose files which are not installed again, or all of it if it was installed with
+/// another method. A summary of the result is printed. Returns the manifest of the output.
+pub(crate) fn apply(
+    dota: &DotaInstall,
+    terrain_file: &str,
+    options: &ApplyOptions,
+) -> Result<Manifest, TMError> {
+    let out_path = dota.out_vpk(&options.base);
+    let previous = Manifest::read(&out_path).ok();
+    if let Some(warning) = manifest::other_user_warning(&out_path) {
+        eprintln!("{}", warning);
+    }
+    let installer = options.method.installer();
+    // Installing touches several files, which are restored if it fails or the program stops
+    // midway, see `journal::recover()`
+    journal::begin(&dota.dota_path, &format!("applying {}", terrain_file))
+        .map_err(TMError::InternalError)?;
+    let installed = installer.install(
+        dota,
+        terrain_file,
+        options,
+        previous
+            .as_ref()
+            .filter(|previous| previous.method == options.method),
+    );
+    let (out_file, manifest, hash) = match installed {
+        Ok(installed) => installed,
+        Err(e) => {
+            journal::rollback();
+            return Err(e);
+        }
+    };
+    install::finish(dota, &options.base, installer.as_ref(), &manifest, previous);
+
+    let base_path = dota.base_vpk(&options.base);
+    let target_path = menu::terrain_vpk(dota, terrain_file);
+    for output in &options.outputs {
+        if let Err(e) = dota.check_output(output, options.allow_maps_dir) {
+            eprintln!("Dota-Terrain-Mod error: {}", e);
+            println!("Skipped {}", output.display());
+            continue;
+        }
+        // Files written by this program have a manifest next to them
+        if output.exists()
+            && Manifest::read(output).is_err()
+            && !utils::answer(
+                &format!("{} already exists. Overwrite it?", output.display()),
+                options.assume_yes,
+                options.interactive,
+            )
+        {
+            println!("Skipped {}", output.display());
+            continue;
+        }
+        let written = output
+            .parent()
+            .map_or(Ok(()), std::fs::create_dir_all)
+            .and_then(|_| std::fs::write(output, &out_file))
+            .and_then(|_| manifest.write(output));
+        match written {
+            Ok(()) => println!("Also written to {}", output.display()),
+            Err(e) => eprintln!("Warning: Could not write {}: {}", output.display(), e),
+        }
+    }
+
+    let entry = HistoryEntry {
+        terrain: terrain_file.to_owned(),
+        timestamp: history::now(),
+        build: dota.build_id(),
+        hash,
+        overrides: options.override_layers.clone(),
+        rules: options.rules_path.clone(),
+        terrain_minimap: options.terrain_minimap,
+        compress: options.compress,
+        outputs: options.outputs.clone(),
+        map_aliases: options.map_aliases.clone(),
+        low_end: options.low_end,
+        strip_editor_files: options.strip_editor_files,
+        downscale: options.downscale,
+        recolor: options.recolor,
+        base: options.base.clone(),
+        method: options.method,
+    };
+    if let Err(e) = history::record(entry) {
+        eprintln!("Warning: Could not record the history: {}", e);
+    }
+
+    for line in manifest.conflicts() {
+        println!("{}", line);
+    }
+    if !manifest.downscaled.is_empty() {
+        println!("{} texture(s) downscaled", manifest.downscaled.len());
+    }
+    if !manifest.recolored.is_empty() {
+        println!("{} ground texture(s) recolored", manifest.recolored.len());
+    }
+
+    let checking = Instant::now();
+    // The checks read the output VPK, loose installs have none
+    if let Some(out_path) = installer.output_vpk(dota, &options.base) {
+        match minimap::check(&out_path, &base_path, &target_path) {
+            Ok(summary) => {
+                for line in summary {
+                    println!("{}", line);
+                }
+            }
+            Err(e) => eprintln!("Warning: Could not check the minimap: {}", e),
+        }
+
+        match references::check(&out_path, &dota.content_dir(), &options.base) {
+            Ok(summary) => {
+                for line in summary {
+                    println!("{}", line);
+                }
+            }
+            Err(e) => eprintln!("Warning: Could not check the map's references: {}", e),
+        }
+    }
+    // The checks run after the manifest was written, so they are only shown
+    let mut stats = manifest.stats.clone();
+    stats.add("checks", checking.elapsed());
+    println!("{}", stats.summary());
+
+    if let Some(location) = sync::synced_location(&dota.dota_path) {
+        eprintln!(
+            "\n{}",
+            sync::synced_warning(&location, &dota.tempcontent_dir())
+        );
+    }
+
+    if installer.needs_launch_option()
+        && launch::launch_option_set(&dota.steam_path, options.interactive) == Some(false)
+    {
+        eprintln!("\n{}\n", launch::missing_warning());
+    }
+    if let Some(advice) = steam_state::advice(dota) {
+        eprintln!("{}", advice);
+    }
+    if dota.is_running() {
+        println!("Dota 2 is running, the terrain loads the next time a match or lobby starts.");
+    }
+    Ok(manifest)
+}
+
+/// Entry point for the `apply <terrain>` and `apply --from-history <n>` commands. Applies a
+/// terrain without the menu, or repeats entry `n` of `history` with the options it was
+/// applied with.
+/// With `--watch`, the program keeps running afterwards and reports when another program
+/// deletes or overwrites the installed terrain, or applies it again with `--auto-reapply`, once
+/// it stayed unchanged for `--quiet-period` seconds, and switches terrains as the
+/// `[[schedule]]` rules select them, see `integrity::watch()`.
+pub(crate) fn run(args: &Args) {
+    let from_history = args.option("from-history");
+    if args.positional.len() != usize::from(from_history.is_none()) {
+        eprintln!(
+            "Usage: dota-terrain-mod apply <terrain> [--watch [--auto-reapply] \
+            [--quiet-period <seconds>]]"
+        );
+        eprintln!(
+            "       dota-terrain-mod apply --from-history <n> [--watch [--auto-reapply] \
+            [--quiet-period <seconds>]]"
+        );
+        std::process::exit(1);
+    }
+    let dota = match DotaInstall::new(args) {
+        Ok(dota) => dota,
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+    };
+    let mut config = match Config::load() {
+        Ok(config) => config,
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+    };
+    config.menu.discover(&dota);
+
+    let (terrain, options) = match from_history {
+        Some(n) => {
+            let entries = match history::load() {
+                Ok(entries) => entries,
+                Err(e) => {
+                    eprintln!("Dota-Terrain-Mod error: Could not read the history: {}", e);
+                    std::process::exit(1);
+                }
+            };
+            let Some(entry) = n
+                .parse::<usize>()
+                .ok()
+                .and_then(|n| entries.get(n.checked_sub(1)?))
+            else {
+                eprintln!(
+                    "Dota-Terrain-Mod error: No history entry {}, see `history` for the entries",
+                    n
+                );
+                std::process::exit(1);
+            };
+            (
+                entry.terrain.clone(),
+                ApplyOptions::from_history(entry, args, &config),
+            )
+        }
+        None => {
+            let terrain = match config.menu.resolve(&args.positional[0]) {
+                Ok(terrain) => terrain,
+                Err(e) => {
+                    eprintln!("Dota-Terrain-Mod error: {}", e);
+                    std::process::exit(1);
+                }
+            };
+            let options = ApplyOptions::from_args(args, &config, &terrain.file);
+            (terrain.file, options)
+        }
+    };
+    let options = match options {
+        Ok(options) => options,
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+    };
+    let quiet_period = match config.quiet_period(args, integrity::DEFAULT_QUIET_PERIOD) {
+        Ok(quiet_period) => quiet_period,
+        Err(e) => {
+            eprintln!("Dota-Terrain-Mod error: {}", e);
+            std::process::exit(1);
+        }
+    };
+
+    println!("Applying {}...", terrain);
+    let manifest = match apply(&dota, &terrain, &options) {
+        Ok(manifest) => manifest,
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+    };
+    println!("Done.");
+    if args.flag("watch") {
+        integrity::watch(
+            &dota,
+            args,
+            &config,
+            &terrain,
+            options,
+            manifest,
+            quiet_period,
+        );
+    }
+}
@@ -0,0 +1,212 @@
+use std::{
+    fs::File,
+    io::{self, BufReader, BufWriter, Read, Write},
+    path::Path,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+/// Format of an archive, chosen by the extension of its name
+#[derive(Clone, Copy, PartialEq)]
+pub(crate) enum ArchiveFormat {
+    /// An uncompressed tar archive (`.tar`)
+    Tar,
+    /// A tar archive compressed with xz (`.tar.xz` or `.txz`). Requires the "lzma" feature.
+    TarXz,
+}
+
+impl ArchiveFormat {
+    /// The format of an archive named `path`, or `None` for unsupported extensions
+    pub(crate) fn from_path(path: &Path) -> Option<ArchiveFormat> {
+        let name = path.file_name()?.to_string_lossy().to_lowercase();
+        if name.ends_with(".tar.xz") || name.ends_with(".txz") {
+            Some(ArchiveFormat::TarXz)
+        } else if name.ends_with(".tar") {
+            Some(ArchiveFormat::Tar)
+        } else {
+            None
+        }
+    }
+
+    /// Whether archives in this format can be read and written by this build
+    pub(crate) fn is_supported(self) -> bool {
+        self == ArchiveFormat::Tar || cfg!(feature = "lzma")
+    }
+}
+
+/// Write `files`, pairs of a name and the contents, to an archive at `path` in `format`. The
+/// archive is streamed to disk, so no second copy of the contents is held in memory.
+pub(crate) fn write(path: &Path, format: ArchiveFormat, files: &[(&str, &[u8])]) -> io::Result<()> {
+    let out = BufWriter::new(File::create(path)?);
+    match format {
+        ArchiveFormat::Tar => write_tar(out, files)?.flush(),
+        ArchiveFormat::TarXz => write_tar_xz(out, files),
+    }
+}
+
+/// Write `files` as a tar archive to `out`, which is returned once the archive is complete.
+/// Names longer than the header allows are stored in an extension header.
+fn write_tar<W: Write>(out: W, files: &[(&str, &[u8])]) -> io::Result<W> {
+    let modified = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |time| time.as_secs());
+    let mut tar = tar::Builder::new(out);
+    for (name, contents) in files {
+        let mut header = tar::Header::new_gnu();
+        header.set_size(contents.len() as u64);
+        header.set_mode(0o644);
+        header.set_mtime(modified);
+        tar.append_data(&mut header, name, *contents)?;
+    }
+    tar.into_inner()
+}
+
+/// Read the regular files of the archive at `path` in `format`, as pairs of a name and the
+/// contents. Directories and other entries are skipped. The archive is decompressed and read
+/// one entry at a time. Fails if a header is damaged or the archive ends before its last file.
+pub(crate) fn read(path: &Path, format: ArchiveFormat) -> io::Result<Vec<(String, Vec<u8>)>> {
+    let input = BufReader::new(File::open(path)?);
+    match format {
+        ArchiveFormat::Tar => read_tar(input),
+        ArchiveFormat::TarXz => read_tar_xz(input),
+    }
+}
+
+/// The regular files of the tar archive read from `input`, see `read()`
+fn read_tar(input: impl Read) -> io::Result<Vec<(String, Vec<u8>)>> {
+    let mut files = Vec::new();
+    for entry in tar::Archive::new(input).entries()? {
+        let mut entry = entry?;
+        if !entry.header().entry_type().is_file() {
+            continue;
+        }
+        let name = String::from_utf8_lossy(&entry.path_bytes()).into_owned();
+        let mut contents = Vec::new();
+        entry.read_to_end(&mut contents)?;
+        if contents.len() as u64 != entry.size() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "archive is truncated",
+            ));
+        }
+        files.push((name, contents));
+    }
+    Ok(files)
+}
+
+/// Write `files` as an xz-compressed tar archive to `out`
+#[cfg(feature = "lzma")]
+fn write_tar_xz(out: impl Write, files: &[(&str, &[u8])]) -> io::Result<()> {
+    let out = write_tar(xz2::write::XzEncoder::new(out, 6), files)?;
+    out.finish()?.flush()
+}
+
+/// The regular files of the xz-compressed tar archive read from `input`, see `read()`
+#[cfg(feature = "lzma")]
+fn read_tar_xz(input: impl Read) -> io::Result<Vec<(String, Vec<u8>)>> {
+    // Tools may write several concatenated streams
+    read_tar(xz2::read::XzDecoder::new_multi_decoder(input))
+}
+
+#[cfg(not(feature = "lzma"))]
+fn write_tar_xz(_out: impl Write, _files: &[(&str, &[u8])]) -> io::Result<()> {
+    Err(xz_unsupported())
+}
+
+#[cfg(not(feature = "lzma"))]
+fn read_tar_xz(_input: impl Read) -> io::Result<Vec<(String, Vec<u8>)>> {
+    Err(xz_unsupported())
+}
+
+#[cfg(not(feature = "lzma"))]
+fn xz_unsupported() -> io::Error {
+    io::Error::new(
+        io::ErrorKind::Unsupported,
+        "xz-compressed archives require a build with the \"lzma\" feature",
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Size of a tar header and of the blocks file contents are padded to
+    const BLOCK_LENGTH: usize = 512;
+
+    fn tar(files: &[(&str, &[u8])]) -> Vec<u8> {
+        write_tar(Vec::new(), files).unwrap()
+    }
+
+    fn owned(files: &[(&str, &[u8])]) -> Vec<(String, Vec<u8>)> {
+        files
+            .iter()
+            .map(|(name, contents)| (name.to_string(), contents.to_vec()))
+            .collect()
+    }
+
+    #[test]
+    fn round_trip() {
+        let large = vec![7_u8; 3 * BLOCK_LENGTH + 1];
+        let files: [(&str, &[u8]); 4] = [
+            ("dota.vpk", b"VPK data"),
+            ("empty.txt", b""),
+            ("maps/large.bin", &large),
+            ("block.bin", &[1; BLOCK_LENGTH]),
+        ];
+        let tar = tar(&files);
+        assert_eq!(tar.len() % BLOCK_LENGTH, 0);
+        assert_eq!(read_tar(tar.as_slice()).unwrap(), owned(&files));
+    }
+
+    #[cfg(feature = "lzma")]
+    #[test]
+    fn xz_round_trip_compresses() {
+        let large = vec![7_u8; 64 * BLOCK_LENGTH];
+        let files: [(&str, &[u8]); 2] = [("dota.vpk", &large), ("conflicts.txt", b"none")];
+        let mut xz = Vec::new();
+        write_tar_xz(&mut xz, &files).unwrap();
+        assert!(xz.len() < large.len() / 10, "{} bytes", xz.len());
+        assert_eq!(read_tar_xz(xz.as_slice()).unwrap(), owned(&files));
+    }
+
+    #[test]
+    fn long_names_round_trip() {
+        let name = format!("{}/{}/{}", "a".repeat(120), "b".repeat(20), "c".repeat(150));
+        let files: [(&str, &[u8]); 1] = [(&name, b"contents")];
+        assert_eq!(read_tar(tar(&files).as_slice()).unwrap(), owned(&files));
+    }
+
+    #[test]
+    fn missing_end_blocks_are_accepted() {
+        let files: [(&str, &[u8]); 1] = [("dota.vpk", b"VPK data")];
+        let tar = tar(&files);
+        let files = owned(&files);
+        assert_eq!(read_tar(&tar[..2 * BLOCK_LENGTH]).unwrap(), files);
+    }
+
+    #[test]
+    fn truncated_and_damaged_archives_fail() {
+        let tar = tar(&[("first.bin", &[1; 600]), ("second.bin", b"data")]);
+        for length in [
+            BLOCK_LENGTH + 10,
+            3 * BLOCK_LENGTH + 100,
+            4 * BLOCK_LENGTH + 3,
+        ] {
+            assert!(read_tar(&tar[..length]).is_err(), "{} bytes", length);
+        }
+        let error = read_tar(&tar[..BLOCK_LENGTH + 10]).unwrap_err();
+        assert_eq!(error.to_string(), "archive is truncated");
+
+        let mut damaged = tar.clone();
+        damaged[0] = b'x';
+        assert!(read_tar(damaged.as_slice()).is_err());
+    }
+
+    #[test]
+    fn archives_are_recognized_by_extension() {
+        let format = |name: &str| ArchiveFormat::from_path(Path::new(name));
+        assert!(format("build.tar") == Some(ArchiveFormat::Tar));
+        assert!(format("Build.TAR.XZ") == Some(ArchiveFormat::TarXz));
+        assert!(format("build.txz") == Some(ArchiveFormat::TarXz));
+        assert!(format("build.zip").is_none());
+    }
+}
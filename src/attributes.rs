@@ -0,0 +1,113 @@
+use std::{
+    collections::BTreeMap,
+    fs, io,
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use serde_json::{json, Value};
+
+/// Extension appended to the file name of a VPK to get its attribute record, e.g.
+/// `pak01_dir.vpk.attributes.json`
+const ATTRIBUTES_EXTENSION: &str = "attributes.json";
+
+/// Attributes of a packed file which the VPK format cannot store
+/// # Properties
+/// - `modified: SystemTime` = Last modification time
+/// - `readonly: bool` = Whether the file was read-only
+#[derive(Clone, Copy)]
+pub(crate) struct FileAttributes {
+    modified: SystemTime,
+    readonly: bool,
+}
+
+impl FileAttributes {
+    /// Read the attributes of the file at `path`
+    pub(crate) fn of(path: &Path) -> io::Result<FileAttributes> {
+        let metadata = fs::metadata(path)?;
+        Ok(FileAttributes {
+            modified: metadata.modified()?,
+            readonly: metadata.permissions().readonly(),
+        })
+    }
+
+    /// Give the file at `path` these attributes. The file is made read-only last, after its
+    /// modification time is set.
+    pub(crate) fn apply(&self, path: &Path) -> io::Result<()> {
+        fs::File::options()
+            .write(true)
+            .open(path)?
+            .set_modified(self.modified)?;
+        if self.readonly {
+            let mut permissions = fs::metadata(path)?.permissions();
+            permissions.set_readonly(true);
+            fs::set_permissions(path, permissions)?;
+        }
+        Ok(())
+    }
+
+    fn to_json(self) -> Value {
+        let modified = self.modified.duration_since(UNIX_EPOCH).unwrap_or_default();
+        json!({
+            "modified": modified.as_secs(),
+            "modified_nanos": modified.subsec_nanos(),
+            "readonly": self.readonly,
+        })
+    }
+
+    fn from_json(value: &Value) -> Option<FileAttributes> {
+        let seconds = value.get("modified")?.as_u64()?;
+        let nanos = value
+            .get("modified_nanos")
+            .and_then(Value::as_u64)
+            .unwrap_or_default();
+        Some(FileAttributes {
+            modified: UNIX_EPOCH + Duration::new(seconds, u32::try_from(nanos).ok()?),
+            readonly: value
+                .get("readonly")
+                .and_then(Value::as_bool)
+                .unwrap_or_default(),
+        })
+    }
+}
+
+/// Path of the attribute record of the VPK at `vpk_path`
+pub(crate) fn attributes_path(vpk_path: &Path) -> PathBuf {
+    let mut name = vpk_path.file_name().unwrap_or_default().to_os_string();
+    name.push(format!(".{}", ATTRIBUTES_EXTENSION));
+    vpk_path.with_file_name(name)
+}
+
+/// Write the attributes of the files packed into the VPK at `vpk_path`, keyed by their path
+/// within the VPK, to the record next to it
+pub(crate) fn write(vpk_path: &Path, files: &BTreeMap<String, FileAttributes>) -> io::Result<()> {
+    let files: serde_json::Map<String, Value> = files
+        .iter()
+        .map(|(path, attributes)| (path.clone(), attributes.to_json()))
+        .collect();
+    let json = serde_json::to_string_pretty(&json!({ "files": files }))?;
+    fs::write(attributes_path(vpk_path), json)
+}
+
+/// Read the attribute record next to the VPK at `vpk_path`. Returns `None` if there is none.
+pub(crate) fn read(vpk_path: &Path) -> io::Result<Option<BTreeMap<String, FileAttributes>>> {
+    let json = match fs::read_to_string(attributes_path(vpk_path)) {
+        Ok(json) => json,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e),
+    };
+    let value: Value = serde_json::from_str(&json)?;
+    let files = value
+        .get("files")
+        .and_then(Value::as_object)
+        .and_then(|files| {
+            files
+                .iter()
+                .map(|(path, attributes)| {
+                    Some((path.clone(), FileAttributes::from_json(attributes)?))
+                })
+                .collect::<Option<BTreeMap<String, FileAttributes>>>()
+        })
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "malformed attribute record"))?;
+    Ok(Some(files))
+}
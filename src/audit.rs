@@ -0,0 +1,90 @@
+use std::{collections::BTreeMap, path::PathBuf};
+
+use crate::cli::Args;
+use crate::vpk::{VpkOptions, VPK};
+
+/// Top-level directories holding only visual or audio assets
+const COSMETIC_DIRS: [&str; 8] = [
+    "maps",
+    "materials",
+    "models",
+    "particles",
+    "sounds",
+    "soundevents",
+    "panorama",
+    "resource",
+];
+
+/// Paths which change gameplay or run code, with what they affect. Matched as prefixes,
+/// before `COSMETIC_DIRS`.
+const RISK_PATHS: [(&str, &str); 9] = [
+    ("scripts/npc/", "hero, unit, ability or item definitions"),
+    ("scripts/items/", "item definitions"),
+    ("scripts/vscripts/", "server scripts"),
+    ("scripts/ai/", "bot and unit AI"),
+    ("scripts/", "game scripts"),
+    ("ai/", "bot and unit AI"),
+    ("bin/", "game binaries"),
+    ("cfg/", "console configuration"),
+    ("panorama/scripts/", "user interface scripts"),
+];
+
+/// What an entry at `path` may affect, or `None` if it is purely cosmetic
+fn risk(path: &str) -> Option<&'static str> {
+    let lowercase = path.to_lowercase();
+    if let Some((_, risk)) = RISK_PATHS
+        .iter()
+        .find(|(prefix, _)| lowercase.starts_with(prefix))
+    {
+        return Some(risk);
+    }
+    match lowercase.split_once('/') {
+        Some((dir, _)) if COSMETIC_DIRS.contains(&dir) => None,
+        _ => Some("unknown, outside the known asset folders"),
+    }
+}
+
+/// Entry point for the `audit <vpk>` command. Lists the entries of a terrain or bundle outside
+/// the folders known to hold only visual and audio assets, such as game scripts, item
+/// definitions and AI, so a downloaded mod can be checked for gameplay changes before it is
+/// installed. Exits with an error if any were found.
+pub(crate) fn run(args: &Args) {
+    let [vpk_path] = args.positional.as_slice() else {
+        eprintln!("Usage: dota-terrain-mod audit <vpk>");
+        std::process::exit(1);
+    };
+    let vpk = match VPK::open_with(PathBuf::from(vpk_path), VpkOptions::from_args(args)) {
+        Ok(vpk) => vpk,
+        Err(e) => {
+            eprintln!("Dota-Terrain-Mod error: Invalid VPK: {}", e);
+            std::process::exit(1);
+        }
+    };
+
+    let mut flagged: BTreeMap<&str, Vec<&String>> = BTreeMap::new();
+    for (path, _) in vpk.entries() {
+        if let Some(risk) = risk(path) {
+            flagged.entry(risk).or_default().push(path);
+        }
+    }
+    println!("Entries: {}", vpk.entries().count());
+    if flagged.is_empty() {
+        println!("Only visual and audio assets found, the mod does not change gameplay.");
+        return;
+    }
+
+    let total: usize = flagged.values().map(Vec::len).sum();
+    for (risk, paths) in &mut flagged {
+        paths.sort();
+        println!("{} ({}):", risk, paths.len());
+        for path in paths.iter() {
+            println!("  {}", path);
+        }
+    }
+    eprintln!(
+        "Audit failed: {} file(s) may change gameplay or run code, check them before \
+        installing the mod.",
+        total
+    );
+    std::process::exit(1);
+}
@@ -0,0 +1,201 @@
+use std::path::Path;
+
+use crate::apply::{self, ApplyOptions};
+use crate::archive::{self, ArchiveFormat};
+use crate::cli::Args;
+use crate::config::Config;
+use crate::history;
+use crate::manifest;
+use crate::utils::{self, DotaInstall};
+
+/// Expand the placeholders of an output name template: `{terrain}` (file name of the terrain
+/// without extension), `{base}` (the base map), `{date}` (today, `YYYY-MM-DD` in UTC) and
+/// `{buildid}` (build ID of the game, `unknown` if it cannot be read), e.g.
+/// `builds/{terrain}-{date}-{buildid}.vpk`. Other text is kept as it is.
+fn expand_template(template: &str, terrain_file: &str, base: &str, build_id: &str) -> String {
+    let terrain = Path::new(terrain_file)
+        .file_stem()
+        .map_or_else(|| terrain_file.into(), |stem| stem.to_string_lossy());
+    let (year, month, day) = history::civil_date((history::now() / 86400) as i64);
+    template
+        .replace("{terrain}", &terrain)
+        .replace("{base}", base)
+        .replace("{date}", &format!("{:04}-{:02}-{:02}", year, month, day))
+        .replace("{buildid}", build_id)
+}
+
+/// Entry point for the `build <terrain> [-o <out>] [--archive <archive>] [--yes]` command.
+/// Builds a terrain exactly as applying it would, but writes the result and its manifest to
+/// `out` only, leaving the game folder untouched so the archive can be checked with `verify`
+/// or `explore` first. `out` may contain the placeholders of `expand_template()`. Without
+/// `-o` and `--archive`, the template `build_output` of the configuration is used, so builds
+/// archived per patch are named consistently. With `--archive`, the output, its manifest and
+/// a conflict report are packed into a `.tar` or `.tar.xz` archive for storage or sharing,
+/// named as the files would be in the game folder. Existing files are only replaced after
+/// confirmation.
+pub(crate) fn run(args: &Args) {
+    let [query] = args.positional.as_slice() else {
+        eprintln!(
+            "Usage: dota-terrain-mod build <terrain> [-o <out>] [--archive <archive>] [--yes]"
+        );
+        std::process::exit(1);
+    };
+    if args
+        .option("method")
+        .is_some_and(|method| !method.eq_ignore_ascii_case("vpk"))
+    {
+        eprintln!("Dota-Terrain-Mod error: build only creates VPKs, --method is for `apply`");
+        std::process::exit(1);
+    }
+    let dota = match DotaInstall::new(args) {
+        Ok(dota) => dota,
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+    };
+    let mut config = match Config::load() {
+        Ok(config) => config,
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+    };
+    config.menu.discover(&dota);
+    let terrain = match config.menu.resolve(query) {
+        Ok(terrain) => terrain,
+        Err(e) => {
+            eprintln!("Dota-Terrain-Mod error: {}", e);
+            std::process::exit(1);
+        }
+    };
+    let template = match (args.option("output"), args.option("archive")) {
+        (Some(template), _) => Some(template.to_owned()),
+        (None, Some(_)) => None,
+        (None, None) => config.build_output.clone(),
+    };
+    if template.is_none() && args.option("archive").is_none() {
+        eprintln!(
+            "Dota-Terrain-Mod error: No output given, pass -o <out>, --archive <archive> or set \
+            build_output in the configuration"
+        );
+        std::process::exit(1);
+    }
+    let options = match ApplyOptions::from_args(args, &config, &terrain.file) {
+        Ok(options) => options,
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+    };
+    let build_id = dota.build_id();
+    let expand = |template: &str| {
+        expand_template(
+            template,
+            &terrain.file,
+            options.base(),
+            build_id.as_deref().unwrap_or("unknown"),
+        )
+    };
+    let out = template.as_deref().map(expand);
+    if let Some(out) = &out {
+        if let Err(e) = dota.check_output(Path::new(out), args.flag("i-know-what-im-doing")) {
+            eprintln!("Dota-Terrain-Mod error: {}", e);
+            std::process::exit(1);
+        }
+    }
+    let archive = args.option("archive").map(expand);
+    if let Some(archive) = &archive {
+        match ArchiveFormat::from_path(Path::new(archive)) {
+            Some(format) if format.is_supported() => {}
+            Some(_) => {
+                eprintln!(
+                    "Dota-Terrain-Mod error: {} needs a build with the \"lzma\" feature, \
+                    use a .tar file",
+                    archive
+                );
+                std::process::exit(1);
+            }
+            None => {
+                eprintln!(
+                    "Dota-Terrain-Mod error: Unsupported archive {}, use a .tar or .tar.xz file",
+                    archive
+                );
+                std::process::exit(1);
+            }
+        }
+    }
+
+    println!("Building {}...", terrain.name);
+    let (out_file, manifest) = match apply::build(&dota, &terrain.file, &options) {
+        Ok(built) => built,
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+    };
+    for path in out.iter().chain(&archive) {
+        if Path::new(path).exists()
+            && !utils::confirm(
+                &format!("{} already exists. Overwrite it?", path),
+                args.assume_yes(),
+            )
+        {
+            std::process::exit(1);
+        }
+    }
+
+    if let Some(out) = &out {
+        let out_path = Path::new(out);
+        let written = out_path
+            .parent()
+            .map_or(Ok(()), std::fs::create_dir_all)
+            .and_then(|_| std::fs::write(out_path, &out_file));
+        if let Err(e) = written {
+            eprintln!("Dota-Terrain-Mod error: Could not write {}: {}", out, e);
+            std::process::exit(1);
+        }
+        if let Err(e) = manifest.write(out_path) {
+            eprintln!("Warning: Could not write the manifest: {}", e);
+        }
+    }
+    if let Some(archive) = &archive {
+        let vpk_name = format!("{}.vpk", options.base());
+        let written = manifest.to_json_string().and_then(|json| {
+            let report = manifest.conflict_report();
+            let manifest_name = manifest::manifest_name(Path::new(&vpk_name));
+            let files: [(&str, &[u8]); 3] = [
+                (&vpk_name, &out_file),
+                (&manifest_name, json.as_bytes()),
+                ("conflicts.txt", report.as_bytes()),
+            ];
+            let archive_path = Path::new(archive);
+            // Checked before building
+            let format = ArchiveFormat::from_path(archive_path).unwrap();
+            archive_path
+                .parent()
+                .filter(|dir| !dir.as_os_str().is_empty())
+                .map_or(Ok(()), std::fs::create_dir_all)
+                .and_then(|_| archive::write(archive_path, format, &files))
+        });
+        if let Err(e) = written {
+            eprintln!("Dota-Terrain-Mod error: Could not write {}: {}", archive, e);
+            std::process::exit(1);
+        }
+    }
+
+    for line in manifest.conflicts() {
+        println!("{}", line);
+    }
+    if !manifest.downscaled.is_empty() {
+        println!("{} texture(s) downscaled", manifest.downscaled.len());
+    }
+    if !manifest.recolored.is_empty() {
+        println!("{} ground texture(s) recolored", manifest.recolored.len());
+    }
+    println!("{}", manifest.stats.summary());
+    for path in out.iter().chain(&archive) {
+        println!("Written to {}.", path);
+    }
+    println!("The game folder was not changed.");
+}
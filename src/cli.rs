@@ -0,0 +1,152 @@
+use std::collections::HashMap;
+
+/// Options which take a value, given either as `--option value` or `--option=value`
+const VALUE_OPTIONS: [&str; 21] = [
+    "archive",
+    "base",
+    "bytes",
+    "conflicts-policy",
+    "depth",
+    "downscale",
+    "duplicates",
+    "from-history",
+    "game",
+    "hotkey",
+    "key",
+    "map-alias",
+    "method",
+    "output",
+    "overrides",
+    "prefix",
+    "quiet-period",
+    "recolor",
+    "rules",
+    "steam-path",
+    "steam-user",
+];
+
+/// Options which take no value, given as `--flag`
+const FLAGS: [&str; 25] = [
+    "auto-reapply",
+    "compress",
+    "detailed",
+    "force",
+    "i-know-what-im-doing",
+    "ignore-steam-state",
+    "ingame",
+    "keep-times",
+    "low-end",
+    "menu",
+    "no-limits",
+    "per-user",
+    "plain",
+    "portable",
+    "preload",
+    "quick",
+    "rehash",
+    "resolve-conflicts",
+    "smart",
+    "strict",
+    "strip",
+    "strip-editor-files",
+    "terrain-minimap",
+    "watch",
+    "yes",
+];
+
+/// Single-letter aliases of options, given as `-o value`
+const SHORT_OPTIONS: [(&str, &str); 1] = [("o", "output")];
+
+/// Parsed command line arguments
+/// # Properties
+/// - `command: Option<String>` = The subcommand, if any. `None` runs the interactive menu
+/// - `positional: Vec<String>` = Positional arguments following the subcommand
+/// - `flags: Vec<String>` = Flags given without a value, without the leading dashes
+/// - `options: HashMap<String, Vec<String>>` = Values of the options given, without the leading
+///   dashes. Options may be given more than once
+pub(crate) struct Args {
+    pub(crate) command: Option<String>,
+    pub(crate) positional: Vec<String>,
+    flags: Vec<String>,
+    options: HashMap<String, Vec<String>>,
+}
+
+impl Args {
+    /// Parse the arguments the program was started with. The first argument which is not
+    /// a flag or option is treated as the subcommand.
+    /// Exits with an error for unknown flags and options, e.g. a misspelled one.
+    pub(crate) fn parse() -> Args {
+        match Args::parse_from(std::env::args().skip(1)) {
+            Ok(args) => args,
+            Err(e) => {
+                eprintln!("Dota-Terrain-Mod error: {}", e);
+                std::process::exit(1);
+            }
+        }
+    }
+
+    /// Parse `args` like the arguments the program was started with, without the program's
+    /// own path, e.g. for `TerrainMod::apply()`. Fails with a message for unknown flags and
+    /// options.
+    pub(crate) fn parse_from(args: impl IntoIterator<Item = String>) -> Result<Args, String> {
+        let mut parsed = Args {
+            command: None,
+            positional: Vec::new(),
+            flags: Vec::new(),
+            options: HashMap::new(),
+        };
+
+        let mut args = args.into_iter();
+        while let Some(arg) = args.next() {
+            if let Some(name) = arg.strip_prefix("--") {
+                match name.split_once('=') {
+                    Some((name, value)) if VALUE_OPTIONS.contains(&name) => {
+                        parsed.add_option(name, value.to_owned())
+                    }
+                    None if VALUE_OPTIONS.contains(&name) => {
+                        let value = args.next().unwrap_or_default();
+                        parsed.add_option(name, value);
+                    }
+                    None if FLAGS.contains(&name) => parsed.flags.push(name.to_owned()),
+                    _ => return Err(format!("Unknown option \"{}\"", arg)),
+                }
+            } else if let Some(&(_, name)) = arg
+                .strip_prefix('-')
+                .and_then(|short| SHORT_OPTIONS.iter().find(|(alias, _)| *alias == short))
+            {
+                let value = args.next().unwrap_or_default();
+                parsed.add_option(name, value);
+            } else if parsed.command.is_none() {
+                parsed.command = Some(arg);
+            } else {
+                parsed.positional.push(arg);
+            }
+        }
+        Ok(parsed)
+    }
+
+    fn add_option(&mut self, name: &str, value: String) {
+        self.options.entry(name.to_owned()).or_default().push(value);
+    }
+
+    /// Whether the flag `--name` was given
+    pub(crate) fn flag(&self, name: &str) -> bool {
+        self.flags.iter().any(|flag| flag == name)
+    }
+
+    /// Whether prompts are to be answered with yes, given as `--yes` or `--force`
+    pub(crate) fn assume_yes(&self) -> bool {
+        self.flag("yes") || self.flag("force")
+    }
+
+    /// The value given for the option `--name`, if any. If it was given more than once, the
+    /// last value is used.
+    pub(crate) fn option(&self, name: &str) -> Option<&str> {
+        self.options.get(name)?.last().map(String::as_str)
+    }
+
+    /// Every value given for the option `--name`, in order
+    pub(crate) fn options(&self, name: &str) -> &[String] {
+        self.options.get(name).map_or(&[], Vec::as_slice)
+    }
+}
@@ -0,0 +1,183 @@
+use std::path::PathBuf;
+
+use crate::cli::Args;
+use crate::manifest::{self, Manifest, Source};
+use crate::utils::DotaInstall;
+use crate::vpk::VPK;
+
+/// Number of changed files listed by name for each source
+const MAX_LISTED: usize = 20;
+
+/// Differences between the base map an output was built from and the base map in the game
+/// folder, see `compare()`
+/// # Properties
+/// - `terrain: String` = File name of the applied terrain
+/// - `base_path: PathBuf` = Path of the base map in the game folder
+/// - `changed: Vec<(String, Source)>` = Files replaced by the terrain or the overrides which
+///   changed in the base map, with the source replacing them
+/// - `removed: Vec<(String, Source)>` = Files replaced by the terrain or the overrides which
+///   no longer exist in the base map
+/// - `outdated: usize` = Number of files copied from the base map which changed or were removed
+/// - `added: usize` = Number of files of the base map missing from the output
+pub(crate) struct Comparison {
+    pub(crate) terrain: String,
+    pub(crate) base_path: PathBuf,
+    pub(crate) changed: Vec<(String, Source)>,
+    pub(crate) removed: Vec<(String, Source)>,
+    pub(crate) outdated: usize,
+    pub(crate) added: usize,
+}
+
+impl Comparison {
+    /// Whether the base map changed any file the terrain or the overrides replace
+    pub(crate) fn touches_replaced(&self) -> bool {
+        !self.changed.is_empty() || !self.removed.is_empty()
+    }
+
+    /// Whether the base map is unchanged since the output was built
+    pub(crate) fn is_unchanged(&self) -> bool {
+        !self.touches_replaced() && self.outdated == 0 && self.added == 0
+    }
+}
+
+/// Compare the installed output of the base map `base` against the base map in the game
+/// folder, using the CRCs of the base map's files recorded in the manifest when the output
+/// was built. Returns `None` if no terrain is applied. Fails if the manifest or the base map
+/// cannot be read, or the manifest was written by a version which did not record the CRCs.
+pub(crate) fn compare(dota: &DotaInstall, base: &str) -> Result<Option<Comparison>, String> {
+    let out_path = dota.out_vpk(base);
+    if !manifest::is_applied(&out_path) {
+        return Ok(None);
+    }
+    let manifest = Manifest::read(&out_path).map_err(|e| {
+        format!(
+            "The manifest of {} could not be read: {}",
+            out_path.display(),
+            e
+        )
+    })?;
+    compare_manifest(&manifest, dota.base_vpk(base)).map(Some)
+}
+
+/// Compare the output described by `manifest` against the base map at `base_path`, see
+/// `compare()`. Fails if the base map cannot be read, or the manifest was written by a
+/// version which did not record the CRCs.
+pub(crate) fn compare_manifest(
+    manifest: &Manifest,
+    base_path: PathBuf,
+) -> Result<Comparison, String> {
+    if manifest
+        .entries
+        .values()
+        .all(|entry| entry.base_crc32.is_none())
+    {
+        return Err(String::from(
+            "The output was built by an older version, which did not record the base map. \
+            Apply the terrain again to compare future updates.",
+        ));
+    }
+    let base_vpk = VPK::open(base_path.clone())
+        .map_err(|e| format!("Could not read {}: {}", base_path.display(), e))?;
+
+    let mut comparison = Comparison {
+        terrain: manifest.terrain.clone(),
+        base_path,
+        changed: Vec::new(),
+        removed: Vec::new(),
+        outdated: 0,
+        added: 0,
+    };
+    for (path, entry) in &manifest.entries {
+        let Some(crc32) = entry.base_crc32 else {
+            continue;
+        };
+        let live = base_vpk.entry(path).map(|live| live.crc32());
+        if live == Some(crc32) {
+            continue;
+        }
+        match (entry.source, live) {
+            (Source::Base, _) => comparison.outdated += 1,
+            (source, Some(_)) => comparison.changed.push((path.clone(), source)),
+            (source, None) => comparison.removed.push((path.clone(), source)),
+        }
+    }
+    comparison.added = base_vpk
+        .entries()
+        .filter(|(path, _)| !manifest.entries.contains_key(*path))
+        .count();
+    Ok(comparison)
+}
+
+/// Entry point for the `compare-install [--base <map>]` command. Compares the installed
+/// output (of the default map, or the map given with `--base`) against the base map in the
+/// game folder, see `compare()`. Reports which files the terrain or the overrides replace have
+/// changed or disappeared in the base map since, e.g. after a game update, along with how many
+/// copied files are outdated and how many new files of the base map are missing from the
+/// output.
+pub(crate) fn run(args: &Args) {
+    if !args.positional.is_empty() {
+        eprintln!("Usage: dota-terrain-mod compare-install [--base <map>]");
+        std::process::exit(1);
+    }
+    let dota = match DotaInstall::new(args) {
+        Ok(dota) => dota,
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+    };
+    let base = args.option("base").unwrap_or(dota.game.default_base);
+    let comparison = match compare(&dota, base) {
+        Ok(Some(comparison)) => comparison,
+        Ok(None) => {
+            println!("No terrain applied.");
+            return;
+        }
+        Err(e) => {
+            eprintln!("Dota-Terrain-Mod error: {}", e);
+            std::process::exit(1);
+        }
+    };
+
+    println!("Applied terrain: {}", comparison.terrain);
+    println!("Compared with: {}", comparison.base_path.display());
+    for (files, what) in [
+        (&comparison.changed, "changed in the base map"),
+        (&comparison.removed, "no longer exist in the base map"),
+    ] {
+        if files.is_empty() {
+            continue;
+        }
+        println!(
+            "\n{} replaced file(s) {} since the terrain was applied:",
+            files.len(),
+            what
+        );
+        for (path, source) in files.iter().take(MAX_LISTED) {
+            println!("  {} (from {})", path, source.name());
+        }
+        if files.len() > MAX_LISTED {
+            println!("  ... and {} more", files.len() - MAX_LISTED);
+        }
+    }
+    if comparison.outdated > 0 || comparison.added > 0 {
+        println!(
+            "\n{} file(s) copied from the base map are outdated or removed, {} new file(s) are missing.",
+            comparison.outdated, comparison.added
+        );
+    }
+
+    if comparison.is_unchanged() {
+        println!("\nThe base map has not changed since the terrain was applied.");
+    } else if !comparison.touches_replaced() {
+        println!(
+            "\nThe base map changed, but none of the files the terrain replaces. \
+            Apply the terrain again to pick up the changes."
+        );
+    } else {
+        println!(
+            "\nThe base map changed files the terrain replaces, the terrain may look broken. \
+            Apply the terrain again, or check for an update of the terrain."
+        );
+    }
+}
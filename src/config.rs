@@ -0,0 +1,293 @@
+use std::{
+    path::{Path, PathBuf},
+    sync::atomic::{AtomicBool, Ordering},
+    time::Duration,
+};
+
+use toml::Table;
+use toml_edit::{Array, DocumentMut};
+
+use crate::cli::Args;
+use crate::conflicts::{self, Decision};
+use crate::hashing::HashAlgorithm;
+use crate::menu::MenuConfig;
+use crate::rules::{self, Rule};
+use crate::schedule::{self, ScheduleRule};
+use crate::terrain_settings::{self, TerrainSettings};
+use crate::utils::TMError;
+
+/// Name of the configuration file inside `config_dir()`
+const CONFIG_FILE: &str = "config.toml";
+
+/// Name of the directory next to the executable holding the configuration in portable mode
+const PORTABLE_DIR: &str = "dota-terrain-mod-data";
+
+/// Whether the configuration is kept next to the executable (`--portable`)
+static PORTABLE: AtomicBool = AtomicBool::new(false);
+
+/// Keep the configuration, history and any other state next to the executable instead of
+/// in the user's profile, so that the tool can be carried between machines
+pub(crate) fn set_portable() {
+    PORTABLE.store(true, Ordering::Relaxed);
+}
+
+/// Settings read from `config.toml` in the configuration directory. A missing file means
+/// every setting has its default.
+/// # Properties
+/// - `rules: Option<Vec<Rule>>` = Rename rules replacing the default rule set, if declared
+/// - `menu: MenuConfig` = Sections, order and names of the selection menu
+/// - `map_aliases: Vec<String>` = Additional names the terrain's map is written under, for
+///   game modes loading a variant map (`map_aliases = ["dota_winter"]`)
+/// - `trusted_keys: Vec<String>` = Public keys of terrain authors, in hex, whose signatures
+///   are trusted (`trusted_keys = ["..."]`)
+/// - `schedule: Vec<ScheduleRule>` = Rules of the `schedule` command, choosing the terrain by
+///   date (`[[schedule]]` tables)
+/// - `build_output: Option<String>` = Output of the `build` command when `-o` is not given,
+///   with placeholders (`build_output = "builds/{terrain}-{date}-{buildid}.vpk"`)
+/// - `hash: HashAlgorithm` = Hash function of pinned terrains and the history, MD5 unless
+///   BLAKE3 is chosen (`hash = "blake3"`)
+/// - `terrains: Vec<(String, TerrainSettings)>` = Options applied with a terrain whenever it is
+///   built, keyed by the terrain (`[terrains.<terrain>]` tables), see `terrain_settings()`
+/// - `conflicts: Vec<Decision>` = Which override layer wins files several layers add, chosen
+///   with `--resolve-conflicts` (`[conflicts]` table)
+/// - `conflicts_policy: Option<PathBuf>` = Policy file choosing the layer for the conflicts
+///   no decision covers (`conflicts_policy = "conflicts.toml"`), see `conflicts::Policy`
+/// - `watch_quiet_period: Option<Duration>` = How long the installed terrain or the sources
+///   have to stay unchanged with `apply --watch` or `pack --watch` before a change is acted
+///   on (`watch_quiet_period = 30`, in seconds), see `Config::quiet_period()`
+#[derive(Default)]
+pub(crate) struct Config {
+    pub(crate) rules: Option<Vec<Rule>>,
+    pub(crate) menu: MenuConfig,
+    pub(crate) map_aliases: Vec<String>,
+    pub(crate) trusted_keys: Vec<String>,
+    pub(crate) schedule: Vec<ScheduleRule>,
+    pub(crate) build_output: Option<String>,
+    pub(crate) hash: HashAlgorithm,
+    pub(crate) terrains: Vec<(String, TerrainSettings)>,
+    pub(crate) conflicts: Vec<Decision>,
+    pub(crate) conflicts_policy: Option<PathBuf>,
+    pub(crate) watch_quiet_period: Option<Duration>,
+}
+
+impl Config {
+    /// Load the configuration file, or the defaults if there is none
+    pub(crate) fn load() -> Result<Config, TMError> {
+        let Some(path) = config_path() else {
+            return Ok(Config::default());
+        };
+        let text = match std::fs::read_to_string(&path) {
+            Ok(text) => text,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Config::default()),
+            Err(e) => return Err(TMError::InternalError(e)),
+        };
+        let document: Table = text
+            .parse()
+            .map_err(|e| TMError::ConfigError(format!("{}: {}", path.display(), e)))?;
+
+        let invalid = |e| TMError::ConfigError(format!("{}: {}", path.display(), e));
+        Ok(Config {
+            rules: rules::from_toml(&document).map_err(invalid)?,
+            menu: MenuConfig::from_toml(&document).map_err(invalid)?,
+            map_aliases: strings_from_toml(&document, "map_aliases", "map names")
+                .map_err(invalid)?,
+            trusted_keys: strings_from_toml(&document, "trusted_keys", "public keys")
+                .map_err(invalid)?,
+            schedule: schedule::from_toml(&document).map_err(invalid)?,
+            build_output: match document.get("build_output") {
+                None => None,
+                Some(template) => Some(
+                    template
+                        .as_str()
+                        .ok_or_else(|| invalid(String::from("\"build_output\" must be a string")))?
+                        .to_owned(),
+                ),
+            },
+            hash: match document.get("hash") {
+                None => HashAlgorithm::default(),
+                Some(name) => name
+                    .as_str()
+                    .and_then(HashAlgorithm::from_name)
+                    .ok_or_else(|| {
+                        invalid(String::from(
+                            "\"hash\" must be \"md5\" or \"blake3\", which needs a build \
+                            with the \"blake3\" feature",
+                        ))
+                    })?,
+            },
+            terrains: terrain_settings::from_toml(&document).map_err(invalid)?,
+            conflicts: conflicts::from_toml(&document).map_err(invalid)?,
+            conflicts_policy: match document.get("conflicts_policy") {
+                None => None,
+                Some(policy) => Some(PathBuf::from(policy.as_str().ok_or_else(|| {
+                    invalid(String::from(
+                        "\"conflicts_policy\" must be the path of a policy file",
+                    ))
+                })?)),
+            },
+            watch_quiet_period: match document.get("watch_quiet_period") {
+                None => None,
+                Some(seconds) => Some(Duration::from_secs(
+                    seconds
+                        .as_integer()
+                        .and_then(|seconds| u64::try_from(seconds).ok())
+                        .ok_or_else(|| {
+                            invalid(String::from(
+                                "\"watch_quiet_period\" must be a number of seconds, e.g. 30",
+                            ))
+                        })?,
+                )),
+            },
+        })
+    }
+
+    /// How long watched files have to stay unchanged before a change is acted on, by
+    /// `apply --watch` and `pack --watch`: `--quiet-period` of `args`, else
+    /// `watch_quiet_period`, else the command's `default`
+    pub(crate) fn quiet_period(&self, args: &Args, default: Duration) -> Result<Duration, String> {
+        match args.option("quiet-period").map(str::parse::<u64>) {
+            None => Ok(self.watch_quiet_period.unwrap_or(default)),
+            Some(Ok(seconds)) => Ok(Duration::from_secs(seconds)),
+            Some(Err(_)) => Err(String::from(
+                "--quiet-period expects a number of seconds, e.g. 30",
+            )),
+        }
+    }
+
+    /// The settings of the terrain in `terrain_file` from a `[terrains.<terrain>]` table, if
+    /// there are any
+    pub(crate) fn terrain_settings(&self, terrain_file: &str) -> Option<&TerrainSettings> {
+        terrain_settings::find(&self.terrains, &self.menu, terrain_file)
+    }
+}
+
+/// Read the top-level array of strings `key`, e.g. `map_aliases`. `description` names what the
+/// strings are in the error message.
+fn strings_from_toml(
+    document: &Table,
+    key: &str,
+    description: &str,
+) -> Result<Vec<String>, String> {
+    let Some(strings) = document.get(key) else {
+        return Ok(Vec::new());
+    };
+    strings
+        .as_array()
+        .and_then(|strings| {
+            strings
+                .iter()
+                .map(|string| string.as_str().map(str::to_owned))
+                .collect()
+        })
+        .ok_or_else(|| format!("\"{}\" must be an array of {}", key, description))
+}
+
+/// Directory holding the configuration: `%APPDATA%\Dota-Terrain-Mod` on Windows,
+/// `$XDG_CONFIG_HOME/dota-terrain-mod` (by default `~/.config/dota-terrain-mod`) on Linux, or
+/// `dota-terrain-mod-data` next to the executable in portable mode
+pub(crate) fn config_dir() -> Option<PathBuf> {
+    if PORTABLE.load(Ordering::Relaxed) {
+        let exe = std::env::current_exe().ok()?;
+        return Some(exe.parent()?.join(PORTABLE_DIR));
+    }
+
+    // The roaming profile of the user running the tool, also when APPDATA is not set, e.g.
+    // when started from a service or scheduled task
+    #[cfg(target_os = "windows")]
+    return std::env::var_os("APPDATA")
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| {
+            std::env::var_os("USERPROFILE")
+                .map(|profile| PathBuf::from(profile).join("AppData").join("Roaming"))
+        })
+        .map(|dir| dir.join("Dota-Terrain-Mod"));
+
+    #[cfg(not(target_os = "windows"))]
+    match std::env::var_os("XDG_CONFIG_HOME") {
+        Some(dir) if !dir.is_empty() => Some(PathBuf::from(dir).join("dota-terrain-mod")),
+        _ => std::env::var_os("HOME")
+            .map(|home| PathBuf::from(home).join(".config").join("dota-terrain-mod")),
+    }
+}
+
+/// Path of the configuration file
+pub(crate) fn config_path() -> Option<PathBuf> {
+    config_dir().map(|dir| dir.join(CONFIG_FILE))
+}
+
+/// Read the configuration file for editing, an empty document if there is none yet
+fn edit_document() -> Result<(PathBuf, DocumentMut), TMError> {
+    let Some(path) = config_path() else {
+        return Err(TMError::ConfigError(String::from(
+            "could not determine the configuration directory",
+        )));
+    };
+    let text = match std::fs::read_to_string(&path) {
+        Ok(text) => text,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => String::new(),
+        Err(e) => return Err(TMError::InternalError(e)),
+    };
+    let document = text
+        .parse()
+        .map_err(|e| TMError::ConfigError(format!("{}: {}", path.display(), e)))?;
+    Ok((path, document))
+}
+
+/// Write the edited configuration file, creating its directory if needed
+fn write_document(path: &Path, document: &DocumentMut) -> Result<(), TMError> {
+    std::fs::create_dir_all(path.parent().unwrap()).map_err(TMError::InternalError)?;
+    std::fs::write(path, document.to_string()).map_err(TMError::InternalError)
+}
+
+/// Add `value` to, or remove it from, the array `key` in the table `table` of the
+/// configuration file. The rest of the file, including comments, is kept as it is and the
+/// file is created if needed. Returns whether the file was changed.
+pub(crate) fn edit_list(table: &str, key: &str, value: &str, add: bool) -> Result<bool, TMError> {
+    let (path, mut document) = edit_document()?;
+    let invalid = |e: String| TMError::ConfigError(format!("{}: {}", path.display(), e));
+
+    let table = document
+        .entry(table)
+        .or_insert(toml_edit::table())
+        .as_table_like_mut()
+        .ok_or_else(|| invalid(format!("\"{}\" must be a table", table)))?;
+    let list = table
+        .entry(key)
+        .or_insert(toml_edit::value(Array::new()))
+        .as_array_mut()
+        .ok_or_else(|| invalid(format!("\"{}\" must be an array", key)))?;
+    let position = list.iter().position(|item| item.as_str() == Some(value));
+    match (add, position) {
+        (true, None) => list.push(value),
+        (false, Some(position)) => {
+            list.remove(position);
+        }
+        _ => return Ok(false),
+    }
+
+    write_document(&path, &document)?;
+    Ok(true)
+}
+
+/// Set the string `key` in the table `table` of the configuration file to `value`, or remove
+/// it if `value` is `None`, keeping the rest of the file like `edit_list()`
+pub(crate) fn edit_value(table: &str, key: &str, value: Option<&str>) -> Result<(), TMError> {
+    let (path, mut document) = edit_document()?;
+    let table = document
+        .entry(table)
+        .or_insert(toml_edit::table())
+        .as_table_like_mut()
+        .ok_or_else(|| {
+            TMError::ConfigError(format!("{}: \"{}\" must be a table", path.display(), table))
+        })?;
+    match value {
+        Some(value) => {
+            table.insert(key, toml_edit::value(value));
+        }
+        None => {
+            table.remove(key);
+        }
+    }
+    write_document(&path, &document)
+}
@@ -0,0 +1,351 @@
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Path, PathBuf};
+
+use regex::Regex;
+use toml::{Table, Value};
+
+use crate::utils::{self, TMError};
+use crate::{config, rules};
+
+/// Which override layer wins the files several layers add, chosen with `--resolve-conflicts`
+/// and kept in the `[conflicts]` table of the configuration, e.g.
+/// `"materials/ground/" = "/home/me/mods/snowfall"`
+/// # Properties
+/// - `path: String` = Path of a file, or of a directory ending with `/` covering every file
+///   below it
+/// - `layer: PathBuf` = Directory or VPK of the layer which wins, as given with `--overrides`
+///   but made absolute
+#[derive(Clone)]
+pub(crate) struct Decision {
+    pub(crate) path: String,
+    pub(crate) layer: PathBuf,
+}
+
+impl Decision {
+    /// Whether the decision is about the file `path`
+    fn covers(&self, path: &str) -> bool {
+        if self.path.ends_with('/') {
+            path.starts_with(&self.path)
+        } else {
+            self.path == path
+        }
+    }
+}
+
+/// Read the `[conflicts]` table of a parsed TOML document
+pub(crate) fn from_toml(document: &Table) -> Result<Vec<Decision>, String> {
+    let Some(conflicts) = document.get("conflicts") else {
+        return Ok(Vec::new());
+    };
+    let Some(conflicts) = conflicts.as_table() else {
+        return Err(String::from("\"conflicts\" must be a table"));
+    };
+    conflicts
+        .iter()
+        .map(|(path, layer)| {
+            let layer = layer
+                .as_str()
+                .ok_or_else(|| format!("\"conflicts.{}\" must be the path of a layer", path))?;
+            Ok(Decision {
+                path: path.clone(),
+                layer: PathBuf::from(layer),
+            })
+        })
+        .collect()
+}
+
+/// A rule of a conflicts policy file
+/// # Properties
+/// - `pattern: Regex` = The glob matched against file paths, see `rules::glob_regex()`
+/// - `source: String` = The source preferred for matching files: a path, relative to the
+///   policy file, or the file name of a directory or VPK
+struct Preference {
+    pattern: Regex,
+    source: String,
+}
+
+/// Which source wins files several override layers or pack sources provide, for runs which
+/// cannot ask, e.g. scripts and `pack --watch`. Read from a TOML file of `[[prefer]]` tables
+/// with a `match` glob and a `source`, tried in order.
+/// # Properties
+/// - `dir: PathBuf` = Directory of the policy file, which sources are relative to
+/// - `preferences: Vec<Preference>` = The rules, in order
+pub(crate) struct Policy {
+    dir: PathBuf,
+    preferences: Vec<Preference>,
+}
+
+impl Policy {
+    /// Read the policy file at `path`
+    pub(crate) fn load(path: &Path) -> Result<Policy, String> {
+        let text = std::fs::read_to_string(path)
+            .map_err(|e| format!("Could not read {}: {}", path.display(), e))?;
+        let document: Table = text
+            .parse()
+            .map_err(|e| format!("{}: {}", path.display(), e))?;
+        let invalid = |e: &str| format!("{}: {}", path.display(), e);
+        let preferences = match document.get("prefer") {
+            None => Vec::new(),
+            Some(Value::Array(preferences)) => preferences
+                .iter()
+                .map(|preference| {
+                    let (Some(glob), Some(source)) = (
+                        preference.get("match").and_then(Value::as_str),
+                        preference.get("source").and_then(Value::as_str),
+                    ) else {
+                        return Err(invalid(
+                            "preferences must have a \"match\" glob and a \"source\"",
+                        ));
+                    };
+                    Ok(Preference {
+                        pattern: rules::glob_regex(glob),
+                        source: source.to_owned(),
+                    })
+                })
+                .collect::<Result<Vec<Preference>, String>>()?,
+            Some(_) => return Err(invalid("\"prefer\" must be an array of tables")),
+        };
+        Ok(Policy {
+            dir: path.parent().unwrap_or(Path::new("")).to_path_buf(),
+            preferences,
+        })
+    }
+
+    /// The source among `sources` preferred for the file `path`: the one named by the first
+    /// matching rule whose source provides the file, if any
+    pub(crate) fn prefer(&self, path: &str, sources: &[&Path]) -> Option<usize> {
+        self.preferences
+            .iter()
+            .filter(|preference| preference.pattern.is_match(path))
+            .find_map(|preference| {
+                let preferred = utils::resolve_path(&self.dir.join(&preference.source));
+                sources.iter().position(|source| {
+                    utils::resolve_path(source) == preferred
+                        || source
+                            .file_name()
+                            .is_some_and(|name| name.to_string_lossy() == preference.source)
+                })
+            })
+    }
+}
+
+/// A file added by more than one override layer
+/// # Properties
+/// - `path: String` = Path of the file
+/// - `layers: Vec<usize>` = Positions of the layers adding it, in order
+pub(crate) struct Conflict {
+    pub(crate) path: String,
+    pub(crate) layers: Vec<usize>,
+}
+
+/// Every file added by more than one of the `loaded` layers, sorted by path
+pub(crate) fn find(loaded: &[HashMap<String, Vec<u8>>]) -> Vec<Conflict> {
+    let mut layers: BTreeMap<&String, Vec<usize>> = BTreeMap::new();
+    for (i, overrides) in loaded.iter().enumerate() {
+        for path in overrides.keys() {
+            layers.entry(path).or_default().push(i);
+        }
+    }
+    layers
+        .into_iter()
+        .filter(|(_, layers)| layers.len() > 1)
+        .map(|(path, layers)| Conflict {
+            path: path.clone(),
+            layers,
+        })
+        .collect()
+}
+
+/// The layer among `layers` winning `conflict`: the one of the most specific decision
+/// covering the file, if it adds the file, or else the one `policy` prefers, or else the last
+/// one. Also returns whether a decision or the policy chose it.
+fn winner(
+    conflict: &Conflict,
+    layers: &[PathBuf],
+    decisions: &[Decision],
+    policy: Option<&Policy>,
+) -> (usize, bool) {
+    let mut covering: Vec<&Decision> = decisions
+        .iter()
+        .filter(|decision| decision.covers(&conflict.path))
+        .collect();
+    covering.sort_by_key(|decision| std::cmp::Reverse(decision.path.len()));
+    let sources: Vec<&Path> = conflict
+        .layers
+        .iter()
+        .map(|&i| layers[i].as_path())
+        .collect();
+    covering
+        .iter()
+        .find_map(|decision| {
+            let layer = utils::resolve_path(&decision.layer);
+            sources
+                .iter()
+                .position(|source| utils::resolve_path(source) == layer)
+        })
+        .or_else(|| policy.and_then(|policy| policy.prefer(&conflict.path, &sources)))
+        .map_or((*conflict.layers.last().unwrap(), false), |i| {
+            (conflict.layers[i], true)
+        })
+}
+
+/// Merge the overrides `loaded` from `layers`, see `utils::load_override_layers()`. Files of
+/// later layers replace those of earlier ones, unless `decisions` or `policy` choose another
+/// layer. Conflicts neither covers are counted in a note.
+pub(crate) fn merge(
+    mut loaded: Vec<HashMap<String, Vec<u8>>>,
+    layers: &[PathBuf],
+    decisions: &[Decision],
+    policy: Option<&Policy>,
+) -> HashMap<String, Vec<u8>> {
+    let winners: Vec<(usize, bool, String)> = find(&loaded)
+        .into_iter()
+        .map(|conflict| {
+            let (i, chosen) = winner(&conflict, layers, decisions, policy);
+            (i, chosen, conflict.path)
+        })
+        .collect();
+    let undecided = winners.iter().filter(|(_, chosen, _)| !chosen).count();
+    if undecided > 0 {
+        println!(
+            "Note: {} file(s) are added by more than one override layer, the last layer wins. \
+            Pass --resolve-conflicts or --conflicts-policy to choose.",
+            undecided
+        );
+    }
+    for (i, _, path) in winners {
+        let data = loaded[i].remove(&path).unwrap();
+        loaded.last_mut().unwrap().insert(path, data);
+    }
+    loaded.into_iter().flatten().collect()
+}
+
+/// Read an answer from the console, `None` without input to read
+fn ask(question: &str) -> Option<String> {
+    println!("{}", question);
+    let mut answer = String::new();
+    match std::io::stdin().read_line(&mut answer) {
+        Ok(n) if n > 0 => Some(answer.trim().to_owned()),
+        _ => None,
+    }
+}
+
+/// The layer numbered `answer` among `choices`, as listed by `resolve()`
+fn chosen(answer: &str, choices: &[usize]) -> Option<usize> {
+    let n: usize = answer.parse().ok()?;
+    choices.contains(&(n.checked_sub(1)?)).then(|| n - 1)
+}
+
+/// Add the decision that `layer` wins `path` to the `[conflicts]` table of the configuration
+/// and to `decisions`, replacing the one made before. Deciding on a directory replaces the
+/// decisions made for the files below it as well.
+fn record(decisions: &mut Vec<Decision>, path: String, layer: &Path) -> Result<(), TMError> {
+    let decision = Decision {
+        path,
+        layer: utils::resolve_path(layer),
+    };
+    for replaced in decisions.iter().filter(|kept| decision.covers(&kept.path)) {
+        config::edit_value("conflicts", &replaced.path, None)?;
+    }
+    decisions.retain(|kept| !decision.covers(&kept.path));
+    config::edit_value(
+        "conflicts",
+        &decision.path,
+        Some(&decision.layer.display().to_string()),
+    )?;
+    decisions.push(decision);
+    Ok(())
+}
+
+/// List the files the `loaded` layers conflict on, grouped by directory, and ask which layer
+/// wins each directory, or each of its files. The answers are added to the `[conflicts]`
+/// table of the configuration and returned along with `decisions`, replacing the ones they
+/// overrule.
+pub(crate) fn resolve(
+    loaded: &[HashMap<String, Vec<u8>>],
+    layers: &[PathBuf],
+    decisions: &[Decision],
+    policy: Option<&Policy>,
+) -> Result<Vec<Decision>, TMError> {
+    let conflicts = find(loaded);
+    let mut decisions = decisions.to_vec();
+    if conflicts.is_empty() {
+        println!("No file is added by more than one override layer.");
+        return Ok(decisions);
+    }
+    println!(
+        "{} file(s) are added by more than one override layer:",
+        conflicts.len()
+    );
+    for (i, layer) in layers.iter().enumerate() {
+        println!("  {}: {}", i + 1, layer.display());
+    }
+    let numbers = |choices: &[usize]| -> String {
+        choices
+            .iter()
+            .map(|i| (i + 1).to_string())
+            .collect::<Vec<_>>()
+            .join(", ")
+    };
+
+    let mut directories: BTreeMap<&str, Vec<&Conflict>> = BTreeMap::new();
+    for conflict in &conflicts {
+        let dir = conflict.path.rsplit_once('/').map_or("", |(dir, _)| dir);
+        directories.entry(dir).or_default().push(conflict);
+    }
+    'directories: for (dir, conflicts) in directories {
+        let mut choices: Vec<usize> = conflicts
+            .iter()
+            .flat_map(|conflict| conflict.layers.iter().copied())
+            .collect();
+        choices.sort();
+        choices.dedup();
+        println!();
+        println!(
+            "{}/ ({} file(s), in layers {})",
+            dir,
+            conflicts.len(),
+            numbers(&choices)
+        );
+        for conflict in &conflicts {
+            println!(
+                "  {} (layers {}, layer {} wins)",
+                conflict.path,
+                numbers(&conflict.layers),
+                winner(conflict, layers, &decisions, policy).0 + 1
+            );
+        }
+        // Files at the top of a VPK have no directory to decide on
+        let answer = if dir.is_empty() {
+            String::from("f")
+        } else {
+            let Some(answer) = ask(
+                "Which layer wins the whole directory? Enter a number, f to choose per file, \
+                or nothing to keep it as it is:",
+            ) else {
+                break;
+            };
+            answer
+        };
+        if answer.eq_ignore_ascii_case("f") {
+            for conflict in &conflicts {
+                let Some(answer) = ask(&format!(
+                    "Which layer wins {}? Enter {} or nothing to keep layer {}:",
+                    conflict.path,
+                    numbers(&conflict.layers),
+                    winner(conflict, layers, &decisions, policy).0 + 1
+                )) else {
+                    break 'directories;
+                };
+                if let Some(i) = chosen(&answer, &conflict.layers) {
+                    record(&mut decisions, conflict.path.clone(), &layers[i])?;
+                }
+            }
+        } else if let Some(i) = chosen(&answer, &choices) {
+            record(&mut decisions, format!("{}/", dir), &layers[i])?;
+        } else if !answer.is_empty() {
+            println!("Unknown layer {}, keeping it as it is.", answer);
+        }
+    }
+    Ok(decisions)
+}
@@ -0,0 +1,62 @@
+use std::path::{Path, PathBuf};
+
+use crate::cli::Args;
+use crate::utils;
+use crate::vpk::{self, HashSections, VpkOptions};
+
+/// Entry point for the `copy <vpk> <out> [--strip | --rehash] [--strict] [--yes]` command.
+/// Rewrites a VPK with this program's writer. By default the hash and signature sections of
+/// the source are preserved, `--rehash` generates new hashes and `--strip` removes them. With
+/// `--strict`, sources with an inconsistent tree or entries failing their CRC are refused. An
+/// existing `out` is only replaced after confirmation.
+pub(crate) fn run(args: &Args) {
+    let [vpk_path, out_path] = args.positional.as_slice() else {
+        eprintln!(
+            "Usage: dota-terrain-mod copy <vpk> <out> [--strip | --rehash] [--strict] [--yes]"
+        );
+        std::process::exit(1);
+    };
+    let hash_sections = match (args.flag("strip"), args.flag("rehash")) {
+        (false, false) => HashSections::Preserve,
+        (false, true) => HashSections::Rehash,
+        (true, false) => HashSections::Strip,
+        (true, true) => {
+            eprintln!("Dota-Terrain-Mod error: --strip and --rehash cannot be combined");
+            std::process::exit(1);
+        }
+    };
+
+    let options = VpkOptions {
+        strict: args.flag("strict"),
+        verify_crc: args.flag("strict"),
+        hash_sections,
+        ..VpkOptions::from_args(args)
+    };
+
+    if Path::new(out_path).exists()
+        && !utils::confirm(
+            &format!("{} already exists. Overwrite it?", out_path),
+            args.assume_yes(),
+        )
+    {
+        std::process::exit(1);
+    }
+
+    match vpk::copy_vpk(PathBuf::from(vpk_path), Path::new(out_path), &options) {
+        Ok((file_count, tree_matches)) => {
+            println!("Copied {} files to {}", file_count, out_path);
+            match tree_matches {
+                Some(true) => println!("The preserved tree hash matches the rewritten tree."),
+                Some(false) => println!(
+                    "The preserved tree hash does not match the rewritten tree. \
+                    Use --rehash to generate new hashes."
+                ),
+                None => {}
+            }
+        }
+        Err(e) => {
+            eprintln!("Dota-Terrain-Mod error: Internal error: {}", e);
+            std::process::exit(1);
+        }
+    }
+}
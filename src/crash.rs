@@ -0,0 +1,114 @@
+use std::{
+    backtrace::Backtrace,
+    cell::RefCell,
+    fmt::Write,
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+
+use crate::{config, history, notify};
+
+/// Number of lines of the log file included in a crash report
+const LOG_LINES: usize = 20;
+
+/// What the program was doing, included in crash reports
+static PHASE: Mutex<String> = Mutex::new(String::new());
+
+thread_local! {
+    /// The file this thread was reading and the offset within it, included in crash reports
+    static OFFSET: RefCell<Option<(PathBuf, u64)>> = const { RefCell::new(None) };
+}
+
+/// Record what the program is doing, e.g. `building the terrain`
+pub(crate) fn phase(phase: &str) {
+    let mut current = PHASE.lock().unwrap_or_else(|e| e.into_inner());
+    current.clear();
+    current.push_str(phase);
+}
+
+/// Record that the current thread is reading `path` at `offset`
+pub(crate) fn offset(path: &Path, offset: u64) {
+    OFFSET.with(|current| {
+        let mut current = current.borrow_mut();
+        match current.as_mut() {
+            Some((current_path, current_offset)) if current_path == path => {
+                *current_offset = offset
+            }
+            _ => *current = Some((path.to_path_buf(), offset)),
+        }
+    });
+}
+
+/// Install a panic hook writing a crash report with the version, the OS, the phase, the last
+/// offset read, the backtrace and the end of the log file. The location of the report is
+/// printed so it can be attached to an issue.
+pub(crate) fn install_hook() {
+    std::panic::set_hook(Box::new(|info| {
+        // Output piped into a program which exited early, e.g. `head`, is not a crash
+        if info.to_string().contains("failed printing to stdout") {
+            std::process::exit(1);
+        }
+        let report = report(&info.to_string());
+        let timestamp = history::now();
+        let dir = config::config_dir().unwrap_or_else(std::env::temp_dir);
+        let path = dir.join(format!("crash-{}.txt", timestamp));
+        let written = std::fs::create_dir_all(&dir).and_then(|_| std::fs::write(&path, &report));
+
+        eprintln!("Dota-Terrain-Mod error: The program crashed: {}", info);
+        match written {
+            Ok(()) => eprintln!(
+                "A crash report was saved to {}. Please attach it when reporting the issue at \
+                https://github.com/ObsoleteXero/Dota-Terrain-Mod/issues",
+                path.display()
+            ),
+            Err(e) => eprintln!("Could not save the crash report: {}\n{}", e, report),
+        }
+    }));
+}
+
+/// Contents of the crash report for a panic with the given message
+fn report(message: &str) -> String {
+    let mut report = String::new();
+    let _ = writeln!(report, "Dota-Terrain-Mod crash report");
+    let _ = writeln!(report, "Version: {}", env!("CARGO_PKG_VERSION"));
+    let _ = writeln!(
+        report,
+        "OS: {} {}",
+        std::env::consts::OS,
+        std::env::consts::ARCH
+    );
+    let _ = writeln!(
+        report,
+        "Time: {}",
+        history::format_timestamp(history::now())
+    );
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let _ = writeln!(report, "Arguments: {}", args.join(" "));
+    let phase = PHASE.lock().unwrap_or_else(|e| e.into_inner());
+    let _ = writeln!(
+        report,
+        "Phase: {}",
+        if phase.is_empty() { "starting" } else { &phase }
+    );
+    let _ = writeln!(
+        report,
+        "Thread: {}",
+        std::thread::current().name().unwrap_or("unnamed")
+    );
+    OFFSET.with(|offset| {
+        if let Some((path, offset)) = &*offset.borrow() {
+            let _ = writeln!(report, "Reading: {} at offset {}", path.display(), offset);
+        }
+    });
+    let _ = writeln!(report, "Panic: {}", message);
+    let _ = writeln!(report, "\nBacktrace:\n{}", Backtrace::force_capture());
+
+    let lines = notify::last_lines(LOG_LINES);
+    if !lines.is_empty() {
+        let _ = writeln!(report, "Last log lines:");
+        for line in lines {
+            let _ = writeln!(report, "{}", line);
+        }
+    }
+    report
+}
@@ -0,0 +1,331 @@
+use crate::apply::{self, ApplyOptions};
+use crate::cli::Args;
+use crate::config::Config;
+use crate::manifest::Manifest;
+use crate::utils::DotaInstall;
+
+/// Key combination used unless `--hotkey` is given
+const DEFAULT_HOTKEY: &str = "ctrl+alt+t";
+
+/// A global key combination: modifiers along with a letter or digit
+/// # Properties
+/// - `ctrl: bool`, `alt: bool`, `shift: bool`, `super_key: bool` = Modifiers which must be held
+/// - `key: char` = The key, an uppercase ASCII letter or a digit
+struct Hotkey {
+    ctrl: bool,
+    alt: bool,
+    shift: bool,
+    super_key: bool,
+    key: char,
+}
+
+impl Hotkey {
+    /// Parse a key combination such as `ctrl+alt+t`. At least one of ctrl, alt or super is
+    /// required, since the key is grabbed system-wide and would be taken from typing otherwise.
+    fn parse(text: &str) -> Option<Hotkey> {
+        let mut hotkey = Hotkey {
+            ctrl: false,
+            alt: false,
+            shift: false,
+            super_key: false,
+            key: ' ',
+        };
+        let mut parts: Vec<String> = text
+            .split('+')
+            .map(|part| part.trim().to_lowercase())
+            .collect();
+        let key = parts.pop()?;
+        for modifier in parts {
+            match modifier.as_str() {
+                "ctrl" | "control" => hotkey.ctrl = true,
+                "alt" => hotkey.alt = true,
+                "shift" => hotkey.shift = true,
+                "super" | "win" | "meta" => hotkey.super_key = true,
+                _ => return None,
+            }
+        }
+        if !(hotkey.ctrl || hotkey.alt || hotkey.super_key) {
+            return None;
+        }
+        let mut chars = key.chars();
+        match (chars.next(), chars.next()) {
+            (Some(key), None) if key.is_ascii_alphanumeric() => {
+                hotkey.key = key.to_ascii_uppercase();
+                Some(hotkey)
+            }
+            _ => None,
+        }
+    }
+}
+
+#[cfg(all(feature = "hotkey", target_os = "windows"))]
+mod platform {
+    use std::io;
+
+    use windows_sys::Win32::UI::Input::KeyboardAndMouse::{
+        RegisterHotKey, MOD_ALT, MOD_CONTROL, MOD_NOREPEAT, MOD_SHIFT, MOD_WIN,
+    };
+    use windows_sys::Win32::UI::WindowsAndMessaging::{
+        GetMessageW, PeekMessageW, MSG, PM_REMOVE, WM_HOTKEY,
+    };
+
+    use super::Hotkey;
+
+    /// Register `hotkey` for this thread and call `on_press` every time it is pressed. Holding
+    /// the keys does not repeat, and presses while `on_press` runs are dropped. Blocks until
+    /// the process is stopped.
+    pub(super) fn listen(hotkey: &Hotkey, mut on_press: impl FnMut()) -> io::Result<()> {
+        let mut modifiers = MOD_NOREPEAT;
+        for (held, modifier) in [
+            (hotkey.ctrl, MOD_CONTROL),
+            (hotkey.alt, MOD_ALT),
+            (hotkey.shift, MOD_SHIFT),
+            (hotkey.super_key, MOD_WIN),
+        ] {
+            if held {
+                modifiers |= modifier;
+            }
+        }
+        // Virtual key codes of letters and digits are their uppercase ASCII codes
+        // SAFETY: a null window binds the hotkey to the message queue of this thread
+        if unsafe { RegisterHotKey(std::ptr::null_mut(), 1, modifiers, hotkey.key as u32) } == 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        // SAFETY: `MSG` is plain data, for which all zeroes is a valid value
+        let mut message: MSG = unsafe { std::mem::zeroed() };
+        // SAFETY: `message` is valid for writes during the calls, and a null window reads
+        // the messages of this thread, where the hotkey was registered
+        while unsafe { GetMessageW(&mut message, std::ptr::null_mut(), 0, 0) } > 0 {
+            if message.message == WM_HOTKEY {
+                on_press();
+                // Presses while applying would queue up further switches
+                while unsafe {
+                    PeekMessageW(
+                        &mut message,
+                        std::ptr::null_mut(),
+                        WM_HOTKEY,
+                        WM_HOTKEY,
+                        PM_REMOVE,
+                    )
+                } != 0
+                {}
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(all(feature = "hotkey", target_os = "linux"))]
+mod platform {
+    use std::{
+        ffi::{c_int, c_uint, CString},
+        io,
+        sync::atomic::{AtomicBool, Ordering},
+    };
+
+    use x11_dl::xlib::{
+        self, ControlMask, Display, GrabModeAsync, LockMask, Mod1Mask, Mod2Mask, Mod4Mask,
+        ShiftMask, XErrorEvent, XEvent, Xlib,
+    };
+
+    use super::Hotkey;
+
+    /// Set when the X server rejects a grab, usually because another program owns the key
+    static GRAB_FAILED: AtomicBool = AtomicBool::new(false);
+
+    unsafe extern "C" fn on_error(_display: *mut Display, _event: *mut XErrorEvent) -> c_int {
+        GRAB_FAILED.store(true, Ordering::SeqCst);
+        0
+    }
+
+    /// Grab `hotkey` on the root window of the X display and call `on_press` every time it
+    /// is pressed. Holding the keys does not repeat, and presses while `on_press` runs are
+    /// dropped. Blocks until the process is stopped. Fails if the X11 library cannot be
+    /// loaded, e.g. on a Wayland-only system.
+    pub(super) fn listen(hotkey: &Hotkey, mut on_press: impl FnMut()) -> io::Result<()> {
+        let x11 = Xlib::open().map_err(|e| io::Error::other(e.to_string()))?;
+        // SAFETY: a null name opens the display named by $DISPLAY, the result is checked
+        let display = unsafe { (x11.XOpenDisplay)(std::ptr::null()) };
+        if display.is_null() {
+            return Err(io::Error::other("cannot open the X display"));
+        }
+        let name = CString::new(hotkey.key.to_ascii_lowercase().to_string()).unwrap();
+        // SAFETY: `display` is open and `name` is a NUL-terminated string outliving the call
+        let keycode =
+            unsafe { (x11.XKeysymToKeycode)(display, (x11.XStringToKeysym)(name.as_ptr())) };
+        if keycode == 0 {
+            return Err(io::Error::other("the key is not on this keyboard"));
+        }
+
+        let mut modifiers = 0;
+        for (held, mask) in [
+            (hotkey.ctrl, ControlMask),
+            (hotkey.alt, Mod1Mask),
+            (hotkey.shift, ShiftMask),
+            (hotkey.super_key, Mod4Mask),
+        ] {
+            if held {
+                modifiers |= mask;
+            }
+        }
+        // SAFETY: `display` is open for the rest of the process. The error handler only
+        // touches an atomic, as handlers must not call back into Xlib. The optional
+        // out-parameter of `XkbSetDetectableAutoRepeat` may be null.
+        unsafe {
+            // Auto-repeat sends a release before every repeated press unless detectable, then
+            // the repeats are presses without releases in between
+            (x11.XkbSetDetectableAutoRepeat)(display, 1, std::ptr::null_mut());
+            (x11.XSetErrorHandler)(Some(on_error));
+            let root = (x11.XDefaultRootWindow)(display);
+            // Caps Lock and Num Lock count as modifiers, grab every combination of them
+            for locks in [0, LockMask, Mod2Mask, LockMask | Mod2Mask] {
+                (x11.XGrabKey)(
+                    display,
+                    c_int::from(keycode),
+                    modifiers | locks,
+                    root,
+                    0,
+                    GrabModeAsync,
+                    GrabModeAsync,
+                );
+            }
+            // Errors of the grabs are reported to the handler by the time this returns
+            (x11.XSync)(display, 0);
+        }
+        if GRAB_FAILED.load(Ordering::SeqCst) {
+            return Err(io::Error::other(
+                "the key combination is already used by another program",
+            ));
+        }
+
+        let mut event = XEvent { pad: [0; 24] };
+        let mut held = false;
+        // SAFETY: `display` is open and `event` is a full-sized `XEvent` written by
+        // `XNextEvent`. Only key events are grabbed on the root window, so the `key` member
+        // is the one the server filled in, and `type` is common to every member.
+        let next_event = |event: &mut XEvent| unsafe {
+            (x11.XNextEvent)(display, event);
+            (event.get_type(), event.key.keycode)
+        };
+        loop {
+            let (kind, code) = next_event(&mut event);
+            if code != c_uint::from(keycode) {
+                continue;
+            }
+            match kind {
+                xlib::KeyPress if !held => {
+                    held = true;
+                    on_press();
+                    // Presses while applying would queue up further switches, only releases
+                    // are kept track of
+                    // SAFETY: `display` is open
+                    while unsafe { (x11.XPending)(display) } > 0 {
+                        let (kind, code) = next_event(&mut event);
+                        if kind == xlib::KeyRelease && code == c_uint::from(keycode) {
+                            held = false;
+                        }
+                    }
+                }
+                xlib::KeyRelease => held = false,
+                _ => {}
+            }
+        }
+    }
+}
+
+#[cfg(not(all(feature = "hotkey", any(target_os = "windows", target_os = "linux"))))]
+mod platform {
+    use super::Hotkey;
+
+    pub(super) fn listen(_hotkey: &Hotkey, _on_press: impl FnMut()) -> std::io::Result<()> {
+        Err(std::io::Error::new(
+            std::io::ErrorKind::Unsupported,
+            "global hotkeys are not supported by this build, rebuild with `--features hotkey`",
+        ))
+    }
+}
+
+/// Entry point for the `daemon [--hotkey <keys>]` command. Stays in the background and
+/// switches to the next favorite terrain every time the hotkey (`ctrl+alt+t` by default) is
+/// pressed, so terrains can be swapped between matches. Takes the same options as applying
+/// a terrain from the menu.
+pub(crate) fn run(args: &Args) {
+    if !args.positional.is_empty() {
+        eprintln!("Usage: dota-terrain-mod daemon [--hotkey <keys>]");
+        std::process::exit(1);
+    }
+    let keys = args.option("hotkey").unwrap_or(DEFAULT_HOTKEY);
+    let Some(hotkey) = Hotkey::parse(keys) else {
+        eprintln!(
+            "Dota-Terrain-Mod error: Invalid hotkey \"{}\", expected at least one of ctrl, alt \
+            or super and a letter or digit, such as ctrl+alt+t",
+            keys
+        );
+        std::process::exit(1);
+    };
+    let dota = match DotaInstall::new(args) {
+        Ok(dota) => dota,
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+    };
+    let mut config = match Config::load() {
+        Ok(config) => config,
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+    };
+    config.menu.discover(&dota);
+    let favorites = config.menu.favorites();
+    if favorites.is_empty() {
+        eprintln!(
+            "Dota-Terrain-Mod error: No favorite terrains, add some with `favorite add <terrain>`"
+        );
+        std::process::exit(1);
+    }
+    // Options are gathered again for every switch, with the settings of the next terrain, but
+    // mistakes on the command line are reported right away
+    let options = match ApplyOptions::from_args(args, &config, &favorites[0].file) {
+        Ok(options) => options,
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+    };
+
+    println!(
+        "Press {} to switch to the next favorite terrain. Press Ctrl+C to stop.",
+        keys
+    );
+    let out_path = dota.out_vpk(options.base());
+    let result = platform::listen(&hotkey, || {
+        let current = Manifest::read(&out_path).ok().and_then(|manifest| {
+            favorites
+                .iter()
+                .position(|terrain| terrain.file == manifest.terrain)
+        });
+        let next = &favorites[current.map_or(0, |i| (i + 1) % favorites.len())];
+        println!("Switching to {}...", next.name);
+        let options = match ApplyOptions::from_args(args, &config, &next.file) {
+            Ok(options) => options,
+            Err(e) => {
+                eprintln!("{}", e);
+                return;
+            }
+        };
+        match apply::apply(&dota, &next.file, &options) {
+            Ok(_) => println!("Switched to {}.", next.name),
+            Err(e) => eprintln!("{}", e),
+        }
+    });
+    if let Err(e) = result {
+        eprintln!(
+            "Dota-Terrain-Mod error: Could not register the hotkey: {}",
+            e
+        );
+        std::process::exit(1);
+    }
+}
@@ -0,0 +1,128 @@
+use std::path::Path;
+
+use crate::cli::Args;
+use crate::manifest::{self, Manifest, Source};
+
+/// Read the manifest at `path`, or the one belonging to the output VPK at `path`
+fn load(path: &str) -> Manifest {
+    let path = Path::new(path);
+    let manifest_path = if path.extension().is_some_and(|extension| extension == "vpk") {
+        manifest::manifest_path(path)
+    } else {
+        path.to_path_buf()
+    };
+    match Manifest::load(&manifest_path) {
+        Ok(manifest) => manifest,
+        Err(e) => {
+            eprintln!(
+                "Dota-Terrain-Mod error: Could not read {}: {}",
+                manifest_path.display(),
+                e
+            );
+            std::process::exit(1);
+        }
+    }
+}
+
+/// Print `lines` under `heading` with their number, unless there are none
+fn section(heading: &str, lines: &[String]) {
+    if lines.is_empty() {
+        return;
+    }
+    println!("\n{} ({}):", heading, lines.len());
+    for line in lines {
+        println!("  {}", line);
+    }
+}
+
+/// Entry point for the `diff-builds <old manifest> <new manifest>` command. Compares the
+/// manifests of two outputs, e.g. one kept from before a change, and shows what changed
+/// between the applies: the terrain, tool version and options, and which entries were added,
+/// removed or now come from another source. Entries whose base map version changed (after a
+/// game update) are listed if the terrain or the overrides provide them, and only counted if
+/// they are copied from the base map. The output VPK can be given instead of its manifest.
+/// Helps to find which change broke a setup.
+pub(crate) fn run(args: &Args) {
+    let [old_path, new_path] = args.positional.as_slice() else {
+        eprintln!("Usage: dota-terrain-mod diff-builds <old manifest> <new manifest>");
+        std::process::exit(1);
+    };
+    let old = load(old_path);
+    let new = load(new_path);
+
+    let mut differs = false;
+    for (what, old_value, new_value) in [
+        ("Terrain", old.terrain.clone(), new.terrain.clone()),
+        ("Built by", old.tool.clone(), new.tool.clone()),
+        ("Options", old.options.join(" "), new.options.join(" ")),
+    ] {
+        if old_value != new_value {
+            differs = true;
+            let or_none = |value: String| {
+                if value.is_empty() {
+                    String::from("(none)")
+                } else {
+                    value
+                }
+            };
+            println!("{}: {} -> {}", what, or_none(old_value), or_none(new_value));
+        }
+    }
+
+    let mut added = Vec::new();
+    let mut removed = Vec::new();
+    let mut resourced = Vec::new();
+    let mut base_changed = Vec::new();
+    let mut base_updated = 0;
+    for (path, entry) in &new.entries {
+        let Some(old_entry) = old.entries.get(path) else {
+            added.push(format!("{}  {}", entry.source.name(), path));
+            continue;
+        };
+        if old_entry.source != entry.source {
+            resourced.push(format!(
+                "{}  {} -> {}",
+                path,
+                old_entry.source.name(),
+                entry.source.name()
+            ));
+        }
+        if old_entry.base_crc32.is_some()
+            && entry.base_crc32.is_some()
+            && old_entry.base_crc32 != entry.base_crc32
+        {
+            if entry.source == Source::Base {
+                base_updated += 1;
+            } else {
+                base_changed.push(format!("{}  {}", entry.source.name(), path));
+            }
+        }
+    }
+    for (path, entry) in &old.entries {
+        if !new.entries.contains_key(path) {
+            removed.push(format!("{}  {}", entry.source.name(), path));
+        }
+    }
+
+    section("Added", &added);
+    section("Removed", &removed);
+    section("Source changed", &resourced);
+    section(
+        "Replaced files whose base map version changed",
+        &base_changed,
+    );
+    if base_updated > 0 {
+        println!(
+            "\n{} file(s) copied from the base map changed with it.",
+            base_updated
+        );
+    }
+    differs |= !added.is_empty()
+        || !removed.is_empty()
+        || !resourced.is_empty()
+        || !base_changed.is_empty()
+        || base_updated > 0;
+    if !differs {
+        println!("The builds do not differ.");
+    }
+}
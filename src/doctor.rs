@@ -0,0 +1,136 @@
+use crate::cli::Args;
+use crate::manifest::Manifest;
+use crate::utils::DotaInstall;
+use crate::vpk::VPK;
+use crate::{accounts, launch, manifest, menu, steam_state, sync};
+
+/// Entry point for the `doctor` command. Checks the setup the terrain depends on: the Steam
+/// and DotaInstall installation, whether Steam is updating the game, whether the game folder is
+/// synced, the base map, the output and the launch option, and explains which shipped
+/// terrains were renamed by a game update. Exits with an error if a problem was found.
+pub(crate) fn run(args: &Args) {
+    if !args.positional.is_empty() {
+        eprintln!("Usage: dota-terrain-mod doctor");
+        std::process::exit(1);
+    }
+    let dota = match DotaInstall::new(args) {
+        Ok(dota) => dota,
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+    };
+    let mut problems = 0;
+    println!("Steam: {}", dota.steam_path.display());
+    println!("{}: {}", dota.game.title, dota.dota_path.display());
+    match accounts::current(&dota.steam_path, true) {
+        Some(user) => println!("Steam user: {} ({})", user.account_name, user.persona_name),
+        None => println!("Steam user: Unknown, checking every account"),
+    }
+    match steam_state::defer_reason(&dota) {
+        Some(reason) => {
+            println!("Game state: UPDATING");
+            eprintln!(
+                "{}, wait for it to finish before applying a terrain.",
+                reason
+            );
+            problems += 1;
+        }
+        None if !dota.steam_running() => println!("Game state: Unknown, Steam is not running"),
+        None => println!("Game state: OK"),
+    }
+    match dota.app_manifest() {
+        Some(app) => match (app.build_id.as_deref(), app.pending_build()) {
+            (Some(build), Some(pending)) => {
+                println!("Game build: {}, update to {} pending", build, pending)
+            }
+            (Some(build), None) => println!("Game build: {}", build),
+            (None, _) => println!("Game build: Unknown"),
+        },
+        None => println!(
+            "Game build: Unknown, appmanifest_{}.acf is missing",
+            dota.game.appid
+        ),
+    }
+
+    match sync::synced_location(&dota.dota_path) {
+        Some(location) => {
+            println!("Game folder: SYNCED ({})", location);
+            eprintln!(
+                "{}",
+                sync::synced_warning(&location, &dota.tempcontent_dir())
+            );
+            problems += 1;
+        }
+        None => println!("Game folder: Local"),
+    }
+
+    let base_path = dota.base_vpk(dota.game.default_base);
+    if base_path.exists() {
+        match VPK::open(base_path.clone()) {
+            Ok(_) => println!("Base map: OK"),
+            Err(e) => {
+                println!("Base map: UNREADABLE");
+                eprintln!("{}", e);
+                problems += 1;
+            }
+        }
+    } else {
+        println!("Base map: MISSING ({})", base_path.display());
+        problems += 1;
+    }
+    let bases = dota.available_bases();
+    if bases.len() > 1 {
+        println!("Base maps available with --base: {}", bases.join(", "));
+    }
+
+    for (file, renamed) in menu::renamed_terrains(&dota) {
+        println!(
+            "Terrain file: {} was renamed to {}, likely by a game update, it is used instead",
+            file, renamed
+        );
+    }
+
+    let out_path = dota.out_vpk(dota.game.default_base);
+    if manifest::is_applied(&out_path) {
+        match Manifest::read(&out_path) {
+            Ok(installed) => println!(
+                "Terrain: {}",
+                installed
+                    .method
+                    .installer()
+                    .status(&dota, dota.game.default_base, &installed)
+            ),
+            Err(_) => println!("Terrain: Applied ({})", out_path.display()),
+        }
+        if let Some(warning) = manifest::other_user_warning(&out_path) {
+            println!("{}", warning);
+        }
+        if let Some(warning) = Manifest::read(&out_path)
+            .ok()
+            .and_then(|manifest| manifest::stale_warning(&manifest, dota.build_id().as_deref()))
+        {
+            println!("Terrain build: OUTDATED");
+            eprintln!("{}", warning);
+            problems += 1;
+        }
+    } else {
+        println!("Terrain: Not applied");
+    }
+
+    match launch::launch_option_set(&dota.steam_path, true) {
+        Some(true) => println!("Launch option: OK"),
+        Some(false) => {
+            println!("Launch option: MISSING");
+            eprintln!("{}", launch::missing_warning());
+            problems += 1;
+        }
+        None => println!("Launch option: Unknown, no Steam account configuration found"),
+    }
+
+    if problems > 0 {
+        eprintln!("{} problem(s) found.", problems);
+        std::process::exit(1);
+    }
+    println!("No problems found.");
+}
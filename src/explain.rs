@@ -0,0 +1,194 @@
+use crate::cli::Args;
+
+/// An entry of the error catalog
+/// # Properties
+/// - `code: &str` = Stable code printed with the error, see `TMError::code()`
+/// - `name: &str` = Short name of the error
+/// - `explanation: &str` = What causes the error and how to fix it
+struct ErrorInfo {
+    code: &'static str,
+    name: &'static str,
+    explanation: &'static str,
+}
+
+/// Every error with a code, in the order of their codes. The codes stay the same across
+/// versions, so an error can be looked up from a screenshot of any version.
+const CATALOG: [ErrorInfo; 8] = [
+    ErrorInfo {
+        code: "TM-001",
+        name: "Steam not found",
+        explanation: "The Steam installation could not be found. On Windows, its path is read \
+            from the registry, which the Steam installer sets; reinstalling Steam restores it. \
+            On Linux, Steam is expected in ~/.local/share/Steam with its library list in \
+            config/libraryfolders.vdf. Flatpak and Snap installs keep Steam elsewhere, link \
+            ~/.local/share/Steam to it.",
+    },
+    ErrorInfo {
+        code: "TM-002",
+        name: "Game not found",
+        explanation: "None of the Steam libraries listed in libraryfolders.vdf holds the game, \
+            or its folder has no maps. Check that the game is installed and starts, and that \
+            --game selects the right game. If the game was moved to another library, start \
+            Steam once so it updates its library list.",
+    },
+    ErrorInfo {
+        code: "TM-003",
+        name: "Internal error",
+        explanation: "Reading or writing a file failed, the message tells which. Common causes \
+            are a full disk, missing permissions for the game folder, an antivirus locking the \
+            output, or the game holding the output open. Close the game and try again; \
+            `doctor` checks the installation.",
+    },
+    ErrorInfo {
+        code: "TM-004",
+        name: "Invalid configuration",
+        explanation: "config.toml, a rules file or the options given are invalid, the message \
+            tells which setting is wrong. Fix or remove the setting, the defaults are used for \
+            anything not set.",
+    },
+    ErrorInfo {
+        code: "TM-005",
+        name: "Base map not found",
+        explanation: "The map given with --base is not in game/dota/maps. The message lists the \
+            maps available, event maps come and go with game updates.",
+    },
+    ErrorInfo {
+        code: "TM-006",
+        name: "Terrain changed",
+        explanation: "The terrain file differs from the one pinned when it was first applied, \
+            e.g. it was replaced by a download or a game update. Pass --yes to accept the new \
+            file if it is expected.",
+    },
+    ErrorInfo {
+        code: "TM-007",
+        name: "Steam user not found",
+        explanation: "The account given with --steam-user has never logged in to Steam on this \
+            computer. The message lists the known accounts; give the account name or the \
+            Steam ID of one of them.",
+    },
+    ErrorInfo {
+        code: "TM-008",
+        name: "Game updating",
+        explanation: "Steam is downloading, installing or verifying the game, so the base map \
+            may be replaced while the terrain is built. Wait until Steam is done and try again, \
+            or pass --ignore-steam-state to build anyway.",
+    },
+];
+
+/// A concept explained by `explain <name>`
+/// # Properties
+/// - `name: &str` = Name to look the concept up by, e.g. `tempcontent`
+/// - `explanation: &str` = How it works
+struct Concept {
+    name: &'static str,
+    explanation: &'static str,
+}
+
+/// The concepts behind the questions asked most often, in alphabetical order
+const CONCEPTS: [Concept; 7] = [
+    Concept {
+        name: "base",
+        explanation: "The base map is the map of the game the terrain is merged with, \
+            game/dota/maps/dota.vpk unless --base names another one, such as an event map. The \
+            output holds the base map with the terrain's files on top, renamed so the game \
+            loads the terrain in place of the base map. `doctor` lists the maps available as a \
+            base.",
+    },
+    Concept {
+        name: "manifest",
+        explanation: "Every output gets a manifest, dota-terrain-mod.json next to it (or in the \
+            configuration directory with --per-user). It records the terrain, the options, the \
+            game build, who applied it and where every file of the output came from. `status`, \
+            `reapply`, `compare-install`, `diff-builds` and `panic-button` read it, and outputs \
+            without one are treated as not created by this tool.",
+    },
+    Concept {
+        name: "method",
+        explanation: "--method chooses how `apply` installs a terrain. `vpk`, the default, \
+            builds a single VPK in game/dota_tempcontent/maps. `loose` writes the merged files \
+            as loose files below game/dota_tempcontent instead, which is faster to update while \
+            tweaking, but applies to every map. `hybrid` packs the base map and the terrain into \
+            the VPK and installs only the overrides as loose files, so changing an override \
+            does not rebuild the terrain. `gameinfo` writes the VPK to a folder of its own and \
+            adds it to the search paths in gameinfo.gi, so no launch option is needed, until a \
+            game update restores gameinfo.gi.",
+    },
+    Concept {
+        name: "overrides",
+        explanation: "--overrides adds the files of a directory or VPK on top of the terrain, by \
+            their path inside it, e.g. <dir>/materials/... Given more than once, the layers \
+            stack and later ones win. Conflicts between the sources are listed by `status` and \
+            can be settled with a conflicts policy.",
+    },
+    Concept {
+        name: "reapply",
+        explanation: "Game updates replace the base map, and the applied terrain keeps the files \
+            of the old one until it is built again. `reapply` builds the applied terrain again \
+            with the options it was applied with; `status` and `doctor` warn when the terrain \
+            was built for an older game build.",
+    },
+    Concept {
+        name: "rules",
+        explanation: "Rename rules decide where the terrain's entries end up in the output, e.g. \
+            that its map replaces dota.vmap_c. The built-in rules fit the terrains shipped with \
+            the game; declare [[rules]] in config.toml or pass --rules <file> to replace them for \
+            terrains laid out differently.",
+    },
+    Concept {
+        name: "tempcontent",
+        explanation: "The game never has its own files changed. The output is written to \
+            game/dota_tempcontent, which the game only searches, ahead of its own files, when \
+            launched with -language tempcontent. Without the launch option the game looks \
+            unmodified; `doctor` checks that it is set, `shortcut` creates a shortcut with it, \
+            and `panic-button` removes everything written there.",
+    },
+];
+
+/// Entry point for the `explain [<code> | <concept>]` command. Explains the error with the
+/// code printed along with it, e.g. `TM-001`, or a concept such as `tempcontent`. Without
+/// either, every code and concept is listed.
+pub(crate) fn run(args: &Args) {
+    match args.positional.as_slice() {
+        [] => {
+            println!("Error codes:");
+            for info in &CATALOG {
+                println!("  {}  {}", info.code, info.name);
+            }
+            println!("Concepts:");
+            for concept in &CONCEPTS {
+                println!("  {}", concept.name);
+            }
+        }
+        [topic] => {
+            if let Some(info) = find(topic) {
+                println!("{}: {}\n\n{}", info.code, info.name, info.explanation);
+            } else if let Some(concept) = CONCEPTS
+                .iter()
+                .find(|concept| concept.name.eq_ignore_ascii_case(topic.trim()))
+            {
+                println!("{}\n\n{}", concept.name, concept.explanation);
+            } else {
+                eprintln!(
+                    "Dota-Terrain-Mod error: Nothing to explain for \"{}\", run `explain` to \
+                    list every error code and concept",
+                    topic
+                );
+                std::process::exit(1);
+            }
+        }
+        _ => {
+            eprintln!("Usage: dota-terrain-mod explain [<code> | <concept>]");
+            std::process::exit(1);
+        }
+    }
+}
+
+/// The catalog entry of `code`, given as `TM-001`, `tm-001` or `001`
+fn find(code: &str) -> Option<&'static ErrorInfo> {
+    let code = code.trim();
+    let number = code
+        .get(..3)
+        .filter(|prefix| prefix.eq_ignore_ascii_case("TM-"))
+        .map_or(code, |_| &code[3..]);
+    CATALOG.iter().find(|info| info.code[3..] == *number)
+}
@@ -0,0 +1,357 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    io,
+    path::{Path, PathBuf},
+};
+
+use ratatui::{
+    crossterm::event::{self, Event, KeyCode, KeyEventKind},
+    layout::{Constraint, Layout},
+    style::{Modifier, Style},
+    text::Line,
+    widgets::{Block, List, ListState, Paragraph},
+    DefaultTerminal, Frame,
+};
+
+use crate::cli::Args;
+use crate::utils::hexdump;
+use crate::vpk::{VpkOptions, VPK};
+
+/// Entries up to this size get a hex preview in the details pane
+const PREVIEW_LIMIT: u64 = 4096;
+
+/// Contents of a single directory in the VPK
+#[derive(Default)]
+struct Listing {
+    dirs: BTreeSet<String>,
+    files: BTreeSet<String>,
+}
+
+/// A row in the directory pane. Paths are full paths within the VPK.
+enum Item {
+    Parent,
+    Dir(String),
+    File(String),
+}
+
+/// State of the archive explorer
+/// # Properties
+/// - `vpk: VPK` = The lazily opened VPK being explored
+/// - `listings: BTreeMap<String, Listing>` = Contents of every directory, keyed by path. The root is `""`
+/// - `cwd: String` = The directory currently shown in the directory pane
+/// - `items: Vec<Item>` = The rows of the directory pane for `cwd`
+/// - `state: ListState` = Selection state of the directory pane
+/// - `preview: Option<(String, Vec<String>)>` = Cached hexdump of the last previewed entry
+/// - `status: String` = Message shown in the status line
+struct Explorer {
+    vpk: VPK,
+    listings: BTreeMap<String, Listing>,
+    cwd: String,
+    items: Vec<Item>,
+    state: ListState,
+    preview: Option<(String, Vec<String>)>,
+    status: String,
+}
+
+impl Explorer {
+    fn new(vpk: VPK) -> Explorer {
+        let mut listings: BTreeMap<String, Listing> = BTreeMap::new();
+        for path in vpk.entries().map(|(path, _)| path) {
+            let (dir, _) = path.rsplit_once('/').unwrap_or(("", path));
+            listings
+                .entry(dir.to_owned())
+                .or_default()
+                .files
+                .insert(path.clone());
+
+            // Register every ancestor directory with its parent
+            let mut dir = dir;
+            while !dir.is_empty() {
+                let parent = dir.rsplit_once('/').map_or("", |(parent, _)| parent);
+                listings
+                    .entry(parent.to_owned())
+                    .or_default()
+                    .dirs
+                    .insert(dir.to_owned());
+                dir = parent;
+            }
+        }
+        listings.entry(String::new()).or_default();
+
+        let mut explorer = Explorer {
+            vpk,
+            listings,
+            cwd: String::new(),
+            items: Vec::new(),
+            state: ListState::default(),
+            preview: None,
+            status: String::from("↑/↓ move  ⏎/→ open  ←/⌫ up  x extract  q quit"),
+        };
+        explorer.change_dir(String::new());
+        explorer
+    }
+
+    /// Show the contents of `dir` in the directory pane
+    fn change_dir(&mut self, dir: String) {
+        let listing = &self.listings[&dir];
+        self.items = Vec::new();
+        if !dir.is_empty() {
+            self.items.push(Item::Parent);
+        }
+        self.items
+            .extend(listing.dirs.iter().map(|dir| Item::Dir(dir.clone())));
+        self.items
+            .extend(listing.files.iter().map(|file| Item::File(file.clone())));
+        self.cwd = dir;
+        self.state.select(Some(0));
+    }
+
+    fn selected(&self) -> Option<&Item> {
+        self.items.get(self.state.selected()?)
+    }
+
+    fn go_up(&mut self) {
+        if !self.cwd.is_empty() {
+            let parent = self.cwd.rsplit_once('/').map_or("", |(parent, _)| parent);
+            self.change_dir(parent.to_owned());
+        }
+    }
+
+    fn open_selected(&mut self) {
+        match self.selected() {
+            Some(Item::Parent) => self.go_up(),
+            Some(Item::Dir(dir)) => self.change_dir(dir.clone()),
+            _ => {}
+        }
+    }
+
+    /// Extract the selected file, or every file below the selected directory, into the
+    /// working directory
+    fn extract_selected(&mut self) {
+        let paths: Vec<String> = match self.selected() {
+            Some(Item::File(path)) => vec![path.clone()],
+            Some(Item::Dir(dir)) => {
+                let prefix = format!("{}/", dir);
+                self.vpk
+                    .entries()
+                    .map(|(path, _)| path)
+                    .filter(|path| path.starts_with(&prefix))
+                    .cloned()
+                    .collect()
+            }
+            _ => return,
+        };
+
+        let save_dir = PathBuf::from(".");
+        let mut extracted = 0;
+        let mut mismatched = 0;
+        for path in &paths {
+            match self.vpk.extract_entry(path, &save_dir) {
+                Ok((_, intact)) => mismatched += usize::from(!intact),
+                Err(e) => {
+                    self.status = format!("Failed to extract {}: {}", path, e);
+                    return;
+                }
+            }
+            extracted += 1;
+        }
+        self.status = format!(
+            "Extracted {} file(s) to {}",
+            extracted,
+            display_dir(&save_dir)
+        );
+        if mismatched > 0 {
+            self.status += &format!(", {} failed their CRC check", mismatched);
+        }
+    }
+
+    /// Lines describing the selected entry, including a hexdump for small files
+    fn details(&mut self) -> Vec<String> {
+        let path = match self.selected() {
+            Some(Item::File(path)) => path.clone(),
+            Some(Item::Dir(dir)) => {
+                let listing = &self.listings[dir];
+                return vec![
+                    format!("Directory:   {}", dir),
+                    format!("Directories: {}", listing.dirs.len()),
+                    format!("Files:       {}", listing.files.len()),
+                ];
+            }
+            _ => return Vec::new(),
+        };
+
+        let (length, mut lines) = {
+            let metadata = self.vpk.entry(&path).unwrap();
+            (
+                metadata.entry_length(),
+                vec![
+                    format!("Path:           {}", path),
+                    format!("Size:           {} bytes", metadata.entry_length()),
+                    format!("CRC32:          {:08x}", metadata.crc32()),
+                    format!("Preload length: {}", metadata.preload_length()),
+                    format!("Archive index:  {}", metadata.archive_index()),
+                    format!("Archive offset: {}", metadata.archive_offset()),
+                    format!("Archive length: {}", metadata.file_length()),
+                    String::new(),
+                ],
+            )
+        };
+
+        if length > PREVIEW_LIMIT {
+            lines.push(String::from("Too large to preview."));
+            return lines;
+        }
+        if self.preview.as_ref().map(|(p, _)| p) != Some(&path) {
+            let dump = match self.vpk.read_entry(&path) {
+                Ok(data) => hexdump(&data),
+                Err(e) => vec![format!("Failed to read entry: {}", e)],
+            };
+            self.preview = Some((path, dump));
+        }
+        let (_, dump) = self.preview.as_ref().unwrap();
+        lines.extend(dump.iter().cloned());
+        lines
+    }
+
+    /// Names of the rows of the directory pane
+    fn rows(&self) -> Vec<String> {
+        self.items
+            .iter()
+            .map(|item| match item {
+                Item::Parent => String::from("../"),
+                Item::Dir(dir) => format!("{}/", file_name(dir)),
+                Item::File(file) => file_name(file).to_owned(),
+            })
+            .collect()
+    }
+
+    fn draw(&mut self, frame: &mut Frame) {
+        let [main, status] =
+            Layout::vertical([Constraint::Min(0), Constraint::Length(1)]).areas(frame.area());
+        let [tree, details] =
+            Layout::horizontal([Constraint::Percentage(40), Constraint::Percentage(60)])
+                .areas(main);
+
+        let rows = self.rows();
+        let title = format!("/{}", self.cwd);
+        let list = List::new(rows)
+            .block(Block::bordered().title(title))
+            .highlight_style(Style::new().add_modifier(Modifier::REVERSED));
+        frame.render_stateful_widget(list, tree, &mut self.state);
+
+        let details_text: Vec<Line> = self.details().into_iter().map(Line::from).collect();
+        frame.render_widget(
+            Paragraph::new(details_text).block(Block::bordered().title("Details")),
+            details,
+        );
+        frame.render_widget(Paragraph::new(self.status.as_str()), status);
+    }
+
+    fn run(&mut self, terminal: &mut DefaultTerminal) -> io::Result<()> {
+        loop {
+            terminal.draw(|frame| self.draw(frame))?;
+            let Event::Key(key) = event::read()? else {
+                continue;
+            };
+            if key.kind != KeyEventKind::Press {
+                continue;
+            }
+            match key.code {
+                KeyCode::Char('q') | KeyCode::Esc => return Ok(()),
+                KeyCode::Up => self.state.select_previous(),
+                KeyCode::Down => self.state.select_next(),
+                KeyCode::Enter | KeyCode::Right => self.open_selected(),
+                KeyCode::Left | KeyCode::Backspace => self.go_up(),
+                KeyCode::Char('x') => self.extract_selected(),
+                _ => {}
+            }
+        }
+    }
+
+    /// Line-by-line variant of `run()` for screen readers and logs. The current directory is
+    /// listed with numbered rows and commands are read from standard input.
+    fn run_plain(&mut self) -> io::Result<()> {
+        loop {
+            println!("Directory: /{}", self.cwd);
+            for (i, row) in self.rows().iter().enumerate() {
+                println!("{}: {}", i + 1, row);
+            }
+            println!(
+                "Enter a number to open it, x and a number to extract it, u to go up or q to quit:"
+            );
+
+            let mut input = String::new();
+            if io::stdin().read_line(&mut input)? == 0 {
+                return Ok(());
+            }
+            let input = input.trim();
+            let (extract, number) = match input.strip_prefix('x') {
+                Some(number) => (true, number.trim()),
+                None => (false, input),
+            };
+            match number {
+                "q" => return Ok(()),
+                "u" => self.go_up(),
+                number => match number.parse::<usize>() {
+                    Ok(row) if (1..=self.items.len()).contains(&row) => {
+                        self.state.select(Some(row - 1));
+                        if extract {
+                            self.extract_selected();
+                            println!("{}", self.status);
+                        } else if let Some(Item::File(_)) = self.selected() {
+                            for line in self.details() {
+                                println!("{}", line);
+                            }
+                        } else {
+                            self.open_selected();
+                        }
+                    }
+                    _ => println!("Invalid input."),
+                },
+            }
+            println!();
+        }
+    }
+}
+
+fn file_name(path: &str) -> &str {
+    path.rsplit_once('/').map_or(path, |(_, name)| name)
+}
+
+fn display_dir(dir: &Path) -> String {
+    dunce::canonicalize(dir)
+        .unwrap_or_else(|_| dir.to_path_buf())
+        .display()
+        .to_string()
+}
+
+/// Entry point for the `explore <vpk> [--plain]` command. Opens an interactive two-pane
+/// browser of the contents of a VPK, or with `--plain` a line-by-line browser.
+pub(crate) fn run(args: &Args) {
+    let [vpk_path] = args.positional.as_slice() else {
+        eprintln!("Usage: dota-terrain-mod explore <vpk> [--plain]");
+        std::process::exit(1);
+    };
+    let vpk = match VPK::open_with(PathBuf::from(vpk_path), VpkOptions::from_args(args)) {
+        Ok(vpk) => vpk,
+        Err(e) => {
+            eprintln!("Dota-Terrain-Mod error: Invalid VPK: {}", e);
+            std::process::exit(1);
+        }
+    };
+    let mut explorer = Explorer::new(vpk);
+
+    let result = if args.flag("plain") {
+        explorer.run_plain()
+    } else {
+        let mut terminal = ratatui::init();
+        let result = explorer.run(&mut terminal);
+        ratatui::restore();
+        result
+    };
+
+    if let Err(e) = result {
+        eprintln!("Dota-Terrain-Mod error: Internal error: {}", e);
+        std::process::exit(1);
+    }
+}
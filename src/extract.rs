@@ -0,0 +1,192 @@
+use std::{
+    collections::BTreeMap,
+    io,
+    path::{Path, PathBuf},
+    thread,
+};
+
+use crate::attributes::{self, FileAttributes};
+use crate::cli::Args;
+use crate::vpk::{VpkOptions, VPK};
+
+/// Number of files listed by name in each part of the report
+const MAX_LISTED: usize = 20;
+
+/// Whether the entry at `path` lies below one of `prefixes`, ignoring case. Every entry
+/// matches if there are no prefixes.
+fn matches_prefix(path: &str, prefixes: &[String]) -> bool {
+    if prefixes.is_empty() {
+        return true;
+    }
+    let path = path.to_lowercase();
+    prefixes.iter().any(|prefix| {
+        let prefix = prefix.trim_end_matches('/').to_lowercase();
+        path == prefix || path.starts_with(&format!("{}/", prefix))
+    })
+}
+
+/// Outcome of an extraction
+/// # Properties
+/// - `mismatched: Vec<String>` = Files which were written, but do not match their stored CRC
+/// - `failed: Vec<(String, io::Error)>` = Files which could not be extracted, with the reason
+#[derive(Default)]
+struct Report {
+    mismatched: Vec<String>,
+    failed: Vec<(String, io::Error)>,
+}
+
+impl Report {
+    fn append(&mut self, mut other: Report) {
+        self.mismatched.append(&mut other.mismatched);
+        self.failed.append(&mut other.failed);
+    }
+}
+
+/// Extract `paths` from the VPK at `vpk_path` to `out_dir`, split across one thread per
+/// available core. Each thread opens the VPK on its own, so files are read and written in
+/// parallel. Every written file is checked against its stored CRC, and given the attributes
+/// recorded for it in `file_attributes`, if any. The VPK is opened with `options`.
+fn extract_parallel(
+    vpk_path: &Path,
+    options: VpkOptions,
+    paths: &[String],
+    out_dir: &Path,
+    file_attributes: &BTreeMap<String, FileAttributes>,
+) -> io::Result<Report> {
+    let threads = thread::available_parallelism().map_or(1, |n| n.get());
+    let chunk_size = paths.len().div_ceil(threads).max(1);
+    thread::scope(|scope| {
+        let workers: Vec<_> = paths
+            .chunks(chunk_size)
+            .map(|chunk| {
+                scope.spawn(move || -> io::Result<Report> {
+                    let mut vpk = VPK::open_with(vpk_path.to_path_buf(), options)?;
+                    let mut report = Report::default();
+                    for path in chunk {
+                        let extracted =
+                            vpk.extract_entry(path, out_dir)
+                                .and_then(|(fpath, intact)| {
+                                    if let Some(attributes) = file_attributes.get(path) {
+                                        attributes.apply(&fpath)?;
+                                    }
+                                    Ok(intact)
+                                });
+                        match extracted {
+                            Ok(true) => {}
+                            Ok(false) => report.mismatched.push(path.clone()),
+                            Err(e) => report.failed.push((path.clone(), e)),
+                        }
+                    }
+                    Ok(report)
+                })
+            })
+            .collect();
+        let mut report = Report::default();
+        for worker in workers {
+            report.append(worker.join().unwrap()?);
+        }
+        report.mismatched.sort();
+        report.failed.sort_by(|a, b| a.0.cmp(&b.0));
+        Ok(report)
+    })
+}
+
+/// Entry point for the `extract <vpk> <out dir> [--prefix <dir>]` command. Writes the files of
+/// a VPK to `out dir`, keeping their paths within the VPK. With `--prefix` (repeatable), only
+/// the files below the given directories are extracted, e.g. `--prefix materials/terrain/` for
+/// just the ground textures, without reading the rest of the archive. Files are extracted in
+/// parallel and checked against their stored CRC. If the VPK was packed with
+/// `pack --keep-times`, the recorded modification times and read-only flags are restored. Files failing the check are kept, but
+/// listed in a summary at the end, so it is clear which files recovered from a damaged
+/// archive can be trusted. Exits with an error if any file failed the check or could not be
+/// extracted.
+pub(crate) fn run(args: &Args) {
+    let [vpk_path, out_dir] = args.positional.as_slice() else {
+        eprintln!("Usage: dota-terrain-mod extract <vpk> <out dir> [--prefix <dir>]");
+        std::process::exit(1);
+    };
+    let vpk_path = PathBuf::from(vpk_path);
+    let options = VpkOptions::from_args(args);
+    let vpk = match VPK::open_with(vpk_path.clone(), options) {
+        Ok(vpk) => vpk,
+        Err(e) => {
+            eprintln!("Dota-Terrain-Mod error: Invalid VPK: {}", e);
+            std::process::exit(1);
+        }
+    };
+    let prefixes = args.options("prefix");
+    let mut paths: Vec<String> = vpk
+        .entries()
+        .map(|(path, _)| path)
+        .filter(|path| matches_prefix(path, prefixes))
+        .cloned()
+        .collect();
+    paths.sort();
+    if paths.is_empty() {
+        println!("No files match, nothing extracted.");
+        return;
+    }
+
+    let file_attributes = match attributes::read(&vpk_path) {
+        Ok(file_attributes) => file_attributes.unwrap_or_default(),
+        Err(e) => {
+            eprintln!(
+                "Warning: The attribute record of {} could not be read, modification times are \
+                not restored: {}",
+                vpk_path.display(),
+                e
+            );
+            BTreeMap::new()
+        }
+    };
+    let report = match extract_parallel(
+        &vpk_path,
+        options,
+        &paths,
+        Path::new(out_dir),
+        &file_attributes,
+    ) {
+        Ok(report) => report,
+        Err(e) => {
+            eprintln!("Dota-Terrain-Mod error: Internal error: {}", e);
+            std::process::exit(1);
+        }
+    };
+    println!(
+        "Extracted {} of {} file(s) to {}",
+        paths.len() - report.failed.len(),
+        paths.len(),
+        out_dir
+    );
+    if report.mismatched.is_empty() && report.failed.is_empty() {
+        println!("Every file matches its CRC.");
+        return;
+    }
+
+    if !report.mismatched.is_empty() {
+        println!(
+            "\n{} file(s) do not match their CRC and may be damaged, they were written anyway:",
+            report.mismatched.len()
+        );
+        for path in report.mismatched.iter().take(MAX_LISTED) {
+            println!("  {}", path);
+        }
+        if report.mismatched.len() > MAX_LISTED {
+            println!("  ... and {} more", report.mismatched.len() - MAX_LISTED);
+        }
+    }
+    if !report.failed.is_empty() {
+        println!("\n{} file(s) could not be extracted:", report.failed.len());
+        for (path, e) in report.failed.iter().take(MAX_LISTED) {
+            println!("  {}: {}", path, e);
+        }
+        if report.failed.len() > MAX_LISTED {
+            println!("  ... and {} more", report.failed.len() - MAX_LISTED);
+        }
+    }
+    println!(
+        "\n{} file(s) match their CRC and can be trusted.",
+        paths.len() - report.mismatched.len() - report.failed.len()
+    );
+    std::process::exit(1);
+}
@@ -0,0 +1,61 @@
+use crate::cli::Args;
+use crate::config::{self, Config};
+use crate::utils::DotaInstall;
+
+/// Add a terrain to, or remove it from, the `favorites` or `hidden` list of the menu
+/// configuration, as given by `command <add|remove> <terrain>`
+fn edit(args: &Args, command: &str, key: &str) {
+    let [action, query] = args.positional.as_slice() else {
+        eprintln!("Usage: dota-terrain-mod {} <add|remove> <terrain>", command);
+        std::process::exit(1);
+    };
+    let add = match action.as_str() {
+        "add" => true,
+        "remove" => false,
+        _ => {
+            eprintln!("Usage: dota-terrain-mod {} <add|remove> <terrain>", command);
+            std::process::exit(1);
+        }
+    };
+    let mut config = match Config::load() {
+        Ok(config) => config,
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+    };
+    // Discovered terrains can only be found while the game is installed
+    if let Ok(dota) = DotaInstall::new(args) {
+        config.menu.discover(&dota);
+    }
+    let terrain = match config.menu.resolve(query) {
+        Ok(terrain) => terrain,
+        Err(e) => {
+            eprintln!("Dota-Terrain-Mod error: {}", e);
+            std::process::exit(1);
+        }
+    };
+
+    match config::edit_list("menu", key, &terrain.file, add) {
+        Ok(true) if add => println!("Added {} to {}.", terrain.name, key),
+        Ok(true) => println!("Removed {} from {}.", terrain.name, key),
+        Ok(false) if add => println!("{} is already in {}.", terrain.name, key),
+        Ok(false) => println!("{} is not in {}.", terrain.name, key),
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+    }
+}
+
+/// Entry point for the `favorite <add|remove> <terrain>` command. Favorites are listed first
+/// in the menu. Terrains are given by file name or menu name.
+pub(crate) fn run(args: &Args) {
+    edit(args, "favorite", "favorites");
+}
+
+/// Entry point for the `hide <add|remove> <terrain>` command. Hidden terrains are not listed
+/// in the menu.
+pub(crate) fn run_hide(args: &Args) {
+    edit(args, "hide", "hidden");
+}
@@ -0,0 +1,102 @@
+use std::sync::OnceLock;
+
+/// The Source 2 game whose maps are patched: where Steam installs it, where its content and
+/// maps are and how it is launched. Dota 2 unless another game is selected with `--game`.
+/// # Properties
+/// - `id: &str` = Name given to `--game`
+/// - `title: &str` = Name of the game shown in messages
+/// - `appid: &str` = Steam app ID
+/// - `install_dir: &str` = Folder of the game in `steamapps/common`
+/// - `content_dir: &str` = Folder of the game's content inside its `game` folder, holding
+///   `pak01_dir.vpk` and `maps`
+/// - `default_base: &str` = Map patched unless another base is selected with `--base`
+/// - `terrain_prefix: Option<&str>` = Prefix of the file names of the terrains shipped in the
+///   maps folder, which are not bases themselves. `None` if the game has no terrains
+/// - `process: &str` = Name of the game's process, without `.exe`
+pub(crate) struct GameProfile {
+    pub(crate) id: &'static str,
+    pub(crate) title: &'static str,
+    pub(crate) appid: &'static str,
+    pub(crate) install_dir: &'static str,
+    pub(crate) content_dir: &'static str,
+    pub(crate) default_base: &'static str,
+    pub(crate) terrain_prefix: Option<&'static str>,
+    pub(crate) process: &'static str,
+}
+
+/// Every supported game, Dota 2 first
+const PROFILES: [GameProfile; 3] = [
+    GameProfile {
+        id: "dota",
+        title: "Dota 2",
+        appid: "570",
+        install_dir: "dota 2 beta",
+        content_dir: "dota",
+        default_base: "dota",
+        terrain_prefix: Some("dota_"),
+        process: "dota2",
+    },
+    GameProfile {
+        id: "cs2",
+        title: "Counter-Strike 2",
+        appid: "730",
+        install_dir: "Counter-Strike Global Offensive",
+        content_dir: "csgo",
+        default_base: "de_dust2",
+        terrain_prefix: None,
+        process: "cs2",
+    },
+    GameProfile {
+        id: "hlvr",
+        title: "Half-Life: Alyx",
+        appid: "546560",
+        install_dir: "Half-Life Alyx",
+        content_dir: "hlvr",
+        default_base: "a1_intro_world",
+        terrain_prefix: None,
+        process: "hlvr",
+    },
+];
+
+/// The game selected with `--game`, if any
+static SELECTED: OnceLock<&'static GameProfile> = OnceLock::new();
+
+/// Patch the maps of the game `id` (see `PROFILES`) instead of Dota 2. Fails with the known
+/// names if there is no such game.
+pub(crate) fn select(id: &str) -> Result<(), String> {
+    let Some(profile) = PROFILES
+        .iter()
+        .find(|profile| profile.id.eq_ignore_ascii_case(id))
+    else {
+        let known: Vec<&str> = PROFILES.iter().map(|profile| profile.id).collect();
+        return Err(format!(
+            "Unknown game \"{}\". Known: {}",
+            id,
+            known.join(", ")
+        ));
+    };
+    let _ = SELECTED.set(profile);
+    Ok(())
+}
+
+/// The game selected with `--game`, or Dota 2
+pub(crate) fn current() -> &'static GameProfile {
+    SELECTED.get().copied().unwrap_or(&PROFILES[0])
+}
+
+impl GameProfile {
+    /// Whether this is Dota 2, which is patched unless `--game` is given
+    pub(crate) fn is_default(&self) -> bool {
+        self.id == PROFILES[0].id
+    }
+
+    /// Folder inside the `game` folder which the game loads with `-language tempcontent`
+    pub(crate) fn tempcontent_dir(&self) -> String {
+        format!("{}_tempcontent", self.content_dir)
+    }
+
+    /// Steam URL launching the game with the launch option loading the patched map
+    pub(crate) fn launch_url(&self) -> String {
+        format!("steam://run/{}//-language%20tempcontent/", self.appid)
+    }
+}
@@ -0,0 +1,200 @@
+use md5::{Digest, Md5};
+
+/// Prefix of hashes made with BLAKE3. MD5 hashes have none, as written by older versions.
+const BLAKE3_PREFIX: &str = "blake3:";
+
+/// A hash function for the tool's own records, such as pinned terrains and the history. The
+/// MD5 and CRC32 required by the VPK format are computed separately, see `vpk`.
+pub(crate) trait Hasher {
+    /// Add `data` to the hashed input
+    fn update(&mut self, data: &[u8]);
+    /// The hash of everything added
+    fn finalize(self: Box<Self>) -> Vec<u8>;
+}
+
+impl Hasher for Md5 {
+    fn update(&mut self, data: &[u8]) {
+        Digest::update(self, data);
+    }
+
+    fn finalize(self: Box<Self>) -> Vec<u8> {
+        Digest::finalize(*self).to_vec()
+    }
+}
+
+#[cfg(feature = "blake3")]
+impl Hasher for blake3::Hasher {
+    fn update(&mut self, data: &[u8]) {
+        blake3::Hasher::update(self, data);
+    }
+
+    fn finalize(self: Box<Self>) -> Vec<u8> {
+        blake3::Hasher::finalize(&self).as_bytes().to_vec()
+    }
+}
+
+/// Hash function used for the tool's own records, chosen with `hash` in the configuration
+#[derive(Clone, Copy, Default, PartialEq)]
+pub(crate) enum HashAlgorithm {
+    /// MD5, which other tools can check the records against
+    #[default]
+    Md5,
+    /// BLAKE3, which unlike MD5 has no known collisions and is faster on large inputs.
+    /// Requires the "blake3" feature.
+    #[cfg(feature = "blake3")]
+    Blake3,
+}
+
+impl HashAlgorithm {
+    /// The algorithm named `name` in the configuration, `md5` or `blake3`. BLAKE3 is only
+    /// known to builds with the "blake3" feature.
+    pub(crate) fn from_name(name: &str) -> Option<HashAlgorithm> {
+        match name.to_lowercase().as_str() {
+            "md5" => Some(HashAlgorithm::Md5),
+            #[cfg(feature = "blake3")]
+            "blake3" => Some(HashAlgorithm::Blake3),
+            _ => None,
+        }
+    }
+
+    /// The algorithm a recorded hash was made with, or `None` for BLAKE3 hashes in builds
+    /// without the "blake3" feature
+    fn of(hash: &str) -> Option<HashAlgorithm> {
+        if !hash.starts_with(BLAKE3_PREFIX) {
+            return Some(HashAlgorithm::Md5);
+        }
+        #[cfg(feature = "blake3")]
+        return Some(HashAlgorithm::Blake3);
+        #[cfg(not(feature = "blake3"))]
+        None
+    }
+
+    fn hasher(self) -> Box<dyn Hasher> {
+        match self {
+            HashAlgorithm::Md5 => Box::new(Md5::new()),
+            #[cfg(feature = "blake3")]
+            HashAlgorithm::Blake3 => Box::new(blake3::Hasher::new()),
+        }
+    }
+
+    /// Hash of `data` as it is recorded: in hex, prefixed with `blake3:` for BLAKE3
+    pub(crate) fn hash(self, data: &[u8]) -> String {
+        self.hash_all(&[data])
+    }
+
+    /// Hash of `parts` one after another, recorded like `hash()`
+    pub(crate) fn hash_all(self, parts: &[&[u8]]) -> String {
+        let mut hasher = self.hasher();
+        for part in parts {
+            hasher.update(part);
+        }
+        let hex: String = hasher
+            .finalize()
+            .iter()
+            .map(|byte| format!("{:02x}", byte))
+            .collect();
+        match self {
+            HashAlgorithm::Md5 => hex,
+            #[cfg(feature = "blake3")]
+            HashAlgorithm::Blake3 => format!("{}{}", BLAKE3_PREFIX, hex),
+        }
+    }
+}
+
+/// Whether `data` has the recorded hash `hash`, whichever algorithm it was made with. BLAKE3
+/// hashes never match in builds without the "blake3" feature.
+pub(crate) fn matches(hash: &str, data: &[u8]) -> bool {
+    HashAlgorithm::of(hash).is_some_and(|algorithm| algorithm.hash(data) == hash)
+}
+
+/// A recorded hash for display, named after its algorithm, e.g. `MD5 9e107d9d...`
+pub(crate) fn describe(hash: &str) -> String {
+    match hash.strip_prefix(BLAKE3_PREFIX) {
+        Some(hex) => format!("BLAKE3 {}", hex),
+        None => format!("MD5 {}", hash),
+    }
+}
+
+#[cfg(all(test, feature = "blake3"))]
+mod tests {
+    use super::*;
+
+    /// Official test vectors of BLAKE3: the hash of `length` bytes counting up modulo 251
+    const VECTORS: [(usize, &str); 15] = [
+        (
+            0,
+            "af1349b9f5f9a1a6a0404dea36dcc9499bcb25c9adc112b7cc9a93cae41f3262",
+        ),
+        (
+            1,
+            "2d3adedff11b61f14c886e35afa036736dcd87a74d27b5c1510225d0f592e213",
+        ),
+        (
+            1023,
+            "10108970eeda3eb932baac1428c7a2163b0e924c9a9e25b35bba72b28f70bd11",
+        ),
+        (
+            1024,
+            "42214739f095a406f3fc83deb889744ac00df831c10daa55189b5d121c855af7",
+        ),
+        (
+            1025,
+            "d00278ae47eb27b34faecf67b4fe263f82d5412916c1ffd97c8cb7fb814b8444",
+        ),
+        (
+            2048,
+            "e776b6028c7cd22a4d0ba182a8bf62205d2ef576467e838ed6f2529b85fba24a",
+        ),
+        (
+            2049,
+            "5f4d72f40d7a5f82b15ca2b2e44b1de3c2ef86c426c95c1af0b6879522563030",
+        ),
+        (
+            3072,
+            "b98cb0ff3623be03326b373de6b9095218513e64f1ee2edd2525c7ad1e5cffd2",
+        ),
+        (
+            3073,
+            "7124b49501012f81cc7f11ca069ec9226cecb8a2c850cfe644e327d22d3e1cd3",
+        ),
+        (
+            4096,
+            "015094013f57a5277b59d8475c0501042c0b642e531b0a1c8f58d2163229e969",
+        ),
+        (
+            4097,
+            "9b4052b38f1c5fc8b1f9ff7ac7b27cd242487b3d890d15c96a1c25b8aa0fb995",
+        ),
+        (
+            5120,
+            "9cadc15fed8b5d854562b26a9536d9707cadeda9b143978f319ab34230535833",
+        ),
+        (
+            8192,
+            "aae792484c8efe4f19e2ca7d371d8c467ffb10748d8a5a1ae579948f718a2a63",
+        ),
+        (
+            8193,
+            "bab6c09cb8ce8cf459261398d2e7aef35700bf488116ceb94a36d0f5f1b7bc3b",
+        ),
+        (
+            102400,
+            "bc3e3d41a1146b069abffad3c0d44860cf664390afce4d9661f7902e7943e085",
+        ),
+    ];
+
+    #[test]
+    fn blake3_matches_the_official_vectors() {
+        for (length, expected) in VECTORS {
+            let data: Vec<u8> = (0..length).map(|i| (i % 251) as u8).collect();
+            let hash = HashAlgorithm::Blake3.hash(&data);
+            assert_eq!(
+                hash,
+                format!("{}{}", BLAKE3_PREFIX, expected),
+                "length {}",
+                length
+            );
+            assert!(matches(&hash, &data));
+        }
+    }
+}
@@ -0,0 +1,375 @@
+use std::{
+    io,
+    path::PathBuf,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use serde_json::{json, Value};
+
+use crate::cli::Args;
+use crate::config;
+use crate::game;
+use crate::hashing;
+use crate::install::InstallMethod;
+use crate::texture::Recolor;
+
+/// Name of the history file inside the configuration directory
+const HISTORY_FILE: &str = "history.json";
+
+/// A terrain applied in the past, along with everything needed to apply it again
+/// # Properties
+/// - `terrain: String` = File name of the applied terrain
+/// - `timestamp: u64` = When the terrain was applied, in seconds since the Unix epoch
+/// - `build: Option<String>` = Build ID of the game at the time, if it could be read
+/// - `hash: String` = Hash of the output VPK, see `HashAlgorithm::hash()`
+/// - `overrides: Vec<PathBuf>` = Directories and VPKs given with `--overrides`
+/// - `rules: Option<PathBuf>` = Rules file given with `--rules`
+/// - `terrain_minimap: bool` = Whether `--terrain-minimap` was given
+/// - `compress: bool` = Whether `--compress` was given
+/// - `outputs: Vec<PathBuf>` = Additional outputs given with `--output`
+/// - `map_aliases: Vec<String>` = Additional names the map was written under
+/// - `low_end: bool` = Whether `--low-end` was given
+/// - `strip_editor_files: bool` = Whether `--strip-editor-files` was given
+/// - `downscale: Option<u16>` = Texture size given with `--downscale`
+/// - `recolor: Option<Recolor>` = Color shift given with `--recolor`
+/// - `base: String` = Name of the map the terrain replaced
+/// - `method: InstallMethod` = How the terrain was installed (`--method`)
+pub(crate) struct HistoryEntry {
+    pub(crate) terrain: String,
+    pub(crate) timestamp: u64,
+    pub(crate) build: Option<String>,
+    pub(crate) hash: String,
+    pub(crate) overrides: Vec<PathBuf>,
+    pub(crate) rules: Option<PathBuf>,
+    pub(crate) terrain_minimap: bool,
+    pub(crate) compress: bool,
+    pub(crate) outputs: Vec<PathBuf>,
+    pub(crate) map_aliases: Vec<String>,
+    pub(crate) low_end: bool,
+    pub(crate) strip_editor_files: bool,
+    pub(crate) downscale: Option<u16>,
+    pub(crate) recolor: Option<Recolor>,
+    pub(crate) base: String,
+    pub(crate) method: InstallMethod,
+}
+
+impl HistoryEntry {
+    fn to_json(&self) -> Value {
+        let outputs: Vec<String> = self
+            .outputs
+            .iter()
+            .map(|output| output.to_string_lossy().into_owned())
+            .collect();
+        let overrides: Vec<String> = self
+            .overrides
+            .iter()
+            .map(|layer| layer.to_string_lossy().into_owned())
+            .collect();
+        json!({
+            "terrain": self.terrain,
+            "timestamp": self.timestamp,
+            "build": self.build,
+            "hash": self.hash,
+            "overrides": overrides,
+            "rules": self.rules.as_ref().map(|path| path.to_string_lossy()),
+            "terrain_minimap": self.terrain_minimap,
+            "compress": self.compress,
+            "outputs": outputs,
+            "map_aliases": self.map_aliases,
+            "low_end": self.low_end,
+            "strip_editor_files": self.strip_editor_files,
+            "downscale": self.downscale,
+            "recolor": self.recolor.map(|recolor| recolor.to_string()),
+            "base": self.base,
+            "method": self.method.name(),
+        })
+    }
+
+    fn from_json(value: &Value) -> Option<HistoryEntry> {
+        let path = |key: &str| value.get(key)?.as_str().map(PathBuf::from);
+        Some(HistoryEntry {
+            terrain: value.get("terrain")?.as_str()?.to_owned(),
+            timestamp: value.get("timestamp")?.as_u64()?,
+            build: value
+                .get("build")
+                .and_then(Value::as_str)
+                .map(str::to_owned),
+            hash: value.get("hash")?.as_str()?.to_owned(),
+            overrides: match value.get("overrides") {
+                // Older versions recorded a single directory
+                Some(Value::String(dir)) => vec![PathBuf::from(dir)],
+                Some(Value::Array(layers)) => layers
+                    .iter()
+                    .map(|layer| layer.as_str().map(PathBuf::from))
+                    .collect::<Option<Vec<PathBuf>>>()?,
+                _ => Vec::new(),
+            },
+            rules: path("rules"),
+            terrain_minimap: value.get("terrain_minimap")?.as_bool()?,
+            compress: value.get("compress")?.as_bool()?,
+            outputs: value
+                .get("outputs")?
+                .as_array()?
+                .iter()
+                .map(|output| output.as_str().map(PathBuf::from))
+                .collect::<Option<Vec<PathBuf>>>()?,
+            // Not recorded by older versions
+            base: value
+                .get("base")
+                .and_then(Value::as_str)
+                .unwrap_or(game::current().default_base)
+                .to_owned(),
+            map_aliases: value
+                .get("map_aliases")
+                .and_then(Value::as_array)
+                .map_or_else(Vec::new, |aliases| {
+                    aliases
+                        .iter()
+                        .filter_map(|alias| alias.as_str().map(str::to_owned))
+                        .collect()
+                }),
+            low_end: value
+                .get("low_end")
+                .and_then(Value::as_bool)
+                .unwrap_or(false),
+            // Not recorded by older versions
+            strip_editor_files: value
+                .get("strip_editor_files")
+                .and_then(Value::as_bool)
+                .unwrap_or(false),
+            downscale: value
+                .get("downscale")
+                .and_then(Value::as_u64)
+                .and_then(|size| u16::try_from(size).ok()),
+            recolor: value
+                .get("recolor")
+                .and_then(Value::as_str)
+                .and_then(Recolor::parse),
+            // Not recorded by older versions
+            method: value
+                .get("method")
+                .and_then(Value::as_str)
+                .and_then(InstallMethod::from_name)
+                .unwrap_or_default(),
+        })
+    }
+}
+
+/// Path of the history file
+fn history_path() -> Option<PathBuf> {
+    config::config_dir().map(|dir| dir.join(HISTORY_FILE))
+}
+
+/// Read every recorded entry, oldest first. A missing file is an empty history.
+pub(crate) fn load() -> io::Result<Vec<HistoryEntry>> {
+    let Some(path) = history_path() else {
+        return Ok(Vec::new());
+    };
+    let json = match std::fs::read_to_string(path) {
+        Ok(json) => json,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e),
+    };
+    let value: Value = serde_json::from_str(&json)?;
+    value
+        .as_array()
+        .and_then(|entries| entries.iter().map(HistoryEntry::from_json).collect())
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "malformed history"))
+}
+
+/// Append an entry to the history file, creating it if needed
+pub(crate) fn record(entry: HistoryEntry) -> io::Result<()> {
+    let path = history_path().ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::NotFound,
+            "could not determine the configuration directory",
+        )
+    })?;
+    let mut entries = load()?;
+    entries.push(entry);
+    let json: Vec<Value> = entries.iter().map(HistoryEntry::to_json).collect();
+    std::fs::create_dir_all(path.parent().unwrap())?;
+    std::fs::write(path, serde_json::to_string_pretty(&json)?)
+}
+
+/// Seconds since the Unix epoch
+pub(crate) fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |time| time.as_secs())
+}
+
+/// Format a Unix timestamp as a UTC date and time, `YYYY-MM-DD HH:MM`
+pub(crate) fn format_timestamp(timestamp: u64) -> String {
+    let (year, month, day) = civil_date((timestamp / 86400) as i64);
+    let minutes = timestamp % 86400 / 60;
+    format!(
+        "{:04}-{:02}-{:02} {:02}:{:02}",
+        year,
+        month,
+        day,
+        minutes / 60,
+        minutes % 60
+    )
+}
+
+/// Offset of the local time from UTC in seconds at the moment, e.g. 3600 for UTC+1, as set
+/// for the user's time zone including daylight saving time
+#[cfg(target_os = "linux")]
+pub(crate) fn local_offset() -> i64 {
+    use std::ffi::{c_char, c_int, c_long};
+
+    #[repr(C)]
+    struct Tm {
+        fields: [c_int; 9],
+        gmtoff: c_long,
+        zone: *const c_char,
+    }
+    extern "C" {
+        fn tzset();
+        fn localtime_r(time: *const c_long, result: *mut Tm) -> *mut Tm;
+    }
+
+    let time = now() as c_long;
+    let mut tm = Tm {
+        fields: [0; 9],
+        gmtoff: 0,
+        zone: std::ptr::null(),
+    };
+    unsafe {
+        tzset();
+        if localtime_r(&time, &mut tm).is_null() {
+            return 0;
+        }
+    }
+    tm.gmtoff as i64
+}
+
+/// Offset of the local time from UTC in seconds at the moment, e.g. 3600 for UTC+1, as set
+/// for the user's time zone including daylight saving time
+#[cfg(target_os = "windows")]
+pub(crate) fn local_offset() -> i64 {
+    #[repr(C)]
+    #[derive(Default)]
+    struct SystemTime {
+        year: u16,
+        month: u16,
+        day_of_week: u16,
+        day: u16,
+        hour: u16,
+        minute: u16,
+        second: u16,
+        milliseconds: u16,
+    }
+    #[link(name = "kernel32")]
+    extern "system" {
+        fn GetLocalTime(time: *mut SystemTime);
+        fn GetSystemTime(time: *mut SystemTime);
+    }
+
+    let (mut local, mut utc) = (SystemTime::default(), SystemTime::default());
+    unsafe {
+        GetLocalTime(&mut local);
+        GetSystemTime(&mut utc);
+    }
+    let minutes = |time: &SystemTime| i64::from(time.hour) * 60 + i64::from(time.minute);
+    // Offsets are less than a day, so the dates differ by a day at most
+    let days = match (local.year, local.month, local.day).cmp(&(utc.year, utc.month, utc.day)) {
+        std::cmp::Ordering::Less => -1,
+        std::cmp::Ordering::Equal => 0,
+        std::cmp::Ordering::Greater => 1,
+    };
+    (days * 1440 + minutes(&local) - minutes(&utc)) * 60
+}
+
+/// Days since the Unix epoch of the local date today, for `civil_date()`
+pub(crate) fn local_today() -> i64 {
+    (now() as i64 + local_offset()).div_euclid(86400)
+}
+
+/// Year, month (1-12) and day of the month of the UTC date `days` days after the Unix epoch
+pub(crate) fn civil_date(days: i64) -> (i64, u32, u32) {
+    // See http://howardhinnant.github.io/date_algorithms.html
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let day_of_era = z.rem_euclid(146097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    (year, month as u32, day as u32)
+}
+
+/// Entry point for the `history` command. Lists every applied terrain, numbered for
+/// `apply --from-history <n>`, with when it was applied, the game build and the hash of the
+/// output.
+pub(crate) fn run(args: &Args) {
+    if !args.positional.is_empty() {
+        eprintln!("Usage: dota-terrain-mod history");
+        std::process::exit(1);
+    }
+    let entries = match load() {
+        Ok(entries) => entries,
+        Err(e) => {
+            eprintln!("Dota-Terrain-Mod error: Could not read the history: {}", e);
+            std::process::exit(1);
+        }
+    };
+    if entries.is_empty() {
+        println!("No terrain applied yet.");
+        return;
+    }
+    for (i, entry) in entries.iter().enumerate() {
+        println!(
+            "[{}] {}  {}  build {}  {}",
+            i + 1,
+            format_timestamp(entry.timestamp),
+            entry.terrain,
+            entry.build.as_deref().unwrap_or("unknown"),
+            hashing::describe(&entry.hash)
+        );
+        let mut options = Vec::new();
+        for layer in &entry.overrides {
+            options.push(format!("--overrides {}", layer.display()));
+        }
+        if let Some(path) = &entry.rules {
+            options.push(format!("--rules {}", path.display()));
+        }
+        if entry.terrain_minimap {
+            options.push(String::from("--terrain-minimap"));
+        }
+        if entry.compress {
+            options.push(String::from("--compress"));
+        }
+        for output in &entry.outputs {
+            options.push(format!("--output {}", output.display()));
+        }
+        if entry.base != game::current().default_base {
+            options.push(format!("--base {}", entry.base));
+        }
+        for alias in &entry.map_aliases {
+            options.push(format!("--map-alias {}", alias));
+        }
+        if entry.low_end {
+            options.push(String::from("--low-end"));
+        }
+        if entry.strip_editor_files {
+            options.push(String::from("--strip-editor-files"));
+        }
+        if let Some(size) = entry.downscale {
+            options.push(format!("--downscale {}", size));
+        }
+        if let Some(recolor) = entry.recolor {
+            options.push(format!("--recolor {}", recolor));
+        }
+        if entry.method != InstallMethod::default() {
+            options.push(format!("--method {}", entry.method.name()));
+        }
+        if !options.is_empty() {
+            println!("    {}", options.join(" "));
+        }
+    }
+}
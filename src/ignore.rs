@@ -0,0 +1,230 @@
+use std::{io, path::Path};
+
+use regex::Regex;
+
+/// Name of the ignore file read from the root of a packed directory
+pub(crate) const IGNORE_FILE: &str = ".vpkignore";
+
+/// A line of an ignore file
+/// # Properties
+/// - `pattern: Regex` = The glob matched against paths relative to the packed directory,
+///   translated to a regular expression
+/// - `negated: bool` = Whether the pattern started with `!`, including paths again
+/// - `dir_only: bool` = Whether the pattern ended with `/`, matching only directories
+struct IgnorePattern {
+    pattern: Regex,
+    negated: bool,
+    dir_only: bool,
+}
+
+impl IgnorePattern {
+    /// Translate a line in gitignore syntax. Returns `None` for blank lines and comments.
+    fn parse(line: &str) -> Option<IgnorePattern> {
+        let line = line.trim_end();
+        if line.is_empty() || line.starts_with('#') {
+            return None;
+        }
+        let (negated, line) = match line.strip_prefix('!') {
+            Some(line) => (true, line),
+            None => (false, line.strip_prefix('\\').unwrap_or(line)),
+        };
+        let (dir_only, line) = match line.strip_suffix('/') {
+            Some(line) => (true, line),
+            None => (false, line),
+        };
+        // Patterns with a slash before the end are relative to the root, others match at
+        // any depth
+        let anchored = line.contains('/');
+        let glob = line.strip_prefix('/').unwrap_or(line);
+        if glob.is_empty() {
+            return None;
+        }
+
+        let mut pattern = String::from(if anchored { "^" } else { "^(?:.*/)?" });
+        let mut chars = glob.chars().peekable();
+        while let Some(c) = chars.next() {
+            match c {
+                '*' if chars.peek() == Some(&'*') => {
+                    chars.next();
+                    if chars.peek() == Some(&'/') {
+                        // `**/` matches any number of directories, including none
+                        chars.next();
+                        pattern.push_str("(?:.*/)?");
+                    } else {
+                        pattern.push_str(".*");
+                    }
+                }
+                '*' => pattern.push_str("[^/]*"),
+                '?' => pattern.push_str("[^/]"),
+                '[' => {
+                    let mut class = String::from("[");
+                    if chars.next_if_eq(&'!').is_some() {
+                        class.push('^');
+                    }
+                    for c in chars.by_ref() {
+                        if c == ']' {
+                            break;
+                        }
+                        if c == '\\' || c == '[' {
+                            class.push('\\');
+                        }
+                        class.push(c);
+                    }
+                    class.push(']');
+                    pattern.push_str(&class);
+                }
+                '\\' => {
+                    if let Some(c) = chars.next() {
+                        pattern.push_str(&regex::escape(&c.to_string()));
+                    }
+                }
+                c => pattern.push_str(&regex::escape(&c.to_string())),
+            }
+        }
+        pattern.push('$');
+
+        Some(IgnorePattern {
+            pattern: Regex::new(&pattern).ok()?,
+            negated,
+            dir_only,
+        })
+    }
+}
+
+/// The patterns of a `.vpkignore` file, in gitignore syntax: `*` and `?` stay within a
+/// directory, `**` crosses directories, a trailing `/` matches only directories, a leading
+/// `!` includes paths again and patterns containing a `/` are relative to the packed
+/// directory. The last matching pattern decides, and files in an ignored directory stay
+/// ignored.
+#[derive(Default)]
+pub(crate) struct IgnoreList {
+    patterns: Vec<IgnorePattern>,
+}
+
+impl IgnoreList {
+    /// Read the patterns of the text of an ignore file
+    pub(crate) fn parse(text: &str) -> IgnoreList {
+        IgnoreList {
+            patterns: text.lines().filter_map(IgnorePattern::parse).collect(),
+        }
+    }
+
+    /// Read the ignore file at the root of `dir`. An empty list if there is none.
+    pub(crate) fn load(dir: &Path) -> io::Result<IgnoreList> {
+        match std::fs::read_to_string(dir.join(IGNORE_FILE)) {
+            Ok(text) => Ok(IgnoreList::parse(&text)),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(IgnoreList::default()),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Whether the last pattern matching `path` excludes it, or `None` if none matches
+    fn decision(&self, path: &str, is_dir: bool) -> Option<bool> {
+        self.patterns
+            .iter()
+            .rev()
+            .find(|pattern| (is_dir || !pattern.dir_only) && pattern.pattern.is_match(path))
+            .map(|pattern| !pattern.negated)
+    }
+
+    /// Whether the file at `path`, relative to the packed directory with `/` separators, is
+    /// ignored, either itself or through one of its directories
+    pub(crate) fn is_ignored(&self, path: &str) -> bool {
+        let mut dir_end = 0;
+        while let Some(slash) = path[dir_end..].find('/') {
+            dir_end += slash;
+            if self.decision(&path[..dir_end], true) == Some(true) {
+                return true;
+            }
+            dir_end += 1;
+        }
+        self.decision(path, false) == Some(true)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn negation_includes_paths_again() {
+        let list = IgnoreList::parse("*.psd\n!keep.psd\n");
+        assert!(list.is_ignored("art/source.psd"));
+        assert!(!list.is_ignored("art/keep.psd"));
+        assert!(!list.is_ignored("art/source.png"));
+
+        // The last matching pattern decides
+        let list = IgnoreList::parse("!keep.psd\n*.psd\n");
+        assert!(list.is_ignored("art/keep.psd"));
+
+        // Files in an ignored directory cannot be included again
+        let list = IgnoreList::parse("build/\n!build/keep.vmat_c\n");
+        assert!(list.is_ignored("build/keep.vmat_c"));
+    }
+
+    #[test]
+    fn double_stars_cross_directories() {
+        let list = IgnoreList::parse("materials/**/draft_*\n");
+        assert!(list.is_ignored("materials/draft_sand.vmat_c"));
+        assert!(list.is_ignored("materials/a/b/draft_sand.vmat_c"));
+        assert!(!list.is_ignored("maps/draft_sand.vmat_c"));
+
+        let list = IgnoreList::parse("**/cache\nlogs/**\n");
+        assert!(list.is_ignored("cache/file.bin"));
+        assert!(list.is_ignored("a/b/cache/file.bin"));
+        assert!(list.is_ignored("logs/a/b.log"));
+        assert!(!list.is_ignored("old/logs/b.log"));
+
+        let list = IgnoreList::parse("*.tmp\n");
+        assert!(list.is_ignored("a/b/c.tmp"));
+        assert!(!list.is_ignored("a/b/c.tmpl"));
+    }
+
+    #[test]
+    fn patterns_with_a_slash_are_anchored() {
+        let list = IgnoreList::parse("/notes.txt\ndocs/*.md\n");
+        assert!(list.is_ignored("notes.txt"));
+        assert!(!list.is_ignored("maps/notes.txt"));
+        assert!(list.is_ignored("docs/readme.md"));
+        assert!(!list.is_ignored("maps/docs/readme.md"));
+        assert!(!list.is_ignored("docs/sub/readme.md"));
+
+        // Without a slash, patterns match at any depth
+        let list = IgnoreList::parse("notes.txt\n");
+        assert!(list.is_ignored("notes.txt"));
+        assert!(list.is_ignored("maps/notes.txt"));
+    }
+
+    #[test]
+    fn trailing_slashes_match_only_directories() {
+        let list = IgnoreList::parse("build/\n");
+        assert!(list.is_ignored("build/out.vpk"));
+        assert!(list.is_ignored("sub/build/out.vpk"));
+        assert!(!list.is_ignored("sub/build"));
+    }
+
+    #[test]
+    fn classes_escapes_and_comments() {
+        let list = IgnoreList::parse(
+            "# a comment\n\n\\#hash.txt\n\\!bang.txt\nfile[0-9].txt\nnot[!a].txt\nq?.txt   \n",
+        );
+        assert!(!list.is_ignored("a comment"));
+        assert!(list.is_ignored("#hash.txt"));
+        assert!(list.is_ignored("!bang.txt"));
+        assert!(list.is_ignored("file7.txt"));
+        assert!(!list.is_ignored("filex.txt"));
+        assert!(list.is_ignored("notb.txt"));
+        assert!(!list.is_ignored("nota.txt"));
+        assert!(list.is_ignored("q1.txt"));
+        assert!(!list.is_ignored("q/.txt"));
+    }
+
+    #[test]
+    fn malformed_patterns_are_skipped() {
+        // Like git, lines which are no valid pattern are left out instead of failing the pack
+        let list = IgnoreList::parse("[]\n[z-a].txt\n/\n!\n*.psd\n");
+        assert_eq!(list.patterns.len(), 1);
+        assert!(list.is_ignored("art/source.psd"));
+        assert!(!list.is_ignored("z.txt"));
+    }
+}
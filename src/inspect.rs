@@ -0,0 +1,129 @@
+use std::path::PathBuf;
+
+use crate::cli::Args;
+use crate::resource::{self, Resource};
+use crate::utils::hexdump;
+use crate::vpk::{VpkOptions, VPK};
+
+/// Number of bytes shown in the hexdump unless `--bytes` is given
+const DEFAULT_BYTES: usize = 256;
+
+/// Print the preload data of an entry, the start of the file stored in the tree itself, in
+/// full or up to `bytes` bytes. Resources keeping their header in the preload data have its
+/// blocks listed, along with whether their contents are in the preload data too.
+fn show_preload(preload: &[u8], bytes: Option<usize>) {
+    println!();
+    if preload.is_empty() {
+        println!("The entry has no preload data.");
+        return;
+    }
+    println!("Preload data ({} bytes):", preload.len());
+    if let Some(resource) = Resource::parse(preload) {
+        for block in &resource.blocks {
+            let location = if block.offset + block.size <= preload.len() {
+                "in the preload data"
+            } else {
+                "in the archive"
+            };
+            println!(
+                "  Block {}      offset {}, {} bytes, {}",
+                block.kind, block.offset, block.size, location
+            );
+        }
+    }
+    let shown = bytes.unwrap_or(preload.len()).min(preload.len());
+    for line in hexdump(&preload[..shown]) {
+        println!("{}", line);
+    }
+    if preload.len() > shown {
+        println!("... {} more bytes", preload.len() - shown);
+    }
+}
+
+/// Entry point for the `inspect <vpk> <entry> [--bytes N] [--preload [-o <file>]]` command.
+/// Prints the metadata of a single entry, a best-effort identification of its type and a
+/// hexdump of its first bytes. With `--preload`, the preload data stored in the tree is dumped
+/// instead, where some resources keep their header, and with `-o` written to `file` as it is.
+pub(crate) fn run(args: &Args) {
+    let [vpk_path, entry] = args.positional.as_slice() else {
+        eprintln!(
+            "Usage: dota-terrain-mod inspect <vpk> <entry> [--bytes N] [--preload [-o <file>]]"
+        );
+        std::process::exit(1);
+    };
+    let bytes = match args.option("bytes").map(str::parse::<usize>) {
+        None => None,
+        Some(Ok(bytes)) => Some(bytes),
+        Some(Err(_)) => {
+            eprintln!("Dota-Terrain-Mod error: --bytes expects a number");
+            std::process::exit(1);
+        }
+    };
+
+    let mut vpk = match VPK::open_with(PathBuf::from(vpk_path), VpkOptions::from_args(args)) {
+        Ok(vpk) => vpk,
+        Err(e) => {
+            eprintln!("Dota-Terrain-Mod error: Invalid VPK: {}", e);
+            std::process::exit(1);
+        }
+    };
+    let entry = entry.replace('\\', "/");
+    let Some(metadata) = vpk.entry(&entry) else {
+        eprintln!(
+            "Dota-Terrain-Mod error: \"{}\" not found in {}",
+            entry, vpk_path
+        );
+        std::process::exit(1);
+    };
+    println!("Entry:          {}", entry);
+    println!("Size:           {} bytes", metadata.entry_length());
+    println!("CRC32:          {:08x}", metadata.crc32());
+    println!("Preload length: {}", metadata.preload_length());
+    println!("Archive index:  {}", metadata.archive_index());
+    println!("Archive offset: {}", metadata.archive_offset());
+    println!("Archive length: {}", metadata.file_length());
+
+    if args.flag("preload") {
+        let preload = metadata.preload();
+        if let Some(out) = args.option("output") {
+            if let Err(e) = std::fs::write(out, preload) {
+                eprintln!("Dota-Terrain-Mod error: Could not write {}: {}", out, e);
+                std::process::exit(1);
+            }
+            println!(
+                "\nWrote {} bytes of preload data to {}.",
+                preload.len(),
+                out
+            );
+            return;
+        }
+        show_preload(preload, bytes);
+        return;
+    }
+    let bytes = bytes.unwrap_or(DEFAULT_BYTES);
+
+    let data = match vpk.read_entry(&entry) {
+        Ok(data) => data,
+        Err(e) => {
+            eprintln!("Dota-Terrain-Mod error: Internal error: {}", e);
+            std::process::exit(1);
+        }
+    };
+    println!("Type:           {}", resource::identify(&entry, &data));
+    if let Some(resource) = Resource::parse(&data) {
+        for block in &resource.blocks {
+            println!(
+                "  Block {}      offset {}, {} bytes",
+                block.kind, block.offset, block.size
+            );
+        }
+    }
+
+    println!();
+    for line in hexdump(&data[..bytes.min(data.len())]) {
+        println!("{}", line);
+    }
+    if data.len() > bytes {
+        println!("... {} more bytes", data.len() - bytes);
+    }
+}
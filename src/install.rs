@@ -0,0 +1,662 @@
+use std::{
+    collections::HashMap,
+    io,
+    path::{Path, PathBuf},
+    time::Instant,
+};
+
+use crate::apply::{self, ApplyOptions};
+use crate::hashing::HashAlgorithm;
+use crate::manifest::{self, BuildStats, Manifest, Source};
+use crate::utils::{self, DotaInstall, TMError};
+use crate::{crash, journal, loose, menu};
+
+/// End of the line `--method gameinfo` adds to `gameinfo.gi`, telling it apart from the
+/// game's own search paths
+const SEARCH_PATH_MARKER: &str = "// Added by Dota-Terrain-Mod";
+
+/// How a terrain is installed into the game folder (`--method`), see `Installer`
+#[derive(Clone, Copy, Default, PartialEq)]
+pub(crate) enum InstallMethod {
+    /// A single VPK replacing the base map in the tempcontent folder, see `Tempcontent`
+    #[default]
+    Vpk,
+    /// Loose files below the tempcontent folder, see `LooseFiles`
+    Loose,
+    /// The base map and the terrain packed into a VPK like `Vpk`, with the overrides as loose
+    /// files on top, see `Hybrid`
+    Hybrid,
+    /// A VPK in a folder of its own, added to the search paths in `gameinfo.gi`, see
+    /// `GameinfoSearchPath`
+    Gameinfo,
+}
+
+impl InstallMethod {
+    /// Every method, in the order they are listed in messages
+    pub(crate) const ALL: [InstallMethod; 4] = [
+        InstallMethod::Vpk,
+        InstallMethod::Loose,
+        InstallMethod::Hybrid,
+        InstallMethod::Gameinfo,
+    ];
+
+    /// The method named `name` on the command line, see `name()`
+    pub(crate) fn from_name(name: &str) -> Option<InstallMethod> {
+        InstallMethod::ALL
+            .into_iter()
+            .find(|method| method.name().eq_ignore_ascii_case(name))
+    }
+
+    /// Name of the method as it is given on the command line
+    pub(crate) fn name(self) -> &'static str {
+        match self {
+            InstallMethod::Vpk => "vpk",
+            InstallMethod::Loose => "loose",
+            InstallMethod::Hybrid => "hybrid",
+            InstallMethod::Gameinfo => "gameinfo",
+        }
+    }
+
+    /// Names of every method for messages, e.g. `vpk, loose or hybrid`
+    pub(crate) fn names() -> String {
+        let names: Vec<&str> = InstallMethod::ALL
+            .iter()
+            .map(|method| method.name())
+            .collect();
+        let (last, rest) = names.split_last().unwrap();
+        format!("{} or {}", rest.join(", "), last)
+    }
+
+    /// Whether the method installs the whole terrain as a single VPK, which can be written to
+    /// additional outputs as well (`--output`)
+    pub(crate) fn is_packed(self) -> bool {
+        matches!(self, InstallMethod::Vpk | InstallMethod::Gameinfo)
+    }
+
+    pub(crate) fn installer(self) -> Box<dyn Installer> {
+        match self {
+            InstallMethod::Vpk => Box::new(Tempcontent),
+            InstallMethod::Loose => Box::new(LooseFiles),
+            InstallMethod::Hybrid => Box::new(Hybrid),
+            InstallMethod::Gameinfo => Box::new(GameinfoSearchPath),
+        }
+    }
+}
+
+/// A way of installing a terrain into the game folder, one for each `InstallMethod`. Every
+/// method keeps the manifest of an install next to the output VPK of `Tempcontent`, see
+/// `DotaInstall::out_vpk()`, where the other commands look for it.
+pub(crate) trait Installer {
+    /// Build the terrain in `terrain_file` with `options` and install it. `previous` is the
+    /// manifest of the install of the same method it replaces, if any. Returns the output VPK,
+    /// empty if the method has none, the manifest and the hash recorded in the history.
+    fn install(
+        &self,
+        dota: &DotaInstall,
+        terrain_file: &str,
+        options: &ApplyOptions,
+        previous: Option<&Manifest>,
+    ) -> Result<(Vec<u8>, Manifest, String), TMError>;
+
+    /// Remove the install for the base map `base` described by `manifest`, except for the
+    /// files in `keep`, which belong to the install replacing it
+    fn uninstall(
+        &self,
+        dota: &DotaInstall,
+        base: &str,
+        manifest: &Manifest,
+        keep: &[PathBuf],
+    ) -> io::Result<()>;
+
+    /// Whether files of the install for the base map `base` described by `manifest` are in
+    /// the game folder
+    fn detect(&self, dota: &DotaInstall, base: &str, manifest: &Manifest) -> bool;
+
+    /// Where the install for the base map `base` described by `manifest` is, and whether it
+    /// is intact, e.g. `Applied (<path>)`
+    fn status(&self, dota: &DotaInstall, base: &str, manifest: &Manifest) -> String;
+
+    /// The VPK holding the base map and the terrain for the base map `base`, checked after
+    /// applying. `None` if the method has none.
+    fn output_vpk(&self, dota: &DotaInstall, base: &str) -> Option<PathBuf>;
+
+    /// Every file of the install for the base map `base` described by `manifest`, besides
+    /// the manifest itself
+    fn files(&self, dota: &DotaInstall, base: &str, manifest: &Manifest) -> Vec<PathBuf>;
+
+    /// Whether the game only loads the install when launched with `-language tempcontent`
+    fn needs_launch_option(&self) -> bool {
+        true
+    }
+}
+
+/// `--method vpk`: The output VPK replaces the base map in the tempcontent folder, which the
+/// game searches when launched with `-language tempcontent`
+struct Tempcontent;
+
+impl Installer for Tempcontent {
+    fn install(
+        &self,
+        dota: &DotaInstall,
+        terrain_file: &str,
+        options: &ApplyOptions,
+        _previous: Option<&Manifest>,
+    ) -> Result<(Vec<u8>, Manifest, String), TMError> {
+        let (out_file, manifest) = apply::build(dota, terrain_file, options)?;
+        let hash = options.hash().hash(&out_file);
+        let manifest = install_vpk(&out_file, manifest, &dota.out_vpk(options.base()))?;
+        Ok((out_file, manifest, hash))
+    }
+
+    fn uninstall(
+        &self,
+        dota: &DotaInstall,
+        base: &str,
+        _manifest: &Manifest,
+        keep: &[PathBuf],
+    ) -> io::Result<()> {
+        remove_vpk(dota, &dota.out_vpk(base), keep)
+    }
+
+    fn detect(&self, dota: &DotaInstall, base: &str, _manifest: &Manifest) -> bool {
+        dota.out_vpk(base).exists()
+    }
+
+    fn status(&self, dota: &DotaInstall, base: &str, _manifest: &Manifest) -> String {
+        format!("Applied ({})", dota.out_vpk(base).display())
+    }
+
+    fn output_vpk(&self, dota: &DotaInstall, base: &str) -> Option<PathBuf> {
+        Some(dota.out_vpk(base))
+    }
+
+    fn files(&self, dota: &DotaInstall, base: &str, _manifest: &Manifest) -> Vec<PathBuf> {
+        vec![dota.out_vpk(base)]
+    }
+}
+
+/// `--method loose`: The merged files are written as loose files below the tempcontent
+/// folder, see `loose::install()`. Installing again only writes the files which changed,
+/// which is much faster while tweaking a terrain.
+struct LooseFiles;
+
+impl Installer for LooseFiles {
+    fn install(
+        &self,
+        dota: &DotaInstall,
+        terrain_file: &str,
+        options: &ApplyOptions,
+        _previous: Option<&Manifest>,
+    ) -> Result<(Vec<u8>, Manifest, String), TMError> {
+        let (files, manifest) = apply::merge(dota, terrain_file, options)?;
+        let hash = hash_install(options.hash(), &[], &files);
+        // The output VPK would hide the loose files for its map, whoever wrote it
+        remove_vpk(dota, &dota.out_vpk(options.base()), &[]).map_err(TMError::InternalError)?;
+        let manifest = install_loose(dota, &files, manifest)?;
+        // Loose installs have no output VPK, nor additional outputs
+        Ok((Vec::new(), manifest, hash))
+    }
+
+    fn uninstall(
+        &self,
+        dota: &DotaInstall,
+        _base: &str,
+        manifest: &Manifest,
+        keep: &[PathBuf],
+    ) -> io::Result<()> {
+        let dir = dota.tempcontent_dir();
+        let paths = manifest
+            .loose
+            .iter()
+            .filter(|path| loose::loose_path(&dir, path).is_some_and(|file| !keep.contains(&file)));
+        let removed = loose::remove(&dir, paths)?;
+        if removed > 0 {
+            println!("Removed {} loose file(s)", removed);
+        }
+        Ok(())
+    }
+
+    fn detect(&self, dota: &DotaInstall, base: &str, manifest: &Manifest) -> bool {
+        self.files(dota, base, manifest)
+            .iter()
+            .any(|file| file.exists())
+    }
+
+    fn status(&self, dota: &DotaInstall, _base: &str, _manifest: &Manifest) -> String {
+        format!(
+            "Applied as loose files ({})",
+            dota.tempcontent_dir().display()
+        )
+    }
+
+    fn output_vpk(&self, _dota: &DotaInstall, _base: &str) -> Option<PathBuf> {
+        None
+    }
+
+    fn files(&self, dota: &DotaInstall, _base: &str, manifest: &Manifest) -> Vec<PathBuf> {
+        let dir = dota.tempcontent_dir();
+        manifest
+            .loose
+            .iter()
+            .filter_map(|path| loose::loose_path(&dir, path))
+            .collect()
+    }
+}
+
+/// `--method hybrid`: The base map and the terrain are packed into the output VPK like
+/// `Tempcontent`, and the overrides are installed loose on top of it like `LooseFiles`.
+/// Applying again only packs the VPK again if more than the overrides changed, see
+/// `packed_key()`.
+struct Hybrid;
+
+impl Installer for Hybrid {
+    fn install(
+        &self,
+        dota: &DotaInstall,
+        terrain_file: &str,
+        options: &ApplyOptions,
+        previous: Option<&Manifest>,
+    ) -> Result<(Vec<u8>, Manifest, String), TMError> {
+        let out_path = dota.out_vpk(options.base());
+        let key = packed_key(dota, terrain_file, options);
+        let reused = previous
+            .filter(|previous| previous.packed == key)
+            .and_then(|previous| Some((std::fs::read(&out_path).ok()?, previous.clone())));
+        let (out_file, mut manifest) = match reused {
+            Some((out_file, mut manifest)) => {
+                println!("The packed terrain is unchanged, only the overrides are installed");
+                manifest.remove_source(Source::Overrides);
+                for path in options.overrides().keys() {
+                    manifest.add(path, Source::Overrides);
+                }
+                manifest.loose = options.overrides().keys().cloned().collect();
+                manifest.loose.sort();
+                manifest.options = options.to_args();
+                manifest.stats = BuildStats::default();
+                (out_file, manifest)
+            }
+            None => {
+                let (out_file, manifest) = apply::build(dota, terrain_file, options)?;
+                let manifest = install_vpk(&out_file, manifest, &out_path)?;
+                (out_file, manifest)
+            }
+        };
+        manifest.packed = key;
+        let manifest = install_loose(dota, options.overrides(), manifest)?;
+        let hash = hash_install(options.hash(), &out_file, options.overrides());
+        Ok((out_file, manifest, hash))
+    }
+
+    fn uninstall(
+        &self,
+        dota: &DotaInstall,
+        base: &str,
+        manifest: &Manifest,
+        keep: &[PathBuf],
+    ) -> io::Result<()> {
+        Tempcontent.uninstall(dota, base, manifest, keep)?;
+        LooseFiles.uninstall(dota, base, manifest, keep)
+    }
+
+    fn detect(&self, dota: &DotaInstall, base: &str, manifest: &Manifest) -> bool {
+        Tempcontent.detect(dota, base, manifest)
+    }
+
+    fn status(&self, dota: &DotaInstall, base: &str, manifest: &Manifest) -> String {
+        format!(
+            "Applied with {} loose override(s) ({})",
+            manifest.loose.len(),
+            dota.out_vpk(base).display()
+        )
+    }
+
+    fn output_vpk(&self, dota: &DotaInstall, base: &str) -> Option<PathBuf> {
+        Some(dota.out_vpk(base))
+    }
+
+    fn files(&self, dota: &DotaInstall, base: &str, manifest: &Manifest) -> Vec<PathBuf> {
+        let mut files = Tempcontent.files(dota, base, manifest);
+        files.extend(LooseFiles.files(dota, base, manifest));
+        files
+    }
+}
+
+/// `--method gameinfo`: The output VPK is written to a folder of its own next to the game's
+/// content, e.g. `game/dota_terrain_mod/maps/dota.vpk`, which is added to the search paths in
+/// `gameinfo.gi` ahead of the game's content. The game loads the terrain without the launch
+/// option, but game updates and verifying the game files restore `gameinfo.gi`, after which
+/// the terrain has to be applied again.
+struct GameinfoSearchPath;
+
+impl GameinfoSearchPath {
+    /// Folder the output VPK is written to, next to the game's content folder
+    fn dir_name(dota: &DotaInstall) -> String {
+        format!("{}_terrain_mod", dota.game.content_dir)
+    }
+
+    fn out_vpk(dota: &DotaInstall, base: &str) -> PathBuf {
+        dota.dota_path
+            .join(GameinfoSearchPath::dir_name(dota))
+            .join("maps")
+            .join(format!("{}.vpk", base))
+    }
+
+    fn gameinfo(dota: &DotaInstall) -> PathBuf {
+        dota.content_dir().join("gameinfo.gi")
+    }
+
+    /// Whether `gameinfo.gi` holds the search path added by `add_search_path()`
+    fn has_search_path(dota: &DotaInstall) -> bool {
+        std::fs::read_to_string(GameinfoSearchPath::gameinfo(dota))
+            .is_ok_and(|text| text.contains(SEARCH_PATH_MARKER))
+    }
+
+    /// Add the folder of the output VPK to the search paths in `gameinfo.gi`, before the
+    /// first `Game` search path, so its maps are found ahead of the game's own
+    fn add_search_path(dota: &DotaInstall) -> Result<(), TMError> {
+        let path = GameinfoSearchPath::gameinfo(dota);
+        let text = std::fs::read_to_string(&path).map_err(TMError::InternalError)?;
+        if text.contains(SEARCH_PATH_MARKER) {
+            return Ok(());
+        }
+        let lines: Vec<&str> = text.split_inclusive('\n').collect();
+        let key = |line: &str| {
+            line.split_whitespace()
+                .next()
+                .unwrap_or_default()
+                .to_owned()
+        };
+        let position = lines
+            .iter()
+            .position(|line| {
+                key(line)
+                    .trim_matches('"')
+                    .eq_ignore_ascii_case("SearchPaths")
+            })
+            .and_then(|start| {
+                lines[start..]
+                    .iter()
+                    .position(|line| key(line).trim_matches('"').eq_ignore_ascii_case("Game"))
+                    .map(|offset| start + offset)
+            })
+            .ok_or_else(|| {
+                TMError::InternalError(io::Error::other(format!(
+                    "{} has no Game search path",
+                    path.display()
+                )))
+            })?;
+        let line = lines[position];
+        let indent = &line[..line.len() - line.trim_start().len()];
+        let ending = if line.ends_with("\r\n") { "\r\n" } else { "\n" };
+        let added = format!(
+            "{}Game\t\t\t\t{}\t{}{}",
+            indent,
+            GameinfoSearchPath::dir_name(dota),
+            SEARCH_PATH_MARKER,
+            ending
+        );
+        let mut updated = lines[..position].concat();
+        updated.push_str(&added);
+        updated.push_str(&lines[position..].concat());
+        journal::record(&path).map_err(TMError::InternalError)?;
+        utils::retry_locked(&path, || std::fs::write(&path, &updated))
+            .map_err(TMError::InternalError)?;
+        println!(
+            "Added the search path {} to {}",
+            GameinfoSearchPath::dir_name(dota),
+            path.display()
+        );
+        Ok(())
+    }
+
+    /// Remove the search path added by `add_search_path()` from `gameinfo.gi`
+    fn remove_search_path(dota: &DotaInstall) -> io::Result<()> {
+        let path = GameinfoSearchPath::gameinfo(dota);
+        let text = std::fs::read_to_string(&path)?;
+        if !text.contains(SEARCH_PATH_MARKER) {
+            return Ok(());
+        }
+        let updated: String = text
+            .split_inclusive('\n')
+            .filter(|line| !line.contains(SEARCH_PATH_MARKER))
+            .collect();
+        journal::record(&path)?;
+        utils::retry_locked(&path, || std::fs::write(&path, &updated))?;
+        println!("Removed the search path from {}", path.display());
+        Ok(())
+    }
+}
+
+impl Installer for GameinfoSearchPath {
+    fn install(
+        &self,
+        dota: &DotaInstall,
+        terrain_file: &str,
+        options: &ApplyOptions,
+        _previous: Option<&Manifest>,
+    ) -> Result<(Vec<u8>, Manifest, String), TMError> {
+        let (out_file, manifest) = apply::build(dota, terrain_file, options)?;
+        let hash = options.hash().hash(&out_file);
+        let out_path = GameinfoSearchPath::out_vpk(dota, options.base());
+        let manifest = install_vpk(&out_file, manifest, &out_path)?;
+        GameinfoSearchPath::add_search_path(dota)?;
+        Ok((out_file, manifest, hash))
+    }
+
+    fn uninstall(
+        &self,
+        dota: &DotaInstall,
+        base: &str,
+        _manifest: &Manifest,
+        keep: &[PathBuf],
+    ) -> io::Result<()> {
+        if !keep.contains(&GameinfoSearchPath::gameinfo(dota)) {
+            GameinfoSearchPath::remove_search_path(dota)?;
+        }
+        remove_vpk(dota, &GameinfoSearchPath::out_vpk(dota, base), keep)
+    }
+
+    fn detect(&self, dota: &DotaInstall, base: &str, _manifest: &Manifest) -> bool {
+        GameinfoSearchPath::out_vpk(dota, base).exists()
+            || GameinfoSearchPath::has_search_path(dota)
+    }
+
+    fn status(&self, dota: &DotaInstall, base: &str, _manifest: &Manifest) -> String {
+        let out_path = GameinfoSearchPath::out_vpk(dota, base);
+        if GameinfoSearchPath::has_search_path(dota) {
+            format!(
+                "Applied through a search path in gameinfo.gi ({})",
+                out_path.display()
+            )
+        } else {
+            format!(
+                "Applied to {}, but its search path is gone from gameinfo.gi, likely restored \
+                by a game update. Run `reapply` to add it again",
+                out_path.display()
+            )
+        }
+    }
+
+    fn output_vpk(&self, dota: &DotaInstall, base: &str) -> Option<PathBuf> {
+        Some(GameinfoSearchPath::out_vpk(dota, base))
+    }
+
+    fn files(&self, dota: &DotaInstall, base: &str, _manifest: &Manifest) -> Vec<PathBuf> {
+        vec![
+            GameinfoSearchPath::out_vpk(dota, base),
+            GameinfoSearchPath::gameinfo(dota),
+        ]
+    }
+
+    fn needs_launch_option(&self) -> bool {
+        false
+    }
+}
+
+/// Finish an install which `installer` wrote for the base map `base` after `journal::begin()`:
+/// the install described by `previous`, which the game would still load on top of this one,
+/// is removed except for the files of the new install, `manifest` is written where the other
+/// commands look for it and the journal is committed
+pub(crate) fn finish(
+    dota: &DotaInstall,
+    base: &str,
+    installer: &dyn Installer,
+    manifest: &Manifest,
+    previous: Option<Manifest>,
+) {
+    if let Some(previous) = previous {
+        let keep = installer.files(dota, base, manifest);
+        if let Err(e) = previous
+            .method
+            .installer()
+            .uninstall(dota, base, &previous, &keep)
+        {
+            eprintln!("Warning: Could not remove the previous install: {}", e);
+        }
+    }
+    // Every method keeps its manifest where the output VPK of the default method is
+    let out_path = dota.out_vpk(base);
+    let written = journal::record(&manifest::manifest_path(&out_path))
+        .and_then(|_| manifest.write(&out_path));
+    if let Err(e) = written {
+        eprintln!("Warning: Could not write the manifest: {}", e);
+    }
+    journal::commit();
+}
+
+/// Install `out_file`, the output VPK of a finished build described by `manifest`, e.g. one
+/// from `build --archive`, for the base map `base` with `--method vpk`. Like
+/// `apply::apply()`, the previous install of any method is replaced within the journal, as
+/// `operation`. Returns the manifest written next to the output.
+pub(crate) fn install_built(
+    dota: &DotaInstall,
+    base: &str,
+    out_file: &[u8],
+    mut manifest: Manifest,
+    operation: &str,
+) -> Result<Manifest, TMError> {
+    let out_path = dota.out_vpk(base);
+    let previous = Manifest::read(&out_path).ok();
+    manifest.method = InstallMethod::Vpk;
+    journal::begin(&dota.dota_path, operation).map_err(TMError::InternalError)?;
+    let manifest = match install_vpk(out_file, manifest, &out_path) {
+        Ok(manifest) => manifest,
+        Err(e) => {
+            journal::rollback();
+            return Err(e);
+        }
+    };
+    finish(dota, base, &Tempcontent, &manifest, previous);
+    Ok(manifest)
+}
+
+/// Remove the output VPK at `out_path` unless it is in `keep`, and the folders it leaves
+/// empty in the game folder. Missing outputs are skipped.
+fn remove_vpk(dota: &DotaInstall, out_path: &Path, keep: &[PathBuf]) -> io::Result<()> {
+    if keep.iter().any(|file| file == out_path) {
+        return Ok(());
+    }
+    if !out_path.exists() {
+        return Ok(());
+    }
+    // During an install, recording the output moves it aside already
+    journal::record(out_path)?;
+    match utils::retry_locked(out_path, || std::fs::remove_file(out_path)) {
+        Ok(()) => {}
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+        Err(e) => return Err(e),
+    }
+    println!("Removed {}", out_path.display());
+    utils::remove_empty_dirs(out_path.parent().unwrap(), &dota.dota_path);
+    Ok(())
+}
+
+/// Write the output VPK `out_file` to `out_path`. Returns `manifest` with the time writing
+/// took.
+fn install_vpk(
+    out_file: &[u8],
+    mut manifest: Manifest,
+    out_path: &Path,
+) -> Result<Manifest, TMError> {
+    crash::phase(&format!("writing {}", out_path.display()));
+    let writing = Instant::now();
+    std::fs::create_dir_all(out_path.parent().unwrap()).map_err(TMError::InternalError)?;
+    journal::record(out_path).map_err(TMError::InternalError)?;
+    // The game may hold the previous output open while running, only this write waits for it
+    utils::retry_locked(out_path, || std::fs::write(out_path, out_file))
+        .map_err(TMError::InternalError)?;
+    manifest.stats.add("write", writing.elapsed());
+    Ok(manifest)
+}
+
+/// Install `files` as loose files below the tempcontent folder, see `loose::install()`.
+/// Returns `manifest` with the time writing took.
+fn install_loose(
+    dota: &DotaInstall,
+    files: &HashMap<String, Vec<u8>>,
+    mut manifest: Manifest,
+) -> Result<Manifest, TMError> {
+    if files.is_empty() {
+        return Ok(manifest);
+    }
+    let dir = dota.tempcontent_dir();
+    crash::phase(&format!("writing loose files to {}", dir.display()));
+    let writing = Instant::now();
+    let written = loose::install(&dir, files).map_err(TMError::InternalError)?;
+    println!(
+        "Wrote {} of {} loose file(s) to {}, the others were unchanged",
+        written,
+        files.len(),
+        dir.display()
+    );
+    manifest.stats.add("loose", writing.elapsed());
+    Ok(manifest)
+}
+
+/// Key of everything the output VPK of a hybrid install is built from besides the overrides:
+/// the program, the game build, the terrain, the options and rules, and the size and
+/// modification time of the base map and the terrain. Applying again reuses the output VPK
+/// while the key matches.
+fn packed_key(dota: &DotaInstall, terrain_file: &str, options: &ApplyOptions) -> String {
+    let stamp = |path: &Path| {
+        std::fs::metadata(path).map_or_else(
+            |_| String::new(),
+            |metadata| format!("{} {:?}", metadata.len(), metadata.modified().ok()),
+        )
+    };
+    let args: Vec<String> = options
+        .to_args()
+        .into_iter()
+        .filter(|arg| !arg.starts_with("--overrides "))
+        .collect();
+    let key = [
+        manifest::tool_version(),
+        dota.build_id().unwrap_or_default(),
+        terrain_file.to_owned(),
+        args.join(" "),
+        format!("{:?}", options.rules()),
+        stamp(&dota.base_vpk(options.base())),
+        stamp(&menu::terrain_vpk(dota, terrain_file)),
+    ]
+    .join("\n");
+    HashAlgorithm::Md5.hash(key.as_bytes())
+}
+
+/// Hash of the output VPK `packed` and the loose `files` of an install, as recorded in the
+/// history
+fn hash_install(
+    algorithm: HashAlgorithm,
+    packed: &[u8],
+    files: &HashMap<String, Vec<u8>>,
+) -> String {
+    let mut sorted: Vec<(&String, &Vec<u8>)> = files.iter().collect();
+    sorted.sort();
+    let parts: Vec<&[u8]> = std::iter::once(packed)
+        .chain(
+            sorted
+                .iter()
+                .flat_map(|(path, data)| [path.as_bytes(), data.as_slice()]),
+        )
+        .collect();
+    algorithm.hash_all(&parts)
+}
@@ -0,0 +1,255 @@
+use std::collections::BTreeSet;
+use std::fs::{DirBuilder, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+use crate::archive::{self, ArchiveFormat};
+use crate::cli::Args;
+use crate::compare_install;
+use crate::install;
+use crate::manifest::{self, Manifest};
+use crate::utils::{self, DotaInstall};
+use crate::verify;
+use crate::vpk::VPK;
+
+/// Write `data` to a new file in a new directory of the temporary folder, only accessible by
+/// this user on Unix, so nothing another user placed there, e.g. a symlink, is written
+/// through. Returns the path of the file, whose directory is removed by the caller.
+fn write_private(data: &[u8]) -> io::Result<PathBuf> {
+    let mut random = [0; 8];
+    getrandom::fill(&mut random)?;
+    let dir = std::env::temp_dir().join(format!(
+        "dota-terrain-mod-{}-{:016x}",
+        std::process::id(),
+        u64::from_le_bytes(random)
+    ));
+    let mut builder = DirBuilder::new();
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::DirBuilderExt;
+        builder.mode(0o700);
+    }
+    // Fails if the directory exists, instead of using one created by someone else
+    builder.create(&dir)?;
+    let path = dir.join("check.vpk");
+    OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(&path)
+        .and_then(|mut file| file.write_all(data))?;
+    Ok(path)
+}
+
+/// Check the VPK of an archived build, written to `path`, against its hashes and `manifest`.
+/// Returns a description of every problem found.
+fn check_vpk(path: &Path, manifest: &Manifest) -> Vec<String> {
+    let mut vpk = match VPK::open(path.to_path_buf()) {
+        Ok(vpk) => vpk,
+        Err(e) => return vec![format!("Invalid VPK: {}", e)],
+    };
+    let mut problems: Vec<String> = vpk.issues().to_vec();
+    problems.extend(
+        vpk.duplicates()
+            .iter()
+            .map(|path| format!("{} (listed more than once)", path)),
+    );
+    problems.extend(verify::crc_mismatches(&mut vpk));
+    match vpk.verify_hashes() {
+        Ok(Some(matches)) => {
+            for (name, matches) in ["Tree", "Chunk hashes", "File"].iter().zip(matches) {
+                if !matches {
+                    problems.push(format!("{} MD5 mismatch", name));
+                }
+            }
+        }
+        Ok(None) => problems.push(String::from("No self hashes section")),
+        Err(e) => problems.push(format!("Failed to read hash sections: {}", e)),
+    }
+
+    let listed: BTreeSet<&String> = manifest.entries.keys().collect();
+    let packed: BTreeSet<&String> = vpk.entries().map(|(path, _)| path).collect();
+    problems.extend(
+        packed
+            .difference(&listed)
+            .map(|path| format!("{} (not in the manifest)", path)),
+    );
+    problems.extend(
+        listed
+            .difference(&packed)
+            .map(|path| format!("{} (missing from the VPK)", path)),
+    );
+    problems
+}
+
+/// Entry point for the `install-archive <archive> [--yes]` command. Installs a build archived
+/// with `build --archive`, e.g. one a friend built, into the game folder. Before anything is
+/// copied, the archive must hold a single output VPK named after a base map of the game along
+/// with its manifest, every entry must match its CRC and the archive its MD5 hashes, and the
+/// manifest must list exactly the files of the VPK. The files the build copied from the base
+/// map are then compared with the base map in the game folder, see `compare_install`; if the
+/// game was updated since, or the build was made for another game build, installing needs
+/// confirmation. The build is installed with `--method vpk`, replacing the previous install of
+/// any method as `apply` does. It is not added to the history, since it cannot be rebuilt
+/// here.
+pub(crate) fn run(args: &Args) {
+    let [archive_path] = args.positional.as_slice() else {
+        eprintln!("Usage: dota-terrain-mod install-archive <archive> [--yes]");
+        std::process::exit(1);
+    };
+    let fail = |message: String| -> ! {
+        eprintln!("Dota-Terrain-Mod error: {}", message);
+        std::process::exit(1);
+    };
+    let archive_path = Path::new(archive_path);
+    let Some(format) = ArchiveFormat::from_path(archive_path) else {
+        fail(format!(
+            "Unsupported archive {}, use a .tar or .tar.xz file",
+            archive_path.display()
+        ));
+    };
+    let dota = match DotaInstall::new(args) {
+        Ok(dota) => dota,
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+    };
+
+    let files = archive::read(archive_path, format)
+        .unwrap_or_else(|e| fail(format!("Could not read {}: {}", archive_path.display(), e)));
+    let vpks: Vec<&(String, Vec<u8>)> = files
+        .iter()
+        .filter(|(name, _)| name.to_lowercase().ends_with(".vpk"))
+        .collect();
+    let [(vpk_name, vpk_data)] = vpks.as_slice() else {
+        fail(format!(
+            "{} must hold exactly one VPK, it holds {}",
+            archive_path.display(),
+            vpks.len()
+        ));
+    };
+    let base = vpk_name[..vpk_name.len() - ".vpk".len()].to_owned();
+    if base.contains(['/', '\\']) || !dota.available_bases().contains(&base) {
+        fail(format!(
+            "{} is not named after a base map of the game",
+            vpk_name
+        ));
+    }
+    let manifest_name = manifest::manifest_name(Path::new(vpk_name.as_str()));
+    let Some((_, manifest_json)) = files.iter().find(|(name, _)| *name == manifest_name) else {
+        fail(format!(
+            "{} holds no manifest ({}), it was not made with build --archive",
+            archive_path.display(),
+            manifest_name
+        ));
+    };
+    let mut manifest = std::str::from_utf8(manifest_json)
+        .map_err(|e| e.to_string())
+        .and_then(|json| Manifest::from_json_str(json).map_err(|e| e.to_string()))
+        .unwrap_or_else(|e| fail(format!("Invalid manifest {}: {}", manifest_name, e)));
+
+    println!("Terrain: {}", manifest.terrain);
+    if !manifest.user.is_empty() {
+        println!("Built by: {}", manifest.user);
+    }
+    if !manifest.tool.is_empty() {
+        println!("Built with: {}", manifest.tool);
+    }
+
+    // The VPK is only read from disk, so it is checked in a temporary file first
+    let problems = match write_private(vpk_data) {
+        Ok(check_path) => {
+            let problems = check_vpk(&check_path, &manifest);
+            let _ = std::fs::remove_dir_all(check_path.parent().unwrap());
+            problems
+        }
+        Err(e) => fail(format!(
+            "Could not write a temporary copy of {}: {}",
+            vpk_name, e
+        )),
+    };
+    if !problems.is_empty() {
+        eprintln!("Problems found in {}:", vpk_name);
+        for problem in &problems {
+            eprintln!("  {}", problem);
+        }
+        fail(format!(
+            "{} failed verification: {} problem(s) found, nothing was installed",
+            archive_path.display(),
+            problems.len()
+        ));
+    }
+    println!("Hashes: OK");
+
+    let mut compatible = true;
+    if let Some(build_id) = dota.build_id() {
+        if !manifest.build.is_empty() && manifest.build != build_id {
+            println!(
+                "Game build: built for {}, the game is at {}",
+                manifest.build, build_id
+            );
+            compatible = false;
+        }
+    }
+    match compare_install::compare_manifest(&manifest, dota.base_vpk(&base)) {
+        Ok(comparison) if comparison.is_unchanged() => {
+            println!("Base map: matches the game folder");
+        }
+        Ok(comparison) => {
+            compatible = false;
+            if comparison.touches_replaced() {
+                println!(
+                    "Base map: {} file(s) the terrain replaces changed or were removed since \
+                    the build, the terrain may look broken",
+                    comparison.changed.len() + comparison.removed.len()
+                );
+            }
+            if comparison.outdated > 0 || comparison.added > 0 {
+                println!(
+                    "Base map: {} copied file(s) are outdated or removed, {} new file(s) are \
+                    missing",
+                    comparison.outdated, comparison.added
+                );
+            }
+        }
+        Err(e) => {
+            compatible = false;
+            println!("Base map: could not be compared, {}", e);
+        }
+    }
+    if !compatible
+        && !utils::confirm(
+            "The build does not match this version of the game. Install it anyway?",
+            args.assume_yes(),
+        )
+    {
+        std::process::exit(1);
+    }
+
+    let out_path = dota.out_vpk(&base);
+    if let Some(warning) = manifest::other_user_warning(&out_path) {
+        eprintln!("{}", warning);
+    }
+    if out_path.exists()
+        && !utils::confirm(
+            &format!("{} already exists. Replace it?", out_path.display()),
+            args.assume_yes(),
+        )
+    {
+        std::process::exit(1);
+    }
+    // The output is installed by this user, whoever built it
+    manifest.user = utils::os_user().unwrap_or_default();
+    // A previous install of another method would still be loaded on top of this one, it is
+    // replaced like by `apply`, see `install::install_built()`
+    let operation = format!("installing {}", archive_path.display());
+    let manifest = install::install_built(&dota, &base, vpk_data, manifest, &operation)
+        .unwrap_or_else(|e| {
+            fail(format!(
+                "Could not install {}: {}",
+                archive_path.display(),
+                e
+            ))
+        });
+    println!("Installed {} to {}.", manifest.terrain, out_path.display());
+}
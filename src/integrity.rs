@@ -0,0 +1,207 @@
+use std::{
+    collections::BTreeMap,
+    path::PathBuf,
+    thread,
+    time::{Duration, SystemTime},
+};
+
+use crate::apply::{self, ApplyOptions};
+use crate::cli::Args;
+use crate::config::Config;
+use crate::manifest::{self, Manifest};
+use crate::utils::{self, DotaInstall};
+use crate::{history, notify, schedule, steam_state};
+
+/// Time between checks of the installed terrain with `apply --watch`
+const WATCH_INTERVAL: Duration = Duration::from_secs(2);
+/// How long the installed terrain has to stay unchanged before a change is acted on, unless
+/// set with `--quiet-period` or `watch_quiet_period`. Steam writes the files of an update in
+/// bursts, often minutes apart.
+pub(crate) const DEFAULT_QUIET_PERIOD: Duration = Duration::from_secs(30);
+
+/// Title of the notifications about the installed terrain
+const TITLE: &str = "Dota Terrain Mod";
+
+/// Modification time and size of every file of an install, `None` for missing files
+type Snapshot = BTreeMap<PathBuf, Option<(SystemTime, u64)>>;
+
+/// Every file the install described by `manifest` consists of, its manifest included, see
+/// `install::Installer::files()`
+fn install_files(dota: &DotaInstall, options: &ApplyOptions, manifest: &Manifest) -> Vec<PathBuf> {
+    let mut files = vec![manifest::manifest_path(&dota.out_vpk(options.base()))];
+    files.extend(
+        options
+            .method()
+            .installer()
+            .files(dota, options.base(), manifest),
+    );
+    files
+}
+
+/// The current state of `files`
+fn snapshot(files: &[PathBuf]) -> Snapshot {
+    files
+        .iter()
+        .map(|file| {
+            let state = std::fs::metadata(file)
+                .ok()
+                .and_then(|metadata| Some((metadata.modified().ok()?, metadata.len())));
+            (file.clone(), state)
+        })
+        .collect()
+}
+
+/// Description of the files which differ between the snapshots `before` and `after`, e.g.
+/// `2 file(s) deleted, 1 overwritten (.../maps/dota.vpk)`
+fn describe_changes(before: &Snapshot, after: &Snapshot) -> String {
+    let changed: Vec<(&PathBuf, bool)> = before
+        .iter()
+        .filter(|(file, state)| after.get(*file) != Some(*state))
+        .map(|(file, _)| (file, after.get(file).is_some_and(Option::is_some)))
+        .collect();
+    let overwritten = changed.iter().filter(|(_, exists)| *exists).count();
+    format!(
+        "{} file(s) deleted, {} overwritten ({})",
+        changed.len() - overwritten,
+        overwritten,
+        changed
+            .first()
+            .map_or_else(String::new, |(file, _)| file.display().to_string())
+    )
+}
+
+/// The terrain the `[[schedule]]` rules of `config` select for the day `today`, see
+/// `history::local_today()`, with its options from `args` and `config`. `None` if no rule
+/// matches or the terrain is `applied` already. Problems with the rules are printed.
+fn scheduled_terrain(
+    args: &Args,
+    config: &Config,
+    today: i64,
+    applied: &str,
+) -> Option<(String, ApplyOptions)> {
+    let terrain = match schedule::scheduled(&config.schedule, today, &config.menu)? {
+        Ok(terrain) => terrain,
+        Err(e) => {
+            eprintln!("Warning: Could not follow the schedule: {}", e);
+            return None;
+        }
+    };
+    if terrain.file == applied {
+        return None;
+    }
+    match ApplyOptions::from_args(args, config, &terrain.file) {
+        Ok(options) => Some((terrain.file, options)),
+        Err(e) => {
+            eprintln!("Warning: Could not follow the schedule: {}", e);
+            None
+        }
+    }
+}
+
+/// Keep checking the install of `terrain_file`, applied with `options` and described by
+/// `manifest`, every `WATCH_INTERVAL` until the program is stopped. If another program, such
+/// as Steam verifying the game files or updating the game, deletes or overwrites any of its
+/// files, the user is notified once the files stayed unchanged for `quiet_period`. With
+/// `--auto-reapply`, the terrain is applied again instead, once Steam is done with the game.
+/// Every change until then, however many, leads to a single rebuild.
+/// Whenever the local date changes, the `[[schedule]]` rules of `config` are evaluated like
+/// the `schedule` command does, and the terrain they select is applied with the options of
+/// `args` and watched from then on.
+pub(crate) fn watch(
+    dota: &DotaInstall,
+    args: &Args,
+    config: &Config,
+    terrain_file: &str,
+    options: ApplyOptions,
+    manifest: Manifest,
+    quiet_period: Duration,
+) -> ! {
+    println!("Watching the installed terrain for changes by other programs, press Ctrl+C to stop.");
+    if !config.schedule.is_empty() {
+        println!("The terrain is switched when the [[schedule]] rules select another one.");
+    }
+    let auto_reapply = args.flag("auto-reapply");
+    let mut terrain_file = terrain_file.to_owned();
+    let mut options = options;
+    let mut files = install_files(dota, &options, &manifest);
+    let mut last = snapshot(&files);
+    let mut day = history::local_today();
+    loop {
+        thread::sleep(WATCH_INTERVAL);
+        let today = history::local_today();
+        // Evaluated again once Steam is done, the base map may be replaced until then
+        if today != day && steam_state::defer_reason(dota).is_none() {
+            day = today;
+            if let Some((file, next)) = scheduled_terrain(args, config, today, &terrain_file) {
+                println!("\nThe schedule selects {} for today, applying it...", file);
+                match apply::apply(dota, &file, &next) {
+                    Ok(manifest) => {
+                        files = install_files(dota, &next, &manifest);
+                        terrain_file = file;
+                        options = next;
+                        println!("Done.");
+                    }
+                    Err(e) => notify::show(
+                        TITLE,
+                        &format!("{} could not be applied as scheduled: {}", file, e),
+                    ),
+                }
+                last = snapshot(&files);
+                continue;
+            }
+        }
+        let current = snapshot(&files);
+        if current == last {
+            continue;
+        }
+        // Wait until the other program is done, e.g. Steam still downloading an update
+        println!(
+            "\n{} is being changed by another program, waiting until it is left alone for {} s...",
+            terrain_file,
+            quiet_period.as_secs()
+        );
+        let current =
+            utils::wait_for_quiet(current, quiet_period, WATCH_INTERVAL, || snapshot(&files));
+        let changes = describe_changes(&last, &current);
+
+        if !auto_reapply {
+            notify::show(
+                TITLE,
+                &format!(
+                    "{} was changed by another program: {}. Apply it again to restore it, or \
+                    pass --auto-reapply to do so automatically.",
+                    terrain_file, changes
+                ),
+            );
+            last = current;
+            continue;
+        }
+        notify::log(&format!(
+            "{} was changed by another program: {}, applying it again",
+            terrain_file, changes
+        ));
+        println!(
+            "{} was changed by another program: {}. Applying it again...",
+            terrain_file, changes
+        );
+        // Steam verifying or updating the game replaces the base map as well
+        if let Some(reason) = steam_state::defer_reason(dota) {
+            println!("{}, waiting for it to finish...", reason);
+            while steam_state::defer_reason(dota).is_some() {
+                thread::sleep(WATCH_INTERVAL);
+            }
+        }
+        // Changes made while waiting for Steam are covered by the same rebuild
+        match apply::apply(dota, &terrain_file, &options) {
+            Ok(manifest) => {
+                files = install_files(dota, &options, &manifest);
+                println!("Done.");
+            }
+            Err(e) => notify::show(
+                TITLE,
+                &format!("{} could not be applied again: {}", terrain_file, e),
+            ),
+        }
+        last = snapshot(&files);
+    }
+}
@@ -0,0 +1,288 @@
+use std::{
+    fs::{self, File, OpenOptions},
+    io::{self, BufRead, BufReader, Write},
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+
+use serde_json::{json, Value};
+
+use crate::{config, history, utils};
+
+/// File of the journal in the configuration directory
+const JOURNAL_FILE: &str = "journal.jsonl";
+/// Suffix of the previous versions of the files changed by an operation, kept next to them
+/// until it is done
+const BACKUP_SUFFIX: &str = "journal-backup";
+
+/// An operation changing several files of the game folder, which either completes or is
+/// undone as a whole. Before a file is changed or removed, it is moved aside next to itself
+/// and the journal records it, so the previous state can be restored if the program stops
+/// midway, see `recover()`.
+/// # Properties
+/// - `path: PathBuf` = Journal file, one JSON object per line: the operation first, then
+///   every file changed, written before the file is touched
+/// - `file: File` = The journal file, open for appending
+/// - `root: PathBuf` = Folder the operation changes, folders it leaves empty are removed up to
+///   it
+/// - `changed: Vec<PathBuf>` = Every file recorded so far
+struct Journal {
+    path: PathBuf,
+    file: File,
+    root: PathBuf,
+    changed: Vec<PathBuf>,
+}
+
+/// The operation in progress, if any
+static CURRENT: Mutex<Option<Journal>> = Mutex::new(None);
+
+/// Path of the previous version of `path` while an operation changes it
+fn backup_path(path: &Path) -> PathBuf {
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(".");
+    name.push(BACKUP_SUFFIX);
+    path.with_file_name(name)
+}
+
+/// Start an operation described by `operation`, e.g. `applying dota_desert.vpk`, changing the
+/// files below `root`, such as the game folder. Files changed until `commit()` or
+/// `rollback()` are recorded with `record()`. Without a configuration directory to keep the
+/// journal in, nothing is recorded. Fails if another running instance of the program is in
+/// the middle of an operation, whose journal must not be replaced.
+pub(crate) fn begin(root: &Path, operation: &str) -> io::Result<()> {
+    let Some(dir) = config::config_dir() else {
+        return Ok(());
+    };
+    let path = dir.join(JOURNAL_FILE);
+    let started = fs::create_dir_all(&dir).and_then(|_| create(&path, operation));
+    match started {
+        Ok(file) => {
+            *CURRENT.lock().unwrap_or_else(|e| e.into_inner()) = Some(Journal {
+                path,
+                file,
+                root: root.to_path_buf(),
+                changed: Vec::new(),
+            })
+        }
+        Err(e) if e.kind() == io::ErrorKind::AlreadyExists => return Err(e),
+        Err(e) => eprintln!(
+            "Warning: Could not start the journal, an interrupted install cannot be undone: {}",
+            e
+        ),
+    }
+    Ok(())
+}
+
+/// Create the journal at `path` for `operation` and write its header. A journal left by a
+/// run which stopped midway is undone first, see `recover()`. Fails with
+/// `io::ErrorKind::AlreadyExists` if the journal belongs to another running instance.
+fn create(path: &Path, operation: &str) -> io::Result<File> {
+    let open = || OpenOptions::new().write(true).create_new(true).open(path);
+    let mut file = match open() {
+        Err(e) if e.kind() == io::ErrorKind::AlreadyExists => {
+            if let Some(pid) = owner(path) {
+                return Err(io::Error::new(
+                    io::ErrorKind::AlreadyExists,
+                    format!(
+                        "another instance of the program (process {}) is installing right \
+                        now, wait for it to finish",
+                        pid
+                    ),
+                ));
+            }
+            undo(path)?;
+            open()?
+        }
+        opened => opened?,
+    };
+    let header = json!({
+        "operation": operation,
+        "started": history::now(),
+        "pid": std::process::id(),
+    });
+    writeln!(file, "{}", header)?;
+    file.sync_data()?;
+    Ok(file)
+}
+
+/// The ID of the process the journal at `path` belongs to, if it is another one which is still
+/// running, e.g. `apply --watch`
+fn owner(path: &Path) -> Option<u32> {
+    let text = fs::read_to_string(path).ok()?;
+    let header: Value = serde_json::from_str(text.lines().next()?).ok()?;
+    let pid = u32::try_from(header.get("pid")?.as_u64()?).ok()?;
+    (pid != std::process::id() && utils::pid_running(pid)).then_some(pid)
+}
+
+/// Record that the file at `path` is about to be written or removed by the operation in
+/// progress. An existing file is moved aside, so the caller writes a new file, and removing
+/// it is already done. Files recorded before are left as they are, so the version from before
+/// the operation is kept. Outside of an operation, nothing happens.
+pub(crate) fn record(path: &Path) -> io::Result<()> {
+    let mut current = CURRENT.lock().unwrap_or_else(|e| e.into_inner());
+    let Some(journal) = current.as_mut() else {
+        return Ok(());
+    };
+    if journal.changed.iter().any(|changed| changed == path) {
+        return Ok(());
+    }
+    let backup = path.exists().then(|| backup_path(path));
+    // Written ahead of the change, an entry cut short means the file was not touched yet
+    writeln!(
+        journal.file,
+        "{}",
+        json!({ "path": path, "backup": backup.as_deref() })
+    )?;
+    journal.file.sync_data()?;
+    journal.changed.push(path.to_path_buf());
+    if let Some(backup) = &backup {
+        // The game may hold the file open while running
+        utils::retry_locked(path, || fs::rename(path, backup))?;
+    }
+    Ok(())
+}
+
+/// Finish the operation in progress: the previous versions of the changed files are removed
+/// along with the journal, and with them the folders of removed files which are empty now
+pub(crate) fn commit() {
+    let Some(journal) = CURRENT.lock().unwrap_or_else(|e| e.into_inner()).take() else {
+        return;
+    };
+    for path in &journal.changed {
+        let _ = fs::remove_file(backup_path(path));
+        if !path.exists() {
+            if let Some(dir) = path.parent() {
+                utils::remove_empty_dirs(dir, &journal.root);
+            }
+        }
+    }
+    drop(journal.file);
+    if let Err(e) = fs::remove_file(&journal.path) {
+        eprintln!("Warning: Could not remove the journal: {}", e);
+    }
+}
+
+/// Undo the operation in progress, e.g. after it failed: every changed file is restored to
+/// its previous version, or removed if it did not exist before
+pub(crate) fn rollback() {
+    let Some(journal) = CURRENT.lock().unwrap_or_else(|e| e.into_inner()).take() else {
+        return;
+    };
+    drop(journal.file);
+    match undo(&journal.path) {
+        Ok((_, restored)) => println!("Undid the changes to {} file(s)", restored),
+        Err(e) => eprintln!("Warning: Could not undo the changes: {}", e),
+    }
+}
+
+/// Undo the operation recorded in the journal at `path`, the last change first, and remove
+/// the journal. Returns the description of the operation and the number of files restored.
+fn undo(path: &Path) -> io::Result<(String, usize)> {
+    let mut lines = BufReader::new(File::open(path)?).lines();
+    let operation = lines
+        .next()
+        .transpose()?
+        .and_then(|line| serde_json::from_str::<Value>(&line).ok())
+        .and_then(|header| Some(header.get("operation")?.as_str()?.to_owned()))
+        .unwrap_or_default();
+    let changes: Vec<(PathBuf, Option<PathBuf>)> = lines
+        .map_while(Result::ok)
+        .filter_map(|line| {
+            let change: Value = serde_json::from_str(&line).ok()?;
+            let path = PathBuf::from(change.get("path")?.as_str()?);
+            let backup = change.get("backup")?.as_str().map(PathBuf::from);
+            Some((path, backup))
+        })
+        .collect();
+    let mut restored = 0;
+    for (path, backup) in changes.iter().rev() {
+        match backup {
+            // Moved aside unless the program stopped right after recording it
+            Some(backup) if backup.exists() => {
+                utils::retry_locked(path, || fs::rename(backup, path))?
+            }
+            Some(_) => continue,
+            None => match fs::remove_file(path) {
+                Ok(()) => {}
+                Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+                Err(e) => return Err(e),
+            },
+        }
+        restored += 1;
+    }
+    fs::remove_file(path)?;
+    Ok((operation, restored))
+}
+
+/// Undo an operation a previous run left unfinished, e.g. because it crashed or was killed
+/// while installing a terrain, so the game folder is never left half-modified. Called on
+/// every start.
+pub(crate) fn recover() {
+    let Some(path) = config::config_dir().map(|dir| dir.join(JOURNAL_FILE)) else {
+        return;
+    };
+    if !path.exists() {
+        return;
+    }
+    // Another instance may still be in the middle of the operation
+    if owner(&path).is_some() {
+        return;
+    }
+    match undo(&path) {
+        Ok((operation, restored)) => eprintln!(
+            "Note: The previous run stopped while {}, its changes to {} file(s) were undone.",
+            operation, restored
+        ),
+        Err(e) => eprintln!(
+            "Warning: The previous run stopped midway and its changes could not be undone: \
+            {}. Run `panic-button` to restore the game folder.",
+            e
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::process::{Command, Stdio};
+
+    /// A process which keeps running for a while, standing in for another instance
+    fn other_instance() -> std::process::Child {
+        #[cfg(windows)]
+        let mut command = Command::new("ping");
+        #[cfg(windows)]
+        command.args(["-n", "60", "127.0.0.1"]);
+        #[cfg(not(windows))]
+        let mut command = Command::new("sleep");
+        #[cfg(not(windows))]
+        command.arg("60");
+        command.stdout(Stdio::null()).spawn().unwrap()
+    }
+
+    #[test]
+    fn journal_of_a_running_instance_is_kept() {
+        let dir =
+            std::env::temp_dir().join(format!("dota-terrain-mod-journal-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join(JOURNAL_FILE);
+        let mut other = other_instance();
+        let journal = format!(
+            "{}\n",
+            json!({ "operation": "applying dota_desert.vpk", "started": 0, "pid": other.id() })
+        );
+        fs::write(&path, &journal).unwrap();
+
+        let error = create(&path, "applying dota_winter.vpk").unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::AlreadyExists);
+        assert!(error.to_string().contains(&other.id().to_string()));
+        assert_eq!(fs::read_to_string(&path).unwrap(), journal);
+
+        // Once the other instance is gone, what it left is undone and replaced
+        other.kill().unwrap();
+        other.wait().unwrap();
+        drop(create(&path, "applying dota_winter.vpk").unwrap());
+        let text = fs::read_to_string(&path).unwrap();
+        assert!(text.contains("dota_winter"), "{}", text);
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,60 @@
+use std::path::Path;
+
+use crate::accounts;
+use crate::game;
+use crate::vdf::Vdf;
+
+/// The launch option making Dota 2 load the patched terrain
+pub(crate) const LAUNCH_OPTION: &str = "-language tempcontent";
+
+/// Whether the launch options contain `-language tempcontent`
+fn has_launch_option(options: &str) -> bool {
+    let words: Vec<&str> = options.split_whitespace().collect();
+    words
+        .windows(2)
+        .any(|pair| pair[0] == "-language" && pair[1] == "tempcontent")
+}
+
+/// Check the `localconfig.vdf` of the current Steam account (see `accounts::current()`,
+/// which is only asked for if `interactive`), or of every account on this machine if it is
+/// not known, for the launch options of Dota 2 (appid 570) or the game selected with
+/// `--game`. The files are only read. Returns whether any account has `-language
+/// tempcontent` set, or `None` if no account configuration could be read.
+pub(crate) fn launch_option_set(steam_path: &Path, interactive: bool) -> Option<bool> {
+    let mut found_config = false;
+    for account in accounts::userdata_dirs(steam_path, interactive) {
+        let config = account.join("config").join("localconfig.vdf");
+        let Some(vdf) = std::fs::read_to_string(config)
+            .ok()
+            .and_then(|text| Vdf::parse(&text))
+        else {
+            continue;
+        };
+        found_config = true;
+        let options = vdf
+            .path(&[
+                "UserLocalConfigStore",
+                "Software",
+                "Valve",
+                "Steam",
+                "apps",
+                game::current().appid,
+            ])
+            .and_then(|app| app.get("LaunchOptions"))
+            .and_then(Vdf::as_str);
+        if options.is_some_and(has_launch_option) {
+            return Some(true);
+        }
+    }
+    found_config.then_some(false)
+}
+
+/// Warning shown when Steam is known not to launch Dota 2 with the launch option
+pub(crate) fn missing_warning() -> String {
+    format!(
+        "Warning: {title} is not set to launch with \"{}\", the terrain will not load. \
+        Add it in Steam under {title} > Properties > Launch Options, or use the `shortcut` command.",
+        LAUNCH_OPTION,
+        title = game::current().title
+    )
+}
@@ -0,0 +1,226 @@
+use crate::apply::ApplyOptions;
+use crate::cli::Args;
+use crate::config::Config;
+use crate::utils::DotaInstall;
+
+mod accounts;
+mod apply;
+mod archive;
+mod attributes;
+mod audit;
+mod build;
+mod cli;
+mod compare_install;
+mod config;
+mod conflicts;
+mod copy;
+mod crash;
+mod daemon;
+mod diff_builds;
+mod doctor;
+mod explain;
+mod explore;
+mod extract;
+mod favorites;
+mod game;
+mod hashing;
+mod history;
+mod ignore;
+mod inspect;
+mod install;
+mod install_archive;
+mod integrity;
+mod journal;
+mod launch;
+mod loose;
+mod lzma;
+mod manifest;
+mod map_info;
+mod menu;
+mod minimap;
+mod mount;
+mod notify;
+mod pack;
+mod pack_cache;
+mod panic_button;
+mod pins;
+mod pipeline;
+mod preview;
+mod reapply;
+mod references;
+mod resource;
+mod rules;
+mod schedule;
+mod shortcut;
+mod signature;
+mod status;
+mod steam_state;
+mod sync;
+mod terrain_settings;
+mod texture;
+mod tree_size;
+mod utils;
+mod vdf;
+mod verify;
+mod vpk;
+
+pub use pipeline::{Options, Report, TerrainMod};
+pub use utils::TMError;
+pub use vpk::{Entry, VPK};
+
+/// Entry point of the command line program: runs the command given on the command line, or
+/// the interactive menu without one
+pub fn run() {
+    crash::install_hook();
+    let args = Args::parse();
+    crash::phase(&format!(
+        "running {}",
+        args.command.as_deref().unwrap_or("the menu")
+    ));
+    if let Err(e) = configure(&args) {
+        eprintln!("Dota-Terrain-Mod error: {}", e);
+        std::process::exit(1);
+    }
+    // A previous run may have stopped in the middle of installing a terrain
+    journal::recover();
+    match args.command.as_deref() {
+        None => apply_interactive(&args),
+        Some("apply") => apply::run(&args),
+        Some("audit") => audit::run(&args),
+        Some("build") => build::run(&args),
+        Some("compare-install") => compare_install::run(&args),
+        Some("copy") => copy::run(&args),
+        Some("daemon") => daemon::run(&args),
+        Some("diff-builds") => diff_builds::run(&args),
+        Some("doctor") => doctor::run(&args),
+        Some("explain") => explain::run(&args),
+        Some("explore") => explore::run(&args),
+        Some("extract") => extract::run(&args),
+        Some("favorite") => favorites::run(&args),
+        Some("hide") => favorites::run_hide(&args),
+        Some("history") => history::run(&args),
+        Some("inspect") => inspect::run(&args),
+        Some("install-archive") => install_archive::run(&args),
+        Some("map-info") => map_info::run(&args),
+        Some("mount") => mount::run(&args),
+        Some("pack") => pack::run(&args),
+        Some("panic-button") => panic_button::run(&args),
+        Some("preview") => preview::run(&args),
+        Some("reapply") => reapply::run(&args),
+        Some("schedule") => schedule::run(&args),
+        Some("shortcut") => shortcut::run(&args),
+        Some("sign") => signature::run(&args),
+        Some("status") => status::run(&args),
+        Some("tree-size") => tree_size::run(&args),
+        Some("verify") => verify::run(&args),
+        Some(command) => {
+            eprintln!("Dota-Terrain-Mod error: Unknown command \"{}\"", command);
+            std::process::exit(1);
+        }
+    }
+}
+
+/// Apply the options of `args` which hold for every command, such as `--game` and
+/// `--per-user`. Fails with a message if one is invalid.
+fn configure(args: &Args) -> Result<(), String> {
+    if args.flag("portable") {
+        config::set_portable();
+    }
+    if args.flag("per-user") {
+        manifest::set_per_user();
+    }
+    if let Some(user) = args.option("steam-user") {
+        accounts::select(user);
+    }
+    if let Some(id) = args.option("game") {
+        game::select(id)?;
+    }
+    Ok(())
+}
+
+/// Select a terrain from the interactive menu and apply it, along with the files in the
+/// directories and VPKs given by `--overrides`. The terrain's entries are renamed according to the
+/// rules in the file given by `--rules`, the configuration file or the default rules. With
+/// `--terrain-minimap`, the terrain's own minimap replaces the one of the base map. With
+/// `--compress`, the output is LZMA-compressed. With `--low-end`, heavyweight assets are
+/// stripped or swapped for the base map's, and with `--downscale <size>`, larger textures are
+/// downscaled. With `--recolor <hue>[:<saturation>]`, the colors of ground textures are
+/// shifted. The output is also written to every path given with `--output`. Options not given
+/// are taken from the settings of the selected terrain in the configuration, if any.
+fn apply_interactive(args: &Args) {
+    println!("-- Dota Terrain Mod (https://github.com/ObsoleteXero/Dota-Terrain-Mod) --\n");
+    let dota = match DotaInstall::new(args) {
+        Ok(dota) => dota,
+        Err(e) => notify::fatal(&e.to_string()),
+    };
+    let mut config = match Config::load() {
+        Ok(config) => config,
+        Err(e) => notify::fatal(&e.to_string()),
+    };
+    config.menu.discover(&dota);
+    crash::phase("selecting a terrain");
+    let Some(terrain) = menu::get_selection(&config.menu) else {
+        apply_without_console(&dota, &config, args);
+        return;
+    };
+    let options = match ApplyOptions::from_args(args, &config, &terrain.file) {
+        Ok(options) => options,
+        Err(e) => notify::fatal(&e.to_string()),
+    };
+
+    if let Err(e) = apply::apply(&dota, &terrain.file, &options) {
+        notify::fatal(&e.to_string());
+    }
+
+    println!("Done. Launch Dota 2 with the \"-language tempcontent\" launch option.");
+    println!("If the game misbehaves, run \"dota-terrain-mod panic-button\" to undo the changes.");
+    if !args.assume_yes() {
+        println!("\nPress any key to exit.");
+        utils::pause();
+    }
+}
+
+/// Apply a terrain when no selection can be read, e.g. when started from a GUI shell without
+/// a console. The `default` terrain of the menu configuration is applied, or else the last
+/// terrain in the history. Progress is written to the log file and the result is shown in a
+/// message box or notification.
+fn apply_without_console(dota: &DotaInstall, config: &Config, args: &Args) {
+    const TITLE: &str = "Dota Terrain Mod";
+    notify::log("No console input, applying the default terrain");
+    let terrain = match &config.menu.default {
+        Some(query) => config.menu.find(query).map(|terrain| terrain.file),
+        None => history::load()
+            .ok()
+            .and_then(|mut entries| entries.pop())
+            .map(|entry| entry.terrain),
+    };
+    let Some(terrain) = terrain else {
+        let message = "No terrain selected. Run Dota Terrain Mod from a console, \
+            or set a default terrain with `default` in the [menu] table of config.toml.";
+        notify::log(message);
+        notify::show(TITLE, message);
+        std::process::exit(1);
+    };
+
+    let options = match ApplyOptions::from_args(args, config, &terrain) {
+        Ok(options) => options,
+        Err(e) => notify::fatal(&e.to_string()),
+    };
+    notify::log(&format!("Applying {}", terrain));
+    match apply::apply(dota, &terrain, &options) {
+        Ok(_) => {
+            let message = format!(
+                "Applied {}. Launch Dota 2 with \"-language tempcontent\".",
+                terrain
+            );
+            notify::log(&message);
+            notify::show(TITLE, &message);
+        }
+        Err(e) => {
+            let message = e.to_string();
+            notify::log(&message);
+            notify::show(TITLE, &message);
+            std::process::exit(1);
+        }
+    }
+}
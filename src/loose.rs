@@ -0,0 +1,75 @@
+use std::collections::HashMap;
+use std::io;
+use std::path::{Component, Path, PathBuf};
+
+use crate::{journal, utils};
+
+/// Path of the loose file for the VPK entry `path` below `dir`. Entries which would end up
+/// outside of `dir`, such as `../gameinfo.gi`, have none.
+pub(crate) fn loose_path(dir: &Path, path: &str) -> Option<PathBuf> {
+    let relative = Path::new(path);
+    relative
+        .components()
+        .all(|component| matches!(component, Component::Normal(_)))
+        .then(|| dir.join(relative))
+}
+
+/// Whether the file at `path` already holds `data`
+fn unchanged(path: &Path, data: &[u8]) -> bool {
+    std::fs::metadata(path).is_ok_and(|metadata| metadata.len() == data.len() as u64)
+        && std::fs::read(path).is_ok_and(|existing| existing == data)
+}
+
+/// Write `files`, keyed by their path inside a VPK, as loose files below `dir`, the
+/// tempcontent folder of the game, whose search path loads them like the entries of a VPK.
+/// Files already holding the same data are left untouched, so installing again after
+/// tweaking a single file only writes that file. Returns the number of files written.
+pub(crate) fn install(dir: &Path, files: &HashMap<String, Vec<u8>>) -> io::Result<usize> {
+    let mut written = 0;
+    for (path, data) in files {
+        let Some(file) = loose_path(dir, path) else {
+            eprintln!(
+                "Warning: Skipped {}, it would be written outside of {}",
+                path,
+                dir.display()
+            );
+            continue;
+        };
+        if unchanged(&file, data) {
+            continue;
+        }
+        if let Some(parent) = file.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        journal::record(&file)?;
+        // The game may hold the previous version open while running
+        utils::retry_locked(&file, || std::fs::write(&file, data))?;
+        written += 1;
+    }
+    Ok(written)
+}
+
+/// Remove the loose files `paths` below `dir` written by `install()`, along with the
+/// directories they leave empty. Files which are already gone are skipped.
+/// Returns the number of files removed.
+pub(crate) fn remove<'a>(
+    dir: &Path,
+    paths: impl IntoIterator<Item = &'a String>,
+) -> io::Result<usize> {
+    let mut removed = 0;
+    for file in paths.into_iter().filter_map(|path| loose_path(dir, path)) {
+        if !file.exists() {
+            continue;
+        }
+        // During an install, recording the file moves it aside already
+        journal::record(&file)?;
+        match std::fs::remove_file(&file) {
+            Ok(()) => {}
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+            Err(e) => return Err(e),
+        }
+        removed += 1;
+        utils::remove_empty_dirs(file.parent().unwrap(), dir);
+    }
+    Ok(removed)
+}
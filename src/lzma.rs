@@ -0,0 +1,123 @@
+use std::io;
+
+/// Identifier at the start of an LZMA-compressed entry
+const LZMA_ID: &[u8; 4] = b"LZMA";
+/// Length of Valve's LZMA header: identifier, uncompressed size, compressed size and the
+/// 5 bytes of LZMA properties
+const LZMA_HEADER_LENGTH: usize = 17;
+
+/// Whether `data` is an entry compressed with Valve's LZMA header. The sizes in the header
+/// have to match the length of `data`, so files which merely start with `LZMA` are not
+/// mistaken for compressed entries.
+pub(crate) fn is_compressed(data: &[u8]) -> bool {
+    data.len() >= LZMA_HEADER_LENGTH
+        && &data[0..4] == LZMA_ID
+        && u32::from_le_bytes(data[8..12].try_into().unwrap()) as usize
+            == data.len() - LZMA_HEADER_LENGTH
+}
+
+/// Size of a compressed entry after decompression, as recorded in its header
+pub(crate) fn decompressed_length(data: &[u8]) -> u32 {
+    u32::from_le_bytes(data[4..8].try_into().unwrap())
+}
+
+/// Decompress an entry compressed with Valve's LZMA header
+#[cfg(feature = "lzma")]
+pub(crate) fn decompress(data: &[u8]) -> io::Result<Vec<u8>> {
+    let actual_size = decompressed_length(data);
+
+    // Rebuild the header of a standard .lzma stream: properties, then the 64-bit size
+    let mut stream = data[12..LZMA_HEADER_LENGTH].to_vec();
+    stream.extend_from_slice(&u64::from(actual_size).to_le_bytes());
+    stream.extend_from_slice(&data[LZMA_HEADER_LENGTH..]);
+
+    let mut decompressed = Vec::with_capacity(actual_size as usize);
+    lzma_rs::lzma_decompress(&mut stream.as_slice(), &mut decompressed)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e.to_string()))?;
+    Ok(decompressed)
+}
+
+#[cfg(not(feature = "lzma"))]
+pub(crate) fn decompress(_data: &[u8]) -> io::Result<Vec<u8>> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "entry is LZMA-compressed, which requires a build with the \"lzma\" feature",
+    ))
+}
+
+/// Fails with a message if `compress` asks for compressed entries in a build without the
+/// "lzma" feature, so `--compress` is refused before anything is built
+pub(crate) fn check_compress(compress: bool) -> Result<(), String> {
+    if compress && !cfg!(feature = "lzma") {
+        return Err(String::from(
+            "--compress requires a build with the \"lzma\" feature",
+        ));
+    }
+    Ok(())
+}
+
+/// Compress `data` and prepend Valve's LZMA header
+#[cfg(feature = "lzma")]
+pub(crate) fn compress(data: &[u8]) -> io::Result<Vec<u8>> {
+    let mut stream = Vec::new();
+    lzma_rs::lzma_compress(&mut &data[..], &mut stream)?;
+
+    // A standard .lzma stream has 5 bytes of properties and a 64-bit size before the data
+    let compressed = &stream[13..];
+    let mut entry = LZMA_ID.to_vec();
+    entry.extend_from_slice(&(data.len() as u32).to_le_bytes());
+    entry.extend_from_slice(&(compressed.len() as u32).to_le_bytes());
+    entry.extend_from_slice(&stream[0..5]);
+    entry.extend_from_slice(compressed);
+    Ok(entry)
+}
+
+#[cfg(not(feature = "lzma"))]
+pub(crate) fn compress(_data: &[u8]) -> io::Result<Vec<u8>> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "compressing entries requires a build with the \"lzma\" feature",
+    ))
+}
+
+#[cfg(all(test, feature = "lzma"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn entries_round_trip() {
+        let text = b"Radiant ancient, dire ancient. ".repeat(100);
+        for data in [&text[..], b"", b"x"] {
+            let entry = compress(data).unwrap();
+            assert_eq!(&entry[0..4], b"LZMA");
+            assert_eq!(
+                u32::from_le_bytes(entry[8..12].try_into().unwrap()) as usize,
+                entry.len() - LZMA_HEADER_LENGTH
+            );
+            assert!(is_compressed(&entry));
+            assert_eq!(decompressed_length(&entry) as usize, data.len());
+            assert_eq!(decompress(&entry).unwrap(), data);
+        }
+    }
+
+    #[test]
+    fn plain_files_are_not_taken_for_entries() {
+        assert!(!is_compressed(b""));
+        assert!(!is_compressed(b"LZMA"));
+        // Starts like an entry, but the compressed size does not match the rest
+        let mut entry = compress(b"some file").unwrap();
+        entry.push(0);
+        assert!(!is_compressed(&entry));
+        assert!(!is_compressed(
+            &b"LZMA is a compression algorithm".repeat(2)
+        ));
+    }
+
+    #[test]
+    fn damaged_entries_fail_to_decompress() {
+        let mut entry = compress(&b"some file".repeat(50)).unwrap();
+        let end = entry.len();
+        entry[LZMA_HEADER_LENGTH..end].fill(0xff);
+        assert!(decompress(&entry).is_err());
+    }
+}
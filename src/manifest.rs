@@ -0,0 +1,511 @@
+use std::{
+    collections::BTreeMap,
+    io,
+    path::{Path, PathBuf},
+    sync::atomic::{AtomicBool, Ordering},
+    time::Duration,
+};
+
+use serde_json::{json, Value};
+
+use crate::hashing::HashAlgorithm;
+use crate::install::InstallMethod;
+use crate::{config, utils};
+
+/// Name of the manifest written next to the output VPK
+const MANIFEST_FILE: &str = "dota-terrain-mod.json";
+
+/// Name of the directory inside the configuration directory holding the manifests in per-user
+/// mode
+const PER_USER_DIR: &str = "manifests";
+
+/// Whether manifests are kept in the configuration directory of the user (`--per-user`)
+static PER_USER: AtomicBool = AtomicBool::new(false);
+
+/// Keep the manifests of outputs in the configuration directory of the user instead of next to
+/// the outputs, for computers where several users share the game folder
+pub(crate) fn set_per_user() {
+    PER_USER.store(true, Ordering::Relaxed);
+}
+
+/// Name, version and, when built from a git checkout, commit of this program, e.g.
+/// `dota-terrain-mod 0.1.0 (1a2b3c4)`
+pub(crate) fn tool_version() -> String {
+    let version = format!("{} {}", env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION"));
+    match option_env!("GIT_HASH") {
+        Some(hash) => format!("{} ({})", version, hash),
+        None => version,
+    }
+}
+
+/// Where an entry of the output VPK came from
+#[derive(Clone, Copy, PartialEq)]
+pub(crate) enum Source {
+    /// The base map (`dota.vpk`)
+    Base,
+    /// The selected terrain
+    Terrain,
+    /// The directory given with `--overrides`
+    Overrides,
+}
+
+impl Source {
+    /// Name of the source as written to the manifest
+    pub(crate) fn name(self) -> &'static str {
+        match self {
+            Source::Base => "base",
+            Source::Terrain => "terrain",
+            Source::Overrides => "overrides",
+        }
+    }
+
+    fn from_name(name: &str) -> Option<Source> {
+        match name {
+            "base" => Some(Source::Base),
+            "terrain" => Some(Source::Terrain),
+            "overrides" => Some(Source::Overrides),
+            _ => None,
+        }
+    }
+}
+
+/// Provenance of a single entry of the output VPK
+/// # Properties
+/// - `source: Source` = The source the entry was taken from
+/// - `replaces: Vec<Source>` = Sources which also provided the entry, in the order they were
+///   replaced
+/// - `base_crc32: Option<u32>` = CRC32 of the base map's version of the entry when the output
+///   was built, if the base map has one, to tell whether a game update changed it since
+#[derive(Clone)]
+pub(crate) struct ManifestEntry {
+    pub(crate) source: Source,
+    pub(crate) replaces: Vec<Source>,
+    pub(crate) base_crc32: Option<u32>,
+}
+
+/// How long building an output took, for spotting slow phases and for issue reports
+/// # Properties
+/// - `phases: Vec<(String, f64)>` = Name and duration in seconds of every phase, in order
+/// - `input_bytes: u64` = Size of the base map, the terrain and the overrides read
+/// - `output_bytes: u64` = Size of the output VPK
+#[derive(Clone, Default)]
+pub(crate) struct BuildStats {
+    pub(crate) phases: Vec<(String, f64)>,
+    pub(crate) input_bytes: u64,
+    pub(crate) output_bytes: u64,
+}
+
+impl BuildStats {
+    /// Record that the phase `name` took `duration`
+    pub(crate) fn add(&mut self, name: &str, duration: Duration) {
+        self.phases.push((name.to_owned(), duration.as_secs_f64()));
+    }
+
+    /// One line listing the phases and the total time, with the throughput of the build
+    /// phase, e.g. `Timings: build 1.40 s (350.2 MB at 250.1 MB/s), write 0.30 s, total 1.70 s`
+    pub(crate) fn summary(&self) -> String {
+        const MB: f64 = 1024.0 * 1024.0;
+        let phases: Vec<String> = self
+            .phases
+            .iter()
+            .map(|(name, seconds)| {
+                let mut phase = format!("{} {:.2} s", name, seconds);
+                if name == "build" && *seconds > 0.0 {
+                    let megabytes = self.input_bytes as f64 / MB;
+                    phase += &format!(" ({:.1} MB at {:.1} MB/s)", megabytes, megabytes / seconds);
+                }
+                phase
+            })
+            .collect();
+        let total: f64 = self.phases.iter().map(|(_, seconds)| seconds).sum();
+        format!("Timings: {}, total {:.2} s", phases.join(", "), total)
+    }
+}
+
+/// Record of how an output VPK was built, written as JSON next to it
+/// # Properties
+/// - `terrain: String` = File name of the applied terrain
+/// - `entries: BTreeMap<String, ManifestEntry>` = Provenance of every entry in the output
+/// - `downscaled: Vec<String>` = Textures of the terrain whose largest mip levels were dropped
+///   (`--downscale`)
+/// - `recolored: Vec<String>` = Ground textures of the terrain whose colors were shifted
+///   (`--recolor`)
+/// - `tool: String` = Version of the program which built the output, see `tool_version()`.
+///   Nothing is sent anywhere, it only identifies the build in support requests
+/// - `options: Vec<String>` = Command line options the output was built with
+/// - `user: String` = Name of the user account of the computer which built the output, see
+///   `utils::os_user()`
+/// - `build: String` = Build ID of the game the output was built for, see
+///   `DotaInstall::build_id()`. Empty if it could not be read
+/// - `stats: BuildStats` = How long building the output took
+/// - `loose: Vec<String>` = Entries installed as loose files below the tempcontent folder
+///   instead of into the output VPK (`--method loose` or `hybrid`), see `loose::install()`
+/// - `packed: String` = Key of the inputs of the output VPK of a hybrid install, see
+///   `install::packed_key()`. Empty for other installs
+/// - `method: InstallMethod` = How the terrain was installed (`--method`), see
+///   `install::Installer`
+#[derive(Clone)]
+pub(crate) struct Manifest {
+    pub(crate) terrain: String,
+    pub(crate) entries: BTreeMap<String, ManifestEntry>,
+    pub(crate) downscaled: Vec<String>,
+    pub(crate) recolored: Vec<String>,
+    pub(crate) tool: String,
+    pub(crate) options: Vec<String>,
+    pub(crate) user: String,
+    pub(crate) build: String,
+    pub(crate) stats: BuildStats,
+    pub(crate) loose: Vec<String>,
+    pub(crate) packed: String,
+    pub(crate) method: InstallMethod,
+}
+
+impl Manifest {
+    /// Create an empty manifest for the given terrain
+    pub(crate) fn new(terrain: &str) -> Manifest {
+        Manifest {
+            terrain: terrain.to_owned(),
+            entries: BTreeMap::new(),
+            downscaled: Vec::new(),
+            recolored: Vec::new(),
+            tool: tool_version(),
+            options: Vec::new(),
+            user: utils::os_user().unwrap_or_default(),
+            build: String::new(),
+            stats: BuildStats::default(),
+            loose: Vec::new(),
+            packed: String::new(),
+            method: InstallMethod::default(),
+        }
+    }
+
+    /// Record that `path` was provided by `source`, replacing the entry of an earlier source
+    /// if there is one
+    pub(crate) fn add(&mut self, path: &str, source: Source) {
+        match self.entries.get_mut(path) {
+            Some(entry) => {
+                let replaced = std::mem::replace(&mut entry.source, source);
+                entry.replaces.push(replaced);
+            }
+            None => {
+                self.entries.insert(
+                    path.to_owned(),
+                    ManifestEntry {
+                        source,
+                        replaces: Vec::new(),
+                        base_crc32: None,
+                    },
+                );
+            }
+        }
+    }
+
+    /// Forget the entries taken from `source`, restoring the entries they replaced
+    pub(crate) fn remove_source(&mut self, source: Source) {
+        self.entries.retain(|_, entry| {
+            if entry.source != source {
+                return true;
+            }
+            match entry.replaces.pop() {
+                Some(replaced) => {
+                    entry.source = replaced;
+                    true
+                }
+                None => false,
+            }
+        });
+    }
+
+    /// Record the CRC32 of the base map's version of `path`, which has to be added already
+    pub(crate) fn set_base_crc32(&mut self, path: &str, crc32: u32) {
+        if let Some(entry) = self.entries.get_mut(path) {
+            entry.base_crc32 = Some(crc32);
+        }
+    }
+
+    /// Number of entries taken from `source`
+    pub(crate) fn count(&self, source: Source) -> usize {
+        self.entries
+            .values()
+            .filter(|entry| entry.source == source)
+            .count()
+    }
+
+    /// Summary of the entries each source replaced in the sources before it
+    pub(crate) fn conflicts(&self) -> Vec<String> {
+        let mut summary = Vec::new();
+        for source in [Source::Terrain, Source::Overrides] {
+            for replaced in [Source::Base, Source::Terrain] {
+                let count = self
+                    .entries
+                    .values()
+                    .filter(|entry| entry.source == source && entry.replaces.contains(&replaced))
+                    .count();
+                if count > 0 {
+                    summary.push(format!(
+                        "{} file(s) from {} replace files from {}",
+                        count,
+                        source.name(),
+                        replaced.name()
+                    ));
+                }
+            }
+        }
+        summary
+    }
+
+    fn to_json(&self) -> Value {
+        let entries: serde_json::Map<String, Value> = self
+            .entries
+            .iter()
+            .map(|(path, entry)| {
+                let replaces: Vec<&str> = entry.replaces.iter().map(|s| s.name()).collect();
+                let mut value = json!({ "source": entry.source.name(), "replaces": replaces });
+                if let Some(crc32) = entry.base_crc32 {
+                    value["base_crc32"] = json!(crc32);
+                }
+                (path.clone(), value)
+            })
+            .collect();
+        json!({
+            "terrain": self.terrain,
+            "entries": entries,
+            "downscaled": self.downscaled,
+            "recolored": self.recolored,
+            "tool": self.tool,
+            "options": self.options,
+            "user": self.user,
+            "build": self.build,
+            "stats": {
+                "phases": self.stats.phases.iter()
+                    .map(|(phase, seconds)| json!({ "phase": phase, "seconds": seconds }))
+                    .collect::<Vec<Value>>(),
+                "input_bytes": self.stats.input_bytes,
+                "output_bytes": self.stats.output_bytes,
+            },
+            "loose": self.loose,
+            "packed": self.packed,
+            "method": self.method.name(),
+        })
+    }
+
+    fn from_json(value: &Value) -> Option<Manifest> {
+        let mut manifest = Manifest::new(value.get("terrain")?.as_str()?);
+        for (path, entry) in value.get("entries")?.as_object()? {
+            let source = Source::from_name(entry.get("source")?.as_str()?)?;
+            let replaces = entry
+                .get("replaces")?
+                .as_array()?
+                .iter()
+                .map(|source| Source::from_name(source.as_str()?))
+                .collect::<Option<Vec<Source>>>()?;
+            // Not recorded by older versions
+            let base_crc32 = entry
+                .get("base_crc32")
+                .and_then(Value::as_u64)
+                .and_then(|crc32| u32::try_from(crc32).ok());
+            manifest.entries.insert(
+                path.clone(),
+                ManifestEntry {
+                    source,
+                    replaces,
+                    base_crc32,
+                },
+            );
+        }
+        // Not recorded by older versions
+        let paths = |key: &str| -> Vec<String> {
+            value
+                .get(key)
+                .and_then(Value::as_array)
+                .map_or_else(Vec::new, |paths| {
+                    paths
+                        .iter()
+                        .filter_map(|path| path.as_str().map(str::to_owned))
+                        .collect()
+                })
+        };
+        manifest.downscaled = paths("downscaled");
+        manifest.recolored = paths("recolored");
+        manifest.options = paths("options");
+        let string = |key: &str| {
+            value
+                .get(key)
+                .and_then(Value::as_str)
+                .unwrap_or_default()
+                .to_owned()
+        };
+        manifest.tool = string("tool");
+        manifest.user = string("user");
+        manifest.build = string("build");
+        manifest.loose = paths("loose");
+        manifest.packed = string("packed");
+        // Not recorded by versions which only knew the methods given in the options
+        manifest.method = InstallMethod::from_name(&string("method"))
+            .or_else(|| {
+                manifest
+                    .options
+                    .iter()
+                    .find_map(|option| InstallMethod::from_name(option.strip_prefix("--method ")?))
+            })
+            .unwrap_or_default();
+        if let Some(stats) = value.get("stats") {
+            let bytes = |key: &str| stats.get(key).and_then(Value::as_u64).unwrap_or_default();
+            manifest.stats = BuildStats {
+                phases: stats.get("phases").and_then(Value::as_array).map_or_else(
+                    Vec::new,
+                    |phases| {
+                        phases
+                            .iter()
+                            .filter_map(|phase| {
+                                Some((
+                                    phase.get("phase")?.as_str()?.to_owned(),
+                                    phase.get("seconds")?.as_f64()?,
+                                ))
+                            })
+                            .collect()
+                    },
+                ),
+                input_bytes: bytes("input_bytes"),
+                output_bytes: bytes("output_bytes"),
+            };
+        }
+        Some(manifest)
+    }
+
+    /// The manifest as it is written to its file
+    pub(crate) fn to_json_string(&self) -> io::Result<String> {
+        Ok(serde_json::to_string_pretty(&self.to_json())?)
+    }
+
+    /// Report of the conflicts between the sources: the summary of `conflicts()`, followed by
+    /// every entry which replaced the entry of another source
+    pub(crate) fn conflict_report(&self) -> String {
+        let mut report = self.conflicts();
+        if report.is_empty() {
+            report.push(String::from("No file replaces a file from another source"));
+        }
+        report.push(String::new());
+        for (path, entry) in &self.entries {
+            if entry.replaces.is_empty() {
+                continue;
+            }
+            let replaces: Vec<&str> = entry.replaces.iter().map(|s| s.name()).collect();
+            report.push(format!(
+                "{}  {} (replaces {})",
+                entry.source.name(),
+                path,
+                replaces.join(", ")
+            ));
+        }
+        report.join("\n") + "\n"
+    }
+
+    /// Write the manifest belonging to the output VPK at `out_path`
+    pub(crate) fn write(&self, out_path: &Path) -> io::Result<()> {
+        let json = self.to_json_string()?;
+        let path = manifest_path(out_path);
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        std::fs::write(path, json)
+    }
+
+    /// Read the manifest belonging to the output VPK at `out_path`
+    pub(crate) fn read(out_path: &Path) -> io::Result<Manifest> {
+        Manifest::load(&manifest_path(out_path))
+    }
+
+    /// Read the manifest file at `path`
+    pub(crate) fn load(path: &Path) -> io::Result<Manifest> {
+        Manifest::from_json_str(&std::fs::read_to_string(path)?)
+    }
+
+    /// Parse the contents of a manifest file
+    pub(crate) fn from_json_str(json: &str) -> io::Result<Manifest> {
+        let value: Value = serde_json::from_str(json)?;
+        Manifest::from_json(&value)
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "malformed manifest"))
+    }
+}
+
+/// Path of the manifest belonging to the output VPK at `out_path`. Outputs other than
+/// `dota.vpk`, e.g. of event maps, get a manifest named after them so they can share a folder.
+/// In per-user mode, the manifest is kept in the configuration directory instead, named after
+/// the output and a hash of its full path.
+pub(crate) fn manifest_path(out_path: &Path) -> PathBuf {
+    if PER_USER.load(Ordering::Relaxed) {
+        if let Some(dir) = config::config_dir() {
+            let stem = out_path.file_stem().unwrap_or_default().to_string_lossy();
+            let hash = HashAlgorithm::Md5.hash(out_path.to_string_lossy().as_bytes());
+            return dir
+                .join(PER_USER_DIR)
+                .join(format!("{}-{}.json", stem, &hash[..8]));
+        }
+    }
+    out_path.with_file_name(manifest_name(out_path))
+}
+
+/// File name of the manifest next to the output VPK at `out_path`, see `manifest_path()`
+pub(crate) fn manifest_name(out_path: &Path) -> String {
+    match out_path.file_stem().and_then(|stem| stem.to_str()) {
+        Some(stem) if stem != "dota" => format!("{}.{}", stem, MANIFEST_FILE),
+        _ => String::from(MANIFEST_FILE),
+    }
+}
+
+/// Whether a terrain is applied to the output VPK at `out_path`: the VPK exists, or its
+/// manifest records an install by another method, whose files are elsewhere, see
+/// `install::Installer`
+pub(crate) fn is_applied(out_path: &Path) -> bool {
+    out_path.exists()
+        || Manifest::read(out_path).is_ok_and(|manifest| manifest.method != InstallMethod::Vpk)
+}
+
+/// Warning if the terrain described by `manifest` was built for another build of the game
+/// than `build_id`, the installed one. The update replaced the base map the terrain was
+/// merged with, so it may not match the map until it is applied again.
+pub(crate) fn stale_warning(manifest: &Manifest, build_id: Option<&str>) -> Option<String> {
+    let build_id = build_id?;
+    // Not recorded by older versions
+    if manifest.build.is_empty() || manifest.build == build_id {
+        return None;
+    }
+    Some(format!(
+        "Warning: {} was built for game build {}, the game was updated to build {} since. \
+        Run `reapply` to rebuild it from the updated base map.",
+        manifest.terrain, manifest.build, build_id
+    ))
+}
+
+/// Warning if the output VPK at `out_path` was applied by another user of this computer: its
+/// manifest names someone else, or, in per-user mode, the user has no manifest for it.
+/// Applying replaces the terrain for everyone sharing the game folder.
+pub(crate) fn other_user_warning(out_path: &Path) -> Option<String> {
+    if !is_applied(out_path) {
+        return None;
+    }
+    match Manifest::read(out_path) {
+        Ok(manifest)
+            if !manifest.user.is_empty()
+                && utils::os_user().is_some_and(|user| user != manifest.user) =>
+        {
+            Some(format!(
+                "Warning: {} was applied by {} ({}). The game folder is shared, applying a \
+                terrain replaces it for every user of this computer.",
+                out_path.display(),
+                manifest.user,
+                manifest.terrain
+            ))
+        }
+        Err(e) if e.kind() == io::ErrorKind::NotFound && PER_USER.load(Ordering::Relaxed) => {
+            Some(format!(
+                "Warning: {} was not applied by you and may belong to another user of this \
+                computer. The game folder is shared, applying a terrain replaces it for every \
+                user.",
+                out_path.display()
+            ))
+        }
+        _ => None,
+    }
+}
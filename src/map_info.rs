@@ -0,0 +1,100 @@
+use std::{collections::BTreeMap, path::PathBuf};
+
+use crate::cli::Args;
+use crate::resource::{self, Resource};
+use crate::vpk::{VpkOptions, VPK};
+
+/// Entry point for the `map-info <vpk>` command. For every map (`maps/*.vmap_c`) in the VPK,
+/// prints its resource version and blocks, the resources it references grouped by type, and
+/// the compiled world, world nodes and entity lumps shipped with it, as a sanity check that a
+/// map compiled as expected.
+pub(crate) fn run(args: &Args) {
+    let [vpk_path] = args.positional.as_slice() else {
+        eprintln!("Usage: dota-terrain-mod map-info <vpk>");
+        std::process::exit(1);
+    };
+    let mut vpk = match VPK::open_with(PathBuf::from(vpk_path), VpkOptions::from_args(args)) {
+        Ok(vpk) => vpk,
+        Err(e) => {
+            eprintln!("Dota-Terrain-Mod error: Invalid VPK: {}", e);
+            std::process::exit(1);
+        }
+    };
+
+    let mut maps: Vec<String> = vpk
+        .entries()
+        .map(|(path, _)| path.clone())
+        .filter(|path| path.starts_with("maps/") && path.ends_with(".vmap_c"))
+        .collect();
+    maps.sort();
+    if maps.is_empty() {
+        println!("No maps found in {}", vpk_path);
+        return;
+    }
+
+    for (i, map) in maps.iter().enumerate() {
+        if i > 0 {
+            println!();
+        }
+        println!("Map: {}", map);
+        let data = match vpk.read_entry(map) {
+            Ok(data) => data,
+            Err(e) => {
+                println!("  Could not be read: {}", e);
+                continue;
+            }
+        };
+        let Some(resource) = Resource::parse(&data) else {
+            println!("  Not a compiled resource");
+            continue;
+        };
+        println!(
+            "  Resource version {}, {} bytes",
+            resource.version,
+            data.len()
+        );
+        let blocks: Vec<&str> = resource
+            .blocks
+            .iter()
+            .map(|block| block.kind.as_str())
+            .collect();
+        println!("  Blocks: {}", blocks.join(", "));
+
+        match resource.external_references(&data) {
+            Some(references) => {
+                let mut kinds: BTreeMap<&str, usize> = BTreeMap::new();
+                for name in &references {
+                    let ext = name.rsplit_once('.').map_or("", |(_, ext)| ext);
+                    let ext = ext.strip_suffix("_c").unwrap_or(ext);
+                    let kind = resource::type_name(&format!("{}_c", ext)).unwrap_or("Other");
+                    *kinds.entry(kind).or_default() += 1;
+                }
+                let kinds: Vec<String> = kinds
+                    .iter()
+                    .map(|(kind, count)| format!("{} {}", count, kind.to_lowercase()))
+                    .collect();
+                if kinds.is_empty() {
+                    println!("  References: none");
+                } else {
+                    println!("  References: {} ({})", references.len(), kinds.join(", "));
+                }
+            }
+            None => println!("  References: no reference list"),
+        }
+
+        // Compiled world data lives in a directory named after the map
+        let dir = format!("{}/", map.strip_suffix(".vmap_c").unwrap());
+        let count = |ext: &str| {
+            vpk.entries()
+                .filter(|(path, _)| path.starts_with(&dir) && path.ends_with(ext))
+                .count()
+        };
+        let worlds = count(".vwrld_c");
+        println!(
+            "  World: {}",
+            if worlds > 0 { "present" } else { "MISSING" }
+        );
+        println!("  World nodes: {}", count(".vwnod_c"));
+        println!("  Entity lumps: {}", count(".vents_c"));
+    }
+}
@@ -0,0 +1,627 @@
+use std::{collections::HashMap, fs, io, path::PathBuf};
+
+use toml::{Table, Value};
+
+use crate::notify;
+use crate::utils::DotaInstall;
+use crate::vpk::VPK;
+
+/// Section of the selection menu a terrain is listed in
+#[derive(Clone, Copy, PartialEq)]
+pub(crate) enum Group {
+    /// Terrains released with events and battle passes
+    Official,
+    /// The seasonal variants of the default terrain
+    Seasonal,
+    /// Terrains added in the configuration file
+    Custom,
+    /// Terrains found in the game folder which are not otherwise known, e.g. of new events
+    Discovered,
+    /// Terrains marked as favorite, always listed first
+    Favorites,
+}
+
+impl Group {
+    /// Name of the group as used in the configuration file
+    fn from_name(name: &str) -> Option<Group> {
+        match name {
+            "official" => Some(Group::Official),
+            "seasonal" => Some(Group::Seasonal),
+            "custom" => Some(Group::Custom),
+            "discovered" => Some(Group::Discovered),
+            _ => None,
+        }
+    }
+
+    /// Heading of the group in the selection menu
+    fn heading(self) -> &'static str {
+        match self {
+            Group::Official => "Official",
+            Group::Seasonal => "Seasonal",
+            Group::Custom => "Custom",
+            Group::Discovered => "Discovered",
+            Group::Favorites => "Favorites",
+        }
+    }
+}
+
+/// A terrain which can be selected in the menu
+/// # Properties
+/// - `name: String` = Name shown in the menu
+/// - `file: String` = File name of the terrain VPK in `game/dota/maps`
+/// - `group: Group` = Section of the menu the terrain is listed in
+#[derive(Clone)]
+pub(crate) struct Terrain {
+    pub(crate) name: String,
+    pub(crate) file: String,
+    group: Group,
+}
+
+impl Terrain {
+    /// Short identifier of the terrain: its file name without `dota_` and `.vpk`, e.g. `winter`
+    pub(crate) fn id(&self) -> &str {
+        let stem = self.file.strip_suffix(".vpk").unwrap_or(&self.file);
+        stem.strip_prefix("dota_").unwrap_or(stem)
+    }
+}
+
+/// Number of terrains suggested for a name which matches none
+const MAX_SUGGESTIONS: usize = 3;
+
+/// Number of single-character insertions, deletions and substitutions turning `a` into `b`
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    for (i, a) in a.chars().enumerate() {
+        let mut current = vec![i + 1];
+        for (j, &b) in b.iter().enumerate() {
+            let substitution = previous[j] + usize::from(a != b);
+            current.push(substitution.min(previous[j + 1] + 1).min(current[j] + 1));
+        }
+        previous = current;
+    }
+    previous[b.len()]
+}
+
+/// The terrains shipped with the game
+const TERRAINS: [(&str, &str, Group); 11] = [
+    ("Desert Terrain", "dota_desert.vpk", Group::Official),
+    (
+        "The King's New Journey",
+        "dota_journey.vpk",
+        Group::Official,
+    ),
+    ("Immortal Gardens", "dota_coloseum.vpk", Group::Official),
+    ("Overgrown Empire", "dota_jungle.vpk", Group::Official),
+    ("Reef's Edge", "dota_reef.vpk", Group::Official),
+    ("Sanctums of the Divine", "dota_ti10.vpk", Group::Official),
+    ("The Emerald Abyss", "dota_cavern.vpk", Group::Official),
+    (
+        "Seasonal Terrain, Autumn",
+        "dota_autumn.vpk",
+        Group::Seasonal,
+    ),
+    (
+        "Seasonal Terrain, Winter",
+        "dota_winter.vpk",
+        Group::Seasonal,
+    ),
+    (
+        "Seasonal Terrain, Spring",
+        "dota_spring.vpk",
+        Group::Seasonal,
+    ),
+    (
+        "Seasonal Terrain: Summer",
+        "dota_summer.vpk",
+        Group::Seasonal,
+    ),
+];
+
+/// Whether the terrain in `file` is shipped with the game, as opposed to a third-party one
+pub(crate) fn is_shipped(file: &str) -> bool {
+    TERRAINS.iter().any(|(_, shipped, _)| *shipped == file)
+}
+
+/// Files shipped terrains were renamed to by game updates, as (file in `TERRAINS`, new file).
+/// Renames not listed yet are found by probing, see `locate()`.
+const RENAMED_TERRAINS: [(&str, &str); 0] = [];
+
+/// Whether the VPK in `path` contains a map, as every terrain does
+fn has_map(path: PathBuf) -> bool {
+    VPK::open(path).is_ok_and(|vpk| {
+        vpk.entries()
+            .any(|(path, _)| path.starts_with("maps/") && path.ends_with(".vmap_c"))
+    })
+}
+
+/// The file the terrain VPK `file` is found in within the game's maps folder. For a shipped
+/// terrain missing under its own name this is the file it was renamed to: the one listed in
+/// `RENAMED_TERRAINS`, or else a terrain VPK which is not in `TERRAINS` and still holds the
+/// map `maps/<name>.vmap_c` or whose name differs by a typo, e.g. `dota_colosseum.vpk` for
+/// `dota_coloseum.vpk`. Names with other numbers, e.g. `dota_ti11.vpk` for `dota_ti10.vpk`,
+/// are other terrains. Returns `None` if the terrain was not found at all.
+pub(crate) fn locate(dota: &DotaInstall, file: &str) -> Option<String> {
+    if dota.terrain_vpk(file).exists() {
+        return Some(file.to_owned());
+    }
+    if !is_shipped(file) {
+        return None;
+    }
+    if let Some((_, renamed)) = RENAMED_TERRAINS
+        .iter()
+        .find(|(shipped, renamed)| *shipped == file && dota.terrain_vpk(renamed).exists())
+    {
+        return Some((*renamed).to_owned());
+    }
+
+    let prefix = dota.game.terrain_prefix?;
+    let stem = file.strip_suffix(".vpk")?;
+    let map = format!("maps/{}.vmap_c", stem);
+    let digits = |name: &str| -> String { name.chars().filter(char::is_ascii_digit).collect() };
+    let mut candidates: Vec<String> = fs::read_dir(dota.maps_dir())
+        .ok()?
+        .flatten()
+        .filter_map(|entry| entry.file_name().into_string().ok())
+        .filter(|candidate| {
+            candidate.starts_with(prefix) && candidate.ends_with(".vpk") && !is_shipped(candidate)
+        })
+        .collect();
+    candidates.sort();
+    let holds_map = candidates.iter().find(|candidate| {
+        VPK::open(dota.terrain_vpk(candidate))
+            .is_ok_and(|vpk| vpk.entries().any(|(path, _)| *path == map))
+    });
+    holds_map
+        .or_else(|| {
+            candidates.iter().find(|candidate| {
+                edit_distance(candidate, file) <= 2
+                    && digits(candidate) == digits(file)
+                    && has_map(dota.terrain_vpk(candidate))
+            })
+        })
+        .cloned()
+}
+
+/// Path of the terrain VPK `file`, or of the file it was renamed to, see `locate()`
+pub(crate) fn terrain_vpk(dota: &DotaInstall, file: &str) -> PathBuf {
+    dota.terrain_vpk(locate(dota, file).as_deref().unwrap_or(file))
+}
+
+/// The shipped terrains which were renamed in the game folder, as (file in `TERRAINS`, file
+/// it was found in), see `locate()`
+pub(crate) fn renamed_terrains(dota: &DotaInstall) -> Vec<(&'static str, String)> {
+    TERRAINS
+        .iter()
+        .filter_map(|&(_, file, _)| {
+            locate(dota, file)
+                .filter(|found| found != file)
+                .map(|found| (file, found))
+        })
+        .collect()
+}
+
+/// Every known terrain: the ones shipped with the game followed by the custom ones from the
+/// configuration file and the discovered ones, see `MenuConfig::discover()`, with aliases
+/// applied. Hidden terrains are included.
+pub(crate) struct TerrainCatalog {
+    terrains: Vec<Terrain>,
+}
+
+impl TerrainCatalog {
+    /// Build the catalog from the terrains shipped with the game and the menu settings
+    pub(crate) fn new(config: &MenuConfig) -> TerrainCatalog {
+        let terrains = TERRAINS
+            .iter()
+            .map(|&(name, file, group)| Terrain {
+                name: name.to_owned(),
+                file: file.to_owned(),
+                group,
+            })
+            .chain(config.custom.iter().cloned())
+            .chain(config.discovered.iter().cloned())
+            .map(|mut terrain| {
+                if let Some(alias) = config.aliases.get(&terrain.file) {
+                    terrain.name = alias.clone();
+                }
+                terrain
+            })
+            .collect();
+        TerrainCatalog { terrains }
+    }
+
+    /// Iterate over every terrain in the catalog
+    pub(crate) fn iter(&self) -> impl Iterator<Item = &Terrain> {
+        self.terrains.iter()
+    }
+
+    /// Find a terrain by its short identifier, see `Terrain::id()`, ignoring case
+    pub(crate) fn by_id(&self, id: &str) -> Option<&Terrain> {
+        self.iter()
+            .find(|terrain| terrain.id().eq_ignore_ascii_case(id))
+    }
+
+    /// Find a terrain by its name in the menu, ignoring case
+    pub(crate) fn by_name(&self, name: &str) -> Option<&Terrain> {
+        let name = name.to_lowercase();
+        self.iter()
+            .find(|terrain| terrain.name.to_lowercase() == name)
+    }
+
+    /// Find a terrain by its file name, with or without `.vpk`, ignoring case
+    pub(crate) fn by_file(&self, file: &str) -> Option<&Terrain> {
+        self.iter().find(|terrain| {
+            terrain.file.eq_ignore_ascii_case(file)
+                || terrain
+                    .file
+                    .strip_suffix(".vpk")
+                    .is_some_and(|stem| stem.eq_ignore_ascii_case(file))
+        })
+    }
+
+    /// Find a terrain by its file name, identifier or name, in that order
+    pub(crate) fn find(&self, query: &str) -> Option<&Terrain> {
+        self.by_file(query)
+            .or_else(|| self.by_id(query))
+            .or_else(|| self.by_name(query))
+    }
+}
+
+/// Settings of the selection menu, read from the `[menu]` table of the configuration file
+/// # Properties
+/// - `order: Vec<Group>` = Order of the sections. Sections left out are not shown
+/// - `aliases: HashMap<String, String>` = Names shown instead of the default ones, keyed by
+///   the file name of the terrain
+/// - `nicknames: HashMap<String, String>` = Additional names terrains can be given by, in
+///   lowercase, each mapped to a file name, identifier or name of the terrain
+/// - `custom: Vec<Terrain>` = Additional terrains, listed in the `Custom` section
+/// - `discovered: Vec<Terrain>` = Terrains found in the game folder, listed in the
+///   `Discovered` section. Empty until `discover()` is called
+/// - `favorites: Vec<String>` = File names of the terrains listed first
+/// - `hidden: Vec<String>` = File names of the terrains never listed
+/// - `default: Option<String>` = Terrain applied when there is no console to show the menu in
+pub(crate) struct MenuConfig {
+    order: Vec<Group>,
+    aliases: HashMap<String, String>,
+    nicknames: HashMap<String, String>,
+    custom: Vec<Terrain>,
+    discovered: Vec<Terrain>,
+    favorites: Vec<String>,
+    hidden: Vec<String>,
+    pub(crate) default: Option<String>,
+}
+
+impl Default for MenuConfig {
+    fn default() -> Self {
+        MenuConfig {
+            order: vec![
+                Group::Official,
+                Group::Seasonal,
+                Group::Custom,
+                Group::Discovered,
+            ],
+            aliases: HashMap::new(),
+            nicknames: HashMap::new(),
+            custom: Vec::new(),
+            discovered: Vec::new(),
+            favorites: Vec::new(),
+            hidden: Vec::new(),
+            default: None,
+        }
+    }
+}
+
+impl MenuConfig {
+    /// Read the `[menu]` table of a parsed configuration file:
+    /// `order = ["seasonal", "official", "custom"]`, `aliases = { "dota_desert.vpk" = "Desert" }`,
+    /// `nicknames = { sotd = "ti10" }`, `favorites` and `hidden` arrays of file names, a
+    /// `default` terrain and `[[menu.custom]]` tables with a `name` and a `file`
+    pub(crate) fn from_toml(document: &Table) -> Result<MenuConfig, String> {
+        let mut config = MenuConfig::default();
+        let Some(menu) = document.get("menu") else {
+            return Ok(config);
+        };
+        let menu = menu
+            .as_table()
+            .ok_or_else(|| String::from("\"menu\" must be a table"))?;
+
+        if let Some(order) = menu.get("order") {
+            let order = order
+                .as_array()
+                .ok_or_else(|| String::from("\"menu.order\" must be an array"))?;
+            config.order = order
+                .iter()
+                .map(|group| {
+                    group
+                        .as_str()
+                        .and_then(Group::from_name)
+                        .ok_or_else(|| format!("unknown menu section {}", group))
+                })
+                .collect::<Result<Vec<Group>, String>>()?;
+        }
+
+        if let Some(aliases) = menu.get("aliases") {
+            let aliases = aliases
+                .as_table()
+                .ok_or_else(|| String::from("\"menu.aliases\" must be a table"))?;
+            for (file, name) in aliases {
+                let name = name
+                    .as_str()
+                    .ok_or_else(|| format!("alias of {} must be a string", file))?;
+                config.aliases.insert(file.clone(), name.to_owned());
+            }
+        }
+
+        if let Some(nicknames) = menu.get("nicknames") {
+            let nicknames = nicknames
+                .as_table()
+                .ok_or_else(|| String::from("\"menu.nicknames\" must be a table"))?;
+            for (nickname, terrain) in nicknames {
+                let terrain = terrain
+                    .as_str()
+                    .ok_or_else(|| format!("nickname {} must name a terrain", nickname))?;
+                config
+                    .nicknames
+                    .insert(nickname.to_lowercase(), terrain.to_owned());
+            }
+        }
+
+        for (key, list) in [
+            ("favorites", &mut config.favorites),
+            ("hidden", &mut config.hidden),
+        ] {
+            let Some(files) = menu.get(key) else {
+                continue;
+            };
+            *list = files
+                .as_array()
+                .and_then(|files| {
+                    files
+                        .iter()
+                        .map(|file| file.as_str().map(str::to_owned))
+                        .collect()
+                })
+                .ok_or_else(|| format!("\"menu.{}\" must be an array of file names", key))?;
+        }
+
+        if let Some(default) = menu.get("default") {
+            let default = default
+                .as_str()
+                .ok_or_else(|| String::from("\"menu.default\" must be a terrain name"))?;
+            config.default = Some(default.to_owned());
+        }
+
+        if let Some(custom) = menu.get("custom") {
+            let custom = custom
+                .as_array()
+                .ok_or_else(|| String::from("\"menu.custom\" must be an array of tables"))?;
+            for terrain in custom {
+                let (Some(name), Some(file)) = (
+                    terrain.get("name").and_then(Value::as_str),
+                    terrain.get("file").and_then(Value::as_str),
+                ) else {
+                    return Err(String::from(
+                        "custom terrains must have a \"name\" and a \"file\"",
+                    ));
+                };
+                config.custom.push(Terrain {
+                    name: name.to_owned(),
+                    file: file.to_owned(),
+                    group: Group::Custom,
+                });
+            }
+        }
+        Ok(config)
+    }
+
+    /// Every terrain shown in the menu, in menu order, with aliases applied. Favorites come
+    /// first and hidden terrains are left out.
+    pub(crate) fn terrains(&self) -> Vec<Terrain> {
+        let mut terrains: Vec<Terrain> = TerrainCatalog::new(self)
+            .terrains
+            .into_iter()
+            .filter(|terrain| !self.hidden.contains(&terrain.file))
+            .map(|mut terrain| {
+                if self.favorites.contains(&terrain.file) {
+                    terrain.group = Group::Favorites;
+                }
+                terrain
+            })
+            .filter(|terrain| {
+                terrain.group == Group::Favorites || self.order.contains(&terrain.group)
+            })
+            .collect();
+        terrains.sort_by_key(|terrain| match terrain.group {
+            Group::Favorites => 0,
+            group => 1 + self.order.iter().position(|&g| g == group).unwrap(),
+        });
+        terrains
+    }
+
+    /// The favorite terrains, in the order they were added, with aliases applied
+    pub(crate) fn favorites(&self) -> Vec<Terrain> {
+        let catalog = TerrainCatalog::new(self);
+        self.favorites
+            .iter()
+            .filter_map(|file| catalog.iter().find(|terrain| &terrain.file == file))
+            .map(|terrain| Terrain {
+                name: terrain.name.clone(),
+                file: terrain.file.clone(),
+                group: Group::Favorites,
+            })
+            .collect()
+    }
+
+    /// Add the terrains in the game's maps folder which are neither shipped nor custom, e.g.
+    /// of an event released after this version, as "Unknown terrain (<file>)". Only
+    /// `dota_*.vpk` files which can be opened and contain a map are added, none for games
+    /// without terrains (see `GameProfile::terrain_prefix`). Shipped terrains which were
+    /// renamed are not added again, see `locate()`.
+    pub(crate) fn discover(&mut self, dota: &DotaInstall) {
+        let catalog = TerrainCatalog::new(self);
+        let Some(prefix) = dota.game.terrain_prefix else {
+            return;
+        };
+        let Ok(dir) = fs::read_dir(dota.maps_dir()) else {
+            return;
+        };
+        let mut files: Vec<String> = dir
+            .flatten()
+            .filter_map(|entry| entry.file_name().into_string().ok())
+            .filter(|file| {
+                // Split archives (`_dir.vpk`, `_000.vpk`) are not terrains by themselves
+                file.strip_prefix(prefix)
+                    .and_then(|file| file.strip_suffix(".vpk"))
+                    .is_some_and(|stem| {
+                        !stem.ends_with("_dir")
+                            && !stem
+                                .rsplit_once('_')
+                                .is_some_and(|(_, n)| n.bytes().all(|b| b.is_ascii_digit()))
+                    })
+            })
+            .filter(|file| catalog.by_file(file).is_none())
+            .collect();
+        files.sort();
+        // Shipped terrains renamed by an update are listed under their own name
+        let renamed: Vec<String> = renamed_terrains(dota)
+            .into_iter()
+            .map(|(_, file)| file)
+            .collect();
+        files.retain(|file| !renamed.contains(file));
+
+        for file in files {
+            if has_map(dota.terrain_vpk(&file)) {
+                self.discovered.push(Terrain {
+                    name: format!("Unknown terrain ({})", file),
+                    file,
+                    group: Group::Discovered,
+                });
+            }
+        }
+    }
+
+    /// Find a terrain by its file name (with or without `dota_` and `.vpk`, e.g. `winter`), its
+    /// name in the menu or one of its nicknames, ignoring case, see `TerrainCatalog::find()`
+    pub(crate) fn find(&self, query: &str) -> Option<Terrain> {
+        let catalog = TerrainCatalog::new(self);
+        catalog
+            .find(query)
+            .or_else(|| {
+                let terrain = self.nicknames.get(&query.to_lowercase())?;
+                catalog.find(terrain)
+            })
+            .cloned()
+    }
+
+    /// Find a terrain like `find()`, or else the only terrain whose identifier or name
+    /// contains `query`, e.g. `sanctums`. Fails with a message suggesting the terrains with
+    /// similar names if none or several terrains match.
+    pub(crate) fn resolve(&self, query: &str) -> Result<Terrain, String> {
+        if let Some(terrain) = self.find(query) {
+            return Ok(terrain);
+        }
+        let catalog = TerrainCatalog::new(self);
+        let lowercase = query.to_lowercase();
+        let matches: Vec<&Terrain> = catalog
+            .iter()
+            .filter(|terrain| {
+                !lowercase.is_empty()
+                    && (terrain.id().to_lowercase().contains(&lowercase)
+                        || terrain.name.to_lowercase().contains(&lowercase))
+            })
+            .collect();
+        if let [terrain] = matches.as_slice() {
+            return Ok((*terrain).clone());
+        }
+
+        let quoted = |terrains: &[&Terrain]| -> String {
+            let names: Vec<String> = terrains
+                .iter()
+                .map(|terrain| format!("\"{}\"", terrain.name))
+                .collect();
+            match names.as_slice() {
+                [rest @ .., last] if !rest.is_empty() => format!("{} or {}", rest.join(", "), last),
+                _ => names.concat(),
+            }
+        };
+        if !matches.is_empty() {
+            return Err(format!(
+                "\"{}\" matches several terrains, did you mean {}?",
+                query,
+                quoted(&matches)
+            ));
+        }
+
+        // Terrains one of whose names is within a few typos of the query
+        let mut close: Vec<(usize, &Terrain)> = catalog
+            .iter()
+            .filter_map(|terrain| {
+                let nicknames = self
+                    .nicknames
+                    .iter()
+                    .filter(|(_, target)| {
+                        catalog
+                            .find(target)
+                            .is_some_and(|target| target.file == terrain.file)
+                    })
+                    .map(|(nickname, _)| nickname.as_str());
+                let distance = [terrain.id(), terrain.name.as_str()]
+                    .into_iter()
+                    .chain(nicknames)
+                    .map(|name| edit_distance(&lowercase, &name.to_lowercase()))
+                    .min()?;
+                (distance <= (lowercase.chars().count() / 3).max(2)).then_some((distance, terrain))
+            })
+            .collect();
+        close.sort_by_key(|(distance, _)| *distance);
+        let close: Vec<&Terrain> = close
+            .into_iter()
+            .take(MAX_SUGGESTIONS)
+            .map(|(_, terrain)| terrain)
+            .collect();
+        if close.is_empty() {
+            Err(format!("Unknown terrain \"{}\"", query))
+        } else {
+            Err(format!(
+                "Unknown terrain \"{}\", did you mean {}?",
+                query,
+                quoted(&close)
+            ))
+        }
+    }
+}
+
+/// Show the selection menu, grouped into sections, and return the terrain entered by the user,
+/// by its number or by name, see `MenuConfig::resolve()`. Returns `None` if there is no input
+/// to read, e.g. when started without a console.
+pub(crate) fn get_selection(config: &MenuConfig) -> Option<Terrain> {
+    let mut terrains = config.terrains();
+    println!("Select a Terrain to apply");
+    let mut group = None;
+    for (i, terrain) in terrains.iter().enumerate() {
+        if group != Some(terrain.group) {
+            group = Some(terrain.group);
+            println!("\n{}:", terrain.group.heading());
+        }
+        println!("[{}] - {}", i + 1, terrain.name);
+    }
+    println!();
+
+    println!("Enter a number or name: ");
+    let mut selection = String::new();
+    if !matches!(io::stdin().read_line(&mut selection), Ok(n) if n > 0) {
+        return None;
+    }
+    let selection = selection.trim();
+    let terrain = match selection.parse::<usize>() {
+        Ok(number) if (1..=terrains.len()).contains(&number) => terrains.swap_remove(number - 1),
+        Ok(_) => notify::fatal("Invalid selection."),
+        // A terrain can also be entered by name
+        Err(_) => match config.resolve(selection) {
+            Ok(terrain) => terrain,
+            Err(e) => notify::fatal(&format!("{}.", e)),
+        },
+    };
+    println!("Selected: {}. Applying terrain...", terrain.name);
+    Some(terrain)
+}
@@ -0,0 +1,88 @@
+use std::{collections::HashMap, io, path::Path};
+
+use crate::vpk::VPK;
+
+/// Whether a file in a VPK belongs to a minimap (minimap images or map overviews)
+pub(crate) fn is_minimap(path: &str) -> bool {
+    let path = path.to_lowercase();
+    path.contains("minimap") || path.contains("overviews/")
+}
+
+/// Copy the minimap files a terrain ships for its own map (e.g. `..._dota_desert.vtex_c`)
+/// over the names used by the base map `base`, so the minimap matches the terrain. Returns
+/// the paths which were written.
+pub(crate) fn inject(
+    target: &mut HashMap<String, Vec<u8>>,
+    map_name: &str,
+    base: &str,
+) -> Vec<String> {
+    let mut injected = Vec::new();
+    let renames: Vec<(String, String)> = target
+        .keys()
+        .filter(|path| is_minimap(path) && path.contains(map_name))
+        .map(|path| (path.clone(), path.replace(map_name, base)))
+        .collect();
+    for (path, base_name) in renames {
+        let data = target[&path].clone();
+        target.insert(base_name.clone(), data);
+        injected.push(base_name);
+    }
+    injected
+}
+
+/// After patching, check where the minimap files the game loads (those named like the
+/// ones of the base map) came from. Files are matched to the terrain or base VPK by CRC.
+/// Returns a summary for the user.
+pub(crate) fn check(
+    out_path: &Path,
+    base_path: &Path,
+    target_path: &Path,
+) -> io::Result<Vec<String>> {
+    let out_vpk = VPK::open(out_path.to_path_buf())?;
+    let base_vpk = VPK::open(base_path.to_path_buf())?;
+    let target_vpk = VPK::open(target_path.to_path_buf())?;
+
+    let target_crcs: Vec<u32> = target_vpk
+        .entries()
+        .filter(|(path, _)| is_minimap(path))
+        .map(|(_, metadata)| metadata.crc32())
+        .collect();
+    let mut from_terrain = 0;
+    let mut inherited = 0;
+    let mut other = 0;
+    for (path, metadata) in out_vpk.entries().filter(|(path, _)| is_minimap(path)) {
+        let Some(base_metadata) = base_vpk.entry(path) else {
+            continue;
+        };
+        if target_crcs.contains(&metadata.crc32()) {
+            from_terrain += 1;
+        } else if base_metadata.crc32() == metadata.crc32() {
+            inherited += 1;
+        } else {
+            other += 1;
+        }
+    }
+
+    let mut summary = Vec::new();
+    if from_terrain + inherited + other == 0 {
+        summary.push(String::from(
+            "Minimap: The base map has no minimap files to check.",
+        ));
+    } else if inherited == 0 {
+        summary.push(format!(
+            "Minimap: {} file(s) from the terrain, {} from overrides.",
+            from_terrain, other
+        ));
+    } else {
+        summary.push(format!(
+            "Minimap: {} file(s) inherited from the base map, {} from the terrain, {} from overrides.",
+            inherited, from_terrain, other
+        ));
+        if !target_crcs.is_empty() {
+            summary.push(String::from(
+                "The terrain ships its own minimap. Apply it with --terrain-minimap.",
+            ));
+        }
+    }
+    Ok(summary)
+}
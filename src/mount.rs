@@ -0,0 +1,252 @@
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::cli::Args;
+use crate::vpk::VpkOptions;
+
+#[cfg(all(feature = "mount", target_os = "linux"))]
+mod fuse {
+    use std::{
+        collections::BTreeMap,
+        ffi::OsStr,
+        os::unix::fs::MetadataExt,
+        path::{Path, PathBuf},
+        sync::Mutex,
+        time::{Duration, SystemTime},
+    };
+
+    use fuser::{
+        Config, Errno, FileAttr, FileHandle, FileType, Filesystem, Generation, INodeNo, LockOwner,
+        MountOption, OpenFlags, ReplyAttr, ReplyData, ReplyDirectory, ReplyEntry, Request,
+    };
+
+    use crate::vpk::{self, VpkOptions, VPK};
+
+    const TTL: Duration = Duration::from_secs(60);
+
+    /// A node in the mounted tree. Inode numbers are the index into `VpkFs::nodes` plus one.
+    enum Node {
+        Dir {
+            parent: u64,
+            children: BTreeMap<String, u64>,
+        },
+        File {
+            path: String,
+            size: u64,
+        },
+    }
+
+    /// Read-only FUSE filesystem serving the files of a lazily opened VPK
+    struct VpkFs {
+        vpk: Mutex<VPK>,
+        nodes: Vec<Node>,
+        mtime: SystemTime,
+        uid: u32,
+        gid: u32,
+    }
+
+    impl VpkFs {
+        /// Build the directory tree from the index of the VPK
+        fn new(vpk: VPK, vpk_path: &Path) -> VpkFs {
+            let mut nodes = vec![Node::Dir {
+                parent: INodeNo::ROOT.0,
+                children: BTreeMap::new(),
+            }];
+            for (path, metadata) in vpk.entries() {
+                if !vpk::is_safe_path(path) {
+                    continue;
+                }
+                let mut parent = INodeNo::ROOT.0;
+                let mut components = path.split('/').peekable();
+                while let Some(name) = components.next() {
+                    let existing = match &nodes[parent as usize - 1] {
+                        Node::Dir { children, .. } => children.get(name).copied(),
+                        Node::File { .. } => None,
+                    };
+                    let ino = match existing {
+                        Some(ino) => ino,
+                        None => {
+                            let node = if components.peek().is_some() {
+                                Node::Dir {
+                                    parent,
+                                    children: BTreeMap::new(),
+                                }
+                            } else {
+                                Node::File {
+                                    path: path.clone(),
+                                    size: metadata.entry_length(),
+                                }
+                            };
+                            nodes.push(node);
+                            let ino = nodes.len() as u64;
+                            if let Node::Dir { children, .. } = &mut nodes[parent as usize - 1] {
+                                children.insert(name.to_owned(), ino);
+                            }
+                            ino
+                        }
+                    };
+                    parent = ino;
+                }
+            }
+
+            let metadata = std::fs::metadata(vpk_path).ok();
+            VpkFs {
+                vpk: Mutex::new(vpk),
+                nodes,
+                mtime: metadata
+                    .as_ref()
+                    .and_then(|m| m.modified().ok())
+                    .unwrap_or(SystemTime::UNIX_EPOCH),
+                uid: metadata.as_ref().map_or(0, |m| m.uid()),
+                gid: metadata.as_ref().map_or(0, |m| m.gid()),
+            }
+        }
+
+        fn node(&self, ino: INodeNo) -> Option<&Node> {
+            self.nodes.get((ino.0 as usize).checked_sub(1)?)
+        }
+
+        fn attr(&self, ino: INodeNo) -> Option<FileAttr> {
+            let (kind, size, perm, nlink) = match self.node(ino)? {
+                Node::Dir { .. } => (FileType::Directory, 0, 0o555, 2),
+                Node::File { size, .. } => (FileType::RegularFile, *size, 0o444, 1),
+            };
+            Some(FileAttr {
+                ino,
+                size,
+                blocks: size.div_ceil(512),
+                atime: self.mtime,
+                mtime: self.mtime,
+                ctime: self.mtime,
+                crtime: self.mtime,
+                kind,
+                perm,
+                nlink,
+                uid: self.uid,
+                gid: self.gid,
+                rdev: 0,
+                blksize: 4096,
+                flags: 0,
+            })
+        }
+    }
+
+    impl Filesystem for VpkFs {
+        fn lookup(&self, _req: &Request, parent: INodeNo, name: &OsStr, reply: ReplyEntry) {
+            let child = match (self.node(parent), name.to_str()) {
+                (Some(Node::Dir { children, .. }), Some(name)) => children.get(name).copied(),
+                _ => None,
+            };
+            match child.and_then(|ino| self.attr(INodeNo(ino))) {
+                Some(attr) => reply.entry(&TTL, &attr, Generation(0)),
+                None => reply.error(Errno::ENOENT),
+            }
+        }
+
+        fn getattr(&self, _req: &Request, ino: INodeNo, _fh: Option<FileHandle>, reply: ReplyAttr) {
+            match self.attr(ino) {
+                Some(attr) => reply.attr(&TTL, &attr),
+                None => reply.error(Errno::ENOENT),
+            }
+        }
+
+        fn read(
+            &self,
+            _req: &Request,
+            ino: INodeNo,
+            _fh: FileHandle,
+            offset: u64,
+            size: u32,
+            _flags: OpenFlags,
+            _lock_owner: Option<LockOwner>,
+            reply: ReplyData,
+        ) {
+            let Some(Node::File { path, .. }) = self.node(ino) else {
+                reply.error(Errno::ENOENT);
+                return;
+            };
+            let mut vpk = self.vpk.lock().unwrap();
+            match vpk.read_entry_at(path, offset, size as usize) {
+                Ok(data) => reply.data(&data),
+                Err(_) => reply.error(Errno::EIO),
+            }
+        }
+
+        fn readdir(
+            &self,
+            _req: &Request,
+            ino: INodeNo,
+            _fh: FileHandle,
+            offset: u64,
+            mut reply: ReplyDirectory,
+        ) {
+            let Some(Node::Dir { parent, children }) = self.node(ino) else {
+                reply.error(Errno::ENOTDIR);
+                return;
+            };
+            let entries = [(".", ino.0), ("..", *parent)]
+                .into_iter()
+                .chain(children.iter().map(|(name, ino)| (name.as_str(), *ino)));
+            for (i, (name, child)) in entries.enumerate().skip(offset as usize) {
+                let kind = match self.node(INodeNo(child)) {
+                    Some(Node::File { .. }) => FileType::RegularFile,
+                    _ => FileType::Directory,
+                };
+                if reply.add(INodeNo(child), i as u64 + 1, kind, name) {
+                    break;
+                }
+            }
+            reply.ok();
+        }
+    }
+
+    /// Mount the VPK at `vpk_path`, opened with `options`, read-only on `mountpoint`. Blocks
+    /// until unmounted.
+    pub(super) fn mount(
+        vpk_path: PathBuf,
+        options: VpkOptions,
+        mountpoint: &Path,
+    ) -> std::io::Result<()> {
+        let fs = VpkFs::new(VPK::open_with(vpk_path.clone(), options)?, &vpk_path);
+        let mut config = Config::default();
+        config.mount_options = vec![
+            MountOption::RO,
+            MountOption::FSName(vpk_path.display().to_string()),
+            MountOption::Subtype("vpk".to_owned()),
+        ];
+        fuser::mount(fs, mountpoint, &config)
+    }
+}
+
+/// Entry point for the `mount <vpk> <mountpoint>` command. Exposes the contents of a VPK as
+/// a read-only filesystem until it is unmounted (e.g. with `fusermount -u <mountpoint>`).
+pub(crate) fn run(args: &Args) {
+    let [vpk_path, mountpoint] = args.positional.as_slice() else {
+        eprintln!("Usage: dota-terrain-mod mount <vpk> <mountpoint>");
+        std::process::exit(1);
+    };
+    let options = VpkOptions::from_args(args);
+    if let Err(e) = mount(PathBuf::from(vpk_path), options, Path::new(mountpoint)) {
+        eprintln!("Dota-Terrain-Mod error: Failed to mount: {}", e);
+        std::process::exit(1);
+    }
+}
+
+#[cfg(all(feature = "mount", target_os = "linux"))]
+fn mount(vpk_path: PathBuf, options: VpkOptions, mountpoint: &Path) -> io::Result<()> {
+    println!(
+        "Mounting {} on {}. Unmount with \"fusermount -u {}\".",
+        vpk_path.display(),
+        mountpoint.display(),
+        mountpoint.display()
+    );
+    fuse::mount(vpk_path, options, mountpoint)
+}
+
+#[cfg(not(all(feature = "mount", target_os = "linux")))]
+fn mount(_vpk_path: PathBuf, _options: VpkOptions, _mountpoint: &Path) -> io::Result<()> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "mounting requires Linux and a build with the \"mount\" feature",
+    ))
+}
@@ -0,0 +1,87 @@
+use std::io::Write;
+
+use crate::config;
+use crate::history;
+
+/// Name of the log file inside the configuration directory
+const LOG_FILE: &str = "dota-terrain-mod.log";
+
+/// Append a timestamped line to the log file in the configuration directory. Used when
+/// there is no console to show messages in.
+pub(crate) fn log(message: &str) {
+    let Some(dir) = config::config_dir() else {
+        return;
+    };
+    let written = std::fs::create_dir_all(&dir).and_then(|_| {
+        let mut file = std::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(dir.join(LOG_FILE))?;
+        writeln!(
+            file,
+            "[{}] {}",
+            history::format_timestamp(history::now()),
+            message
+        )
+    });
+    if let Err(e) = written {
+        eprintln!("Warning: Could not write the log file: {}", e);
+    }
+}
+
+/// The last `count` lines of the log file, oldest first
+pub(crate) fn last_lines(count: usize) -> Vec<String> {
+    let Some(text) =
+        config::config_dir().and_then(|dir| std::fs::read_to_string(dir.join(LOG_FILE)).ok())
+    else {
+        return Vec::new();
+    };
+    let lines: Vec<&str> = text.lines().collect();
+    lines[lines.len().saturating_sub(count)..]
+        .iter()
+        .map(|line| line.to_string())
+        .collect()
+}
+
+/// Show a message outside the console: a message box on Windows, a desktop notification
+/// (through `notify-send`) on Linux. Failures are ignored, the message is also printed.
+pub(crate) fn show(title: &str, message: &str) {
+    println!("{}", message);
+    show_native(title, message);
+}
+
+/// Report an error ending the program on stderr and, on Windows, in a message box as well,
+/// so users who started the program by double-clicking see it before the console closes
+pub(crate) fn fatal(message: &str) -> ! {
+    eprintln!("{}", message);
+    #[cfg(target_os = "windows")]
+    show_native("Dota Terrain Mod", message);
+    std::process::exit(1);
+}
+
+#[cfg(target_os = "windows")]
+fn show_native(title: &str, message: &str) {
+    use std::ffi::c_void;
+
+    #[link(name = "user32")]
+    extern "system" {
+        fn MessageBoxW(
+            window: *mut c_void,
+            text: *const u16,
+            caption: *const u16,
+            kind: u32,
+        ) -> i32;
+    }
+
+    let wide = |text: &str| -> Vec<u16> { text.encode_utf16().chain(Some(0)).collect() };
+    let (message, title) = (wide(message), wide(title));
+    unsafe { MessageBoxW(std::ptr::null_mut(), message.as_ptr(), title.as_ptr(), 0) };
+}
+
+#[cfg(not(target_os = "windows"))]
+fn show_native(title: &str, message: &str) {
+    let _ = std::process::Command::new("notify-send")
+        .arg(title)
+        .arg(message)
+        .status();
+}
@@ -0,0 +1,486 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    path::{Path, PathBuf},
+    thread,
+    time::{Duration, SystemTime},
+};
+
+use regex::Regex;
+use toml::{Table, Value};
+
+use crate::attributes::{self, FileAttributes};
+use crate::cli::Args;
+use crate::config::Config;
+use crate::conflicts::Policy;
+use crate::ignore::{self, IgnoreList};
+use crate::lzma;
+use crate::pack_cache::{self, PackCache};
+use crate::rules;
+use crate::utils;
+use crate::vpk::{self, VpkOptions};
+
+/// Time between checks of the sources for changes with `--watch`
+const WATCH_INTERVAL: Duration = Duration::from_millis(500);
+/// How long the sources have to stay unchanged with `--watch` before they are packed again,
+/// unless set with `--quiet-period` or `watch_quiet_period`, so an editor or exporter can
+/// finish writing
+const DEFAULT_QUIET_PERIOD: Duration = Duration::from_secs(1);
+
+/// A directory packed into the VPK
+/// # Properties
+/// - `root: PathBuf` = The directory
+/// - `include: Vec<Regex>` = Globs of the files packed, relative to `root`. Every file if empty
+/// - `exclude: Vec<Regex>` = Globs of the files left out, relative to `root`
+/// - `target: String` = Directory inside the VPK the files are placed in, e.g.
+///   `materials/custom`. The root of the VPK if empty
+struct PackSource {
+    root: PathBuf,
+    include: Vec<Regex>,
+    exclude: Vec<Regex>,
+    target: String,
+}
+
+impl PackSource {
+    /// A source packing every file below `root` to the same path in the VPK
+    fn new(root: PathBuf) -> PackSource {
+        PackSource {
+            root,
+            include: Vec::new(),
+            exclude: Vec::new(),
+            target: String::new(),
+        }
+    }
+
+    /// Read a `[[sources]]` table of a recipe: a `root` directory, relative to `base_dir`,
+    /// and optionally `include` and `exclude` globs and a `target` directory
+    fn from_table(table: &Table, base_dir: &Path) -> Result<PackSource, String> {
+        let Some(root) = table.get("root").and_then(Value::as_str) else {
+            return Err(String::from("source without a \"root\" directory"));
+        };
+        let globs = |key: &str| -> Result<Vec<Regex>, String> {
+            match table.get(key) {
+                None => Ok(Vec::new()),
+                Some(Value::Array(globs)) => globs
+                    .iter()
+                    .map(|glob| {
+                        glob.as_str()
+                            .map(rules::glob_regex)
+                            .ok_or_else(|| format!("\"{}\" must be an array of globs", key))
+                    })
+                    .collect(),
+                Some(_) => Err(format!("\"{}\" must be an array of globs", key)),
+            }
+        };
+        let target = match table.get("target") {
+            None => String::new(),
+            Some(Value::String(target)) => target.trim_matches('/').to_owned(),
+            Some(_) => return Err(String::from("\"target\" must be a directory in the VPK")),
+        };
+        Ok(PackSource {
+            root: base_dir.join(root),
+            include: globs("include")?,
+            exclude: globs("exclude")?,
+            target,
+        })
+    }
+
+    /// Whether the file at `path`, relative to `root`, is packed according to the globs
+    fn selects(&self, path: &str) -> bool {
+        (self.include.is_empty() || self.include.iter().any(|glob| glob.is_match(path)))
+            && !self.exclude.iter().any(|glob| glob.is_match(path))
+    }
+}
+
+/// What is packed where, given on the command line or by a recipe file (`vpk.toml`) kept
+/// alongside the assets
+/// # Properties
+/// - `sources: Vec<PackSource>` = The directories packed, in order. Later sources replace
+///   files of earlier ones at the same path
+/// - `output: PathBuf` = Path of the VPK written
+/// - `compress: bool` = Store the files LZMA-compressed
+/// - `keep_times: bool` = Record the modification times and read-only flags of the files
+/// - `conflicts_policy: Option<PathBuf>` = Policy file choosing the source of files several
+///   sources provide, see `conflicts::Policy`
+struct PackRecipe {
+    sources: Vec<PackSource>,
+    output: PathBuf,
+    compress: bool,
+    keep_times: bool,
+    conflicts_policy: Option<PathBuf>,
+}
+
+impl PackRecipe {
+    /// Read a recipe file. Paths in it are relative to the directory of the file.
+    fn load(path: &Path) -> Result<PackRecipe, String> {
+        let text = std::fs::read_to_string(path)
+            .map_err(|e| format!("Could not read {}: {}", path.display(), e))?;
+        let document: Table = text
+            .parse()
+            .map_err(|e| format!("{}: {}", path.display(), e))?;
+        let invalid = |e: String| format!("{}: {}", path.display(), e);
+        let base_dir = path.parent().unwrap_or(Path::new(""));
+
+        let Some(output) = document.get("output").and_then(Value::as_str) else {
+            return Err(invalid(String::from("no \"output\" path")));
+        };
+        let flag = |key: &str| -> Result<bool, String> {
+            match document.get(key) {
+                None => Ok(false),
+                Some(Value::Boolean(value)) => Ok(*value),
+                Some(_) => Err(invalid(format!("\"{}\" must be true or false", key))),
+            }
+        };
+        let sources = match document.get("sources") {
+            Some(Value::Array(sources)) => sources
+                .iter()
+                .map(|source| match source {
+                    Value::Table(table) => PackSource::from_table(table, base_dir),
+                    _ => Err(String::from("\"sources\" must be an array of tables")),
+                })
+                .collect::<Result<Vec<PackSource>, String>>()
+                .map_err(invalid)?,
+            _ => return Err(invalid(String::from("no [[sources]] to pack"))),
+        };
+        let conflicts_policy = match document.get("conflicts_policy") {
+            None => None,
+            Some(Value::String(policy)) => Some(base_dir.join(policy)),
+            Some(_) => {
+                return Err(invalid(String::from(
+                    "\"conflicts_policy\" must be the path of a policy file",
+                )))
+            }
+        };
+        Ok(PackRecipe {
+            sources,
+            output: base_dir.join(output),
+            compress: flag("compress")?,
+            keep_times: flag("keep_times")?,
+            conflicts_policy,
+        })
+    }
+}
+
+/// Files gathered for packing
+/// # Properties
+/// - `files: HashMap<String, PathBuf>` = Every file to pack, keyed by its path in the VPK
+/// - `attributes: BTreeMap<String, FileAttributes>` = Attributes of every file
+/// - `origins: HashMap<String, PathBuf>` = Root of the source each file was taken from
+/// - `ignored: usize` = Number of files left out by `.vpkignore` files
+#[derive(Default)]
+struct Gathered {
+    files: HashMap<String, PathBuf>,
+    origins: HashMap<String, PathBuf>,
+    attributes: BTreeMap<String, FileAttributes>,
+    ignored: usize,
+}
+
+/// Add the files of `source` to `gathered`. Files matching the patterns of a `.vpkignore`
+/// file at the root of the source (see `IgnoreList`) or not selected by its globs are left
+/// out. Files without an extension or outside a directory are never looked up by the game
+/// and are skipped. Files an earlier source provided are replaced, unless `policy` prefers the
+/// earlier source.
+fn gather(
+    source: &PackSource,
+    policy: Option<&Policy>,
+    gathered: &mut Gathered,
+) -> Result<(), String> {
+    let root = &source.root;
+    let files =
+        utils::walk_dir(root).map_err(|e| format!("Could not read {}: {}", root.display(), e))?;
+    let ignore_list = IgnoreList::load(root).map_err(|e| {
+        format!(
+            "Could not read {}: {}",
+            root.join(ignore::IGNORE_FILE).display(),
+            e
+        )
+    })?;
+
+    for file in files {
+        let relative = file
+            .strip_prefix(root)
+            .unwrap()
+            .iter()
+            .map(|component| component.to_string_lossy())
+            .collect::<Vec<_>>()
+            .join("/");
+        if relative == ignore::IGNORE_FILE || !source.selects(&relative) {
+            continue;
+        }
+        if ignore_list.is_ignored(&relative) {
+            gathered.ignored += 1;
+            continue;
+        }
+        let path = if source.target.is_empty() {
+            relative
+        } else {
+            format!("{}/{}", source.target, relative)
+        };
+        let vpk_path = Path::new(&path);
+        if vpk_path.extension().is_none() || vpk_path.parent() == Some(Path::new("")) {
+            eprintln!(
+                "Warning: Skipping {}, files need an extension and a directory",
+                path
+            );
+            continue;
+        }
+
+        if let Some(earlier) = gathered.origins.get(&path) {
+            match policy.and_then(|policy| policy.prefer(&path, &[earlier, root])) {
+                Some(0) => continue,
+                Some(_) => {}
+                None => eprintln!(
+                    "Warning: {} is provided by more than one source, the last one is packed",
+                    path
+                ),
+            }
+        }
+        let attributes = FileAttributes::of(&file)
+            .map_err(|e| format!("Could not read {}: {}", file.display(), e))?;
+        gathered.files.insert(path.clone(), file);
+        gathered.origins.insert(path.clone(), root.clone());
+        gathered.attributes.insert(path, attributes);
+    }
+    Ok(())
+}
+
+/// Outcome of packing a recipe
+/// # Properties
+/// - `files: usize` = Number of files packed
+/// - `reused: usize` = Number of unchanged files copied from the previous output
+/// - `ignored: usize` = Number of files left out by `.vpkignore` files
+struct Packed {
+    files: usize,
+    reused: usize,
+    ignored: usize,
+}
+
+/// Gather the files of every source of `recipe` and write them to its output, along with
+/// the attribute record if `recipe.keep_times` is set. Files unchanged since the output was
+/// last packed are not read again, see `PackCache`.
+fn pack(recipe: &PackRecipe) -> Result<Packed, String> {
+    // Read on every pack, so `--watch` picks up changes to the policy
+    let policy = recipe
+        .conflicts_policy
+        .as_deref()
+        .map(Policy::load)
+        .transpose()?;
+    let mut gathered = Gathered::default();
+    for source in &recipe.sources {
+        gather(source, policy.as_ref(), &mut gathered)?;
+    }
+    if gathered.files.is_empty() {
+        return Err(String::from("No files to pack"));
+    }
+
+    let mut cache = PackCache::load(&recipe.output, recipe.compress);
+    let mut data = HashMap::new();
+    for (path, file) in &gathered.files {
+        let packed = cache
+            .read(path, file)
+            .map_err(|e| format!("Could not read {}: {}", file.display(), e))?;
+        data.insert(path.clone(), packed);
+    }
+
+    let out_path = recipe.output.as_path();
+    let options = VpkOptions {
+        compress: recipe.compress,
+        ..VpkOptions::default()
+    };
+    out_path
+        .parent()
+        .filter(|dir| !dir.as_os_str().is_empty())
+        .map_or(Ok(()), std::fs::create_dir_all)
+        .and_then(|_| vpk::create_vpk_from(data, &options))
+        .and_then(|file| std::fs::write(out_path, file))
+        .and_then(|_| {
+            let record = attributes::attributes_path(out_path);
+            if recipe.keep_times {
+                attributes::write(out_path, &gathered.attributes)
+            } else if record.exists() {
+                // A record of an earlier pack would no longer match the contents
+                std::fs::remove_file(record)
+            } else {
+                Ok(())
+            }
+        })
+        .map_err(|e| format!("Internal error: {}", e))?;
+
+    let packed = Packed {
+        files: gathered.files.len(),
+        reused: cache.reused(),
+        ignored: gathered.ignored,
+    };
+    if let Err(e) = cache.save(out_path) {
+        eprintln!(
+            "Warning: The pack cache could not be written, every file is read again next \
+            time: {}",
+            e
+        );
+    }
+    Ok(packed)
+}
+
+/// Modification time and size of every file below the sources of `recipe`, and of the recipe
+/// file at `recipe_path` and the conflicts policy if there are, to notice changes. The output, its attribute record
+/// and its pack cache are left out, in case they are written inside a source.
+fn snapshot(
+    recipe: &PackRecipe,
+    recipe_path: Option<&Path>,
+) -> BTreeMap<PathBuf, (SystemTime, u64)> {
+    let record = attributes::attributes_path(&recipe.output);
+    let cache = pack_cache::cache_path(&recipe.output);
+    recipe
+        .sources
+        .iter()
+        .flat_map(|source| utils::walk_dir(&source.root).unwrap_or_default())
+        .chain(recipe_path.map(Path::to_path_buf))
+        .chain(recipe.conflicts_policy.clone())
+        .filter(|file| *file != recipe.output && *file != record && *file != cache)
+        .filter_map(|file| {
+            let metadata = std::fs::metadata(&file).ok()?;
+            Some((file, (metadata.modified().ok()?, metadata.len())))
+        })
+        .collect()
+}
+
+/// Print the outcome of packing `recipe`
+fn report(recipe: &PackRecipe, result: Result<Packed, String>) {
+    match result {
+        Ok(packed) => {
+            println!(
+                "Packed {} files into {}",
+                packed.files,
+                recipe.output.display()
+            );
+            if packed.reused > 0 {
+                println!(
+                    "{} unchanged file(s) copied from the previous pack",
+                    packed.reused
+                );
+            }
+            if packed.ignored > 0 {
+                println!(
+                    "{} file(s) left out by {}",
+                    packed.ignored,
+                    ignore::IGNORE_FILE
+                );
+            }
+        }
+        Err(e) => eprintln!("Dota-Terrain-Mod error: {}", e),
+    }
+}
+
+/// Entry point for the `pack <dir> <out> [--compress] [--keep-times] [--watch] [--yes]` and
+/// `pack <recipe.toml> [--watch] [--conflicts-policy <file>] [--yes]` commands. Packs every
+/// file below `dir` into the VPK `out`, keeping its path relative to `dir`, or the sources
+/// declared in a recipe file, see
+/// `PackRecipe::load()`. Files matching the patterns of a `.vpkignore` file at the root of a
+/// directory are left out, e.g. build artifacts and source files, see `gather()`.
+/// With `--keep-times`, the modification times and read-only flags of the files are written
+/// to a record next to the VPK (`<out>.attributes.json`), which `extract` applies to the
+/// extracted files, so a directory survives a round trip unchanged for build systems. An
+/// existing `out` is only replaced after confirmation. With `--watch`, the sources (and the
+/// recipe) are checked for changes every `WATCH_INTERVAL` and the VPK is packed again once
+/// they stayed unchanged for the quiet period, see `Config::quiet_period()`, until the
+/// program is stopped. Checksums of the packed files are kept in a
+/// cache next to the VPK (`<out>.cache.json`), so packing again only reads the files which
+/// changed. Which source wins a file several sources of a recipe provide is chosen by the
+/// policy file given with `--conflicts-policy` or the recipe's `conflicts_policy`, see
+/// `conflicts::Policy`, or else the last source.
+pub(crate) fn run(args: &Args) {
+    let recipe_path = match args.positional.as_slice() {
+        [recipe] if recipe.ends_with(".toml") => Some(Path::new(recipe)),
+        [_, _] => None,
+        _ => {
+            eprintln!(
+                "Usage: dota-terrain-mod pack <dir> <out> [--compress] [--keep-times] \
+                [--watch [--quiet-period <seconds>]] [--yes]"
+            );
+            eprintln!(
+                "       dota-terrain-mod pack <recipe.toml> [--watch [--quiet-period <seconds>]] \
+                [--conflicts-policy <file>] [--yes]"
+            );
+            std::process::exit(1);
+        }
+    };
+    let load = || -> Result<PackRecipe, String> {
+        let mut recipe = match recipe_path {
+            Some(path) => PackRecipe::load(path)?,
+            None => PackRecipe {
+                sources: vec![PackSource::new(PathBuf::from(&args.positional[0]))],
+                output: PathBuf::from(&args.positional[1]),
+                compress: false,
+                keep_times: false,
+                conflicts_policy: None,
+            },
+        };
+        if let Some(policy) = args.option("conflicts-policy") {
+            recipe.conflicts_policy = Some(PathBuf::from(policy));
+        }
+        recipe.compress |= args.flag("compress");
+        recipe.keep_times |= args.flag("keep-times");
+        lzma::check_compress(recipe.compress)?;
+        Ok(recipe)
+    };
+    let mut recipe = match load() {
+        Ok(recipe) => recipe,
+        Err(e) => {
+            eprintln!("Dota-Terrain-Mod error: {}", e);
+            std::process::exit(1);
+        }
+    };
+
+    if recipe.output.exists()
+        && !utils::confirm(
+            &format!("{} already exists. Overwrite it?", recipe.output.display()),
+            args.assume_yes(),
+        )
+    {
+        std::process::exit(1);
+    }
+    // Checked before packing, so a mistyped --quiet-period is reported at once
+    let quiet_period = args.flag("watch").then(|| {
+        Config::load()
+            .map_err(|e| e.to_string())
+            .and_then(|config| config.quiet_period(args, DEFAULT_QUIET_PERIOD))
+            .unwrap_or_else(|e| {
+                eprintln!("Dota-Terrain-Mod error: {}", e);
+                std::process::exit(1);
+            })
+    });
+    let result = pack(&recipe);
+    let Some(quiet_period) = quiet_period else {
+        let failed = result.is_err();
+        report(&recipe, result);
+        if failed {
+            std::process::exit(1);
+        }
+        return;
+    };
+    report(&recipe, result);
+
+    println!("Watching for changes, press Ctrl+C to stop.");
+    let mut last = snapshot(&recipe, recipe_path);
+    loop {
+        thread::sleep(WATCH_INTERVAL);
+        let current = snapshot(&recipe, recipe_path);
+        if current == last {
+            continue;
+        }
+        // Wait until files stop changing, e.g. while an editor or exporter is still writing
+        utils::wait_for_quiet(current, quiet_period, WATCH_INTERVAL, || {
+            snapshot(&recipe, recipe_path)
+        });
+
+        println!("\nChange detected, packing again...");
+        match load() {
+            Ok(reloaded) => recipe = reloaded,
+            Err(e) => {
+                eprintln!("Dota-Terrain-Mod error: {}", e);
+                continue;
+            }
+        }
+        report(&recipe, pack(&recipe));
+        last = snapshot(&recipe, recipe_path);
+    }
+}
@@ -0,0 +1,196 @@
+use std::{
+    collections::BTreeMap,
+    fs, io,
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use serde_json::{json, Value};
+
+use crate::vpk::{PackedFile, VPK};
+
+/// Extension appended to the file name of a VPK to get its pack cache, e.g.
+/// `pak01_dir.vpk.cache.json`
+const CACHE_EXTENSION: &str = "cache.json";
+
+/// A packed file as it was when it was read
+/// # Properties
+/// - `source: PathBuf` = The file its contents were read from
+/// - `modified: SystemTime` = Modification time of the source
+/// - `length: u64` = Size of the source in bytes
+/// - `crc32: u32` = CRC32 checksum of its contents
+#[derive(PartialEq)]
+struct CachedFile {
+    source: PathBuf,
+    modified: SystemTime,
+    length: u64,
+    crc32: u32,
+}
+
+impl CachedFile {
+    fn to_json(&self) -> Value {
+        let mut value = stamp_to_json(self.modified, self.length);
+        value["source"] = json!(self.source.to_string_lossy());
+        value["crc32"] = json!(self.crc32);
+        value
+    }
+
+    fn from_json(value: &Value) -> Option<CachedFile> {
+        let (modified, length) = stamp_from_json(value)?;
+        Some(CachedFile {
+            source: PathBuf::from(value.get("source")?.as_str()?),
+            modified,
+            length,
+            crc32: u32::try_from(value.get("crc32")?.as_u64()?).ok()?,
+        })
+    }
+}
+
+fn stamp_to_json(modified: SystemTime, length: u64) -> Value {
+    let modified = modified.duration_since(UNIX_EPOCH).unwrap_or_default();
+    json!({
+        "modified": modified.as_secs(),
+        "modified_nanos": modified.subsec_nanos(),
+        "length": length,
+    })
+}
+
+fn stamp_from_json(value: &Value) -> Option<(SystemTime, u64)> {
+    let nanos = u32::try_from(value.get("modified_nanos")?.as_u64()?).ok()?;
+    let modified = UNIX_EPOCH + Duration::new(value.get("modified")?.as_u64()?, nanos);
+    Some((modified, value.get("length")?.as_u64()?))
+}
+
+/// Path of the pack cache of the VPK at `vpk_path`
+pub(crate) fn cache_path(vpk_path: &Path) -> PathBuf {
+    let mut name = vpk_path.file_name().unwrap_or_default().to_os_string();
+    name.push(format!(".{}", CACHE_EXTENSION));
+    vpk_path.with_file_name(name)
+}
+
+/// Modification time and size of the file at `path`, which tell whether it changed
+fn stamp(path: &Path) -> io::Result<(SystemTime, u64)> {
+    let metadata = fs::metadata(path)?;
+    Ok((metadata.modified()?, metadata.len()))
+}
+
+/// Checksums of the files packed into a VPK, kept next to it (`<out>.cache.json`), so packing
+/// it again only reads, checksums and compresses the files which changed since. The bytes of
+/// unchanged files are copied from the previous output instead. The cache is only used if the
+/// output is still the one it was written for and was packed with the same compression.
+/// # Properties
+/// - `compress: bool` = Whether the new output is compressed
+/// - `previous: Option<VPK>` = The previous output, if the cache is valid for it
+/// - `files: BTreeMap<String, CachedFile>` = Files of the previous output, keyed by their path
+///   in the VPK
+/// - `next: BTreeMap<String, CachedFile>` = Files read for the new output
+/// - `reused: usize` = Number of files copied from the previous output
+#[derive(Default)]
+pub(crate) struct PackCache {
+    compress: bool,
+    previous: Option<VPK>,
+    files: BTreeMap<String, CachedFile>,
+    next: BTreeMap<String, CachedFile>,
+    reused: usize,
+}
+
+impl PackCache {
+    /// Read the cache of the VPK at `out_path`, to be packed again with `compress`. Empty if
+    /// there is none, it cannot be read, or it does not match the VPK or `compress`, so every
+    /// file is read.
+    pub(crate) fn load(out_path: &Path, compress: bool) -> PackCache {
+        let empty = PackCache {
+            compress,
+            ..PackCache::default()
+        };
+        let Some(value) = fs::read_to_string(cache_path(out_path))
+            .ok()
+            .and_then(|json| serde_json::from_str::<Value>(&json).ok())
+        else {
+            return empty;
+        };
+        if value["compress"].as_bool() != Some(compress)
+            || stamp_from_json(&value["output"]) != stamp(out_path).ok()
+        {
+            return empty;
+        }
+        let files = value["files"].as_object().map(|files| {
+            files
+                .iter()
+                .filter_map(|(path, file)| Some((path.clone(), CachedFile::from_json(file)?)))
+                .collect()
+        });
+        match (files, VPK::open(out_path.to_path_buf())) {
+            (Some(files), Ok(previous)) => PackCache {
+                previous: Some(previous),
+                files,
+                ..empty
+            },
+            _ => empty,
+        }
+    }
+
+    /// The file packed at `path`, read from `source`. If `source` has the same modification
+    /// time and size as when it was last packed there, its stored bytes are copied from the
+    /// previous output instead.
+    pub(crate) fn read(&mut self, path: &str, source: &Path) -> io::Result<PackedFile> {
+        // Taken before reading, so a change while reading is caught by the next pack
+        let (modified, length) = stamp(source)?;
+        let cached = self.files.get(path).filter(|cached| {
+            cached.source == source && cached.modified == modified && cached.length == length
+        });
+        let stored = match (cached, self.previous.as_mut()) {
+            (Some(cached), Some(previous))
+                if previous.entry(path).map(|entry| entry.crc32()) == Some(cached.crc32) =>
+            {
+                previous.read_entry_at(path, 0, usize::MAX).ok()
+            }
+            _ => None,
+        };
+
+        let file = match (cached, stored) {
+            (Some(cached), Some(bytes)) => {
+                self.reused += 1;
+                PackedFile {
+                    crc32: cached.crc32,
+                    bytes,
+                    stored: true,
+                }
+            }
+            _ => PackedFile::new(fs::read(source)?),
+        };
+        self.next.insert(
+            path.to_owned(),
+            CachedFile {
+                source: source.to_path_buf(),
+                modified,
+                length,
+                crc32: file.crc32,
+            },
+        );
+        Ok(file)
+    }
+
+    /// Number of files copied from the previous output so far
+    pub(crate) fn reused(&self) -> usize {
+        self.reused
+    }
+
+    /// Write the cache for the files read since `load()`, once they were packed into the VPK
+    /// at `out_path`
+    pub(crate) fn save(self, out_path: &Path) -> io::Result<()> {
+        drop(self.previous);
+        let (modified, length) = stamp(out_path)?;
+        let files: serde_json::Map<String, Value> = self
+            .next
+            .iter()
+            .map(|(path, file)| (path.clone(), file.to_json()))
+            .collect();
+        let json = serde_json::to_string_pretty(&json!({
+            "compress": self.compress,
+            "output": stamp_to_json(modified, length),
+            "files": files,
+        }))?;
+        fs::write(cache_path(out_path), json)
+    }
+}
@@ -0,0 +1,105 @@
+use crate::cli::Args;
+use crate::install::InstallMethod;
+use crate::manifest::{self, Manifest};
+use crate::utils::{self, DotaInstall};
+use crate::verify;
+use crate::vpk::VPK;
+
+/// Entry point for the `panic-button` command. Undoes everything applying a terrain does to
+/// the game folder in one step, for when the game misbehaves afterwards: the outputs of every
+/// base map, loose files included, and their manifests are removed, as is the search path
+/// `--method gameinfo` adds to `gameinfo.gi`, and the base maps are checked for damage.
+/// Applying a terrain never changes the launch options or the base files, so there is nothing
+/// else to revert. Outputs without a manifest may not have been created by this program and
+/// are left in place, unless `--i-know-what-im-doing` is given.
+pub(crate) fn run(args: &Args) {
+    if !args.positional.is_empty() {
+        eprintln!("Usage: dota-terrain-mod panic-button [--i-know-what-im-doing]");
+        std::process::exit(1);
+    }
+    let dota = match DotaInstall::new(args) {
+        Ok(dota) => dota,
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+    };
+    let mut problems = 0;
+
+    // Every base map which has an output, always including the default one
+    let bases: Vec<String> = dota
+        .available_bases()
+        .into_iter()
+        .filter(|base| base == dota.game.default_base || manifest::is_applied(&dota.out_vpk(base)))
+        .chain(std::iter::once(String::from(dota.game.default_base)))
+        .collect::<std::collections::BTreeSet<String>>()
+        .into_iter()
+        .collect();
+
+    for base in &bases {
+        let out_path = dota.out_vpk(base);
+        let installed = Manifest::read(&out_path).ok();
+        let mut keep = Vec::new();
+        if out_path.exists() && installed.is_none() && !args.flag("i-know-what-im-doing") {
+            eprintln!(
+                "Left {} in place, it has no manifest and may not have been created by this \
+                program. Pass --i-know-what-im-doing to remove it anyway.",
+                out_path.display()
+            );
+            problems += 1;
+            keep.push(out_path.clone());
+        }
+        // Whatever any method left behind, loose installs are listed by their manifest, which
+        // has to be read before removing it
+        let installed = installed.unwrap_or_else(|| Manifest::new(""));
+        for method in InstallMethod::ALL {
+            let installer = method.installer();
+            if !installer.detect(&dota, base, &installed) {
+                continue;
+            }
+            if let Err(e) = installer.uninstall(&dota, base, &installed, &keep) {
+                eprintln!("Could not remove the {} install: {}", method.name(), e);
+                problems += 1;
+            }
+        }
+        let path = manifest::manifest_path(&out_path);
+        match std::fs::remove_file(&path) {
+            Ok(()) => println!("Removed {}", path.display()),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+            Err(e) => {
+                eprintln!("Could not remove {}: {}", path.display(), e);
+                problems += 1;
+            }
+        }
+        utils::remove_empty_dirs(out_path.parent().unwrap(), &dota.dota_path);
+    }
+
+    for base in &bases {
+        let base_path = dota.base_vpk(base);
+        println!("Checking {}...", base_path.display());
+        match VPK::open(base_path) {
+            Ok(mut base_vpk) => {
+                let mismatches = verify::crc_mismatches(&mut base_vpk);
+                if mismatches.is_empty() {
+                    println!("Base map {}: OK", base);
+                } else {
+                    println!("Base map {}: {} damaged file(s)", base, mismatches.len());
+                    problems += 1;
+                }
+            }
+            Err(e) => {
+                println!("Base map {}: Could not be read: {}", base, e);
+                problems += 1;
+            }
+        }
+    }
+
+    if problems > 0 {
+        eprintln!(
+            "Some problems remain. If the base map is damaged, use \"Verify integrity of game \
+            files\" for Dota 2 in Steam."
+        );
+        std::process::exit(1);
+    }
+    println!("The game is back to its original state. You can remove the \"-language tempcontent\" launch option.");
+}
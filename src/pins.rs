@@ -0,0 +1,87 @@
+use std::{collections::BTreeMap, io};
+
+use serde_json::Value;
+
+use crate::config;
+use crate::hashing::{self, HashAlgorithm};
+use crate::utils::{self, TMError};
+
+/// Name of the file in the configuration directory holding the hash of every third-party
+/// terrain, keyed by file name
+const PINS_FILE: &str = "pins.json";
+
+/// Read the pinned hashes. A missing file holds no pins.
+fn load() -> io::Result<BTreeMap<String, String>> {
+    let Some(path) = config::config_dir().map(|dir| dir.join(PINS_FILE)) else {
+        return Ok(BTreeMap::new());
+    };
+    let json = match std::fs::read_to_string(path) {
+        Ok(json) => json,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(BTreeMap::new()),
+        Err(e) => return Err(e),
+    };
+    let value: Value = serde_json::from_str(&json)?;
+    Ok(value
+        .as_object()
+        .map(|pins| {
+            pins.iter()
+                .filter_map(|(file, hash)| Some((file.clone(), hash.as_str()?.to_owned())))
+                .collect()
+        })
+        .unwrap_or_default())
+}
+
+/// Write the pinned hashes
+fn save(pins: &BTreeMap<String, String>) -> io::Result<()> {
+    let Some(dir) = config::config_dir() else {
+        return Err(io::Error::new(
+            io::ErrorKind::NotFound,
+            "no configuration directory",
+        ));
+    };
+    std::fs::create_dir_all(&dir)?;
+    std::fs::write(dir.join(PINS_FILE), serde_json::to_string_pretty(pins)?)
+}
+
+/// Check a third-party terrain, one not shipped with the game, against the hash recorded the
+/// first time it was applied, so a mod file silently swapped on disk is not applied. The
+/// hash of its contents `data`, made with `algorithm`, is pinned on first use. Hashes pinned
+/// with another algorithm are still checked with it. A changed file is only applied, and
+/// pinned again, after confirmation (`assume_yes` for `--yes`), which is only asked for on
+/// the console if `interactive`.
+pub(crate) fn check(
+    terrain_file: &str,
+    data: &[u8],
+    algorithm: HashAlgorithm,
+    assume_yes: bool,
+    interactive: bool,
+) -> Result<(), TMError> {
+    let mut pins = load().map_err(TMError::InternalError)?;
+    if pins
+        .get(terrain_file)
+        .is_some_and(|pinned| hashing::matches(pinned, data))
+    {
+        return Ok(());
+    }
+    let hash = algorithm.hash(data);
+    match pins.get(terrain_file) {
+        Some(pinned) => {
+            eprintln!(
+                "Warning: {} changed since it was first applied ({}, now {}). It may have \
+                been replaced by a different mod.",
+                terrain_file,
+                hashing::describe(pinned),
+                hashing::describe(&hash)
+            );
+            if !utils::answer("Apply the changed file anyway?", assume_yes, interactive) {
+                return Err(TMError::TerrainChanged(terrain_file.to_owned()));
+            }
+        }
+        None => println!("Pinned {} ({})", terrain_file, hashing::describe(&hash)),
+    }
+    pins.insert(terrain_file.to_owned(), hash);
+    if let Err(e) = save(&pins) {
+        eprintln!("Warning: Could not record the terrain's hash: {}", e);
+    }
+    Ok(())
+}
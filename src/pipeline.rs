@@ -0,0 +1,146 @@
+use std::path::PathBuf;
+
+use crate::apply::{self, ApplyOptions};
+use crate::config::Config;
+use crate::utils::{DotaInstall, TMError};
+use crate::{journal, launch};
+
+/// Options of `TerrainMod::apply()`, those of the `apply` command. Options not given, `None`,
+/// empty or `false`, are taken from the settings of the terrain and then the rest of the
+/// configuration, as on the command line. Questions are never asked: without `assume_yes`,
+/// they are answered with no. No setting of the process is changed, so the game is Dota 2,
+/// found in `steam_path` or else the Steam installation the command finds, and the
+/// configuration is read from the usual directory.
+/// # Properties
+/// - `terrain: String` = Terrain to apply, by file, identifier or name, see `menu::resolve()`
+/// - `base: Option<String>` = Base map the terrain replaces (`--base`)
+/// - `method: Option<String>` = How the terrain is installed, e.g. `"loose"` (`--method`)
+/// - `overrides: Vec<PathBuf>` = Directories and VPKs of files installed along with the
+///   terrain, each above the ones before it (`--overrides`)
+/// - `rules: Option<PathBuf>` = File with the rename rules (`--rules`)
+/// - `outputs: Vec<PathBuf>` = Further paths the output is written to (`--output`)
+/// - `conflicts_policy: Option<PathBuf>` = Which override layer wins files several layers
+///   add (`--conflicts-policy`)
+/// - `map_aliases: Vec<String>` = Maps also replaced by the terrain (`--map-alias`)
+/// - `terrain_minimap: bool` = Keep the terrain's own minimap (`--terrain-minimap`)
+/// - `compress: bool` = LZMA-compress the output (`--compress`)
+/// - `low_end: bool` = Strip or swap heavyweight assets (`--low-end`)
+/// - `strip_editor_files: bool` = Leave out editor leftovers and uncompiled assets
+///   (`--strip-editor-files`)
+/// - `downscale: Option<u16>` = Largest texture size kept from the terrain (`--downscale`)
+/// - `recolor: Option<(f32, f32)>` = Hue shift in degrees and saturation factor of ground
+///   textures (`--recolor`)
+/// - `assume_yes: bool` = Answer every question with yes, e.g. to overwrite files which were
+///   not written by this program or to apply a terrain whose pinned hash changed (`--yes`)
+/// - `allow_maps_dir: bool` = Allow outputs in the maps folder of the game
+///   (`--i-know-what-im-doing`)
+/// - `ignore_steam_state: bool` = Build even while Steam is updating the game
+///   (`--ignore-steam-state`)
+/// - `no_limits: bool` = Open a base map or terrain claiming implausible sizes (`--no-limits`)
+/// - `steam_path: Option<PathBuf>` = Steam installation directory the game is found in
+///   (`--steam-path`)
+#[derive(Clone, Default)]
+pub struct Options {
+    pub terrain: String,
+    pub base: Option<String>,
+    pub method: Option<String>,
+    pub overrides: Vec<PathBuf>,
+    pub rules: Option<PathBuf>,
+    pub outputs: Vec<PathBuf>,
+    pub conflicts_policy: Option<PathBuf>,
+    pub map_aliases: Vec<String>,
+    pub terrain_minimap: bool,
+    pub compress: bool,
+    pub low_end: bool,
+    pub strip_editor_files: bool,
+    pub downscale: Option<u16>,
+    pub recolor: Option<(f32, f32)>,
+    pub assume_yes: bool,
+    pub allow_maps_dir: bool,
+    pub ignore_steam_state: bool,
+    pub no_limits: bool,
+    pub steam_path: Option<PathBuf>,
+}
+
+impl Options {
+    /// Apply the terrain `terrain` with the options of the configuration
+    pub fn new(terrain: &str) -> Options {
+        Options {
+            terrain: terrain.to_owned(),
+            ..Options::default()
+        }
+    }
+}
+
+/// Result of `TerrainMod::apply()`, what the `apply` command prints as a summary
+/// # Properties
+/// - `terrain: String` = File name of the applied terrain
+/// - `method: String` = How the terrain was installed, as given with `--method`
+/// - `output: Option<PathBuf>` = The VPK holding the base map and the terrain, `None` for
+///   loose installs
+/// - `files: Vec<PathBuf>` = Every file of the install besides its manifest
+/// - `build: Option<String>` = Build ID of the game the terrain was built for
+/// - `entries: usize` = Number of entries of the merged map
+/// - `conflicts: Vec<String>` = Summary of the entries each source replaced in the sources
+///   before it
+/// - `downscaled: Vec<String>` = Textures whose largest mip levels were dropped
+///   (`--downscale`)
+/// - `recolored: Vec<String>` = Ground textures whose colors were shifted (`--recolor`)
+/// - `timings: Vec<(String, f64)>` = Name and duration in seconds of every phase of the build
+/// - `launch_option_missing: bool` = Whether the game still has to be launched with
+///   `-language tempcontent` to load the terrain
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Report {
+    pub terrain: String,
+    pub method: String,
+    pub output: Option<PathBuf>,
+    pub files: Vec<PathBuf>,
+    pub build: Option<String>,
+    pub entries: usize,
+    pub conflicts: Vec<String>,
+    pub downscaled: Vec<String>,
+    pub recolored: Vec<String>,
+    pub timings: Vec<(String, f64)>,
+    pub launch_option_missing: bool,
+}
+
+/// The program as a library, for GUIs, launchers and tests to apply terrains through the same
+/// code as the `apply` command
+pub struct TerrainMod;
+
+impl TerrainMod {
+    /// Apply a terrain like `apply <terrain>` does: an operation a previous run left
+    /// unfinished is undone, the game and the configuration are found, the terrain is
+    /// resolved, merged with the base map and installed, see `apply::apply()`. Progress and
+    /// warnings are printed as by the command. Nothing is read from the console and no
+    /// setting of the process is changed, so it can be called repeatedly by a GUI.
+    pub fn apply(options: &Options) -> Result<Report, TMError> {
+        journal::recover();
+
+        let dota = DotaInstall::locate(options.steam_path.clone())?;
+        let mut config = Config::load()?;
+        config.menu.discover(&dota);
+        let terrain = config
+            .menu
+            .resolve(&options.terrain)
+            .map_err(TMError::ConfigError)?;
+        let apply_options = ApplyOptions::from_options(options, &config, &terrain.file)?;
+        let manifest = apply::apply(&dota, &terrain.file, &apply_options)?;
+
+        let installer = apply_options.method().installer();
+        Ok(Report {
+            output: installer.output_vpk(&dota, apply_options.base()),
+            files: installer.files(&dota, apply_options.base(), &manifest),
+            method: apply_options.method().name().to_owned(),
+            build: Some(manifest.build.clone()).filter(|build| !build.is_empty()),
+            entries: manifest.entries.len(),
+            conflicts: manifest.conflicts(),
+            launch_option_missing: installer.needs_launch_option()
+                && launch::launch_option_set(&dota.steam_path, false) == Some(false),
+            terrain: manifest.terrain,
+            downscaled: manifest.downscaled,
+            recolored: manifest.recolored,
+            timings: manifest.stats.phases,
+        })
+    }
+}
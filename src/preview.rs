@@ -0,0 +1,62 @@
+use std::path::Path;
+use std::process::Command;
+
+use crate::cli::Args;
+use crate::launch::LAUNCH_OPTION;
+use crate::manifest;
+use crate::utils::DotaInstall;
+
+/// Console commands loading straight into a map in a local game, where the terrain can be
+/// looked at without queueing. The map name follows.
+const INGAME_ARGS: [&str; 2] = ["-console", "+map"];
+
+/// Command starting the Steam client: `steam.exe` in the installation directory on Windows,
+/// `steam` from the `PATH` elsewhere
+fn steam_command(steam_path: &Path) -> Command {
+    #[cfg(target_os = "windows")]
+    return Command::new(steam_path.join("steam.exe"));
+
+    #[cfg(not(target_os = "windows"))]
+    {
+        let _ = steam_path;
+        Command::new("steam")
+    }
+}
+
+/// Entry point for the `preview [--ingame] [--base <map>]` command. Launches Dota 2 through
+/// Steam with `-language tempcontent`, so the applied terrain can be checked. With
+/// `--ingame`, the game loads straight into the map (the default one, or the one given with
+/// `--base`) in a local game instead of the main menu.
+pub(crate) fn run(args: &Args) {
+    if !args.positional.is_empty() {
+        eprintln!("Usage: dota-terrain-mod preview [--ingame] [--base <map>]");
+        std::process::exit(1);
+    }
+    let dota = match DotaInstall::new(args) {
+        Ok(dota) => dota,
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+    };
+    let base = args.option("base").unwrap_or(dota.game.default_base);
+    if !manifest::is_applied(&dota.out_vpk(base)) {
+        eprintln!("Dota-Terrain-Mod error: No terrain applied, apply one first");
+        std::process::exit(1);
+    }
+
+    let mut command = steam_command(&dota.steam_path);
+    command
+        .args(["-applaunch", dota.game.appid])
+        .args(LAUNCH_OPTION.split_whitespace());
+    if args.flag("ingame") {
+        command.args(INGAME_ARGS).arg(base);
+    }
+    match command.spawn() {
+        Ok(_) => println!("Launching {}...", dota.game.title),
+        Err(e) => {
+            eprintln!("Dota-Terrain-Mod error: Could not start Steam: {}", e);
+            std::process::exit(1);
+        }
+    }
+}
@@ -0,0 +1,114 @@
+use crate::apply::{self, ApplyOptions};
+use crate::cli::Args;
+use crate::compare_install;
+use crate::config::Config;
+use crate::history;
+use crate::manifest::{self, Manifest};
+use crate::utils::DotaInstall;
+
+/// Entry point for the `reapply [--smart] [--base <map>] [--yes]` command. Builds the applied
+/// terrain (of the default map, or the map given with `--base`) again with the options of its
+/// latest history entry, e.g. after a game update. With `--smart`, the installed output is
+/// first compared against the base map, see `compare_install::compare()`, and only rebuilt if
+/// the base map changed files the terrain or the overrides replace.
+pub(crate) fn run(args: &Args) {
+    if !args.positional.is_empty() {
+        eprintln!("Usage: dota-terrain-mod reapply [--smart] [--base <map>] [--yes]");
+        std::process::exit(1);
+    }
+    let dota = match DotaInstall::new(args) {
+        Ok(dota) => dota,
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+    };
+    let base = args.option("base").unwrap_or(dota.game.default_base);
+
+    if args.flag("smart") {
+        match compare_install::compare(&dota, base) {
+            Ok(None) => {
+                println!("No terrain applied.");
+                return;
+            }
+            Ok(Some(comparison)) if comparison.is_unchanged() => {
+                println!("No rebuild necessary, the base map has not changed.");
+                return;
+            }
+            Ok(Some(comparison)) if !comparison.touches_replaced() => {
+                println!(
+                    "No rebuild necessary, the base map changed none of the files the terrain \
+                    replaces."
+                );
+                println!(
+                    "{} file(s) copied from the base map are outdated or removed and {} new \
+                    file(s) are missing until `reapply` is run without --smart.",
+                    comparison.outdated, comparison.added
+                );
+                return;
+            }
+            Ok(Some(comparison)) => println!(
+                "The base map changed {} file(s) the terrain replaces, rebuilding.",
+                comparison.changed.len() + comparison.removed.len()
+            ),
+            // Without a usable comparison, rebuild to be safe
+            Err(e) => eprintln!("Warning: {} Rebuilding.", e),
+        }
+    }
+    let out_path = dota.out_vpk(base);
+    if !manifest::is_applied(&out_path) {
+        println!("No terrain applied.");
+        return;
+    }
+    let terrain = match Manifest::read(&out_path) {
+        Ok(manifest) => manifest.terrain,
+        Err(e) => {
+            eprintln!(
+                "Dota-Terrain-Mod error: The manifest of {} could not be read: {}",
+                out_path.display(),
+                e
+            );
+            std::process::exit(1);
+        }
+    };
+
+    let entries = match history::load() {
+        Ok(entries) => entries,
+        Err(e) => {
+            eprintln!("Dota-Terrain-Mod error: Could not read the history: {}", e);
+            std::process::exit(1);
+        }
+    };
+    let Some(entry) = entries
+        .iter()
+        .rev()
+        .find(|entry| entry.terrain == terrain && entry.base == base)
+    else {
+        eprintln!(
+            "Dota-Terrain-Mod error: {} is not in the history, apply it with `apply` instead",
+            terrain
+        );
+        std::process::exit(1);
+    };
+    let config = match Config::load() {
+        Ok(config) => config,
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+    };
+    let options = match ApplyOptions::from_history(entry, args, &config) {
+        Ok(options) => options,
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+    };
+
+    println!("Applying {}...", terrain);
+    if let Err(e) = apply::apply(&dota, &terrain, &options) {
+        eprintln!("{}", e);
+        std::process::exit(1);
+    }
+    println!("Done.");
+}
@@ -0,0 +1,78 @@
+use std::{collections::HashSet, io, path::Path};
+
+use crate::resource::Resource;
+use crate::vpk::{self, VPK};
+
+/// Number of missing references listed by name
+const LISTED_MISSING: usize = 10;
+
+/// After patching, read the external references of the map replacing the base map `base` in
+/// the output VPK at `out_path` and check that every referenced resource exists in the output or in the game's
+/// `pak01_dir.vpk` in `content_dir`. Returns a summary for the user, warning about missing resources.
+pub(crate) fn check(out_path: &Path, content_dir: &Path, base: &str) -> io::Result<Vec<String>> {
+    let map_path = vpk::map_path(base);
+    let mut out_vpk = VPK::open(out_path.to_path_buf())?;
+    if out_vpk.entry(&map_path).is_none() {
+        return Ok(vec![format!(
+            "Warning: The output contains no {}, the game will load the default map.",
+            map_path
+        )]);
+    }
+    let map = out_vpk.read_entry(&map_path)?;
+    let Some(references) =
+        Resource::parse(&map).and_then(|resource| resource.external_references(&map))
+    else {
+        return Ok(vec![String::from(
+            "References: The map has no readable reference list, they were not checked.",
+        )]);
+    };
+
+    let pak_path = content_dir.join("pak01_dir.vpk");
+    let game_files: HashSet<String> = match VPK::open(pak_path.clone()) {
+        Ok(pak) => pak.entries().map(|(path, _)| path.to_lowercase()).collect(),
+        Err(e) => {
+            return Ok(vec![format!(
+                "References: Could not read {} ({}), they were not checked.",
+                pak_path.display(),
+                e
+            )]);
+        }
+    };
+    let out_files: HashSet<String> = out_vpk
+        .entries()
+        .map(|(path, _)| path.to_lowercase())
+        .collect();
+
+    let missing: Vec<&String> = references
+        .iter()
+        .filter(|name| {
+            let name = name.to_lowercase();
+            let compiled = if name.ends_with("_c") {
+                name
+            } else {
+                name + "_c"
+            };
+            !out_files.contains(&compiled) && !game_files.contains(&compiled)
+        })
+        .collect();
+
+    if missing.is_empty() {
+        return Ok(vec![format!(
+            "References: All {} resource(s) referenced by the map exist.",
+            references.len()
+        )]);
+    }
+    let mut summary = vec![format!(
+        "Warning: {} of {} resource(s) referenced by the map exist neither in the output nor \
+        in the game, the terrain may look broken:",
+        missing.len(),
+        references.len()
+    )];
+    for name in missing.iter().take(LISTED_MISSING) {
+        summary.push(format!("  {}", name));
+    }
+    if missing.len() > LISTED_MISSING {
+        summary.push(format!("  and {} more", missing.len() - LISTED_MISSING));
+    }
+    Ok(summary)
+}
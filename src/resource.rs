@@ -0,0 +1,307 @@
+/// Length of the fixed part of a resource header
+const RESOURCE_HEADER_LENGTH: usize = 16;
+/// Length of a single entry in the block table
+const BLOCK_ENTRY_LENGTH: usize = 12;
+/// The header version every compiled Source 2 resource uses
+const RESOURCE_HEADER_VERSION: u16 = 12;
+
+/// A block inside a compiled resource
+/// # Properties
+/// - `kind: String` = Four character block type, e.g. `RED2`, `RERL`, `DATA`
+/// - `offset: usize` = Position of the block data from the start of the file
+/// - `size: usize` = Length of the block data
+pub(crate) struct ResourceBlock {
+    pub(crate) kind: String,
+    pub(crate) offset: usize,
+    pub(crate) size: usize,
+}
+
+/// Header of a compiled Source 2 resource file (`*_c`). Only the parts needed to identify
+/// a file and locate its blocks are read.
+/// # Properties
+/// - `file_size: u32` = Size of the file as recorded in the header
+/// - `version: u16` = Resource type specific version
+/// - `blocks: Vec<ResourceBlock>` = The block table
+pub(crate) struct Resource {
+    pub(crate) file_size: u32,
+    pub(crate) version: u16,
+    pub(crate) blocks: Vec<ResourceBlock>,
+}
+
+pub(crate) fn read_u16(data: &[u8], offset: usize) -> Option<u16> {
+    Some(u16::from_le_bytes(
+        data.get(offset..offset + 2)?.try_into().unwrap(),
+    ))
+}
+
+pub(crate) fn read_u32(data: &[u8], offset: usize) -> Option<u32> {
+    Some(u32::from_le_bytes(
+        data.get(offset..offset + 4)?.try_into().unwrap(),
+    ))
+}
+
+impl Resource {
+    /// Parse the resource header and block table from the start of `data`. Returns `None`
+    /// if `data` does not look like a compiled resource.
+    pub(crate) fn parse(data: &[u8]) -> Option<Resource> {
+        if data.len() < RESOURCE_HEADER_LENGTH {
+            return None;
+        }
+        let file_size = read_u32(data, 0)?;
+        let header_version = read_u16(data, 4)?;
+        let version = read_u16(data, 6)?;
+        let block_offset = read_u32(data, 8)? as usize;
+        let block_count = read_u32(data, 12)? as usize;
+        if header_version != RESOURCE_HEADER_VERSION || block_count > 64 {
+            return None;
+        }
+
+        let mut blocks = Vec::with_capacity(block_count);
+        let table = 8 + block_offset;
+        for i in 0..block_count {
+            let entry = table + i * BLOCK_ENTRY_LENGTH;
+            let kind = data.get(entry..entry + 4)?;
+            if !kind.iter().all(u8::is_ascii_alphanumeric) {
+                return None;
+            }
+            let offset = entry + 4 + read_u32(data, entry + 4)? as usize;
+            let size = read_u32(data, entry + 8)? as usize;
+            blocks.push(ResourceBlock {
+                kind: String::from_utf8_lossy(kind).into_owned(),
+                offset,
+                size,
+            });
+        }
+
+        Some(Resource {
+            file_size,
+            version,
+            blocks,
+        })
+    }
+
+    /// Find the first block of the given type
+    pub(crate) fn block(&self, kind: &str) -> Option<&ResourceBlock> {
+        self.blocks.iter().find(|block| block.kind == kind)
+    }
+
+    /// Get the data of the first block of the given type, if it lies within `data`
+    pub(crate) fn block_data<'a>(&self, data: &'a [u8], kind: &str) -> Option<&'a [u8]> {
+        let block = self.block(kind)?;
+        data.get(block.offset..block.offset + block.size)
+    }
+
+    /// Names of the resources this one depends on, read from the external reference list
+    /// (`RERL` block), e.g. `materials/ground.vmat`. Names are as written by the compiler,
+    /// without the `_c` of the compiled file. Returns `None` if there is no such block or it
+    /// is malformed.
+    pub(crate) fn external_references(&self, data: &[u8]) -> Option<Vec<String>> {
+        let block = self.block_data(data, "RERL")?;
+        // Offset of the entries relative to the start of the block, and their count
+        let entries = read_u32(block, 0)? as usize;
+        let count = read_u32(block, 4)? as usize;
+        let mut names = Vec::with_capacity(count.min(block.len() / 16));
+        for i in 0..count {
+            // Each entry is a 64 bit id followed by the offset of the name, relative to the
+            // offset itself
+            let name_field = entries.checked_add(i * 16 + 8)?;
+            let name_start = name_field.checked_add(read_u32(block, name_field)? as usize)?;
+            let name = block.get(name_start..)?;
+            let end = name.iter().position(|&byte| byte == 0)?;
+            names.push(String::from_utf8_lossy(&name[..end]).into_owned());
+        }
+        Some(names)
+    }
+}
+
+/// Extensions of source assets, which the game ignores unless they are compiled
+pub(crate) const SOURCE_EXTENSIONS: [&str; 16] = [
+    "vmat", "vtex", "vmdl", "vmesh", "vpcf", "vsnd", "vmap", "vxml", "vcss", "vjs", "png", "tga",
+    "psd", "jpg", "wav", "fbx",
+];
+
+/// Check that an override file is something the game will actually load. Compiled files
+/// (`*_c`) must start with a plausible resource header, and source assets are reported since
+/// the game silently ignores them. Returns a warning describing the problem, if any.
+pub(crate) fn check_override(path: &str, data: &[u8]) -> Option<String> {
+    let ext = path.rsplit_once('.').map_or("", |(_, ext)| ext);
+    if ext.ends_with("_c") {
+        return match Resource::parse(data) {
+            None => Some(format!(
+                "{} does not start with a compiled resource header and will not load",
+                path
+            )),
+            Some(resource) if resource.file_size as usize != data.len() => Some(format!(
+                "{} records {} bytes in its header but is {} bytes long, it may be truncated",
+                path,
+                resource.file_size,
+                data.len()
+            )),
+            Some(_) => None,
+        };
+    }
+    if SOURCE_EXTENSIONS.contains(&ext.to_lowercase().as_str()) {
+        return Some(format!(
+            "{} is an uncompiled asset, the game only loads compiled files. \
+            Compile it with the Dota 2 Workshop Tools first",
+            path
+        ));
+    }
+    None
+}
+
+/// Directories at the root of a VPK the game loads resources from. Files anywhere else are
+/// never looked up.
+const RESOURCE_ROOTS: [&str; 7] = [
+    "materials",
+    "models",
+    "particles",
+    "maps",
+    "sounds",
+    "soundevents",
+    "panorama",
+];
+
+/// Check that a file merged into the output is where the game looks for resources: below one
+/// of `RESOURCE_ROOTS`, with the extension of a known compiled resource type. Catches
+/// mistyped paths, which otherwise silently do nothing in game. Source assets are left to
+/// `check_override()`. Returns a warning describing the problem, if any.
+pub(crate) fn check_location(path: &str) -> Option<String> {
+    let root = path.split_once('/').map_or("", |(root, _)| root);
+    if !RESOURCE_ROOTS.contains(&root) {
+        return Some(format!(
+            "{} is outside the directories the game loads resources from ({}), it will \
+            never be loaded",
+            path,
+            RESOURCE_ROOTS.join(", ")
+        ));
+    }
+    let ext = path.rsplit_once('.').map_or("", |(_, ext)| ext);
+    if type_name(ext).is_none() && !SOURCE_EXTENSIONS.contains(&ext.to_lowercase().as_str()) {
+        return Some(format!(
+            "{} has the unknown extension \"{}\", the game may never load it",
+            path, ext
+        ));
+    }
+    None
+}
+
+/// Human readable name of the resource type belonging to a compiled file extension
+pub(crate) fn type_name(ext: &str) -> Option<&'static str> {
+    Some(match ext {
+        "vtex_c" => "Texture",
+        "vmdl_c" => "Model",
+        "vmesh_c" => "Mesh",
+        "vsnd_c" => "Sound",
+        "vsndevts_c" => "Sound events",
+        "vmat_c" => "Material",
+        "vmap_c" => "Map",
+        "vwrld_c" => "World",
+        "vwnod_c" => "World node",
+        "vpcf_c" => "Particle system",
+        "vphys_c" => "Physics",
+        "vanim_c" => "Animation",
+        "vagrp_c" => "Animation group",
+        "vseq_c" => "Sequence group",
+        "vxml_c" => "Panorama layout",
+        "vcss_c" => "Panorama style",
+        "vjs_c" => "Panorama script",
+        "vrman_c" => "Resource manifest",
+        "vents_c" => "Entity lump",
+        _ => return None,
+    })
+}
+
+/// Name of a texture format as stored in the texture data block
+fn texture_format(format: u8) -> &'static str {
+    match format {
+        1 => "DXT1",
+        2 => "DXT5",
+        3 => "I8",
+        4 => "RGBA8888",
+        5 => "R16",
+        6 => "RG1616",
+        7 => "RGBA16161616",
+        8 => "R16F",
+        9 => "RG1616F",
+        10 => "RGBA16161616F",
+        11 => "R32F",
+        12 => "RG3232F",
+        13 => "RGB323232F",
+        14 => "RGBA32323232F",
+        15 => "JPEG_RGBA8888",
+        16 => "PNG_RGBA8888",
+        17 => "JPEG_DXT5",
+        18 => "PNG_DXT5",
+        19 => "BC6H",
+        20 => "BC7",
+        21 => "ATI2N",
+        22 => "IA88",
+        23 => "ETC2",
+        24 => "ETC2_EAC",
+        25 => "R11_EAC",
+        26 => "RG11_EAC",
+        27 => "ATI1N",
+        28 => "BGRA8888",
+        _ => "unknown format",
+    }
+}
+
+/// Best-effort identification of a file from its extension and contents
+pub(crate) fn identify(path: &str, data: &[u8]) -> String {
+    let ext = path.rsplit_once('.').map_or("", |(_, ext)| ext);
+    let Some(resource) = Resource::parse(data) else {
+        return match type_name(ext) {
+            Some(name) => format!(
+                "Not a compiled resource, although the extension suggests a {}",
+                name.to_lowercase()
+            ),
+            None => String::from("Unknown (not a compiled Source 2 resource)"),
+        };
+    };
+
+    let mut description = match type_name(ext) {
+        Some(name) => format!("Source 2 {} (resource version {})", name, resource.version),
+        None => format!("Source 2 resource (version {})", resource.version),
+    };
+    if resource.file_size as usize != data.len() {
+        description.push_str(&format!(
+            ", header records {} bytes but the file has {}",
+            resource.file_size,
+            data.len()
+        ));
+    }
+
+    match ext {
+        "vtex_c" => {
+            // Texture data block: version, flags, reflectivity[4], width, height, depth,
+            // format, mip count
+            if let Some(block) = resource.block_data(data, "DATA") {
+                if let (Some(width), Some(height), Some(format), Some(mips)) = (
+                    read_u16(block, 20),
+                    read_u16(block, 22),
+                    block.get(26),
+                    block.get(27),
+                ) {
+                    description.push_str(&format!(
+                        ", {}x{} {}, {} mip level(s)",
+                        width,
+                        height,
+                        texture_format(*format),
+                        mips
+                    ));
+                }
+            }
+        }
+        _ if resource.block("MBUF").is_some() || resource.block("VBIB").is_some() => {
+            description.push_str(", with embedded mesh buffers");
+        }
+        "vsnd_c" => {
+            if let Some(block) = resource.block("DATA") {
+                description.push_str(&format!(", {} bytes of sound data", block.size));
+            }
+        }
+        _ => {}
+    }
+    description
+}
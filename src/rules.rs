@@ -0,0 +1,386 @@
+use std::{collections::HashMap, path::Path};
+
+use regex::Regex;
+use toml::{Table, Value};
+
+use crate::resource;
+use crate::utils::TMError;
+
+/// What a rule does with the entries it matches
+/// # Properties
+/// - `Rename(String)` = Give the entry a new file name, or a new path if it contains a `/`
+/// - `Replace { from, to }` = Replace every occurrence of `from` in the path with `to`
+/// - `Drop` = Remove the entry
+/// - `Swap` = Remove the entry if the base map has a file at the same path, which is used
+///   instead
+#[derive(Clone, Debug)]
+pub(crate) enum RuleAction {
+    Rename(String),
+    Replace { from: String, to: String },
+    Drop,
+    Swap,
+}
+
+/// A rule applied to the entries of the terrain VPK before it is merged with the base.
+/// # Properties
+/// - `pattern: Regex` = The glob matched against entry paths, translated to a regular expression
+/// - `action: RuleAction` = What is done with matching entries
+#[derive(Clone, Debug)]
+pub(crate) struct Rule {
+    pattern: Regex,
+    action: RuleAction,
+}
+
+/// Translate a glob matched against whole entry paths to a regular expression. `*` and `?`
+/// do not match across a `/`, `**` matches any number of directories.
+pub(crate) fn glob_regex(glob: &str) -> Regex {
+    let mut pattern = String::from("^");
+    let mut chars = glob.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '*' if chars.peek() == Some(&'*') => {
+                chars.next();
+                pattern.push_str(".*");
+            }
+            '*' => pattern.push_str("[^/]*"),
+            '?' => pattern.push_str("[^/]"),
+            c => pattern.push_str(&regex::escape(&c.to_string())),
+        }
+    }
+    pattern.push('$');
+    Regex::new(&pattern).unwrap()
+}
+
+impl Rule {
+    /// Create a rule applying `action` to the entries matching `glob`, see `glob_regex()`
+    pub(crate) fn new(glob: &str, action: RuleAction) -> Rule {
+        Rule {
+            pattern: glob_regex(glob),
+            action,
+        }
+    }
+
+    /// Read a rule from a `[[rules]]` table: a `match` glob along with exactly one of
+    /// `rename = "name"`, `replace = { from = "a", to = "b" }`, `drop = true` or `swap = true`
+    fn from_table(table: &Table) -> Result<Rule, String> {
+        let Some(glob) = table.get("match").and_then(Value::as_str) else {
+            return Err(String::from("rule without a \"match\" pattern"));
+        };
+        let mut actions = Vec::new();
+        if let Some(rename) = table.get("rename") {
+            let name = rename
+                .as_str()
+                .ok_or_else(|| format!("\"rename\" of rule \"{}\" must be a string", glob))?;
+            actions.push(RuleAction::Rename(name.to_owned()));
+        }
+        if let Some(replace) = table.get("replace") {
+            let (Some(from), Some(to)) = (
+                replace.get("from").and_then(Value::as_str),
+                replace.get("to").and_then(Value::as_str),
+            ) else {
+                return Err(format!(
+                    "\"replace\" of rule \"{}\" must have \"from\" and \"to\" strings",
+                    glob
+                ));
+            };
+            actions.push(RuleAction::Replace {
+                from: from.to_owned(),
+                to: to.to_owned(),
+            });
+        }
+        if table.get("drop").and_then(Value::as_bool) == Some(true) {
+            actions.push(RuleAction::Drop);
+        }
+        if table.get("swap").and_then(Value::as_bool) == Some(true) {
+            actions.push(RuleAction::Swap);
+        }
+        if actions.len() != 1 {
+            return Err(format!(
+                "rule \"{}\" must have exactly one of \"rename\", \"replace\", \"drop\" or \"swap\"",
+                glob
+            ));
+        }
+        Ok(Rule::new(glob, actions.pop().unwrap()))
+    }
+
+    /// Whether the rule applies to the entry at `path`
+    pub(crate) fn matches(&self, path: &str) -> bool {
+        self.pattern.is_match(path)
+    }
+
+    /// The path of the entry at `path` after applying the rule, or `None` if it is dropped.
+    /// `base` holds the files of the base map.
+    fn apply_to(&self, path: &str, base: &HashMap<String, Vec<u8>>) -> Option<String> {
+        match &self.action {
+            RuleAction::Rename(name) if name.contains('/') => Some(name.clone()),
+            RuleAction::Rename(name) => Some(match path.rsplit_once('/') {
+                Some((dir, _)) => format!("{}/{}", dir, name),
+                None => name.clone(),
+            }),
+            RuleAction::Replace { from, to } => Some(path.replace(from.as_str(), to)),
+            RuleAction::Drop => None,
+            RuleAction::Swap if base.contains_key(path) => None,
+            RuleAction::Swap => Some(path.to_owned()),
+        }
+    }
+}
+
+/// The rules used unless configured otherwise: the map of the terrain is renamed after the
+/// base map (`dota.vmap_c` for `dota`), so the game loads it in place of the base map
+pub(crate) fn default_rules(base: &str) -> Vec<Rule> {
+    vec![Rule::new(
+        "maps/*.vmap_c",
+        RuleAction::Rename(format!("{}.vmap_c", base)),
+    )]
+}
+
+/// Heavyweight assets handled by `--low-end`: terrain particles which only add ambience are
+/// dropped, while textures and remaining particles the terrain replaces are swapped back to
+/// the base map's versions
+const LOW_END_ASSETS: &[(&str, bool)] = &[
+    ("particles/ambient/**", true),
+    ("particles/environment/**", true),
+    ("particles/world_environmental_fx/**", true),
+    ("particles/weather/**", true),
+    ("particles/**.vpcf_c", false),
+    ("materials/**_normal*.vtex_c", false),
+    ("materials/**_rough*.vtex_c", false),
+    ("materials/**_spec*.vtex_c", false),
+    ("materials/**_detail*.vtex_c", false),
+    ("materials/**_color*.vtex_c", false),
+    ("materials/**_blend*.vtex_c", false),
+];
+
+/// The rules added by `--low-end`, producing a lighter build for weak GPUs from the curated
+/// `LOW_END_ASSETS`. Applied after the other rules.
+pub(crate) fn low_end_rules() -> Vec<Rule> {
+    LOW_END_ASSETS
+        .iter()
+        .map(|&(glob, drop)| {
+            Rule::new(
+                glob,
+                if drop {
+                    RuleAction::Drop
+                } else {
+                    RuleAction::Swap
+                },
+            )
+        })
+        .collect()
+}
+
+/// Leftovers of editors and build tools sometimes shipped in community terrains, which the
+/// game never loads, removed by `--strip-editor-files` along with uncompiled source assets
+/// (see `resource::SOURCE_EXTENSIONS`), such as uncompiled `.vmap` maps
+const EDITOR_FILES: [&str; 6] = [
+    "**.txt",
+    "**.log",
+    "**.bak",
+    "**.tmp",
+    "**Thumbs.db",
+    "**.DS_Store",
+];
+
+/// The rules added by `--strip-editor-files`, dropping `EDITOR_FILES` and uncompiled source
+/// assets. Applied after the other rules.
+pub(crate) fn editor_file_rules() -> Vec<Rule> {
+    EDITOR_FILES
+        .iter()
+        .map(|glob| glob.to_string())
+        .chain(
+            resource::SOURCE_EXTENSIONS
+                .iter()
+                .map(|ext| format!("**.{}", ext)),
+        )
+        .map(|glob| Rule::new(&glob, RuleAction::Drop))
+        .collect()
+}
+
+/// Read the `[[rules]]` array of a parsed TOML document. Returns `None` if the document
+/// declares no rules.
+pub(crate) fn from_toml(document: &Table) -> Result<Option<Vec<Rule>>, String> {
+    let Some(rules) = document.get("rules") else {
+        return Ok(None);
+    };
+    let Some(rules) = rules.as_array() else {
+        return Err(String::from("\"rules\" must be an array of tables"));
+    };
+    rules
+        .iter()
+        .map(|rule| match rule.as_table() {
+            Some(table) => Rule::from_table(table),
+            None => Err(String::from("\"rules\" must be an array of tables")),
+        })
+        .collect::<Result<Vec<Rule>, String>>()
+        .map(Some)
+}
+
+/// Load the rules from the TOML file at `path`, as given with `--rules`
+pub(crate) fn load(path: &Path) -> Result<Vec<Rule>, TMError> {
+    let text = std::fs::read_to_string(path).map_err(TMError::InternalError)?;
+    let document: Table = text
+        .parse()
+        .map_err(|e| TMError::ConfigError(format!("{}: {}", path.display(), e)))?;
+    match from_toml(&document) {
+        Ok(Some(rules)) => Ok(rules),
+        Ok(None) => Err(TMError::ConfigError(format!(
+            "{}: no [[rules]] declared",
+            path.display()
+        ))),
+        Err(e) => Err(TMError::ConfigError(format!("{}: {}", path.display(), e))),
+    }
+}
+
+/// Apply `rules` in order to every entry in `files`, where `base` holds the files of the base
+/// map. An entry renamed onto an existing path replaces it.
+pub(crate) fn apply(
+    rules: &[Rule],
+    files: &mut HashMap<String, Vec<u8>>,
+    base: &HashMap<String, Vec<u8>>,
+) {
+    for rule in rules {
+        let mut paths: Vec<String> = files
+            .keys()
+            .filter(|path| rule.matches(path))
+            .cloned()
+            .collect();
+        paths.sort();
+        for path in paths {
+            let data = files.remove(&path).unwrap();
+            if let Some(new_path) = rule.apply_to(&path, base) {
+                files.insert(new_path, data);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(text: &str) -> Result<Option<Vec<Rule>>, String> {
+        from_toml(&text.parse::<Table>().unwrap())
+    }
+
+    fn files(paths: &[&str]) -> HashMap<String, Vec<u8>> {
+        paths
+            .iter()
+            .map(|path| (path.to_string(), path.as_bytes().to_vec()))
+            .collect()
+    }
+
+    fn sorted(files: &HashMap<String, Vec<u8>>) -> Vec<&str> {
+        let mut paths: Vec<&str> = files.keys().map(String::as_str).collect();
+        paths.sort();
+        paths
+    }
+
+    #[test]
+    fn globs_match_whole_paths() {
+        let glob = glob_regex("maps/*.vmap_c");
+        assert!(glob.is_match("maps/dota_desert.vmap_c"));
+        assert!(!glob.is_match("maps/sub/dota.vmap_c"));
+        assert!(!glob.is_match("old/maps/dota.vmap_c"));
+        assert!(!glob.is_match("maps/dota.vmap_c.bak"));
+
+        let glob = glob_regex("particles/**.vpcf_c");
+        assert!(glob.is_match("particles/rain.vpcf_c"));
+        assert!(glob.is_match("particles/weather/snow/flake.vpcf_c"));
+        assert!(!glob.is_match("materials/particles/rain.vpcf_c"));
+
+        let glob = glob_regex("maps/dota?.txt");
+        assert!(glob.is_match("maps/dota2.txt"));
+        assert!(!glob.is_match("maps/dota/.txt"));
+        assert!(!glob.is_match("maps/dota.txt"));
+
+        // Regular expression syntax is matched literally
+        let glob = glob_regex("maps/(a+b)|[c].txt");
+        assert!(glob.is_match("maps/(a+b)|[c].txt"));
+        assert!(!glob.is_match("maps/aab.txt"));
+    }
+
+    #[test]
+    fn rules_rename_replace_drop_and_swap() {
+        let rules = parse(
+            r#"
+            [[rules]]
+            match = "maps/*.vmap_c"
+            rename = "dota.vmap_c"
+
+            [[rules]]
+            match = "materials/desert/**"
+            replace = { from = "desert", to = "default" }
+
+            [[rules]]
+            match = "**.psd"
+            drop = true
+
+            [[rules]]
+            match = "sounds/**"
+            swap = true
+            "#,
+        )
+        .unwrap()
+        .unwrap();
+        let mut terrain = files(&[
+            "maps/dota_desert.vmap_c",
+            "materials/desert/sand.vmat_c",
+            "materials/source.psd",
+            "sounds/wind.vsnd_c",
+            "sounds/new.vsnd_c",
+        ]);
+        let base = files(&["sounds/wind.vsnd_c"]);
+        apply(&rules, &mut terrain, &base);
+        assert_eq!(
+            sorted(&terrain),
+            [
+                "maps/dota.vmap_c",
+                "materials/default/sand.vmat_c",
+                "sounds/new.vsnd_c"
+            ]
+        );
+        assert_eq!(terrain["maps/dota.vmap_c"], b"maps/dota_desert.vmap_c");
+    }
+
+    #[test]
+    fn renames_with_a_slash_move_the_entry() {
+        let rules = [Rule::new(
+            "maps/*.vmap_c",
+            RuleAction::Rename(String::from("maps/sub/dota.vmap_c")),
+        )];
+        let mut terrain = files(&["maps/desert.vmap_c"]);
+        apply(&rules, &mut terrain, &HashMap::new());
+        assert_eq!(sorted(&terrain), ["maps/sub/dota.vmap_c"]);
+    }
+
+    #[test]
+    fn malformed_rules_are_refused() {
+        assert!(parse("").unwrap().is_none());
+        for (text, error) in [
+            ("rules = 1", "\"rules\" must be an array of tables"),
+            ("rules = [1]", "\"rules\" must be an array of tables"),
+            (
+                "[[rules]]\nrename = \"a.txt\"",
+                "rule without a \"match\" pattern",
+            ),
+            (
+                "[[rules]]\nmatch = \"*\"",
+                "rule \"*\" must have exactly one of \"rename\", \"replace\", \"drop\" or \"swap\"",
+            ),
+            (
+                "[[rules]]\nmatch = \"*\"\nrename = \"a\"\ndrop = true",
+                "rule \"*\" must have exactly one of \"rename\", \"replace\", \"drop\" or \"swap\"",
+            ),
+            (
+                "[[rules]]\nmatch = \"*\"\nrename = 1",
+                "\"rename\" of rule \"*\" must be a string",
+            ),
+            (
+                "[[rules]]\nmatch = \"*\"\nreplace = { from = \"a\" }",
+                "\"replace\" of rule \"*\" must have \"from\" and \"to\" strings",
+            ),
+        ] {
+            assert_eq!(parse(text).err().as_deref(), Some(error), "{}", text);
+        }
+    }
+}
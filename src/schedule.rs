@@ -0,0 +1,379 @@
+use toml::{Table, Value};
+
+use crate::apply::{self, ApplyOptions};
+use crate::cli::Args;
+use crate::config::Config;
+use crate::history;
+use crate::manifest::Manifest;
+use crate::menu::{MenuConfig, Terrain};
+use crate::utils::DotaInstall;
+
+/// Days of the week as written in the configuration file, starting on Monday
+const WEEKDAYS: [&str; 7] = [
+    "monday",
+    "tuesday",
+    "wednesday",
+    "thursday",
+    "friday",
+    "saturday",
+    "sunday",
+];
+
+/// What a schedule rule applies
+pub(crate) enum ScheduleTarget {
+    /// A terrain, given by file name or menu name
+    Terrain(String),
+    /// The favorite terrains in turn, switching to the next one every day
+    Daily,
+    /// The favorite terrains in turn, switching to the next one every Monday
+    Weekly,
+}
+
+/// A rule of the `[[schedule]]` list of the configuration file, selecting the terrain to apply
+/// on the days it matches
+/// # Properties
+/// - `target: ScheduleTarget` = The terrain to apply, or the rotation of favorites
+/// - `months: Vec<u32>` = Months (1 to 12) the rule applies in, every month if empty
+/// - `weekdays: Vec<u32>` = Days of the week (0 for Monday to 6 for Sunday) the rule applies
+///   on, every day if empty
+pub(crate) struct ScheduleRule {
+    target: ScheduleTarget,
+    months: Vec<u32>,
+    weekdays: Vec<u32>,
+}
+
+impl ScheduleRule {
+    /// Read a rule from a `[[schedule]]` table: either a `terrain` or `rotate = "daily"` or
+    /// `"weekly"`, optionally limited to `months = [12, 1]` and
+    /// `weekdays = ["saturday", "sunday"]`
+    fn from_table(table: &Table) -> Result<ScheduleRule, String> {
+        let target = match (
+            table.get("terrain").map(Value::as_str),
+            table.get("rotate").map(Value::as_str),
+        ) {
+            (Some(Some(terrain)), None) => ScheduleTarget::Terrain(terrain.to_owned()),
+            (None, Some(Some("daily"))) => ScheduleTarget::Daily,
+            (None, Some(Some("weekly"))) => ScheduleTarget::Weekly,
+            _ => {
+                return Err(String::from(
+                    "schedule rules must have either a \"terrain\" or \
+                    rotate = \"daily\" or \"weekly\"",
+                ))
+            }
+        };
+
+        let list = |key: &str| -> Result<Vec<&Value>, String> {
+            match table.get(key) {
+                None => Ok(Vec::new()),
+                Some(Value::Array(values)) => Ok(values.iter().collect()),
+                Some(_) => Err(format!("\"{}\" of a schedule rule must be an array", key)),
+            }
+        };
+        let months = list("months")?
+            .into_iter()
+            .map(|month| {
+                month
+                    .as_integer()
+                    .filter(|month| (1..=12).contains(month))
+                    .map(|month| month as u32)
+                    .ok_or_else(|| format!("{} is not a month, expected 1 to 12", month))
+            })
+            .collect::<Result<Vec<u32>, String>>()?;
+        let weekdays = list("weekdays")?
+            .into_iter()
+            .map(|day| {
+                day.as_str()
+                    .and_then(|day| {
+                        WEEKDAYS
+                            .iter()
+                            .position(|weekday| weekday.eq_ignore_ascii_case(day))
+                    })
+                    .map(|day| day as u32)
+                    .ok_or_else(|| format!("{} is not a day of the week, e.g. \"monday\"", day))
+            })
+            .collect::<Result<Vec<u32>, String>>()?;
+
+        Ok(ScheduleRule {
+            target,
+            months,
+            weekdays,
+        })
+    }
+
+    /// Whether the rule applies on the date `days` days after the Unix epoch
+    fn matches(&self, days: i64) -> bool {
+        let (_, month, _) = history::civil_date(days);
+        // The epoch was a Thursday
+        let weekday = (days + 3).rem_euclid(7) as u32;
+        (self.months.is_empty() || self.months.contains(&month))
+            && (self.weekdays.is_empty() || self.weekdays.contains(&weekday))
+    }
+
+    /// The terrain the rule applies on the date `days` days after the Unix epoch. Fails if
+    /// the terrain is unknown, or the rule rotates favorites and there are none.
+    fn terrain(&self, days: i64, menu: &MenuConfig) -> Result<Terrain, String> {
+        let turn = match &self.target {
+            ScheduleTarget::Terrain(query) => {
+                return menu
+                    .find(query)
+                    .ok_or_else(|| format!("unknown terrain \"{}\" in the schedule", query));
+            }
+            ScheduleTarget::Daily => days,
+            // Weeks starting on Monday, the epoch was a Thursday
+            ScheduleTarget::Weekly => (days + 3).div_euclid(7),
+        };
+        let mut favorites = menu.favorites();
+        if favorites.is_empty() {
+            return Err(String::from(
+                "the schedule rotates favorites, but there are none. \
+                Add some with `favorite add <terrain>`",
+            ));
+        }
+        let turn = turn.rem_euclid(favorites.len() as i64) as usize;
+        Ok(favorites.swap_remove(turn))
+    }
+}
+
+/// Read the `[[schedule]]` array of a parsed TOML document, in order
+pub(crate) fn from_toml(document: &Table) -> Result<Vec<ScheduleRule>, String> {
+    let Some(rules) = document.get("schedule") else {
+        return Ok(Vec::new());
+    };
+    let Some(rules) = rules.as_array() else {
+        return Err(String::from("\"schedule\" must be an array of tables"));
+    };
+    rules
+        .iter()
+        .map(|rule| match rule.as_table() {
+            Some(table) => ScheduleRule::from_table(table),
+            None => Err(String::from("\"schedule\" must be an array of tables")),
+        })
+        .collect()
+}
+
+/// The terrain the first of `rules` matching the date `days` days after the Unix epoch
+/// applies, see `history::local_today()`. `None` if no rule matches, fails like
+/// `ScheduleRule::terrain()`.
+pub(crate) fn scheduled(
+    rules: &[ScheduleRule],
+    days: i64,
+    menu: &MenuConfig,
+) -> Option<Result<Terrain, String>> {
+    let rule = rules.iter().find(|rule| rule.matches(days))?;
+    Some(rule.terrain(days, menu))
+}
+
+/// Entry point for the `schedule [--yes]` command. Applies the terrain selected by the first
+/// `[[schedule]]` rule of the configuration matching today's local date, unless it is
+/// already applied. Meant to be run daily or at logon from a scheduler, so terrains follow
+/// the seasons or rotate through the favorites; `apply --watch` follows the rules as well.
+/// Takes the same options as applying a terrain from the menu.
+pub(crate) fn run(args: &Args) {
+    if !args.positional.is_empty() {
+        eprintln!("Usage: dota-terrain-mod schedule [--yes]");
+        std::process::exit(1);
+    }
+    let dota = match DotaInstall::new(args) {
+        Ok(dota) => dota,
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+    };
+    let mut config = match Config::load() {
+        Ok(config) => config,
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+    };
+    config.menu.discover(&dota);
+    if config.schedule.is_empty() {
+        eprintln!("Dota-Terrain-Mod error: No [[schedule]] rules in config.toml");
+        std::process::exit(1);
+    }
+
+    let today = history::local_today();
+    let terrain = match scheduled(&config.schedule, today, &config.menu) {
+        None => {
+            println!("No schedule rule matches today, nothing to do.");
+            return;
+        }
+        Some(Ok(terrain)) => terrain,
+        Some(Err(e)) => {
+            eprintln!("Dota-Terrain-Mod error: {}", e);
+            std::process::exit(1);
+        }
+    };
+    let options = match ApplyOptions::from_args(args, &config, &terrain.file) {
+        Ok(options) => options,
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+    };
+    let applied = Manifest::read(&dota.out_vpk(options.base()))
+        .is_ok_and(|manifest| manifest.terrain == terrain.file);
+    if applied {
+        println!("{} is already applied.", terrain.name);
+        return;
+    }
+
+    println!("Applying {}...", terrain.name);
+    if let Err(e) = apply::apply(&dota, &terrain.file, &options) {
+        eprintln!("{}", e);
+        std::process::exit(1);
+    }
+    println!("Done.");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 2024-12-25, a Wednesday, and 2024-06-15, a Saturday
+    const CHRISTMAS: i64 = 20082;
+    const SUMMER_SATURDAY: i64 = 19889;
+
+    fn rules(text: &str) -> Result<Vec<ScheduleRule>, String> {
+        from_toml(&text.parse::<Table>().unwrap())
+    }
+
+    fn menu(text: &str) -> MenuConfig {
+        MenuConfig::from_toml(&text.parse::<Table>().unwrap()).unwrap()
+    }
+
+    #[test]
+    fn rules_are_read_in_order() {
+        let rules = rules(
+            r#"
+            [[schedule]]
+            terrain = "winter"
+            months = [12, 1]
+
+            [[schedule]]
+            rotate = "weekly"
+            weekdays = ["Saturday", "SUNDAY"]
+
+            [[schedule]]
+            rotate = "daily"
+            "#,
+        )
+        .unwrap();
+        assert_eq!(rules.len(), 3);
+        assert!(
+            matches!(&rules[0].target, ScheduleTarget::Terrain(terrain) if terrain == "winter")
+        );
+        assert_eq!(rules[0].months, [12, 1]);
+        assert!(matches!(rules[1].target, ScheduleTarget::Weekly));
+        assert_eq!(rules[1].weekdays, [5, 6]);
+        assert!(matches!(rules[2].target, ScheduleTarget::Daily));
+        assert!(rules[2].months.is_empty() && rules[2].weekdays.is_empty());
+
+        assert!(from_toml(&Table::new()).unwrap().is_empty());
+    }
+
+    #[test]
+    fn dates_are_matched_by_month_and_weekday() {
+        let rules = rules(
+            r#"
+            [[schedule]]
+            terrain = "winter"
+            months = [12]
+
+            [[schedule]]
+            terrain = "desert"
+            weekdays = ["saturday"]
+            "#,
+        )
+        .unwrap();
+        // The epoch was a Thursday in January
+        assert!(!rules[0].matches(0) && !rules[1].matches(0));
+        assert!(rules[0].matches(CHRISTMAS));
+        assert!(!rules[1].matches(CHRISTMAS));
+        assert!(!rules[0].matches(SUMMER_SATURDAY));
+        assert!(rules[1].matches(SUMMER_SATURDAY));
+        // Dates before the epoch
+        assert!(rules[1].matches(-5));
+
+        let menu = menu("");
+        let winter = scheduled(&rules, CHRISTMAS, &menu).unwrap().unwrap();
+        assert_eq!(winter.file, "dota_winter.vpk");
+        let desert = scheduled(&rules, SUMMER_SATURDAY, &menu).unwrap().unwrap();
+        assert_eq!(desert.file, "dota_desert.vpk");
+        assert!(scheduled(&rules, 0, &menu).is_none());
+    }
+
+    #[test]
+    fn rotations_follow_the_favorites() {
+        let rules =
+            rules("[[schedule]]\nrotate = \"daily\"\n[[schedule]]\nrotate = \"weekly\"\n").unwrap();
+        let menu = menu(r#"menu = { favorites = ["dota_desert.vpk", "dota_reef.vpk"] }"#);
+        let daily = |days| rules[0].terrain(days, &menu).unwrap().file;
+        assert_eq!(daily(CHRISTMAS), "dota_desert.vpk");
+        assert_eq!(daily(CHRISTMAS + 1), "dota_reef.vpk");
+        assert_eq!(daily(CHRISTMAS + 2), "dota_desert.vpk");
+
+        // Weeks start on Monday: 2024-12-23 to 2024-12-29 is the same turn
+        let weekly = |days| rules[1].terrain(days, &menu).unwrap().file;
+        assert_eq!(weekly(CHRISTMAS - 2), weekly(CHRISTMAS + 4));
+        assert_ne!(weekly(CHRISTMAS + 4), weekly(CHRISTMAS + 5));
+
+        let error = rules[0]
+            .terrain(CHRISTMAS, &MenuConfig::default())
+            .err()
+            .unwrap();
+        assert!(error.contains("there are none"), "{}", error);
+    }
+
+    #[test]
+    fn malformed_rules_are_refused() {
+        for (text, error) in [
+            ("schedule = 1", "must be an array of tables"),
+            ("schedule = [1]", "must be an array of tables"),
+            ("[[schedule]]\nmonths = [1]", "either a \"terrain\""),
+            (
+                "[[schedule]]\nterrain = \"a\"\nrotate = \"daily\"",
+                "either a \"terrain\"",
+            ),
+            ("[[schedule]]\nrotate = \"monthly\"", "either a \"terrain\""),
+            ("[[schedule]]\nterrain = 1", "either a \"terrain\""),
+            (
+                "[[schedule]]\nterrain = \"a\"\nmonths = 12",
+                "must be an array",
+            ),
+            (
+                "[[schedule]]\nterrain = \"a\"\nmonths = [13]",
+                "13 is not a month",
+            ),
+            (
+                "[[schedule]]\nterrain = \"a\"\nmonths = [0]",
+                "0 is not a month",
+            ),
+            (
+                "[[schedule]]\nterrain = \"a\"\nmonths = [\"may\"]",
+                "is not a month",
+            ),
+            (
+                "[[schedule]]\nterrain = \"a\"\nweekdays = [\"mon\"]",
+                "is not a day of the week",
+            ),
+            (
+                "[[schedule]]\nterrain = \"a\"\nweekdays = [1]",
+                "1 is not a day of the week",
+            ),
+        ] {
+            let message = rules(text)
+                .err()
+                .unwrap_or_else(|| panic!("{} was accepted", text));
+            assert!(message.contains(error), "{}: {}", text, message);
+        }
+
+        let rules = rules("[[schedule]]\nterrain = \"nowhere\"").unwrap();
+        let error = scheduled(&rules, 0, &MenuConfig::default())
+            .unwrap()
+            .err()
+            .unwrap();
+        assert!(error.contains("unknown terrain \"nowhere\""), "{}", error);
+    }
+}
@@ -0,0 +1,89 @@
+use std::path::PathBuf;
+
+use crate::cli::Args;
+use crate::game;
+
+/// Directory the shortcut is placed in: the desktop, or the start menu / application menu
+/// with `menu` set
+fn shortcut_dir(menu: bool) -> Option<PathBuf> {
+    #[cfg(target_os = "windows")]
+    return if menu {
+        std::env::var_os("APPDATA").map(|dir| {
+            PathBuf::from(dir)
+                .join("Microsoft")
+                .join("Windows")
+                .join("Start Menu")
+                .join("Programs")
+        })
+    } else {
+        std::env::var_os("USERPROFILE").map(|dir| PathBuf::from(dir).join("Desktop"))
+    };
+
+    #[cfg(not(target_os = "windows"))]
+    {
+        let home = PathBuf::from(std::env::var_os("HOME")?);
+        if menu {
+            return Some(match std::env::var_os("XDG_DATA_HOME") {
+                Some(dir) if !dir.is_empty() => PathBuf::from(dir).join("applications"),
+                _ => home.join(".local").join("share").join("applications"),
+            });
+        }
+        Some(match std::env::var_os("XDG_DESKTOP_DIR") {
+            Some(dir) if !dir.is_empty() => PathBuf::from(dir),
+            _ => home.join("Desktop"),
+        })
+    }
+}
+
+/// File name and contents of the shortcut: an Internet Shortcut on Windows, a desktop entry
+/// elsewhere
+fn shortcut_file() -> (String, String) {
+    let game = game::current();
+    let name = format!("{} (Custom Terrain)", game.title);
+    let launch_url = game.launch_url();
+
+    #[cfg(target_os = "windows")]
+    return (
+        format!("{}.url", name),
+        format!("[InternetShortcut]\r\nURL={}\r\n", launch_url),
+    );
+
+    #[cfg(not(target_os = "windows"))]
+    (
+        format!("{}.desktop", name),
+        format!(
+            "[Desktop Entry]\nType=Application\nName={}\nComment=Launch {} with -language tempcontent\nExec=xdg-open {}\nIcon=steam\nTerminal=false\nCategories=Game;\n",
+            name,
+            game.title,
+            // Exec field codes start with %, a literal % has to be doubled
+            launch_url.replace('%', "%%")
+        ),
+    )
+}
+
+/// Entry point for the `shortcut [--menu]` command. Creates a shortcut on the desktop, or in
+/// the start menu with `--menu`, which launches Dota 2 through Steam with
+/// `-language tempcontent`, so the launch options do not have to be changed in Steam.
+pub(crate) fn run(args: &Args) {
+    if !args.positional.is_empty() {
+        eprintln!("Usage: dota-terrain-mod shortcut [--menu]");
+        std::process::exit(1);
+    }
+    let Some(dir) = shortcut_dir(args.flag("menu")) else {
+        eprintln!("Dota-Terrain-Mod error: Could not determine where to place the shortcut");
+        std::process::exit(1);
+    };
+    let (name, contents) = shortcut_file();
+    let path = dir.join(name);
+    let written = std::fs::create_dir_all(&dir).and_then(|_| std::fs::write(&path, contents));
+    if let Err(e) = written {
+        eprintln!("Dota-Terrain-Mod error: Internal error: {}", e);
+        std::process::exit(1);
+    }
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        let _ = std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o755));
+    }
+    println!("Shortcut created: {}", path.display());
+}
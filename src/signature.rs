@@ -0,0 +1,195 @@
+use std::{
+    fs::OpenOptions,
+    io::{self, Write},
+    path::{Path, PathBuf},
+};
+
+use ed25519_dalek::{Signature, Signer, SigningKey, Verifier, VerifyingKey};
+use serde_json::{json, Value};
+
+use crate::cli::Args;
+
+/// Extension appended to the file name of a terrain to get its detached signature, e.g.
+/// `dota_custom.vpk.sig`
+const SIGNATURE_EXTENSION: &str = "sig";
+
+/// Result of checking the detached signature of a terrain
+/// # Properties
+/// - `Unsigned` = There is no signature next to the terrain
+/// - `Trusted` = Signed by a trusted key
+/// - `Untrusted(String)` = Correctly signed, but by a key which is not trusted
+/// - `Mismatch(String)` = The signature does not match the contents, or cannot be read
+pub(crate) enum SignatureStatus {
+    Unsigned,
+    Trusted,
+    Untrusted(String),
+    Mismatch(String),
+}
+
+/// Encode bytes as lowercase hex
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+/// Decode exactly `N` bytes of hex
+fn from_hex<const N: usize>(hex: &str) -> Option<[u8; N]> {
+    let hex = hex.trim();
+    if hex.len() != N * 2 || !hex.is_ascii() {
+        return None;
+    }
+    let mut bytes = [0; N];
+    for (i, byte) in bytes.iter_mut().enumerate() {
+        *byte = u8::from_str_radix(&hex[i * 2..i * 2 + 2], 16).ok()?;
+    }
+    Some(bytes)
+}
+
+/// Path of the detached signature of the terrain at `path`
+fn signature_path(path: &Path) -> PathBuf {
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(format!(".{}", SIGNATURE_EXTENSION));
+    path.with_file_name(name)
+}
+
+/// Check the detached signature next to the terrain at `path` against its contents `data`.
+/// `trusted_keys` are the public keys, in hex, configured as `trusted_keys`.
+pub(crate) fn check(path: &Path, data: &[u8], trusted_keys: &[String]) -> SignatureStatus {
+    let json = match std::fs::read_to_string(signature_path(path)) {
+        Ok(json) => json,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return SignatureStatus::Unsigned,
+        Err(e) => return SignatureStatus::Mismatch(e.to_string()),
+    };
+    let Some((key, signature)) = serde_json::from_str::<Value>(&json).ok().and_then(|value| {
+        Some((
+            from_hex::<32>(value.get("key")?.as_str()?)?,
+            from_hex::<64>(value.get("signature")?.as_str()?)?,
+        ))
+    }) else {
+        return SignatureStatus::Mismatch(String::from("malformed signature file"));
+    };
+    let Ok(verifying_key) = VerifyingKey::from_bytes(&key) else {
+        return SignatureStatus::Mismatch(String::from("invalid public key"));
+    };
+    if verifying_key
+        .verify(data, &Signature::from_bytes(&signature))
+        .is_err()
+    {
+        return SignatureStatus::Mismatch(String::from(
+            "the signature does not match the contents",
+        ));
+    }
+    let key = to_hex(&key);
+    if trusted_keys
+        .iter()
+        .any(|trusted| trusted.trim().eq_ignore_ascii_case(&key))
+    {
+        SignatureStatus::Trusted
+    } else {
+        SignatureStatus::Untrusted(key)
+    }
+}
+
+/// Warning about the signature of a third-party terrain, if any. Unsigned terrains are only
+/// reported once keys are trusted.
+pub(crate) fn warning(
+    terrain_file: &str,
+    status: &SignatureStatus,
+    trusted_keys: &[String],
+) -> Option<String> {
+    match status {
+        SignatureStatus::Trusted => None,
+        SignatureStatus::Unsigned if trusted_keys.is_empty() => None,
+        SignatureStatus::Unsigned => Some(format!(
+            "Warning: {} is not signed, its author cannot be verified",
+            terrain_file
+        )),
+        SignatureStatus::Untrusted(key) => Some(format!(
+            "Warning: {} is signed by {}, which is not one of the trusted_keys",
+            terrain_file, key
+        )),
+        SignatureStatus::Mismatch(reason) => Some(format!(
+            "Warning: The signature of {} is invalid ({}), the file may have been modified",
+            terrain_file, reason
+        )),
+    }
+}
+
+/// Read the signing key in `path`, a hex encoded secret key, or create a new one there. A new
+/// key file is only readable by its owner on Unix.
+fn signing_key(path: &Path) -> io::Result<SigningKey> {
+    match std::fs::read_to_string(path) {
+        Ok(hex) => from_hex::<32>(&hex)
+            .map(|secret| SigningKey::from_bytes(&secret))
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "malformed signing key")),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {
+            let mut secret = [0; 32];
+            getrandom::fill(&mut secret)?;
+            let mut options = OpenOptions::new();
+            options.write(true).create_new(true);
+            #[cfg(unix)]
+            {
+                use std::os::unix::fs::OpenOptionsExt;
+                options.mode(0o600);
+            }
+            options.open(path)?.write_all(to_hex(&secret).as_bytes())?;
+            println!(
+                "Created a new signing key in {}, keep it private",
+                path.display()
+            );
+            Ok(SigningKey::from_bytes(&secret))
+        }
+        Err(e) => Err(e),
+    }
+}
+
+/// Entry point for the `sign <vpk> --key <key file>` command. Signs a terrain for sharing,
+/// writing the signature to `<vpk>.sig` next to it. A new key is created if the key file does
+/// not exist. Users add the printed public key to `trusted_keys` to verify the author.
+pub(crate) fn run(args: &Args) {
+    let ([vpk_path], Some(key_path)) = (args.positional.as_slice(), args.option("key")) else {
+        eprintln!("Usage: dota-terrain-mod sign <vpk> --key <key file>");
+        std::process::exit(1);
+    };
+    let signing_key = match signing_key(Path::new(key_path)) {
+        Ok(key) => key,
+        Err(e) => {
+            eprintln!(
+                "Dota-Terrain-Mod error: Could not read the signing key {}: {}",
+                key_path, e
+            );
+            std::process::exit(1);
+        }
+    };
+    let vpk_path = Path::new(vpk_path);
+    let data = match std::fs::read(vpk_path) {
+        Ok(data) => data,
+        Err(e) => {
+            eprintln!(
+                "Dota-Terrain-Mod error: Could not read {}: {}",
+                vpk_path.display(),
+                e
+            );
+            std::process::exit(1);
+        }
+    };
+
+    let key = to_hex(&signing_key.verifying_key().to_bytes());
+    let signature = json!({
+        "key": key,
+        "signature": to_hex(&signing_key.sign(&data).to_bytes()),
+    });
+    let out_path = signature_path(vpk_path);
+    let written = serde_json::to_string_pretty(&signature)
+        .map_err(io::Error::from)
+        .and_then(|json| std::fs::write(&out_path, json));
+    if let Err(e) = written {
+        eprintln!(
+            "Dota-Terrain-Mod error: Could not write {}: {}",
+            out_path.display(),
+            e
+        );
+        std::process::exit(1);
+    }
+    println!("Signature written to {}", out_path.display());
+    println!("Public key: {}", key);
+}
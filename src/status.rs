@@ -0,0 +1,85 @@
+use crate::cli::Args;
+use crate::manifest::{self, Manifest, Source};
+use crate::utils::DotaInstall;
+
+/// Entry point for the `status [--detailed] [--base <map>]` command. Shows which terrain is
+/// applied (to the default map, or the map given with `--base`) and how many files of the
+/// output came from each source. With `--detailed`, the source of every file is listed.
+pub(crate) fn run(args: &Args) {
+    if !args.positional.is_empty() {
+        eprintln!("Usage: dota-terrain-mod status [--detailed] [--base <map>]");
+        std::process::exit(1);
+    }
+    let dota = match DotaInstall::new(args) {
+        Ok(dota) => dota,
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+    };
+    let base = args.option("base").unwrap_or(dota.game.default_base);
+    let out_path = dota.out_vpk(base);
+    if !manifest::is_applied(&out_path) {
+        println!("No terrain applied.");
+        return;
+    }
+    if let Some(warning) = manifest::other_user_warning(&out_path) {
+        eprintln!("{}", warning);
+    }
+    let manifest = match Manifest::read(&out_path) {
+        Ok(manifest) => manifest,
+        Err(e) => {
+            println!(
+                "A terrain is applied, but its manifest could not be read: {}",
+                e
+            );
+            return;
+        }
+    };
+
+    println!("Applied terrain: {}", manifest.terrain);
+    println!(
+        "{}, with --method {}",
+        manifest.method.installer().status(&dota, base, &manifest),
+        manifest.method.name()
+    );
+    if let Some(warning) = manifest::stale_warning(&manifest, dota.build_id().as_deref()) {
+        eprintln!("{}", warning);
+    }
+    // Not recorded by older versions
+    if !manifest.tool.is_empty() {
+        println!("Built by: {}", manifest.tool);
+    }
+    if !manifest.user.is_empty() {
+        println!("Applied by: {}", manifest.user);
+    }
+    if !manifest.options.is_empty() {
+        println!("Options: {}", manifest.options.join(" "));
+    }
+    if !manifest.stats.phases.is_empty() {
+        println!("{}", manifest.stats.summary());
+    }
+    for source in [Source::Base, Source::Terrain, Source::Overrides] {
+        println!("Files from {}: {}", source.name(), manifest.count(source));
+    }
+    for line in manifest.conflicts() {
+        println!("{}", line);
+    }
+
+    if args.flag("detailed") {
+        println!();
+        for (path, entry) in &manifest.entries {
+            let replaces: Vec<&str> = entry.replaces.iter().map(|s| s.name()).collect();
+            if replaces.is_empty() {
+                println!("{}  {}", entry.source.name(), path);
+            } else {
+                println!(
+                    "{}  {} (replaces {})",
+                    entry.source.name(),
+                    path,
+                    replaces.join(", ")
+                );
+            }
+        }
+    }
+}
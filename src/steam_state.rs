@@ -0,0 +1,66 @@
+use crate::utils::DotaInstall;
+
+// Bits of the `StateFlags` Steam keeps in the app manifest of a game
+const UPDATE_REQUIRED: u64 = 1 << 1;
+const UPDATE_RUNNING: u64 = 1 << 8;
+const UPDATE_PAUSED: u64 = 1 << 9;
+const UPDATE_STARTED: u64 = 1 << 10;
+const VALIDATING: u64 = 1 << 17;
+const ADDING_FILES: u64 = 1 << 18;
+const PREALLOCATING: u64 = 1 << 19;
+const DOWNLOADING: u64 = 1 << 20;
+const STAGING: u64 = 1 << 21;
+const COMMITTING: u64 = 1 << 22;
+
+/// States in which Steam is changing the files of the game, the most specific first
+const CHANGING: [(u64, &str); 9] = [
+    (COMMITTING, "installing an update of"),
+    (STAGING, "staging an update of"),
+    (DOWNLOADING, "downloading an update of"),
+    (PREALLOCATING, "allocating space for an update of"),
+    (ADDING_FILES, "adding files to"),
+    (VALIDATING, "verifying the files of"),
+    (UPDATE_PAUSED, "in the middle of updating"),
+    (UPDATE_RUNNING, "updating"),
+    (UPDATE_STARTED, "updating"),
+];
+
+/// What Steam is doing to the game according to its `StateFlags`, if it is changing its
+/// files, e.g. `downloading an update of`
+fn changing(flags: u64) -> Option<&'static str> {
+    CHANGING
+        .iter()
+        .find(|(flag, _)| flags & flag != 0)
+        .map(|(_, state)| *state)
+}
+
+/// Why building a terrain should wait: Steam is updating or verifying the game, so the base
+/// map may be replaced halfway through, e.g. `Steam is downloading an update of Dota 2`.
+/// `None` if the game is left alone, or its state cannot be read.
+pub(crate) fn defer_reason(dota: &DotaInstall) -> Option<String> {
+    changing(dota.app_manifest()?.state_flags)
+        .map(|state| format!("Steam is {} {}", state, dota.game.title))
+}
+
+/// Advice for when Steam may still update the game after a terrain is applied: an update is
+/// waiting, or Steam is not running, so one would only be found once it starts. Updates
+/// replace the base map the terrain was built on.
+pub(crate) fn advice(dota: &DotaInstall) -> Option<String> {
+    if !dota.steam_running() {
+        Some(format!(
+            "Note: Steam is not running. If it finds an update of {} when it starts, run \
+            `reapply` once the update is installed.",
+            dota.game.title
+        ))
+    } else if dota.app_manifest().is_some_and(|manifest| {
+        manifest.state_flags & UPDATE_REQUIRED != 0 || manifest.pending_build().is_some()
+    }) {
+        Some(format!(
+            "Warning: Steam has an update of {} waiting, which replaces the base map. Run \
+            `reapply` once it is installed.",
+            dota.game.title
+        ))
+    } else {
+        None
+    }
+}
@@ -0,0 +1,100 @@
+use std::path::Path;
+
+/// Folders of file syncing clients, matched against the components of a path ignoring case
+const SYNCED_FOLDERS: [(&str, &str); 6] = [
+    ("onedrive", "OneDrive"),
+    ("dropbox", "Dropbox"),
+    ("google drive", "Google Drive"),
+    ("googledrive", "Google Drive"),
+    ("icloud drive", "iCloud Drive"),
+    ("iclouddrive", "iCloud Drive"),
+];
+
+/// Filesystems which store their files on another machine
+#[cfg(target_os = "linux")]
+const NETWORK_FILESYSTEMS: [&str; 8] = [
+    "nfs",
+    "nfs4",
+    "cifs",
+    "smb3",
+    "smbfs",
+    "9p",
+    "fuse.sshfs",
+    "fuse.rclone",
+];
+
+/// Describe why files written to `path` would be synced or sent over the network: a folder
+/// of a syncing client, a network share, or (on Windows) a file placeholder managed by a
+/// cloud provider. Returns `None` for an ordinary local folder.
+pub(crate) fn synced_location(path: &Path) -> Option<String> {
+    for component in path.components() {
+        let name = component.as_os_str().to_string_lossy().to_lowercase();
+        if let Some((_, client)) = SYNCED_FOLDERS
+            .iter()
+            .find(|(folder, _)| name.starts_with(folder))
+        {
+            return Some(format!("a {} folder", client));
+        }
+    }
+    network_location(path)
+}
+
+#[cfg(target_os = "windows")]
+fn network_location(path: &Path) -> Option<String> {
+    use std::os::windows::fs::MetadataExt;
+
+    const FILE_ATTRIBUTE_REPARSE_POINT: u32 = 0x400;
+    const FILE_ATTRIBUTE_RECALL_ON_DATA_ACCESS: u32 = 0x400000;
+
+    let text = path.canonicalize().ok()?.to_string_lossy().into_owned();
+    // UNC paths, \\server\share, are canonicalized to \\?\UNC\server\share
+    if text.starts_with(r"\\?\UNC\") || (text.starts_with(r"\\") && !text.starts_with(r"\\?\")) {
+        return Some(String::from("a network share"));
+    }
+    // Cloud providers keep their files as reparse points which download on access
+    let attributes = std::fs::symlink_metadata(path).ok()?.file_attributes();
+    if attributes & FILE_ATTRIBUTE_RECALL_ON_DATA_ACCESS != 0 {
+        return Some(String::from("a folder managed by a cloud storage provider"));
+    }
+    if attributes & FILE_ATTRIBUTE_REPARSE_POINT != 0 && path.is_dir() {
+        return Some(String::from(
+            "a linked folder, possibly managed by a cloud storage provider",
+        ));
+    }
+    None
+}
+
+#[cfg(target_os = "linux")]
+fn network_location(path: &Path) -> Option<String> {
+    let path = path.canonicalize().ok()?;
+    let mounts = std::fs::read_to_string("/proc/mounts").ok()?;
+    // The mount point closest to the path is the one containing it
+    let (_, filesystem) = mounts
+        .lines()
+        .filter_map(|line| {
+            let mut fields = line.split_whitespace();
+            let mount_point = fields.nth(1)?.replace("\\040", " ");
+            Some((mount_point, fields.next()?.to_owned()))
+        })
+        .filter(|(mount_point, _)| path.starts_with(mount_point))
+        .max_by_key(|(mount_point, _)| mount_point.len())?;
+    NETWORK_FILESYSTEMS
+        .contains(&filesystem.as_str())
+        .then(|| format!("a network share ({})", filesystem))
+}
+
+#[cfg(not(any(target_os = "windows", target_os = "linux")))]
+fn network_location(_path: &Path) -> Option<String> {
+    None
+}
+
+/// Warning shown when the game folder is synced or on a network share
+pub(crate) fn synced_warning(location: &str, tempcontent_dir: &Path) -> String {
+    format!(
+        "Warning: The Dota 2 folder is in {}. The output dota.vpk can be hundreds of megabytes \
+        and is rewritten after every game update, so syncing it will use a lot of bandwidth. \
+        Consider excluding {} from syncing.",
+        location,
+        tempcontent_dir.display()
+    )
+}
@@ -0,0 +1,133 @@
+use std::path::PathBuf;
+
+use toml::{Table, Value};
+
+use crate::menu::MenuConfig;
+use crate::texture::Recolor;
+
+/// Options applied with a terrain whenever it is built, read from a `[terrains.<terrain>]`
+/// table of the configuration file. Options given on the command line take precedence, and
+/// settings left out keep their defaults.
+/// # Properties
+/// - `overrides: Vec<PathBuf>` = Override layers applied below those of `--overrides`
+/// - `terrain_minimap: Option<bool>` = Use the terrain's own minimap (`--terrain-minimap`)
+/// - `compress: Option<bool>` = Store the output LZMA-compressed (`--compress`)
+/// - `low_end: Option<bool>` = Strip or swap heavyweight assets (`--low-end`)
+/// - `strip_editor_files: Option<bool>` = Leave out editor leftovers
+///   (`--strip-editor-files`)
+/// - `downscale: Option<u16>` = Largest texture size kept (`--downscale`)
+/// - `recolor: Option<Recolor>` = Shift of the colors of ground textures (`--recolor`)
+/// - `map_aliases: Option<Vec<String>>` = Additional map names, replacing `map_aliases` of the
+///   configuration (`--map-alias`)
+#[derive(Default)]
+pub(crate) struct TerrainSettings {
+    pub(crate) overrides: Vec<PathBuf>,
+    pub(crate) terrain_minimap: Option<bool>,
+    pub(crate) compress: Option<bool>,
+    pub(crate) low_end: Option<bool>,
+    pub(crate) strip_editor_files: Option<bool>,
+    pub(crate) downscale: Option<u16>,
+    pub(crate) recolor: Option<Recolor>,
+    pub(crate) map_aliases: Option<Vec<String>>,
+}
+
+impl TerrainSettings {
+    /// Read the settings table of the terrain `terrain`
+    fn from_table(terrain: &str, table: &Table) -> Result<TerrainSettings, String> {
+        let invalid = |key: &str, expected: &str| {
+            format!("\"terrains.{}.{}\" must be {}", terrain, key, expected)
+        };
+        let strings = |key: &str, expected: &str| -> Result<Option<Vec<String>>, String> {
+            table
+                .get(key)
+                .map(|strings| {
+                    strings
+                        .as_array()
+                        .and_then(|strings| {
+                            strings
+                                .iter()
+                                .map(|string| string.as_str().map(str::to_owned))
+                                .collect()
+                        })
+                        .ok_or_else(|| invalid(key, expected))
+                })
+                .transpose()
+        };
+        let flag = |key: &str| -> Result<Option<bool>, String> {
+            table
+                .get(key)
+                .map(|flag| flag.as_bool().ok_or_else(|| invalid(key, "true or false")))
+                .transpose()
+        };
+
+        let mut settings = TerrainSettings {
+            overrides: strings("overrides", "an array of paths")?
+                .unwrap_or_default()
+                .into_iter()
+                .map(PathBuf::from)
+                .collect(),
+            terrain_minimap: flag("terrain_minimap")?,
+            compress: flag("compress")?,
+            low_end: flag("low_end")?,
+            strip_editor_files: flag("strip_editor_files")?,
+            map_aliases: strings("map_aliases", "an array of map names")?,
+            ..TerrainSettings::default()
+        };
+        if let Some(size) = table.get("downscale") {
+            settings.downscale = Some(
+                size.as_integer()
+                    .and_then(|size| u16::try_from(size).ok())
+                    .filter(|&size| size > 0)
+                    .ok_or_else(|| invalid("downscale", "a texture size in pixels, e.g. 1024"))?,
+            );
+        }
+        if let Some(recolor) = table.get("recolor") {
+            let expected = "a hue shift in degrees and optionally a saturation factor, \
+                e.g. \"40:1.3\"";
+            settings.recolor = Some(
+                recolor
+                    .as_str()
+                    .and_then(Recolor::parse)
+                    .ok_or_else(|| invalid("recolor", expected))?,
+            );
+        }
+        Ok(settings)
+    }
+}
+
+/// Read the `[terrains]` table of a parsed TOML document: a table of settings per terrain,
+/// keyed by its file name, identifier or menu name, e.g. `[terrains.winter]` with
+/// `overrides = ["weather/snow"]` and `low_end = true`. The keys are resolved when the
+/// settings are looked up, see `find()`, since terrains may be discovered later.
+pub(crate) fn from_toml(document: &Table) -> Result<Vec<(String, TerrainSettings)>, String> {
+    let Some(terrains) = document.get("terrains") else {
+        return Ok(Vec::new());
+    };
+    let Some(terrains) = terrains.as_table() else {
+        return Err(String::from("\"terrains\" must be a table"));
+    };
+    terrains
+        .iter()
+        .map(|(terrain, settings)| match settings {
+            Value::Table(table) => TerrainSettings::from_table(terrain, table)
+                .map(|settings| (terrain.clone(), settings)),
+            _ => Err(format!("\"terrains.{}\" must be a table", terrain)),
+        })
+        .collect()
+}
+
+/// The settings of the terrain in `terrain_file` among `settings`, if any of their keys names
+/// it
+pub(crate) fn find<'a>(
+    settings: &'a [(String, TerrainSettings)],
+    menu: &MenuConfig,
+    terrain_file: &str,
+) -> Option<&'a TerrainSettings> {
+    settings
+        .iter()
+        .find(|(key, _)| {
+            menu.find(key)
+                .is_some_and(|terrain| terrain.file == terrain_file)
+        })
+        .map(|(_, settings)| settings)
+}
@@ -0,0 +1,532 @@
+use crate::resource::{read_u16, read_u32, Resource};
+
+/// Version of the texture data block this reader understands
+const TEXTURE_VERSION: u16 = 1;
+/// Textures the game never lowers the detail of
+const FLAG_NO_LOD: u16 = 0x0008;
+/// Textures with six faces per mip level
+const FLAG_CUBE_TEXTURE: u16 = 0x0010;
+/// Textures with more than one slice per mip level
+const FLAG_VOLUME_OR_ARRAY: u16 = 0x0020 | 0x0040;
+/// Extra data describing padding to a power of two size, which depends on the dimensions
+const EXTRA_FILL_TO_POWER_OF_TWO: u32 = 3;
+/// Extra data holding the sizes of individually compressed mip levels
+const EXTRA_COMPRESSED_MIP_SIZE: u32 = 4;
+
+/// The texture data block of a compiled texture (`*.vtex_c`), along with where its mip levels
+/// are stored. Mip levels follow the data block, smallest first, so the largest level is at
+/// the end of the file.
+/// # Properties
+/// - `block_offset: usize` = Position of the texture data block from the start of the file
+/// - `pixels_offset: usize` = Position of the first (smallest) mip level
+/// - `width: u16`, `height: u16` = Size of the largest mip level
+/// - `format: u8` = Pixel format, see `resource::texture_format()`
+/// - `mip_levels: u8` = Number of mip levels
+/// - `faces: usize` = Number of faces per mip level, 6 for cube maps
+pub(crate) struct Texture {
+    block_offset: usize,
+    pixels_offset: usize,
+    pub(crate) width: u16,
+    pub(crate) height: u16,
+    pub(crate) format: u8,
+    pub(crate) mip_levels: u8,
+    faces: usize,
+}
+
+/// Size of a block of pixels in the given format and the number of bytes it takes, or `None`
+/// for formats whose mip levels cannot be sized without decoding them (JPEG, PNG, ETC)
+fn format_layout(format: u8) -> Option<(usize, usize)> {
+    Some(match format {
+        1 | 27 => (4, 8),       // DXT1, ATI1N
+        2 | 19..=21 => (4, 16), // DXT5, BC6H, BC7, ATI2N
+        3 => (1, 1),            // I8
+        5 | 8 | 22 => (1, 2),   // R16, R16F, IA88
+        4 | 6 | 9 | 11 | 28 => (1, 4),
+        7 | 10 | 12 => (1, 8),
+        13 => (1, 12),
+        14 => (1, 16),
+        _ => return None,
+    })
+}
+
+impl Texture {
+    /// Read the texture data block of the compiled texture in `data`. Returns `None` unless
+    /// it is a plain 2D texture or cube map whose mip levels, stored uncompressed after the
+    /// data block, account for the rest of the file exactly.
+    pub(crate) fn parse(data: &[u8]) -> Option<Texture> {
+        let resource = Resource::parse(data)?;
+        let block = resource.block("DATA")?;
+        let end = block.offset + block.size;
+        if resource
+            .blocks
+            .iter()
+            .any(|other| other.offset + other.size > end)
+        {
+            return None;
+        }
+        let header = data.get(block.offset..end)?;
+        let flags = read_u16(header, 2)?;
+        if read_u16(header, 0)? != TEXTURE_VERSION
+            || flags & (FLAG_NO_LOD | FLAG_VOLUME_OR_ARRAY) != 0
+            || read_u16(header, 24)? > 1
+        {
+            return None;
+        }
+
+        // Extra data: offset relative to the field itself, then the entry count
+        let extra_offset = 32 + read_u32(header, 32)? as usize;
+        for i in 0..read_u32(header, 36)? as usize {
+            let kind = read_u32(header, extra_offset + i * 12)?;
+            if kind == EXTRA_FILL_TO_POWER_OF_TWO || kind == EXTRA_COMPRESSED_MIP_SIZE {
+                return None;
+            }
+        }
+
+        let texture = Texture {
+            block_offset: block.offset,
+            pixels_offset: end,
+            width: read_u16(header, 20)?,
+            height: read_u16(header, 22)?,
+            format: *header.get(26)?,
+            mip_levels: *header.get(27)?,
+            faces: if flags & FLAG_CUBE_TEXTURE != 0 { 6 } else { 1 },
+        };
+        let total: usize = (0..texture.mip_levels)
+            .map(|level| texture.mip_size(level))
+            .sum::<Option<usize>>()?;
+        (texture.mip_levels > 0 && end + total == data.len()).then_some(texture)
+    }
+
+    /// Number of bytes taken by mip level `level`, 0 being the largest
+    fn mip_size(&self, level: u8) -> Option<usize> {
+        let (block, bytes) = format_layout(self.format)?;
+        let width = (usize::from(self.width) >> level).max(1);
+        let height = (usize::from(self.height) >> level).max(1);
+        Some(width.div_ceil(block) * height.div_ceil(block) * bytes * self.faces)
+    }
+}
+
+/// Drop the largest mip levels of the compiled texture in `data` until neither side exceeds
+/// `max_size`, keeping at least one level. Returns the smaller texture, or `None` if it
+/// already fits or cannot be processed (see `Texture::parse()`).
+pub(crate) fn downscale(data: &[u8], max_size: u16) -> Option<Vec<u8>> {
+    let texture = Texture::parse(data)?;
+    let mut dropped = 0;
+    while dropped + 1 < texture.mip_levels
+        && (texture.width >> dropped).max(texture.height >> dropped) > max_size
+    {
+        dropped += 1;
+    }
+    if dropped == 0 {
+        return None;
+    }
+
+    let removed: usize = (0..dropped)
+        .map(|level| texture.mip_size(level))
+        .sum::<Option<usize>>()?;
+    let mut output = data[..data.len() - removed].to_vec();
+    let header = texture.block_offset;
+    let width = (texture.width >> dropped).max(1);
+    let height = (texture.height >> dropped).max(1);
+    output[header + 20..header + 22].copy_from_slice(&width.to_le_bytes());
+    output[header + 22..header + 24].copy_from_slice(&height.to_le_bytes());
+    output[header + 27] = texture.mip_levels - dropped;
+    let file_size = output.len() as u32;
+    output[..4].copy_from_slice(&file_size.to_le_bytes());
+    Some(output)
+}
+
+/// Words in the paths of ground textures, which `--recolor` applies to
+const GROUND_WORDS: [&str; 9] = [
+    "ground", "terrain", "grass", "dirt", "sand", "mud", "snow", "cliff", "rock",
+];
+/// Words in the paths of textures holding data rather than colors, e.g. normal maps
+const DATA_WORDS: [&str; 6] = ["normal", "rough", "spec", "mask", "height", "_ao"];
+
+/// Whether the texture at `path` colors the ground, going by its name
+pub(crate) fn is_ground_texture(path: &str) -> bool {
+    let path = path.to_lowercase();
+    path.starts_with("materials/")
+        && path.ends_with(".vtex_c")
+        && GROUND_WORDS.iter().any(|word| path.contains(word))
+        && !DATA_WORDS.iter().any(|word| path.contains(word))
+}
+
+/// A hue and saturation shift applied to ground textures for more contrast, given as
+/// `--recolor <hue>[:<saturation>]`
+/// # Properties
+/// - `hue: f32` = Rotation of the hue in degrees
+/// - `saturation: f32` = Factor the saturation is multiplied by
+#[derive(Clone, Copy)]
+pub(crate) struct Recolor {
+    pub(crate) hue: f32,
+    pub(crate) saturation: f32,
+}
+
+impl Recolor {
+    /// Parse `<hue>[:<saturation>]`, e.g. `40` or `40:1.3`
+    pub(crate) fn parse(text: &str) -> Option<Recolor> {
+        let (hue, saturation): (f32, f32) = match text.split_once(':') {
+            Some((hue, saturation)) => (hue.parse().ok()?, saturation.parse().ok()?),
+            None => (text.parse().ok()?, 1.0),
+        };
+        Recolor::new(hue, saturation)
+    }
+
+    /// A shift of the hue by `hue` degrees and of the saturation by the factor `saturation`,
+    /// or `None` if either cannot be applied
+    pub(crate) fn new(hue: f32, saturation: f32) -> Option<Recolor> {
+        (hue.is_finite() && saturation >= 0.0 && saturation.is_finite())
+            .then_some(Recolor { hue, saturation })
+    }
+
+    /// Apply the shift to an 8 bit RGB color
+    fn apply(&self, [r, g, b]: [u8; 3]) -> [u8; 3] {
+        let [r, g, b] = [r, g, b].map(|c| f32::from(c) / 255.0);
+        let max = r.max(g).max(b);
+        let delta = max - r.min(g).min(b);
+        let hue = if delta == 0.0 {
+            0.0
+        } else if max == r {
+            60.0 * ((g - b) / delta).rem_euclid(6.0)
+        } else if max == g {
+            60.0 * ((b - r) / delta + 2.0)
+        } else {
+            60.0 * ((r - g) / delta + 4.0)
+        };
+        let saturation = if max == 0.0 { 0.0 } else { delta / max };
+
+        let hue = (hue + self.hue).rem_euclid(360.0);
+        let saturation = (saturation * self.saturation).min(1.0);
+        let chroma = max * saturation;
+        let x = chroma * (1.0 - ((hue / 60.0).rem_euclid(2.0) - 1.0).abs());
+        let (r, g, b) = match (hue / 60.0) as u32 {
+            0 => (chroma, x, 0.0),
+            1 => (x, chroma, 0.0),
+            2 => (0.0, chroma, x),
+            3 => (0.0, x, chroma),
+            4 => (x, 0.0, chroma),
+            _ => (chroma, 0.0, x),
+        };
+        let min = max - chroma;
+        [r, g, b].map(|c| ((c + min) * 255.0).round().clamp(0.0, 255.0) as u8)
+    }
+
+    /// Apply the shift to an RGB565 color as stored in DXT blocks
+    fn apply_565(&self, color: u16) -> u16 {
+        let expand = |value: u16, bits: u32| {
+            let max = (1 << bits) - 1;
+            (u32::from(value) * 255 / max) as u8
+        };
+        let [r, g, b] = self.apply([
+            expand(color >> 11, 5),
+            expand((color >> 5) & 0x3f, 6),
+            expand(color & 0x1f, 5),
+        ]);
+        let pack =
+            |value: u8, bits: u32| ((u32::from(value) * ((1 << bits) - 1) + 127) / 255) as u16;
+        pack(r, 5) << 11 | pack(g, 6) << 5 | pack(b, 5)
+    }
+}
+
+impl std::fmt::Display for Recolor {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{}:{}", self.hue, self.saturation)
+    }
+}
+
+/// Shift the colors of a DXT color block: the two RGB565 endpoints are recolored and the
+/// pixels keep interpolating between them. In DXT1 blocks, the order of the endpoints selects
+/// between 4 colors and 3 colors with transparency, so they are swapped back into that order
+/// if the shift reversed it.
+fn recolor_dxt_block(block: &mut [u8], recolor: &Recolor, dxt1: bool) {
+    let color0 = u16::from_le_bytes([block[0], block[1]]);
+    let color1 = u16::from_le_bytes([block[2], block[3]]);
+    let four_colors = color0 > color1;
+    let mut new0 = recolor.apply_565(color0);
+    let mut new1 = recolor.apply_565(color1);
+    let mut indices = u32::from_le_bytes(block[4..8].try_into().unwrap());
+    if dxt1 && four_colors && new0 == new1 {
+        // Every pixel takes the first endpoint
+        indices = 0;
+        if new0 == 0 {
+            new0 = 1;
+        } else {
+            new1 = new0 - 1;
+        }
+    } else if dxt1 && (new0 > new1) != four_colors {
+        std::mem::swap(&mut new0, &mut new1);
+        // Swap the indices of the endpoints, and of the interpolated colors in 4 color mode
+        let mut swapped = 0;
+        for pixel in 0..16 {
+            let index = (indices >> (pixel * 2)) & 3;
+            let index = if index < 2 || four_colors {
+                index ^ 1
+            } else {
+                index
+            };
+            swapped |= index << (pixel * 2);
+        }
+        indices = swapped;
+    }
+    block[0..2].copy_from_slice(&new0.to_le_bytes());
+    block[2..4].copy_from_slice(&new1.to_le_bytes());
+    block[4..8].copy_from_slice(&indices.to_le_bytes());
+}
+
+/// Apply `recolor` to every mip level of the compiled texture in `data`. Supported are DXT1,
+/// DXT5, RGBA8888 and BGRA8888 textures, returns `None` for anything else.
+pub(crate) fn recolor(data: &[u8], recolor: &Recolor) -> Option<Vec<u8>> {
+    let texture = Texture::parse(data)?;
+    let mut output = data.to_vec();
+    let pixels = &mut output[texture.pixels_offset..];
+    match texture.format {
+        // DXT1 blocks are a color block, DXT5 blocks an alpha block followed by one
+        1 => pixels
+            .chunks_exact_mut(8)
+            .for_each(|block| recolor_dxt_block(block, recolor, true)),
+        2 => pixels
+            .chunks_exact_mut(16)
+            .for_each(|block| recolor_dxt_block(&mut block[8..], recolor, false)),
+        4 => pixels.chunks_exact_mut(4).for_each(|pixel| {
+            let [r, g, b] = recolor.apply([pixel[0], pixel[1], pixel[2]]);
+            pixel[..3].copy_from_slice(&[r, g, b]);
+        }),
+        28 => pixels.chunks_exact_mut(4).for_each(|pixel| {
+            let [r, g, b] = recolor.apply([pixel[2], pixel[1], pixel[0]]);
+            pixel[..3].copy_from_slice(&[b, g, r]);
+        }),
+        _ => return None,
+    }
+    Some(output)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A compiled 2D texture of `width` by `height` pixels in `format`, whose `mip_levels` mip
+    /// levels are `pixels`, smallest first: the resource header, a block table with the
+    /// texture data block only, the data block and the pixels
+    fn texture(width: u16, height: u16, format: u8, mip_levels: u8, pixels: &[u8]) -> Vec<u8> {
+        with_extra(width, height, format, mip_levels, &[], pixels)
+    }
+
+    /// A texture like `texture()` whose data block lists extra data of the kinds `extra`
+    fn with_extra(
+        width: u16,
+        height: u16,
+        format: u8,
+        mip_levels: u8,
+        extra: &[u32],
+        pixels: &[u8],
+    ) -> Vec<u8> {
+        let block_size = 40 + 12 * extra.len();
+        let mut data = Vec::new();
+        let size = (16 + 12 + block_size + pixels.len()) as u32;
+        data.extend_from_slice(&size.to_le_bytes());
+        data.extend_from_slice(&12_u16.to_le_bytes());
+        data.extend_from_slice(&0_u16.to_le_bytes());
+        // The block table follows the header, its offset is relative to the field
+        data.extend_from_slice(&8_u32.to_le_bytes());
+        data.extend_from_slice(&1_u32.to_le_bytes());
+        data.extend_from_slice(b"DATA");
+        // The block follows the table, its offset is relative to the field
+        data.extend_from_slice(&8_u32.to_le_bytes());
+        data.extend_from_slice(&(block_size as u32).to_le_bytes());
+
+        let mut header = [0; 40];
+        header[0..2].copy_from_slice(&TEXTURE_VERSION.to_le_bytes());
+        header[20..22].copy_from_slice(&width.to_le_bytes());
+        header[22..24].copy_from_slice(&height.to_le_bytes());
+        header[24..26].copy_from_slice(&1_u16.to_le_bytes());
+        header[26] = format;
+        header[27] = mip_levels;
+        // Extra data follows, its offset is relative to the field
+        header[32..36].copy_from_slice(&8_u32.to_le_bytes());
+        header[36..40].copy_from_slice(&(extra.len() as u32).to_le_bytes());
+        data.extend_from_slice(&header);
+        for kind in extra {
+            data.extend_from_slice(&kind.to_le_bytes());
+            data.extend_from_slice(&[0; 8]);
+        }
+        data.extend_from_slice(pixels);
+        data
+    }
+
+    /// `length` bytes counting up from `start`, telling the mip levels apart
+    fn bytes(start: u8, length: usize) -> Vec<u8> {
+        (0..length).map(|i| start.wrapping_add(i as u8)).collect()
+    }
+
+    #[test]
+    fn downscaling_drops_the_largest_mip_levels() {
+        // DXT1 mip levels of 4x4, 8x8 and 16x16 pixels, 8 bytes per block of 4x4
+        let small = [bytes(0, 8), bytes(100, 32)].concat();
+        let pixels = [small.clone(), bytes(200, 128)].concat();
+        let data = texture(16, 16, 1, 3, &pixels);
+        let parsed = Texture::parse(&data).unwrap();
+        assert_eq!(
+            (parsed.width, parsed.height, parsed.mip_levels),
+            (16, 16, 3)
+        );
+
+        let downscaled = downscale(&data, 8).unwrap();
+        assert_eq!(downscaled, texture(8, 8, 1, 2, &small));
+        // Width and height, then the mip count in the data block, and the file size
+        assert_eq!(&downscaled[28 + 20..28 + 24], &[8, 0, 8, 0]);
+        assert_eq!(downscaled[28 + 27], 2);
+        assert_eq!(read_u32(&downscaled, 0), Some(downscaled.len() as u32));
+
+        // At least one level is kept
+        assert_eq!(
+            downscale(&data, 1).unwrap(),
+            texture(4, 4, 1, 1, &bytes(0, 8))
+        );
+    }
+
+    #[test]
+    fn downscaling_keeps_the_sides_apart() {
+        // RGBA8888 mip levels of 1x1, 2x1 and 4x2 pixels
+        let pixels = [bytes(0, 4), bytes(10, 8), bytes(30, 32)].concat();
+        let data = texture(4, 2, 4, 3, &pixels);
+        assert_eq!(
+            downscale(&data, 2).unwrap(),
+            texture(2, 1, 4, 2, &pixels[..12])
+        );
+    }
+
+    #[test]
+    fn textures_which_fit_or_are_unsupported_are_kept() {
+        let data = texture(16, 16, 1, 3, &bytes(0, 168));
+        assert!(downscale(&data, 16).is_none());
+        assert!(downscale(&data, 1024).is_none());
+        // A single level is never dropped
+        assert!(downscale(&texture(16, 16, 1, 1, &bytes(0, 128)), 4).is_none());
+        // Formats whose mip levels cannot be sized, e.g. PNG
+        assert!(downscale(&texture(16, 16, 16, 1, &bytes(0, 128)), 4).is_none());
+        // Pixels not matching the mip levels
+        assert!(downscale(&texture(16, 16, 1, 3, &bytes(0, 167)), 4).is_none());
+
+        // Textures the game never lowers the detail of
+        let mut no_lod = data;
+        no_lod[28 + 2] = FLAG_NO_LOD as u8;
+        assert!(downscale(&no_lod, 4).is_none());
+        // Padding to a power of two depends on the size, compressed mip levels cannot be cut
+        let pixels = bytes(0, 168);
+        for kind in [EXTRA_FILL_TO_POWER_OF_TWO, EXTRA_COMPRESSED_MIP_SIZE] {
+            assert!(downscale(&with_extra(16, 16, 1, 3, &[kind], &pixels), 4).is_none());
+        }
+        // Other extra data is kept as it is
+        let sheet = with_extra(16, 16, 1, 3, &[1], &pixels);
+        let downscaled = downscale(&sheet, 8).unwrap();
+        assert_eq!(downscaled, with_extra(8, 8, 1, 2, &[1], &pixels[..40]));
+        assert!(downscale(b"not a texture", 4).is_none());
+    }
+
+    const RED: u16 = 0xf800;
+    const GREEN: u16 = 0x07e0;
+    const BLUE: u16 = 0x001f;
+    /// Every pixel of a block taking index 0, 1, 2 and 3 in turn
+    const INDICES: u32 = 0xe4e4_e4e4;
+
+    /// A DXT1 color block with the endpoints `color0` and `color1` and the pixel `indices`
+    fn dxt1_block(color0: u16, color1: u16, indices: u32) -> Vec<u8> {
+        [
+            &color0.to_le_bytes()[..],
+            &color1.to_le_bytes(),
+            &indices.to_le_bytes(),
+        ]
+        .concat()
+    }
+
+    /// Apply `shift` to a single DXT1 block
+    fn recolored_block(block: &[u8], shift: Recolor) -> Vec<u8> {
+        let mut block = block.to_vec();
+        recolor_dxt_block(&mut block, &shift, true);
+        block
+    }
+
+    #[test]
+    fn recoloring_without_a_shift_keeps_every_byte() {
+        let unchanged = Recolor::new(0.0, 1.0).unwrap();
+        let dxt1 = [
+            dxt1_block(RED, BLUE, INDICES),
+            dxt1_block(BLUE, RED, INDICES),
+            dxt1_block(0x1234, 0x1234, 0x0123_4567),
+            dxt1_block(0xffff, 0x0000, 0),
+        ]
+        .concat();
+        let dxt5 = [&bytes(0, 8)[..], &dxt1_block(0x7bef, 0x0841, INDICES)].concat();
+        let colors: Vec<u8> = (0..=255).flat_map(|c| [c, 255 - c, c / 3, c]).collect();
+        // Four DXT1 blocks, one DXT5 block and 256 pixels of RGBA8888 and BGRA8888
+        for (format, width, height, pixels) in [
+            (1, 16, 4, &dxt1),
+            (2, 4, 4, &dxt5),
+            (4, 16, 16, &colors),
+            (28, 16, 16, &colors),
+        ] {
+            let data = texture(width, height, format, 1, pixels);
+            let recolored = recolor(&data, &unchanged).unwrap();
+            assert_eq!(recolored, data, "format {}", format);
+        }
+    }
+
+    #[test]
+    fn dxt1_blocks_keep_their_mode() {
+        let shift = Recolor::new(120.0, 1.0).unwrap();
+        // 4 colors: red and blue turn green and red, so the endpoints are swapped back into
+        // descending order, and so are the indices of both endpoints and interpolated colors
+        let four_colors = dxt1_block(RED, BLUE, INDICES);
+        assert_eq!(
+            recolored_block(&four_colors, shift),
+            dxt1_block(RED, GREEN, 0xb1b1_b1b1)
+        );
+        // 3 colors and transparency: only the indices of the endpoints are swapped, the
+        // interpolated color and transparency keep theirs
+        let three_colors = dxt1_block(BLUE, RED, INDICES);
+        assert_eq!(
+            recolored_block(&three_colors, shift),
+            dxt1_block(GREEN, RED, 0xe1e1_e1e1)
+        );
+        // Without a swap, the block is recolored as it is
+        let kept = dxt1_block(GREEN, BLUE, INDICES);
+        assert_eq!(
+            recolored_block(&kept, Recolor::new(-120.0, 1.0).unwrap()),
+            dxt1_block(RED, GREEN, INDICES)
+        );
+
+        // Red and green both turn white without saturation. Equal endpoints would select
+        // 3 color mode, so every pixel takes the first one and the second is set below it.
+        let grey = Recolor::new(0.0, 0.0).unwrap();
+        assert_eq!(
+            recolored_block(&dxt1_block(RED, GREEN, INDICES), grey),
+            dxt1_block(0xffff, 0xfffe, 0)
+        );
+    }
+
+    #[test]
+    fn dxt5_and_uncompressed_textures_are_recolored() {
+        let shift = Recolor::new(120.0, 1.0).unwrap();
+        // DXT5 blocks keep their alpha block, and their colors are never in 3 color mode
+        let alpha = bytes(7, 8);
+        let block = [&alpha[..], &dxt1_block(RED, BLUE, INDICES)].concat();
+        let recolored = recolor(&texture(4, 4, 2, 1, &block), &shift).unwrap();
+        assert_eq!(
+            recolored[68..],
+            [&alpha[..], &dxt1_block(GREEN, RED, INDICES)].concat()
+        );
+
+        // RGBA8888 and BGRA8888 pixels, alpha is kept
+        let rgba = recolor(&texture(2, 1, 4, 1, &[255, 0, 0, 77, 0, 0, 255, 5]), &shift);
+        assert_eq!(rgba.unwrap()[68..], [0, 255, 0, 77, 255, 0, 0, 5]);
+        let bgra = recolor(
+            &texture(2, 1, 28, 1, &[0, 0, 255, 77, 255, 0, 0, 5]),
+            &shift,
+        );
+        assert_eq!(bgra.unwrap()[68..], [0, 255, 0, 77, 0, 0, 255, 5]);
+
+        // Other formats, e.g. I8, are left alone
+        assert!(recolor(&texture(4, 4, 3, 1, &bytes(0, 16)), &shift).is_none());
+    }
+}
@@ -0,0 +1,149 @@
+use std::{collections::BTreeMap, path::PathBuf};
+
+use crate::cli::Args;
+use crate::vpk::{VpkOptions, VPK};
+
+/// Directory levels shown unless `--depth` is given
+const DEFAULT_DEPTH: usize = 2;
+/// Largest entries shown at each level, the rest are summed up
+const MAX_SHOWN: usize = 10;
+/// Width of the bar of an entry taking up the whole VPK
+const BAR_WIDTH: usize = 30;
+
+/// Size in bytes, in the largest unit which keeps it at 1 or above
+pub(crate) fn format_size(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["KB", "MB", "GB", "TB"];
+    if bytes < 1024 {
+        return format!("{} B", bytes);
+    }
+    let mut size = bytes as f64 / 1024.0;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    format!("{:.1} {}", size, UNITS[unit])
+}
+
+/// Line of the breakdown: the bar, size and share of `bytes` in `total`, then `label`
+fn breakdown_line(indent: usize, label: &str, bytes: u64, total: u64) -> String {
+    let share = bytes as f64 / total.max(1) as f64;
+    let bar = "█".repeat((share * BAR_WIDTH as f64).round() as usize);
+    format!(
+        "{:<width$} {:>9} {:>5.1}%  {}{}",
+        bar,
+        format_size(bytes),
+        share * 100.0,
+        "  ".repeat(indent),
+        label,
+        width = BAR_WIDTH
+    )
+}
+
+/// Bytes of every file and directory below a directory, keyed by name
+/// # Properties
+/// - `bytes: u64` = Total size of the files below the directory
+/// - `files: usize` = Number of files below the directory
+/// - `children: BTreeMap<String, Node>` = Subdirectories and files directly in the directory
+#[derive(Default)]
+struct Node {
+    bytes: u64,
+    files: usize,
+    children: BTreeMap<String, Node>,
+}
+
+impl Node {
+    /// Add a file of `bytes` bytes at the path made of `components`
+    fn add(&mut self, components: &[&str], bytes: u64) {
+        self.bytes += bytes;
+        self.files += 1;
+        if let Some((first, rest)) = components.split_first() {
+            let name = if rest.is_empty() {
+                (*first).to_owned()
+            } else {
+                format!("{}/", first)
+            };
+            self.children.entry(name).or_default().add(rest, bytes);
+        }
+    }
+
+    /// Lines breaking down the children of this node by size, largest first, `depth` levels
+    /// deep
+    fn lines(&self, indent: usize, depth: usize, total: u64) -> Vec<String> {
+        let mut children: Vec<(&String, &Node)> = self.children.iter().collect();
+        children.sort_by(|a, b| b.1.bytes.cmp(&a.1.bytes).then(a.0.cmp(b.0)));
+
+        let mut lines = Vec::new();
+        for (name, node) in children.iter().take(MAX_SHOWN) {
+            let label = if node.children.is_empty() {
+                (*name).clone()
+            } else {
+                format!("{} ({} file(s))", name, node.files)
+            };
+            lines.push(breakdown_line(indent, &label, node.bytes, total));
+            if depth > 1 {
+                lines.append(&mut node.lines(indent + 1, depth - 1, total));
+            }
+        }
+        if children.len() > MAX_SHOWN {
+            let rest = &children[MAX_SHOWN..];
+            let bytes = rest.iter().map(|(_, node)| node.bytes).sum();
+            let label = format!("... {} more", rest.len());
+            lines.push(breakdown_line(indent, &label, bytes, total));
+        }
+        lines
+    }
+}
+
+/// Entry point for the `tree-size <vpk> [--depth N]` command. Shows where the bytes of a VPK
+/// go, as bars sized by share: per directory, `--depth` levels deep (2 by default), and per
+/// file extension. Sizes are as stored, so compressed files count with their compressed size.
+/// Helps terrain authors find what to optimize before sharing a terrain.
+pub(crate) fn run(args: &Args) {
+    let [vpk_path] = args.positional.as_slice() else {
+        eprintln!("Usage: dota-terrain-mod tree-size <vpk> [--depth N]");
+        std::process::exit(1);
+    };
+    let depth = match args.option("depth").map(str::parse::<usize>) {
+        None => DEFAULT_DEPTH,
+        Some(Ok(depth)) if depth > 0 => depth,
+        Some(_) => {
+            eprintln!("Dota-Terrain-Mod error: --depth expects a number of at least 1");
+            std::process::exit(1);
+        }
+    };
+    let vpk = match VPK::open_with(PathBuf::from(vpk_path), VpkOptions::from_args(args)) {
+        Ok(vpk) => vpk,
+        Err(e) => {
+            eprintln!("Dota-Terrain-Mod error: Invalid VPK: {}", e);
+            std::process::exit(1);
+        }
+    };
+
+    let mut root = Node::default();
+    let mut extensions = Node::default();
+    for (path, entry) in vpk.entries() {
+        let components: Vec<&str> = path.split('/').collect();
+        root.add(&components, entry.entry_length());
+        let extension = match path.rsplit_once('.') {
+            Some((_, extension)) if !extension.contains('/') => format!(".{}", extension),
+            _ => String::from("(none)"),
+        };
+        extensions.add(&[&extension], entry.entry_length());
+    }
+
+    println!(
+        "{}: {} file(s), {}",
+        vpk_path,
+        root.files,
+        format_size(root.bytes)
+    );
+    println!("\nBy directory:");
+    for line in root.lines(0, depth, root.bytes) {
+        println!("{}", line);
+    }
+    println!("\nBy extension:");
+    for line in extensions.lines(0, 1, root.bytes) {
+        println!("{}", line);
+    }
+}
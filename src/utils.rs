@@ -1,173 +1,720 @@
-pub mod utils {
-    use regex::Regex;
-    use std::error::Error;
-    use std::fmt::{Display, Formatter};
-    use std::fs;
-    use std::io::Read;
-    use std::path::{Path, PathBuf};
+use regex::Regex;
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt::{Display, Formatter};
+use std::fs;
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::{Duration, Instant};
 
-    #[cfg(target_os = "windows")]
-    use winreg::RegKey;
-
-    #[derive(Debug)]
-    pub(crate) enum TMError {
-        SteamNotFound,
-        DotaNotFound,
-        InternalError(std::io::Error),
-    }
-
-    impl Display for TMError {
-        fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
-            match self {
-                TMError::SteamNotFound => write!(f, "Dota-Terrain-Mod error: Steam not found"),
-                TMError::DotaNotFound => write!(f, "Dota-Terrain-Mod error: Dota not found"),
-                TMError::InternalError(io_err) => {
-                    write!(f, "Dota-Terrain-Mod error: Internal error: {}", io_err)
-                }
-            }
+use crate::accounts;
+use crate::cli::Args;
+use crate::game::{self, GameProfile};
+use crate::vdf::Vdf;
+
+#[cfg(target_os = "windows")]
+use winreg::RegKey;
+
+/// Longest time a file locked by another process is waited for, see `retry_locked()`
+const LOCK_WAIT: Duration = Duration::from_secs(60);
+/// Time between attempts to access a locked file
+const LOCK_RETRY_DELAY: Duration = Duration::from_millis(500);
+
+/// Errors of the program, each with a stable code, see `code()`
+#[derive(Debug)]
+pub enum TMError {
+    SteamNotFound,
+    DotaNotFound,
+    InternalError(std::io::Error),
+    ConfigError(String),
+    BaseNotFound(String, Vec<String>),
+    TerrainChanged(String),
+    SteamUserNotFound(String, Vec<String>),
+    GameUpdating(String),
+}
+
+impl TMError {
+    /// Stable code of the error, printed along with its message and explained by
+    /// `explain <code>`, see `explain::CATALOG`. Codes are never reused or renumbered.
+    pub fn code(&self) -> &'static str {
+        match self {
+            TMError::SteamNotFound => "TM-001",
+            TMError::DotaNotFound => "TM-002",
+            TMError::InternalError(_) => "TM-003",
+            TMError::ConfigError(_) => "TM-004",
+            TMError::BaseNotFound(..) => "TM-005",
+            TMError::TerrainChanged(_) => "TM-006",
+            TMError::SteamUserNotFound(..) => "TM-007",
+            TMError::GameUpdating(_) => "TM-008",
         }
     }
+}
 
-    impl Error for TMError {}
-
-    /// Object representing a dota installation. Exists to encapsulate the paths and identify
-    /// if `dota_path` cannot be found
-    pub struct Dota {
-        pub(crate) dota_path: PathBuf,
-        pub(crate) base_path: Option<PathBuf>,
-        pub(crate) target_path: Option<PathBuf>,
-        pub(crate) out_path: Option<PathBuf>,
+impl Display for TMError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Dota-Terrain-Mod error {}: ", self.code())?;
+        match self {
+            TMError::SteamNotFound => write!(f, "Steam not found"),
+            TMError::DotaNotFound => write!(f, "{} not found", game::current().title),
+            TMError::InternalError(io_err) => write!(f, "Internal error: {}", io_err),
+            TMError::ConfigError(message) => write!(f, "Invalid configuration: {}", message),
+            TMError::BaseNotFound(base, available) => write!(
+                f,
+                "Base map \"{}\" not found. Available: {}",
+                base,
+                available.join(", ")
+            ),
+            TMError::TerrainChanged(terrain) => write!(
+                f,
+                "{} changed since it was first applied and was not applied. Pass --yes to \
+                accept the new file",
+                terrain
+            ),
+            TMError::SteamUserNotFound(user, known) => write!(
+                f,
+                "Steam user \"{}\" not found. Known: {}",
+                user,
+                known.join(", ")
+            ),
+            TMError::GameUpdating(state) => write!(
+                f,
+                "{}, the base map may be half-updated. Try again once Steam is done, or pass \
+                --ignore-steam-state to build anyway",
+                state
+            ),
+        }
     }
+}
 
-    impl Dota {
-        /// On initialization of a `Dota` instance, tries to locate the dota installation and
-        /// sets the attribute accordingly
-        pub(crate) fn new() -> Result<Self, TMError> {
-            let steam_path = get_steam_path()?;
-            let libtext = load_libraries(steam_path)?;
-            let dota_path = get_dota_path(libtext)?;
+impl Error for TMError {}
 
-            Ok(Dota {
-                dota_path,
-                base_path: None,
-                target_path: None,
-                out_path: None,
-            })
+/// A located Dota 2 installation, or of the game selected with `--game`. Created with
+/// `new()`, which only succeeds for an existing game folder, and queried for the paths of the
+/// maps and the output.
+/// # Properties
+/// - `steam_path: PathBuf` = Steam installation directory
+/// - `dota_path: PathBuf` = The game's `game` folder, `<library>/steamapps/common/dota 2 beta/game`
+/// - `game: &GameProfile` = The game installed there, see `game::current()`
+pub struct DotaInstall {
+    pub(crate) steam_path: PathBuf,
+    pub(crate) dota_path: PathBuf,
+    pub(crate) game: &'static GameProfile,
+}
+
+impl DotaInstall {
+    /// Locate the Dota 2 installation like `locate()`, in the Steam installation directory
+    /// given with `--steam-path`, if any
+    pub(crate) fn new(args: &Args) -> Result<Self, TMError> {
+        DotaInstall::locate(args.option("steam-path").map(PathBuf::from))
+    }
+
+    /// Locate the Dota 2 installation in the Steam installation directory `steam_path`, or
+    /// else the one of the system, see `get_steam_path()`. Fails if either cannot be found,
+    /// if the game folder does not exist (see `validate()`), or if the account selected with
+    /// `--steam-user` is unknown.
+    pub(crate) fn locate(steam_path: Option<PathBuf>) -> Result<Self, TMError> {
+        let steam_path = match steam_path {
+            Some(steam_path) => steam_path,
+            None => get_steam_path()?,
+        };
+        if let Err(known) = accounts::find_selected(&steam_path) {
+            let user = accounts::selected().unwrap_or_default().to_owned();
+            return Err(TMError::SteamUserNotFound(user, known));
         }
+        let game = game::current();
+        let libtext = load_libraries(&steam_path)?;
+        let dota_path = get_dota_path(libtext, game)?;
 
-        /// Using the `dota_path` which is assumed to exist if this function is called,
-        /// populate the other attributes by creating the paths to the base `dota.vpk`,
-        /// the given target terrain vpk, and the file path where the output vpk will be written
-        pub(crate) fn build_paths(&mut self, target: &str) {
-            let dota_path = &self.dota_path;
-            let base_path = get_base_path(dota_path);
-            let target_path = get_target_path(dota_path, target);
-            let out_path = get_out_path(dota_path);
+        let dota = DotaInstall {
+            steam_path,
+            dota_path,
+            game,
+        };
+        dota.validate()?;
+        Ok(dota)
+    }
 
-            self.base_path = Some(base_path);
-            self.target_path = Some(target_path);
-            self.out_path = Some(out_path);
+    /// Check that the game folder holds the game's maps, e.g. that it was not uninstalled
+    /// while its library is still listed
+    pub(crate) fn validate(&self) -> Result<(), TMError> {
+        if self.maps_dir().is_dir() {
+            Ok(())
+        } else {
+            Err(TMError::DotaNotFound)
         }
     }
 
-    #[cfg(target_os = "windows")]
-    /// Reads the windows registry and returns the Steam installation directory
-    fn get_steam_path() -> Result<PathBuf, TMError> {
-        let hkcu = RegKey::predef(winreg::enums::HKEY_CURRENT_USER);
-        match hkcu.open_subkey("Software\\Valve\\Steam") {
-            Ok(steam_key) => match steam_key.get_value::<String, _>("SteamPath") {
-                Ok(steam_path) => Ok(PathBuf::from(steam_path)),
-                Err(_) => Err(TMError::SteamNotFound),
-            },
-            Err(_) => Err(TMError::SteamNotFound),
+    /// Check that an additional output can be written to `path`. Writing over a base map is
+    /// always refused, as is anything else in the maps folder of the game unless `allow_maps`
+    /// is set (`--i-know-what-im-doing`), since terrains and base maps live there.
+    pub(crate) fn check_output(&self, path: &Path, allow_maps: bool) -> Result<(), String> {
+        let path = resolve_path(path);
+        let maps_dir = resolve_path(&self.maps_dir());
+        if !path.starts_with(&maps_dir) {
+            return Ok(());
+        }
+        let overwrites_base = self
+            .available_bases()
+            .iter()
+            .chain(std::iter::once(&String::from(self.game.default_base)))
+            .any(|base| resolve_path(&self.base_vpk(base)) == path);
+        if overwrites_base {
+            return Err(format!(
+                "{} is a base map of the game and is never overwritten",
+                path.display()
+            ));
+        }
+        if !allow_maps {
+            return Err(format!(
+                "{} is inside the maps folder of the game, where its own maps and terrains \
+                live. Pass --i-know-what-im-doing to write there anyway",
+                path.display()
+            ));
         }
+        Ok(())
     }
 
-    #[cfg(target_os = "linux")]
-    /// Send default Steam config location on linux
-    fn get_steam_path() -> Result<PathBuf, TMError> {
-        let homedir = match std::env::var("HOME") {
-            Ok(home) => PathBuf::from(home),
-            Err(err) => {
-                return Err(TMError::InternalError(std::io::Error::new(
-                    std::io::ErrorKind::Other,
-                    err,
-                )))
-            }
+    /// Folder holding the maps and terrains shipped with the game, `game/dota/maps`
+    pub(crate) fn maps_dir(&self) -> PathBuf {
+        self.content_dir().join("maps")
+    }
+
+    /// Folder of the game's content, `game/dota`, holding `pak01_dir.vpk` and the maps
+    pub(crate) fn content_dir(&self) -> PathBuf {
+        self.dota_path.join(self.game.content_dir)
+    }
+
+    /// Path of the base map VPK named `base`, e.g. `dota`
+    pub(crate) fn base_vpk(&self, base: &str) -> PathBuf {
+        self.maps_dir().join(format!("{}.vpk", base))
+    }
+
+    /// Path of the terrain VPK with the file name `file`, e.g. `dota_desert.vpk`
+    pub(crate) fn terrain_vpk(&self, file: &str) -> PathBuf {
+        self.maps_dir().join(file)
+    }
+
+    /// Folder the game loads with `-language tempcontent`, which the output is written to
+    pub(crate) fn tempcontent_dir(&self) -> PathBuf {
+        self.dota_path.join(self.game.tempcontent_dir())
+    }
+
+    /// Path of the output replacing the base map `base`
+    pub(crate) fn out_vpk(&self, base: &str) -> PathBuf {
+        self.tempcontent_dir()
+            .join("maps")
+            .join(format!("{}.vpk", base))
+    }
+
+    /// Names of the maps in the game folder which can be used as the base map: `dota` and the
+    /// event maps, every VPK in `game/dota/maps` which is not a terrain
+    pub(crate) fn available_bases(&self) -> Vec<String> {
+        let Ok(dir) = fs::read_dir(self.maps_dir()) else {
+            return Vec::new();
         };
-        let steam_path =
-            PathBuf::from(homedir).join(".local/share/Steam/config/libraryfolders.vdf");
-        if steam_path.exists() {
+        let mut bases: Vec<String> = dir
+            .flatten()
+            .filter_map(|entry| {
+                let name = entry.file_name().to_string_lossy().into_owned();
+                name.strip_suffix(".vpk").map(str::to_owned)
+            })
+            .filter(|name| {
+                !self
+                    .game
+                    .terrain_prefix
+                    .is_some_and(|prefix| name.starts_with(prefix))
+            })
+            .collect();
+        bases.sort();
+        bases
+    }
+
+    /// The game's `appmanifest_570.acf` in the Steam library, where Steam keeps its state
+    pub(crate) fn app_manifest(&self) -> Option<AppManifest> {
+        // dota_path is <library>/steamapps/common/dota 2 beta/game
+        let library = self.dota_path.parent()?.parent()?.parent()?.parent()?;
+        AppManifest::load(library, self.game.appid)
+    }
+
+    /// Build ID of the installed game, read from `appmanifest_570.acf` in the Steam library
+    pub(crate) fn build_id(&self) -> Option<String> {
+        self.app_manifest()?.build_id
+    }
+
+    /// Whether the game is running, in which case a newly applied terrain only loads once
+    /// the map is loaded again
+    pub(crate) fn is_running(&self) -> bool {
+        process_running(self.game.process)
+    }
+
+    /// Whether the Steam client is running, which installs updates of the game
+    pub(crate) fn steam_running(&self) -> bool {
+        process_running("steam")
+    }
+}
+
+/// The state Steam keeps of an installed game in `steamapps/appmanifest_<appid>.acf` of its
+/// library
+/// # Properties
+/// - `install_dir: Option<String>` = Folder of the game in `steamapps/common` (`installdir`)
+/// - `build_id: Option<String>` = Build of the installed files (`buildid`)
+/// - `target_build_id: Option<String>` = Build Steam is updating the game to
+///   (`TargetBuildID`), `0` or the same as `build_id` when no update is pending
+/// - `state_flags: u64` = What Steam is doing with the game (`StateFlags`), see `steam_state`
+pub(crate) struct AppManifest {
+    pub(crate) install_dir: Option<String>,
+    pub(crate) build_id: Option<String>,
+    pub(crate) target_build_id: Option<String>,
+    pub(crate) state_flags: u64,
+}
+
+impl AppManifest {
+    /// Parse the contents of an app manifest. Returns `None` if it is malformed or has no
+    /// `AppState` block.
+    pub(crate) fn parse(text: &str) -> Option<AppManifest> {
+        let vdf = Vdf::parse(text)?;
+        let state = vdf.get("AppState")?;
+        let string = |key: &str| {
+            state
+                .get(key)
+                .and_then(Vdf::as_str)
+                .filter(|value| !value.is_empty())
+                .map(str::to_owned)
+        };
+        Some(AppManifest {
+            install_dir: string("installdir"),
+            build_id: string("buildid"),
+            target_build_id: string("TargetBuildID"),
+            state_flags: string("StateFlags")
+                .and_then(|flags| flags.parse().ok())
+                .unwrap_or(0),
+        })
+    }
+
+    /// Read the app manifest of the game `appid` in the Steam library at `library`
+    pub(crate) fn load(library: &Path, appid: &str) -> Option<AppManifest> {
+        let path = library
+            .join("steamapps")
+            .join(format!("appmanifest_{}.acf", appid));
+        AppManifest::parse(&fs::read_to_string(path).ok()?)
+    }
+
+    /// The build Steam is going to update the game to, if it differs from the installed one
+    pub(crate) fn pending_build(&self) -> Option<&str> {
+        self.target_build_id
+            .as_deref()
+            .filter(|target| *target != "0" && Some(*target) != self.build_id.as_deref())
+    }
+}
+
+/// Whether a process named `name` is running
+#[cfg(target_os = "linux")]
+fn process_running(name: &str) -> bool {
+    let Ok(processes) = fs::read_dir("/proc") else {
+        return false;
+    };
+    processes.flatten().any(|process| {
+        fs::read_to_string(process.path().join("comm")).is_ok_and(|comm| comm.trim_end() == name)
+    })
+}
+
+/// Whether a process named `name`, without `.exe`, is running
+#[cfg(target_os = "windows")]
+fn process_running(name: &str) -> bool {
+    let image = format!("{}.exe", name);
+    std::process::Command::new("tasklist")
+        .args(["/FI", &format!("IMAGENAME eq {}", image), "/NH"])
+        .output()
+        .is_ok_and(|output| String::from_utf8_lossy(&output.stdout).contains(&image))
+}
+
+/// Whether the process with the ID `pid` is running
+#[cfg(target_os = "linux")]
+pub(crate) fn pid_running(pid: u32) -> bool {
+    Path::new("/proc").join(pid.to_string()).exists()
+}
+
+/// Whether the process with the ID `pid` is running
+#[cfg(target_os = "windows")]
+pub(crate) fn pid_running(pid: u32) -> bool {
+    std::process::Command::new("tasklist")
+        .args(["/FI", &format!("PID eq {}", pid), "/NH", "/FO", "CSV"])
+        .output()
+        .is_ok_and(|output| {
+            String::from_utf8_lossy(&output.stdout).contains(&format!("\"{}\"", pid))
+        })
+}
+
+#[cfg(target_os = "windows")]
+/// Reads the windows registry and returns the Steam installation directory. Looks in the
+/// current user's hive first, then the machine-wide installation path and finally the hives
+/// of the other users loaded on this machine, for Steam installed under a different account
+/// than the one running the tool.
+fn get_steam_path() -> Result<PathBuf, TMError> {
+    use winreg::enums::{HKEY_CURRENT_USER, HKEY_LOCAL_MACHINE, HKEY_USERS};
+
+    let value = |hive: &RegKey, key: &str, name: &str| {
+        hive.open_subkey(key)
+            .and_then(|key| key.get_value::<String, _>(name))
+            .ok()
+            .map(PathBuf::from)
+    };
+    let hkcu = RegKey::predef(HKEY_CURRENT_USER);
+    if let Some(steam_path) = value(&hkcu, "Software\\Valve\\Steam", "SteamPath") {
+        return Ok(steam_path);
+    }
+    let hklm = RegKey::predef(HKEY_LOCAL_MACHINE);
+    for key in [
+        "SOFTWARE\\WOW6432Node\\Valve\\Steam",
+        "SOFTWARE\\Valve\\Steam",
+    ] {
+        if let Some(steam_path) = value(&hklm, key, "InstallPath") {
             return Ok(steam_path);
         }
-        return Err(TMError::SteamNotFound);
     }
+    let hku = RegKey::predef(HKEY_USERS);
+    hku.enum_keys()
+        .flatten()
+        .find_map(|sid| {
+            value(
+                &hku,
+                &format!("{}\\Software\\Valve\\Steam", sid),
+                "SteamPath",
+            )
+        })
+        .ok_or(TMError::SteamNotFound)
+}
 
-    /// Given the Steam installation path, return the contents of `libraryfolders.vdf`
-    fn load_libraries(steam_path: PathBuf) -> Result<String, TMError> {
-        #[cfg(target_os = "windows")]
-        let library_folders = Path::new(&steam_path)
-            .join("steamapps")
-            .join("libraryfolders.vdf");
-
-        #[cfg(target_os = "linux")]
-        let library_folders = steam_path;
-
-        fs::read_to_string(library_folders).map_err(|_| TMError::SteamNotFound)
-    }
-
-    /// Given the contents of `libraryfolders.vdf`, returns the path to the dota installation
-    /// directory.
-    fn get_dota_path(lib_file: String) -> Result<PathBuf, TMError> {
-        let lib_regex = Regex::new(r#"\d"\n\s\{\n[\s\S]+?}\n\s}"#).unwrap(); // "\d"\n\s\{\n[\s\S]+?\}\n\s}
-        let appid_regex = Regex::new(r#"\t{3}"570"\t{2}"\d+"\n"#).unwrap(); // \t{3}"570"\t{2}"\d+"\n
-        let path_regex = Regex::new(r#"(\w+:\\\\[^"]+|/[^"]+)"#).unwrap(); // (\w+:\\\\[^"]+|/[^"]+)
-
-        for lib in lib_regex.captures_iter(&lib_file) {
-            if appid_regex.is_match(&lib[0]) {
-                return match path_regex.captures(&lib[0]) {
-                    Some(capture) => match capture.get(1) {
-                        Some(path) => {
-                            let lib_path_str = path.as_str();
-                            let lib_path = Path::new(lib_path_str)
-                                .canonicalize()
-                                .map_err(TMError::InternalError)?;
-                            Ok(lib_path
-                                .join("steamapps")
-                                .join("common")
-                                .join("dota 2 beta")
-                                .join("game"))
-                        }
-                        None => Err(TMError::DotaNotFound),
-                    },
+#[cfg(target_os = "linux")]
+/// Send default Steam installation directory on linux
+fn get_steam_path() -> Result<PathBuf, TMError> {
+    let homedir = match std::env::var("HOME") {
+        Ok(home) => PathBuf::from(home),
+        Err(err) => return Err(TMError::InternalError(std::io::Error::other(err))),
+    };
+    let steam_path = homedir.join(".local/share/Steam");
+    if steam_path.join("config/libraryfolders.vdf").exists() {
+        return Ok(steam_path);
+    }
+    Err(TMError::SteamNotFound)
+}
+
+/// Given the Steam installation path, return the contents of `libraryfolders.vdf`
+fn load_libraries(steam_path: &Path) -> Result<String, TMError> {
+    #[cfg(target_os = "windows")]
+    let library_folders = steam_path.join("steamapps").join("libraryfolders.vdf");
+
+    #[cfg(target_os = "linux")]
+    let library_folders = steam_path.join("config").join("libraryfolders.vdf");
+
+    fs::read_to_string(library_folders).map_err(|_| TMError::SteamNotFound)
+}
+
+/// Given the contents of `libraryfolders.vdf`, returns the path to the `game` folder of the
+/// installation of `game`.
+fn get_dota_path(lib_file: String, game: &GameProfile) -> Result<PathBuf, TMError> {
+    let lib_regex = Regex::new(r#"\d"\n\s\{\n[\s\S]+?}\n\s}"#).unwrap(); // "\d"\n\s\{\n[\s\S]+?\}\n\s}
+    let appid_regex = Regex::new(&format!(r#"\t{{3}}"{}"\t{{2}}"\d+"\n"#, game.appid)).unwrap(); // \t{3}"570"\t{2}"\d+"\n
+    let path_regex = Regex::new(r#"(\w+:\\\\[^"]+|/[^"]+)"#).unwrap(); // (\w+:\\\\[^"]+|/[^"]+)
+
+    for lib in lib_regex.captures_iter(&lib_file) {
+        if appid_regex.is_match(&lib[0]) {
+            return match path_regex.captures(&lib[0]) {
+                Some(capture) => match capture.get(1) {
+                    Some(path) => {
+                        let lib_path_str = path.as_str();
+                        // Libraries may be symlinks or junctions to another drive, which
+                        // std canonicalizes to \\?\ paths on Windows
+                        let lib_path = dunce::canonicalize(Path::new(lib_path_str))
+                            .map_err(TMError::InternalError)?;
+                        // Steam records the folder it installed the game to, which is
+                        // only the default one unless the game was moved or renamed
+                        let install_dir = AppManifest::load(&lib_path, game.appid)
+                            .and_then(|manifest| manifest.install_dir)
+                            .unwrap_or_else(|| game.install_dir.to_owned());
+                        Ok(lib_path
+                            .join("steamapps")
+                            .join("common")
+                            .join(install_dir)
+                            .join("game"))
+                    }
                     None => Err(TMError::DotaNotFound),
-                };
+                },
+                None => Err(TMError::DotaNotFound),
             };
+        };
+    }
+    Err(TMError::DotaNotFound)
+}
+
+/// Recursively list every file below `dir`
+pub(crate) fn walk_dir(dir: &Path) -> std::io::Result<Vec<PathBuf>> {
+    let mut files = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            files.append(&mut walk_dir(&path)?);
+        } else {
+            files.push(path);
         }
-        Err(TMError::DotaNotFound)
     }
+    Ok(files)
+}
 
-    /// Create the path to the base terrain vpk using the dota installation directory
-    fn get_base_path(dota_path: &PathBuf) -> PathBuf {
-        dota_path.join("dota").join("maps").join("dota.vpk")
+/// Remove `dir` if it is empty, along with its parents up to `root`
+pub(crate) fn remove_empty_dirs(dir: &Path, root: &Path) {
+    let mut dir = dir;
+    while dir.starts_with(root) && dir != root && std::fs::remove_dir(dir).is_ok() {
+        let Some(parent) = dir.parent() else {
+            break;
+        };
+        dir = parent;
     }
+}
 
-    /// Create output path from the patched vpk using the dota installation directory
-    fn get_out_path(dota_path: &PathBuf) -> PathBuf {
-        dota_path
-            .join("dota_tempcontent")
-            .join("maps")
-            .join("dota.vpk")
+/// Whether the override at `path`, in the form used inside a VPK, is skipped. Files the game
+/// never looks up (no extension or directory) are skipped with a warning, whether they come
+/// from a directory or a VPK layer.
+fn skip_override(path: &str) -> bool {
+    let has_extension = path
+        .rsplit_once('/')
+        .and_then(|(_, name)| name.rsplit_once('.'))
+        .is_some_and(|(stem, ext)| !stem.is_empty() && !ext.is_empty());
+    if !has_extension {
+        eprintln!(
+            "Warning: Skipping override {}, files need an extension and a directory",
+            path
+        );
     }
+    !has_extension
+}
 
-    /// Create the path to the selected terrain vpk using the dota installation directory
-    fn get_target_path(dota_path: &PathBuf, target: &str) -> PathBuf {
-        dota_path.join("dota").join("maps").join(target)
+/// Load every file below `dir` as an override, keyed by its path relative to `dir` in the
+/// form used inside a VPK. Files the game will not load are reported as warnings, and files
+/// without an extension or directory are skipped, see `skip_override()`.
+pub(crate) fn load_overrides(dir: &Path) -> Result<HashMap<String, Vec<u8>>, TMError> {
+    let mut overrides = HashMap::new();
+    for file in walk_dir(dir).map_err(TMError::InternalError)? {
+        let relative = file.strip_prefix(dir).unwrap();
+        let path = relative
+            .iter()
+            .map(|component| component.to_string_lossy())
+            .collect::<Vec<_>>()
+            .join("/");
+        if skip_override(&path) {
+            continue;
+        }
+
+        let data = fs::read(&file).map_err(TMError::InternalError)?;
+        if let Some(warning) = crate::resource::check_override(&path, &data) {
+            eprintln!("Warning: {}", warning);
+        }
+        overrides.insert(path, data);
     }
+    Ok(overrides)
+}
 
-    pub(crate) fn pause() {
-        std::io::stdin().read(&mut [0_u8]).unwrap();
+/// Load the overrides of every layer given with `--overrides`, in order, one map per layer. A
+/// layer is either a directory, see `load_overrides()`, or a VPK, whose files are taken with
+/// their paths inside it and skipped by the same rules. See `conflicts::merge()` for
+/// combining them.
+pub(crate) fn load_override_layers(
+    layers: &[PathBuf],
+) -> Result<Vec<HashMap<String, Vec<u8>>>, TMError> {
+    let mut loaded = Vec::new();
+    for layer in layers {
+        if layer.is_dir() {
+            loaded.push(load_overrides(layer)?);
+            continue;
+        }
+        let mut vpk = crate::vpk::VPK::open(layer.clone()).map_err(TMError::InternalError)?;
+        let mut paths: Vec<String> = vpk.entries().map(|(path, _)| path.clone()).collect();
+        paths.sort();
+        let mut overrides = HashMap::new();
+        for path in paths {
+            if skip_override(&path) {
+                continue;
+            }
+            let data = vpk.read_entry(&path).map_err(TMError::InternalError)?;
+            if let Some(warning) = crate::resource::check_override(&path, &data) {
+                eprintln!("Warning: {}", warning);
+            }
+            overrides.insert(path, data);
+        }
+        loaded.push(overrides);
     }
+    Ok(loaded)
 }
 
-pub use self::utils::*;
+/// Name of the user account of the computer running the program, if it can be determined
+pub(crate) fn os_user() -> Option<String> {
+    #[cfg(target_os = "windows")]
+    let user = std::env::var("USERNAME");
+    #[cfg(not(target_os = "windows"))]
+    let user = std::env::var("USER");
+    user.ok().filter(|user| !user.is_empty())
+}
+
+/// `path` made absolute with symbolic links and `..` resolved as far as it exists, so paths
+/// to the same file compare equal even if the file does not exist yet
+pub(crate) fn resolve_path(path: &Path) -> PathBuf {
+    if let Ok(resolved) = dunce::canonicalize(path) {
+        return resolved;
+    }
+    match (path.parent(), path.file_name()) {
+        (Some(parent), Some(name)) if !parent.as_os_str().is_empty() => {
+            resolve_path(parent).join(name)
+        }
+        _ => std::env::current_dir()
+            .map(|dir| dir.join(path))
+            .unwrap_or_else(|_| path.to_path_buf()),
+    }
+}
+
+/// Whether `e` means that another process holds the file in a way which blocks this access,
+/// e.g. the game or Steam while updating a map. Only happens on Windows
+/// (`ERROR_SHARING_VIOLATION` and `ERROR_LOCK_VIOLATION`), where files are already opened
+/// sharing every kind of access, so there is nothing to do but wait.
+pub(crate) fn is_locked(e: &io::Error) -> bool {
+    cfg!(target_os = "windows") && matches!(e.raw_os_error(), Some(32 | 33))
+}
+
+/// Run `action` on the file at `path`, trying again while the file is locked by another
+/// process (see `is_locked()`), for up to `LOCK_WAIT`. A note is printed once when waiting.
+pub(crate) fn retry_locked<T>(
+    path: &Path,
+    mut action: impl FnMut() -> io::Result<T>,
+) -> io::Result<T> {
+    let start = Instant::now();
+    let mut waiting = false;
+    loop {
+        match action() {
+            Err(e) if is_locked(&e) && start.elapsed() < LOCK_WAIT => {
+                if !waiting {
+                    eprintln!(
+                        "{} is in use by another program, waiting for it to be released...",
+                        path.display()
+                    );
+                    waiting = true;
+                }
+                thread::sleep(LOCK_RETRY_DELAY);
+            }
+            result => return result,
+        }
+    }
+}
+
+/// Wait until `snapshot()`, taken every `interval`, stayed equal to `current` for
+/// `quiet_period`, starting over whenever it changes. Returns the last snapshot. Used by the
+/// watch modes, so changes written in bursts are acted on once.
+pub(crate) fn wait_for_quiet<T: PartialEq>(
+    mut current: T,
+    quiet_period: Duration,
+    interval: Duration,
+    mut snapshot: impl FnMut() -> T,
+) -> T {
+    let mut quiet_since = Instant::now();
+    while quiet_since.elapsed() < quiet_period {
+        thread::sleep(interval);
+        let settled = snapshot();
+        if settled != current {
+            current = settled;
+            quiet_since = Instant::now();
+        }
+    }
+    current
+}
+
+/// Ask a yes/no question on the console. With `assume_yes` (`--yes` or `--force`), the
+/// question is answered with yes without waiting. Without input to read, e.g. when run
+/// from a script, the answer is no.
+pub(crate) fn confirm(question: &str, assume_yes: bool) -> bool {
+    if assume_yes {
+        println!("{} [y/N] y", question);
+        return true;
+    }
+    println!("{} [y/N]", question);
+    let mut answer = String::new();
+    match std::io::stdin().read_line(&mut answer) {
+        Ok(n) if n > 0 => matches!(answer.trim().to_lowercase().as_str(), "y" | "yes"),
+        _ => {
+            println!("No input, assuming no. Pass --yes to confirm without a prompt.");
+            false
+        }
+    }
+}
+
+/// Answer a yes/no question: asked on the console as by `confirm()` if `interactive`,
+/// otherwise answered with yes for `assume_yes` and with no without reading any input
+pub(crate) fn answer(question: &str, assume_yes: bool, interactive: bool) -> bool {
+    if interactive || assume_yes {
+        return confirm(question, assume_yes);
+    }
+    println!("{} [y/N] n", question);
+    false
+}
+
+/// Wait for a key press. Returns at once if there is no input to read.
+pub(crate) fn pause() {
+    let _ = std::io::stdin().read(&mut [0_u8]);
+}
+
+/// Format `data` as a hexdump with 16 bytes per line, each line showing the offset,
+/// the bytes in hex and the printable ASCII characters
+pub(crate) fn hexdump(data: &[u8]) -> Vec<String> {
+    data.chunks(16)
+        .enumerate()
+        .map(|(i, chunk)| {
+            let hex: Vec<String> = chunk.iter().map(|byte| format!("{:02x}", byte)).collect();
+            let ascii: String = chunk
+                .iter()
+                .map(|&byte| {
+                    if byte.is_ascii_graphic() || byte == b' ' {
+                        byte as char
+                    } else {
+                        '.'
+                    }
+                })
+                .collect();
+            format!("{:08x}  {:<47}  |{}|", i * 16, hex.join(" "), ascii)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn layers_skip_the_same_files() {
+        let root =
+            std::env::temp_dir().join(format!("dota-terrain-mod-overrides-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+        let files: [(&str, &[u8]); 4] = [
+            ("materials/tweak.vmat_c", b"tweak"),
+            ("readme.txt", b"root"),
+            ("materials/LICENSE", b"no extension"),
+            ("materials/.hidden", b"dot file"),
+        ];
+        let dir = root.join("overrides");
+        for (path, data) in files {
+            fs::create_dir_all(dir.join(path).parent().unwrap()).unwrap();
+            fs::write(dir.join(path), data).unwrap();
+        }
+        let vpk = root.join("overrides.vpk");
+        let data = files
+            .iter()
+            .map(|(path, data)| (path.to_string(), data.to_vec()))
+            .collect();
+        let options = crate::vpk::VpkOptions::default();
+        fs::write(&vpk, crate::vpk::create_vpk(data, &options).unwrap()).unwrap();
+
+        let layers = load_override_layers(&[dir, vpk]).unwrap();
+        for layer in layers {
+            let paths: Vec<&String> = layer.keys().collect();
+            assert_eq!(paths, ["materials/tweak.vmat_c"]);
+        }
+        let _ = fs::remove_dir_all(&root);
+    }
+}
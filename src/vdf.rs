@@ -0,0 +1,122 @@
+/// A node of a Valve KeyValues (`.vdf`, `.acf`) text file
+/// # Properties
+/// - `Value(String)` = A string value
+/// - `Object(Vec<(String, Vdf)>)` = A block of key, value pairs, in file order
+pub(crate) enum Vdf {
+    Value(String),
+    Object(Vec<(String, Vdf)>),
+}
+
+impl Vdf {
+    /// Parse the contents of a KeyValues file. The file is a sequence of key, value pairs at
+    /// the top level, which are returned as a single object. Returns `None` if the file is
+    /// malformed.
+    pub(crate) fn parse(text: &str) -> Option<Vdf> {
+        parse_object(&mut tokenize(text)?.into_iter(), false)
+    }
+
+    /// Look up a key of an object. Keys are compared case-insensitively, as Steam does.
+    pub(crate) fn get(&self, key: &str) -> Option<&Vdf> {
+        match self {
+            Vdf::Object(pairs) => pairs
+                .iter()
+                .find(|(name, _)| name.eq_ignore_ascii_case(key))
+                .map(|(_, value)| value),
+            Vdf::Value(_) => None,
+        }
+    }
+
+    /// Follow a path of keys through nested objects
+    pub(crate) fn path(&self, keys: &[&str]) -> Option<&Vdf> {
+        keys.iter().try_fold(self, |node, key| node.get(key))
+    }
+
+    /// The string value of this node, if it is one
+    pub(crate) fn as_str(&self) -> Option<&str> {
+        match self {
+            Vdf::Value(value) => Some(value),
+            Vdf::Object(_) => None,
+        }
+    }
+}
+
+enum Token {
+    Open,
+    Close,
+    String(String),
+}
+
+/// Split the file into strings and braces. Strings are quoted (with `\` escapes) or bare
+/// words, `//` starts a comment and `[$CONDITION]` suffixes are skipped.
+fn tokenize(text: &str) -> Option<Vec<Token>> {
+    let mut tokens = Vec::new();
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '{' => tokens.push(Token::Open),
+            '}' => tokens.push(Token::Close),
+            '"' => {
+                let mut value = String::new();
+                loop {
+                    match chars.next()? {
+                        '"' => break,
+                        '\\' => match chars.next()? {
+                            'n' => value.push('\n'),
+                            't' => value.push('\t'),
+                            c => value.push(c),
+                        },
+                        c => value.push(c),
+                    }
+                }
+                tokens.push(Token::String(value));
+            }
+            '/' if chars.peek() == Some(&'/') => {
+                for c in chars.by_ref() {
+                    if c == '\n' {
+                        break;
+                    }
+                }
+            }
+            '[' => {
+                for c in chars.by_ref() {
+                    if c == ']' {
+                        break;
+                    }
+                }
+            }
+            c if c.is_whitespace() => {}
+            c => {
+                let mut value = String::from(c);
+                while let Some(&c) = chars.peek() {
+                    if c.is_whitespace() || matches!(c, '{' | '}' | '"') {
+                        break;
+                    }
+                    value.push(c);
+                    chars.next();
+                }
+                tokens.push(Token::String(value));
+            }
+        }
+    }
+    Some(tokens)
+}
+
+/// Parse key, value pairs until the closing brace of a `nested` object, or the end of the
+/// tokens at the top level
+fn parse_object(tokens: &mut std::vec::IntoIter<Token>, nested: bool) -> Option<Vdf> {
+    let mut pairs = Vec::new();
+    loop {
+        let key = match tokens.next() {
+            Some(Token::String(key)) => key,
+            Some(Token::Close) if nested => return Some(Vdf::Object(pairs)),
+            None if !nested => return Some(Vdf::Object(pairs)),
+            _ => return None,
+        };
+        let value = match tokens.next()? {
+            Token::String(value) => Vdf::Value(value),
+            Token::Open => parse_object(tokens, true)?,
+            Token::Close => return None,
+        };
+        pairs.push((key, value));
+    }
+}
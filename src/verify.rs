@@ -0,0 +1,129 @@
+use std::path::PathBuf;
+
+use crate::cli::Args;
+use crate::vpk::{self, DuplicatePolicy, VpkOptions, VPK};
+
+/// Entry point for the `verify <vpk> [--quick] [--duplicates error|keep-first|keep-last]`
+/// command. Checks the entries of a VPK against their CRCs and the archive against its MD5
+/// hashes, and reports paths listed more than once and inconsistencies between the header and
+/// tree. With `--quick`, only the header, the bounds of the tree and the tree MD5 are checked,
+/// without reading the file data, which is enough to tell that an output is not truncated or
+/// overwritten. Exits with an error if any problem was found.
+pub(crate) fn run(args: &Args) {
+    let [vpk_path] = args.positional.as_slice() else {
+        eprintln!(
+            "Usage: dota-terrain-mod verify <vpk> [--quick] \
+            [--duplicates error|keep-first|keep-last]"
+        );
+        std::process::exit(1);
+    };
+    let quick = args.flag("quick");
+    let mut options = VpkOptions::from_args(args);
+    if let Some(name) = args.option("duplicates") {
+        match DuplicatePolicy::from_name(name) {
+            Some(policy) => options.duplicates = policy,
+            None => {
+                eprintln!(
+                    "Dota-Terrain-Mod error: Unknown duplicate policy \"{}\"",
+                    name
+                );
+                std::process::exit(1);
+            }
+        }
+    }
+
+    let mut vpk = match VPK::open_with(PathBuf::from(vpk_path), options) {
+        Ok(vpk) => vpk,
+        Err(e) => {
+            eprintln!("Dota-Terrain-Mod error: Invalid VPK: {}", e);
+            std::process::exit(1);
+        }
+    };
+    let mut problems = 0;
+
+    let entry_count = vpk.entries().count();
+    println!("Entries: {}", entry_count);
+
+    if !vpk.issues().is_empty() {
+        println!("Tree inconsistencies: {}", vpk.issues().len());
+        for issue in vpk.issues() {
+            println!("  {}", issue);
+        }
+        problems += vpk.issues().len();
+    }
+
+    if !vpk.duplicates().is_empty() {
+        println!("Duplicate entries: {}", vpk.duplicates().len());
+        for path in vpk.duplicates() {
+            println!("  {}", path);
+        }
+        problems += vpk.duplicates().len();
+    }
+
+    if quick {
+        match vpk.verify_tree_hash() {
+            Ok(Some(true)) => println!("Tree MD5: OK"),
+            Ok(Some(false)) => {
+                println!("Tree MD5: MISMATCH");
+                problems += 1;
+            }
+            Ok(None) => println!("MD5: No self hashes section"),
+            Err(e) => {
+                println!("MD5: Failed to read hash sections: {}", e);
+                problems += 1;
+            }
+        }
+        if problems > 0 {
+            eprintln!("Quick verification failed: {} problem(s) found.", problems);
+            std::process::exit(1);
+        }
+        println!("Quick verification passed, file data was not checked.");
+        return;
+    }
+
+    let mismatches = crc_mismatches(&mut vpk);
+    println!("Entries failing CRC check: {}", mismatches.len());
+    for mismatch in &mismatches {
+        println!("  {}", mismatch);
+    }
+    problems += mismatches.len();
+
+    match vpk.verify_hashes() {
+        Ok(Some(matches)) => {
+            for (name, matches) in ["Tree", "Chunk hashes", "File"].iter().zip(matches) {
+                println!("{} MD5: {}", name, if matches { "OK" } else { "MISMATCH" });
+                if !matches {
+                    problems += 1;
+                }
+            }
+        }
+        Ok(None) => println!("MD5: No self hashes section"),
+        Err(e) => {
+            println!("MD5: Failed to read hash sections: {}", e);
+            problems += 1;
+        }
+    }
+
+    if problems > 0 {
+        eprintln!("Verification failed: {} problem(s) found.", problems);
+        std::process::exit(1);
+    }
+    println!("Verification passed.");
+}
+
+/// Check every entry of `vpk` against its CRC. Returns a description of each entry which does
+/// not match or cannot be read.
+pub(crate) fn crc_mismatches(vpk: &mut VPK) -> Vec<String> {
+    let mut paths: Vec<String> = vpk.entries().map(|(path, _)| path.clone()).collect();
+    paths.sort();
+    let mut mismatches = Vec::new();
+    for path in paths {
+        let expected = vpk.entry(&path).unwrap().crc32();
+        match vpk.read_entry(&path) {
+            Ok(data) if vpk::crc32(&data) == expected => {}
+            Ok(_) => mismatches.push(format!("{} (CRC mismatch)", path)),
+            Err(e) => mismatches.push(format!("{} ({})", path, e)),
+        }
+    }
+    mismatches
+}
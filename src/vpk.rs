@@ -1,68 +1,189 @@
 use std::{
-    collections::HashMap,
+    collections::{BTreeMap, HashMap},
     ffi::CString,
     fs::{create_dir_all, File},
-    io::{BufRead, Cursor},
-    io::{Read, Write},
+    io::{self, BufRead, Cursor, SeekFrom},
+    io::{Read, Seek, Write},
     path::{Path, PathBuf},
     sync::mpsc,
     thread,
 };
 
-use crc::{Crc, CRC_32_CKSUM};
+use crc::{Crc, CRC_32_ISO_HDLC};
 use md5::{Digest, Md5};
 
+use crate::cli::Args;
+use crate::manifest::{Manifest, Source};
+use crate::rules::{self, Rule};
+use crate::texture::{self, Recolor};
+use crate::{crash, lzma, minimap, utils};
+
 const HEADER_LENGTH: usize = 28;
-const CRC32: Crc<u32> = Crc::<u32>::new(&CRC_32_CKSUM);
+/// The CRC-32 of zlib, which Valve's tools store in the tree. The POSIX `cksum` variant
+/// used before gives other checksums, so entries could not be compared with the game's.
+const CRC32: Crc<u32> = Crc::<u32>::new(&CRC_32_ISO_HDLC);
+
+/// Largest tree, and largest total of the sections after the embedded data, read into memory
+const MAX_TREE_LENGTH: u64 = 256 << 20;
+/// Largest number of entries in a tree
+const MAX_ENTRIES: usize = 1_000_000;
+/// Largest total size of the entries of a VPK, including those in other archives
+const MAX_DATA_LENGTH: u64 = 10 << 30;
+/// Largest size of a single entry read into memory, after decompression
+const MAX_ENTRY_LENGTH: u64 = 2 << 30;
+
+/// Fail if `length`, the size of `what`, exceeds `limit`, unless `enabled` is unset
+fn check_limit(enabled: bool, what: &str, length: u64, limit: u64) -> io::Result<()> {
+    if enabled && length > limit {
+        return Err(invalid_data(format!(
+            "{} ({}) exceeds the limit of {}, the file is probably damaged. \
+            Pass --no-limits to open it anyway",
+            what, length, limit
+        )));
+    }
+    Ok(())
+}
+/// Path of the map the game loads for the base map `base`, which the terrain's map is
+/// renamed to
+pub(crate) fn map_path(base: &str) -> String {
+    format!("maps/{}.vmap_c", base)
+}
 
 /// Object representing a VPK file
 /// # Properties
 /// - `path: PathBuf` = Path to the VPK file on disk
-/// - `data: Cursor<Vec<u8>>` = Data in the VPK file as vector of bytes
+/// - `file: File` = Open handle to the VPK file, used to read file data on demand
+/// - `data: Cursor<Vec<u8>>` = Header and tree of the VPK file as vector of bytes
 /// - `header: Option<VPKHeader>` = The header of the VPK file. Initially None, until `read_header() is called`
-/// - `index: HashMap<String, VPKMetadata>` = HashMap containing the path to each file in the VPK, and its respective metadata
+/// - `index: HashMap<String, Entry>` = HashMap containing the path to each file in the VPK, and its respective metadata
 /// - `files: HashMap<String, Vec<u8>>` = HashMap containing the path to each file in the VPK, and the file itself as a Vector of bytes
-struct VPK {
-    _path: PathBuf,
+/// - `options: VpkOptions` = Options controlling how the VPK is read
+/// - `duplicates: Vec<String>` = Paths which the tree lists more than once
+/// - `issues: Vec<String>` = Inconsistencies between the header, the tree and the file found while reading
+#[allow(clippy::upper_case_acronyms)]
+pub struct VPK {
+    path: PathBuf,
+    file: File,
     data: Cursor<Vec<u8>>,
     header: Option<VPKHeader>,
-    index: HashMap<String, VPKMetadata>,
+    index: HashMap<String, Entry>,
     files: HashMap<String, Vec<u8>>,
+    options: VpkOptions,
+    duplicates: Vec<String>,
+    issues: Vec<String>,
+}
+
+/// What to do when the tree of a VPK lists the same path more than once
+#[derive(Clone, Copy)]
+pub(crate) enum DuplicatePolicy {
+    /// Refuse to open the VPK
+    Error,
+    /// Use the first entry for the path
+    KeepFirst,
+    /// Use the last entry for the path
+    KeepLast,
+}
+
+impl DuplicatePolicy {
+    /// Parse a policy as given on the command line: `error`, `keep-first` or `keep-last`
+    pub(crate) fn from_name(name: &str) -> Option<DuplicatePolicy> {
+        match name {
+            "error" => Some(DuplicatePolicy::Error),
+            "keep-first" => Some(DuplicatePolicy::KeepFirst),
+            "keep-last" => Some(DuplicatePolicy::KeepLast),
+            _ => None,
+        }
+    }
+}
+
+/// How the chunk hash, self hash and signature sections of a VPK are written
+#[derive(Clone, Copy)]
+pub(crate) enum HashSections {
+    /// Keep the sections of the source as they are, see `copy_vpk()`
+    Preserve,
+    /// Generate new self hashes for the written VPK and drop the signature
+    Rehash,
+    /// Write no hash or signature sections at all
+    Strip,
+}
+
+/// Options controlling how a VPK is read and written
+/// # Properties
+/// - `duplicates: DuplicatePolicy` = How paths listed more than once are handled. Defaults to `KeepLast`
+/// - `strict: bool` = Refuse VPKs whose tree is inconsistent with the header or the file,
+///   instead of only recording the problems in `issues()`
+/// - `verify_crc: bool` = Check every entry against its CRC when the whole VPK is read
+/// - `limits: bool` = Refuse VPKs whose header or tree claim more data than any real game
+///   archive, before memory is allocated for it. Defaults to on, unset by `--no-limits`
+/// - `compress: bool` = Store every written file LZMA-compressed
+/// - `hash_sections: HashSections` = How the hash and signature sections are written.
+///   Defaults to `Rehash`
+#[derive(Clone, Copy)]
+pub(crate) struct VpkOptions {
+    pub(crate) duplicates: DuplicatePolicy,
+    pub(crate) strict: bool,
+    pub(crate) verify_crc: bool,
+    pub(crate) limits: bool,
+    pub(crate) compress: bool,
+    pub(crate) hash_sections: HashSections,
+}
+
+impl Default for VpkOptions {
+    fn default() -> Self {
+        VpkOptions {
+            duplicates: DuplicatePolicy::KeepLast,
+            strict: false,
+            verify_crc: false,
+            limits: true,
+            compress: false,
+            hash_sections: HashSections::Rehash,
+        }
+    }
+}
+
+impl VpkOptions {
+    /// The default options, with the limits unset if `--no-limits` was given
+    pub(crate) fn from_args(args: &Args) -> VpkOptions {
+        VpkOptions {
+            limits: !args.flag("no-limits"),
+            ..VpkOptions::default()
+        }
+    }
 }
 
 /// Object representing the header of a VPK file. The expected header length is 28 bytes,
-/// and is the first 28 bytes of a VPK file. Each property is 4 bytes.
+/// and is the first 28 bytes of a VPK file. Each property is 4 bytes, after the signature
+/// 0x55aa1234 and the VPK version, which must be 2. Both are checked but not kept.
 /// # Properties
-/// - `signature: u32` = Expected signature for a valid VPK is 0x55aa1234
-/// - `version: u32` = VPK Version. This program expects VPK Version 2.
 /// - `tree_length: u32` = Determined by the number of files, per root directory,
-/// per file extension in the VPK.
+///   per file extension in the VPK.
 /// - `embed_chunk_length: u32`
 /// - `chunk_hashes_length: u32`
 /// - `self_hashes_length: u32`
 /// - `signature_length: u32`
 struct VPKHeader {
-    _signature: u32,
-    version: u32,
     tree_length: u32,
-    _embed_chunk_length: u32,
-    _chunk_hashes_length: u32,
-    _self_hashes_length: u32,
-    _signature_length: u32,
+    embed_chunk_length: u32,
+    chunk_hashes_length: u32,
+    self_hashes_length: u32,
+    signature_length: u32,
 }
 
-/// Object representing the metadata for each file inside a VPK file.
+/// An entry in the tree of a VPK file: the metadata of a single file, read through the
+/// accessor methods.
 /// # Properties
-/// - `preload: Vec<u8>`
+/// - `preload: Vec<u8>` = Start of the file, stored in the tree itself
 /// - `crc32: u32` = CRC32 checksum of the file
-/// - `preload_length: u16`
-/// - `archive_index: u16`
-/// - `archive_offset: u32` = Starting position of the file in the VPK file.
-/// - `file_length: u32` = Size of the file in bits
-/// - `suffix: u16`
-struct VPKMetadata {
-    _preload: Vec<u8>,
-    _crc32: u32,
+/// - `preload_length: u16` = Length of `preload`
+/// - `archive_index: u16` = Number of the archive holding the rest of the file, `0x7fff` if
+///   it is embedded in the VPK itself
+/// - `archive_offset: u32` = Starting position of the rest of the file in its archive
+/// - `file_length: u32` = Size of the rest of the file in bytes
+/// - `suffix: u16` = Terminator of the entry, always `0xffff`
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Entry {
+    preload: Vec<u8>,
+    crc32: u32,
     preload_length: u16,
     archive_index: u16,
     archive_offset: u32,
@@ -71,133 +192,239 @@ struct VPKMetadata {
 }
 
 impl VPKHeader {
-
     /// Create a new `VPKHeader` from a 28 byte array containing the header data
-    /// Panics if the signature is not `0x55aa1234`
-    fn new(header_data: Vec<u32>) -> VPKHeader {
+    /// Fails if the signature is not `0x55aa1234` or the version is not 2, since the layout
+    /// of the header and tree of any other version is not known
+    fn new(header_data: Vec<u32>) -> io::Result<VPKHeader> {
         let signature = header_data[0];
         if signature != 0x55aa1234 {
-            panic!("Invalid VPK");
+            return Err(invalid_data(format!(
+                "not a VPK file (signature {:08x})",
+                signature
+            )));
         }
-        VPKHeader {
-            _signature: signature,
-            version: header_data[1],
-            tree_length: header_data[2],
-            _embed_chunk_length: header_data[3],
-            _chunk_hashes_length: header_data[4],
-            _self_hashes_length: header_data[5],
-            _signature_length: header_data[6],
+        let version = header_data[1];
+        if version != 2 {
+            return Err(invalid_data(format!(
+                "unsupported VPK version {}, only version 2 is supported. If Dota 2 was \
+                updated recently, Valve may have changed the format: check for a new release \
+                at https://github.com/ObsoleteXero/Dota-Terrain-Mod/releases or report it at \
+                https://github.com/ObsoleteXero/Dota-Terrain-Mod/issues",
+                version
+            )));
         }
+        Ok(VPKHeader {
+            tree_length: header_data[2],
+            embed_chunk_length: header_data[3],
+            chunk_hashes_length: header_data[4],
+            self_hashes_length: header_data[5],
+            signature_length: header_data[6],
+        })
     }
 }
 
-impl VPKMetadata {
+impl Entry {
+    /// Size of the file in bytes, including any preload data stored in the tree
+    pub fn entry_length(&self) -> u64 {
+        u64::from(self.preload_length) + u64::from(self.file_length)
+    }
+
+    /// CRC32 checksum of the whole, uncompressed file
+    pub fn crc32(&self) -> u32 {
+        self.crc32
+    }
+
+    /// Start of the file stored in the tree itself
+    pub fn preload(&self) -> &[u8] {
+        &self.preload
+    }
+
+    /// Number of bytes of the file stored in the tree itself
+    pub fn preload_length(&self) -> u16 {
+        self.preload_length
+    }
+
+    /// Number of the archive (`<name>_<index>.vpk`) holding the rest of the file, or
+    /// `0x7fff` if it is embedded in the VPK itself
+    pub fn archive_index(&self) -> u16 {
+        self.archive_index
+    }
 
-    /// Validate `VPKMetadata` object by checking the header
+    /// Whether the rest of the file is stored in the VPK itself rather than another archive
+    pub fn is_embedded(&self) -> bool {
+        self.archive_index == 32767
+    }
+
+    /// Position of the rest of the file in its archive. For embedded files, this is relative
+    /// to the start of the VPK rather than to the embedded data section.
+    pub fn archive_offset(&self) -> u32 {
+        self.archive_offset
+    }
+
+    /// Number of bytes of the file stored in its archive, after the preload data
+    pub fn file_length(&self) -> u32 {
+        self.file_length
+    }
+
+    /// Validate `Entry` object by checking the suffix
     /// and updating `archive_offset` as necessary.
-    fn validate(&mut self, header: &VPKHeader) {
+    fn validate(&mut self, header: &VPKHeader) -> Result<(), String> {
         if self.suffix != 65535 {
-            panic!("Error while parsing index.");
+            return Err(format!("unexpected entry terminator {:04x}", self.suffix));
         }
-        if self.archive_index == 32767 {
-            self.archive_offset += HEADER_LENGTH as u32 + header.tree_length;
+        if self.is_embedded() {
+            self.archive_offset = self
+                .archive_offset
+                .checked_add(HEADER_LENGTH as u32 + header.tree_length)
+                .ok_or_else(|| String::from("archive offset out of range"))?;
         }
+        Ok(())
     }
 }
 
 impl VPK {
+    /// Create a new `VPK` object from a file on disk, read according to `options`. A file
+    /// locked by another process, e.g. the base map while Steam updates it, is waited for, see
+    /// `utils::retry_locked()`.
+    fn new(path: PathBuf, options: VpkOptions) -> io::Result<VPK> {
+        let file = utils::retry_locked(&path, || File::open(&path))?;
 
-    /// Create a new `VPK` object from a file on disk
-    fn new(path: PathBuf) -> VPK {
-        // Open file and load bytes
-        let mut f = File::open(&path).unwrap();
-        let mut vpk_data = Vec::new();
-        f.read_to_end(&mut vpk_data).unwrap();
-        let vpk_cursor = Cursor::new(vpk_data);
-
-        VPK {
-            _path: path,
+        Ok(VPK {
+            path,
+            file,
             header: None,
             index: HashMap::new(),
-            data: vpk_cursor,
+            data: Cursor::new(Vec::new()),
             files: HashMap::new(),
+            options,
+            duplicates: Vec::new(),
+            issues: Vec::new(),
+        })
+    }
+
+    /// Open a VPK file lazily. Only the header and the tree are read, file data is
+    /// loaded on demand with `read_entry()` and `read_entry_at()`
+    pub fn open(path: PathBuf) -> io::Result<VPK> {
+        VPK::open_with(path, VpkOptions::default())
+    }
+
+    /// Open a VPK file lazily like `open()`, using the given options, see `read_index()`
+    pub(crate) fn open_with(path: PathBuf, options: VpkOptions) -> io::Result<VPK> {
+        let mut vpk = VPK::new(path, options)?;
+        vpk.read_index()?;
+        Ok(vpk)
+    }
+
+    /// Read the header and the tree. Fails if the tree lists a path more than once and
+    /// `options.duplicates` is `DuplicatePolicy::Error`, or if it has inconsistencies and
+    /// `options.strict` is set.
+    fn read_index(&mut self) -> io::Result<()> {
+        self.read_header()?;
+        self.populate_index()?;
+        if matches!(self.options.duplicates, DuplicatePolicy::Error) && !self.duplicates.is_empty()
+        {
+            return Err(invalid_data(format!(
+                "duplicate entries: {}",
+                self.duplicates.join(", ")
+            )));
+        }
+        if self.options.strict && !self.issues.is_empty() {
+            return Err(invalid_data(format!(
+                "inconsistent tree: {}",
+                self.issues.join(", ")
+            )));
         }
+        Ok(())
     }
 
     /// Read the file into memory and fully populate the object attributes
-    fn read(&mut self) {
-        self.read_header();
-        self.populate_index();
-        self.load_file_data();
+    fn read(&mut self) -> io::Result<()> {
+        self.read_index()?;
+        self.load_file_data()
     }
 
-    /// Read the header of the VPK file and populate the relevant attributes
-    fn read_header(&mut self) {
-        let mut header = [b'0'; HEADER_LENGTH];
-        self.data.read_exact(&mut header).unwrap();
-        let header_data: Vec<u32> = header
+    /// Read the header and the tree of the VPK file and populate the relevant attributes
+    fn read_header(&mut self) -> io::Result<()> {
+        let mut header_bytes = [b'0'; HEADER_LENGTH];
+        self.file
+            .read_exact(&mut header_bytes)
+            .map_err(|_| invalid_data(String::from("file is too short for a VPK header")))?;
+        let header_data: Vec<u32> = header_bytes
             .chunks_exact(4)
             .map(|chunk| u32::from_le_bytes(chunk.try_into().unwrap()))
             .collect();
+        let header = VPKHeader::new(header_data)?;
+        check_limit(
+            self.options.limits,
+            "tree length",
+            u64::from(header.tree_length),
+            MAX_TREE_LENGTH,
+        )?;
 
-        self.header = Some(VPKHeader::new(header_data));
+        let mut data = header_bytes.to_vec();
+        data.resize(HEADER_LENGTH + header.tree_length as usize, b'0');
+        self.file
+            .read_exact(&mut data[HEADER_LENGTH..])
+            .map_err(|_| {
+                invalid_data(format!(
+                    "header declares a {} byte tree, which is longer than the file",
+                    header.tree_length
+                ))
+            })?;
+
+        self.data = Cursor::new(data);
+        self.header = Some(header);
+        Ok(())
     }
 
     /// Read the index of file within the VPK and create an index of file path and
-    /// metadata for each file
-    fn populate_index(&mut self) {
+    /// metadata for each file. Fails if the tree cannot be parsed. Afterwards, the tree is
+    /// cross-checked against the header and the file, and any inconsistencies are recorded
+    /// in `issues`.
+    fn populate_index(&mut self) -> io::Result<()> {
         self.data.set_position(HEADER_LENGTH as u64);
-        let header = self.header.as_ref().unwrap();
+        let mut entries = 0;
+        let mut data_length = 0;
 
         loop {
-            if header.version > 0
-                && self.data.position() > (header.tree_length + HEADER_LENGTH as u32).into()
-            {
-                panic!("Error parsing index.");
-            }
-            let mut cstr = Vec::new();
-            self.data.read_until(b'\x00', &mut cstr).unwrap();
-            let Ok(ext) = CString::from_vec_with_nul(cstr) else { return; };
-            if &ext.to_str().unwrap() == &"" {
+            let ext = self.read_tree_string()?;
+            if ext.is_empty() {
                 break;
-            };
+            }
 
             loop {
-                let mut cstr = Vec::new();
-                self.data.read_until(b'\x00', &mut cstr).unwrap();
-                let Ok(mut path) = CString::from_vec_with_nul(cstr) else { return; };
-                if &path.to_str().unwrap() == &"" {
+                let dir = self.read_tree_string()?;
+                if dir.is_empty() {
                     break;
-                };
-
-                if path.to_str().unwrap() != " " {
-                    path = CString::new(path.to_str().unwrap().to_owned() + "/").unwrap();
-                } else {
-                    path = CString::new("").unwrap();
                 }
+                let dir = if dir != " " { dir + "/" } else { String::new() };
+
                 loop {
-                    let mut cstr = Vec::new();
-                    self.data.read_until(b'\x00', &mut cstr).unwrap();
-                    let Ok(name) = CString::from_vec_with_nul(cstr) else { return; };
-                    if &name.to_str().unwrap() == &"" {
+                    let name = self.read_tree_string()?;
+                    if name.is_empty() {
                         break;
+                    }
+                    let path = if ext != " " {
+                        format!("{dir}{name}.{ext}")
+                    } else {
+                        format!("{dir}{name}")
                     };
 
+                    crash::offset(&self.path, self.data.position());
                     let mut metadata = [b'0'; 18];
-                    self.data.read_exact(&mut metadata).unwrap();
-
-                    let path = path.to_str().unwrap().to_owned()
-                        + name.to_str().unwrap()
-                        + "."
-                        + ext.to_str().unwrap();
+                    self.data.read_exact(&mut metadata).map_err(|_| {
+                        invalid_data(format!("tree ends inside the entry for {}", path))
+                    })?;
 
                     let preload_length = u16::from_le_bytes(metadata[4..6].try_into().unwrap());
                     let mut preload = vec![b'0'; preload_length.into()];
-                    self.data.read_exact(&mut preload).unwrap();
+                    self.data.read_exact(&mut preload).map_err(|_| {
+                        invalid_data(format!("tree ends inside the preload data of {}", path))
+                    })?;
 
-                    let mut meta = VPKMetadata {
-                        _preload: preload,
-                        _crc32: u32::from_le_bytes(metadata[0..4].try_into().unwrap()),
+                    let mut meta = Entry {
+                        preload,
+                        crc32: u32::from_le_bytes(metadata[0..4].try_into().unwrap()),
                         preload_length,
                         archive_index: u16::from_le_bytes(metadata[6..8].try_into().unwrap()),
                         archive_offset: u32::from_le_bytes(metadata[8..12].try_into().unwrap()),
@@ -205,23 +432,291 @@ impl VPK {
                         suffix: u16::from_le_bytes(metadata[16..18].try_into().unwrap()),
                     };
 
-                    meta.validate(header);
+                    meta.validate(self.header.as_ref().unwrap())
+                        .map_err(|e| invalid_data(format!("{}: {}", path, e)))?;
+                    entries += 1;
+                    data_length += meta.entry_length();
+                    let limits = self.options.limits;
+                    check_limit(limits, "number of entries", entries, MAX_ENTRIES as u64)?;
+                    check_limit(
+                        limits,
+                        "total size of the entries",
+                        data_length,
+                        MAX_DATA_LENGTH,
+                    )?;
+                    if self.index.contains_key(&path) {
+                        self.duplicates.push(path.clone());
+                        if matches!(self.options.duplicates, DuplicatePolicy::KeepFirst) {
+                            continue;
+                        }
+                    }
                     self.index.insert(path, meta);
                 }
             }
         }
+
+        self.check_consistency()
+    }
+
+    /// Read a null-terminated string from the tree
+    fn read_tree_string(&mut self) -> io::Result<String> {
+        let mut cstr = Vec::new();
+        self.data.read_until(b'\x00', &mut cstr)?;
+        let cstr = CString::from_vec_with_nul(cstr).map_err(|_| {
+            invalid_data(format!(
+                "tree ends without a terminator at offset {}",
+                self.data.position()
+            ))
+        })?;
+        cstr.into_string().map_err(|_| {
+            invalid_data(format!(
+                "tree contains a name which is not valid UTF-8 before offset {}",
+                self.data.position()
+            ))
+        })
+    }
+
+    /// Cross-check the parsed tree against the header and the file on disk: the tree must
+    /// end exactly where the header says, the data of every entry stored in this file
+    /// must lie within the embedded data section, and no path may escape the directory it is
+    /// extracted to.
+    fn check_consistency(&mut self) -> io::Result<()> {
+        let header = self.header.as_ref().unwrap();
+        let tree_end = HEADER_LENGTH as u64 + u64::from(header.tree_length);
+        if self.data.position() != tree_end {
+            self.issues.push(format!(
+                "Tree ends at offset {} but the header declares it ends at {}",
+                self.data.position(),
+                tree_end
+            ));
+        }
+
+        let file_length = self.file.metadata()?.len();
+        let data_end = tree_end + u64::from(header.embed_chunk_length);
+        if data_end > file_length {
+            self.issues.push(format!(
+                "Header declares {} bytes of embedded data but the file ends {} bytes early",
+                header.embed_chunk_length,
+                data_end - file_length
+            ));
+        }
+
+        let mut paths: Vec<&String> = self.index.keys().collect();
+        paths.sort();
+        for path in paths {
+            if !is_safe_path(path) {
+                self.issues.push(format!(
+                    "{} is absolute or leaves its directory and will not be extracted",
+                    path
+                ));
+            }
+            let metadata = &self.index[path];
+            if !metadata.is_embedded() {
+                continue;
+            }
+            let end = u64::from(metadata.archive_offset) + u64::from(metadata.file_length);
+            if end > data_end.min(file_length) {
+                self.issues.push(format!(
+                    "{} spans offsets {} to {}, past the end of the embedded data at {}",
+                    path,
+                    metadata.archive_offset,
+                    end,
+                    data_end.min(file_length)
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    /// Use the created file path, metadata pairs to load the contents of each file into memory.
+    /// With `options.verify_crc`, fails for the first file not matching its CRC.
+    fn load_file_data(&mut self) -> io::Result<()> {
+        let paths: Vec<String> = self.index.keys().cloned().collect();
+        for path in paths {
+            let file_data = self
+                .read_entry(&path)
+                .map_err(|e| io::Error::new(e.kind(), format!("{}: {}", path, e)))?;
+            if self.options.verify_crc && crc32(&file_data) != self.index[&path].crc32 {
+                return Err(invalid_data(format!("{}: CRC mismatch", path)));
+            }
+            self.files.insert(path, file_data);
+        }
+        Ok(())
+    }
+
+    /// Read the sections following the embedded file data: chunk hashes, self hashes and
+    /// signature, along with the length of each
+    fn trailing_sections(&mut self) -> io::Result<([u32; 3], Vec<u8>)> {
+        let header = self.header.as_ref().unwrap();
+        let lengths = [
+            header.chunk_hashes_length,
+            header.self_hashes_length,
+            header.signature_length,
+        ];
+        let offset = HEADER_LENGTH as u64
+            + u64::from(header.tree_length)
+            + u64::from(header.embed_chunk_length);
+        let total: u64 = lengths.iter().copied().map(u64::from).sum();
+        check_limit(
+            self.options.limits,
+            "length of the hash sections",
+            total,
+            MAX_TREE_LENGTH,
+        )?;
+        let mut sections = vec![0; total as usize];
+        self.file.seek(SeekFrom::Start(offset))?;
+        self.file.read_exact(&mut sections)?;
+        Ok((lengths, sections))
+    }
+
+    /// Paths which the tree lists more than once, in the order they were encountered
+    pub(crate) fn duplicates(&self) -> &[String] {
+        &self.duplicates
+    }
+
+    /// Inconsistencies between the header, the tree and the file found while reading
+    pub(crate) fn issues(&self) -> &[String] {
+        &self.issues
+    }
+
+    /// Check the MD5 hashes in the self hashes section against the contents of the VPK.
+    /// Returns whether the tree, chunk hashes and file hashes match, or `None` if the VPK
+    /// has no self hashes section.
+    pub(crate) fn verify_hashes(&mut self) -> io::Result<Option<[bool; 3]>> {
+        let (lengths, sections) = self.trailing_sections()?;
+        if lengths[1] != 48 {
+            return Ok(None);
+        }
+        let chunk_hashes = &sections[..lengths[0] as usize];
+        let self_hashes = &sections[lengths[0] as usize..][..48];
+
+        let tree_digest = Md5::digest(&self.data.get_ref()[HEADER_LENGTH..]);
+        let chunk_hashes_digest = Md5::digest(chunk_hashes);
+
+        // The file hash covers everything up to itself
+        let header = self.header.as_ref().unwrap();
+        let mut file_checksum = Md5::new();
+        file_checksum.update(self.data.get_ref());
+        self.file.seek(SeekFrom::Start(
+            HEADER_LENGTH as u64 + u64::from(header.tree_length),
+        ))?;
+        io::copy(
+            &mut (&mut self.file).take(u64::from(header.embed_chunk_length)),
+            &mut file_checksum,
+        )?;
+        file_checksum.update(chunk_hashes);
+        file_checksum.update(&self_hashes[..32]);
+
+        Ok(Some([
+            tree_digest.as_slice() == &self_hashes[0..16],
+            chunk_hashes_digest.as_slice() == &self_hashes[16..32],
+            file_checksum.finalize().as_slice() == &self_hashes[32..48],
+        ]))
     }
 
-    /// Use the created file path, metadata pairs to load the contents of each file into memory
-    fn load_file_data(&mut self) {
-        for (path, metadata) in &self.index {
-            let file_length = metadata.file_length + u32::from(metadata.preload_length);
+    /// Check only the tree against its MD5 hash in the self hashes section, without reading the
+    /// file data. Returns whether it matches, or `None` if the VPK has no self hashes section.
+    pub(crate) fn verify_tree_hash(&mut self) -> io::Result<Option<bool>> {
+        let (lengths, sections) = self.trailing_sections()?;
+        if lengths[1] != 48 {
+            return Ok(None);
+        }
+        let self_hashes = &sections[lengths[0] as usize..][..48];
+        let tree_digest = Md5::digest(&self.data.get_ref()[HEADER_LENGTH..]);
+        Ok(Some(tree_digest.as_slice() == &self_hashes[0..16]))
+    }
 
-            self.data.set_position(metadata.archive_offset.into());
-            let mut file_data = vec![b'0'; file_length.try_into().unwrap()];
-            self.data.read_exact(&mut file_data).unwrap();
-            self.files.insert(path.to_string(), file_data);
+    /// Iterate over the path and metadata of every file in the VPK
+    pub fn entries(&self) -> impl Iterator<Item = (&String, &Entry)> {
+        self.index.iter()
+    }
+
+    /// Get the metadata of a single file in the VPK
+    pub fn entry(&self, path: &str) -> Option<&Entry> {
+        self.index.get(path)
+    }
+
+    /// Read the full contents of a single file in the VPK. LZMA-compressed entries are
+    /// decompressed. Fails for entries larger than `MAX_ENTRY_LENGTH` unless limits are
+    /// disabled.
+    pub fn read_entry(&mut self, path: &str) -> io::Result<Vec<u8>> {
+        if let Some(metadata) = self.index.get(path) {
+            check_limit(
+                self.options.limits,
+                &format!("size of {}", path),
+                metadata.entry_length(),
+                MAX_ENTRY_LENGTH,
+            )?;
         }
+        let file_data = self.read_entry_at(path, 0, usize::MAX)?;
+        if lzma::is_compressed(&file_data) {
+            check_limit(
+                self.options.limits,
+                &format!("decompressed size of {}", path),
+                u64::from(lzma::decompressed_length(&file_data)),
+                MAX_ENTRY_LENGTH,
+            )?;
+            return lzma::decompress(&file_data);
+        }
+        Ok(file_data)
+    }
+
+    /// Read up to `size` bytes of a single file in the VPK, starting at `offset` within
+    /// that file. Preload data stored in the tree is served before the archive data.
+    /// The data is returned as stored, compressed entries are not decompressed.
+    pub(crate) fn read_entry_at(
+        &mut self,
+        path: &str,
+        offset: u64,
+        size: usize,
+    ) -> io::Result<Vec<u8>> {
+        let metadata = self
+            .index
+            .get(path)
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, path.to_owned()))?;
+        let preload_length = metadata.preload().len() as u64;
+        let end = offset
+            .saturating_add(size as u64)
+            .min(metadata.entry_length());
+        if offset >= end {
+            return Ok(Vec::new());
+        }
+
+        let mut file_data = Vec::with_capacity((end - offset) as usize);
+        if offset < preload_length {
+            file_data.extend_from_slice(
+                &metadata.preload()[offset as usize..end.min(preload_length) as usize],
+            );
+        }
+        if end > preload_length {
+            let start = offset.max(preload_length) - preload_length;
+            let mut archive_data = vec![b'0'; (end - preload_length - start) as usize];
+            crash::offset(&self.path, u64::from(metadata.archive_offset) + start);
+            self.file
+                .seek(SeekFrom::Start(u64::from(metadata.archive_offset) + start))?;
+            self.file.read_exact(&mut archive_data)?;
+            file_data.append(&mut archive_data);
+        }
+        Ok(file_data)
+    }
+
+    /// Write a single file in the VPK to `save_dir`, keeping its path within the VPK.
+    /// Returns the path of the written file and whether its data matches the CRC stored in
+    /// the tree. Files failing the check are written anyway, so what is left of a damaged
+    /// archive can be recovered. Fails for paths which would escape `save_dir`.
+    pub(crate) fn extract_entry(
+        &mut self,
+        path: &str,
+        save_dir: &Path,
+    ) -> io::Result<(PathBuf, bool)> {
+        let fpath = extraction_path(save_dir, path)?;
+        let file_data = self.read_entry(path)?;
+        let intact = self.index[path].crc32 == crc32(&file_data);
+        if let Some(fparent) = fpath.parent() {
+            create_dir_all(fparent)?;
+        }
+        std::fs::write(&fpath, file_data)?;
+        Ok((fpath, intact))
     }
 
     /// Save extracted files in tree to Disk
@@ -234,7 +729,9 @@ impl VPK {
     /// ```
     fn _save_file_data(&self, save_dir: &Path) {
         for (path, file_data) in &self.files {
-            let fpath = save_dir.join(Path::new(path));
+            let Ok(fpath) = extraction_path(save_dir, path) else {
+                continue;
+            };
             let fparent = fpath.parent().unwrap();
             create_dir_all(fparent).unwrap();
             std::fs::write(fpath, file_data).unwrap();
@@ -242,32 +739,119 @@ impl VPK {
     }
 }
 
+/// Whether the entry path `path` stays inside the directory it is extracted to: it must be
+/// relative, without `..` components, and without a drive or stream separator (`:`)
+pub(crate) fn is_safe_path(path: &str) -> bool {
+    !path.is_empty()
+        && !path.starts_with(['/', '\\'])
+        && !path.contains(':')
+        && !path.split(['/', '\\']).any(|component| component == "..")
+}
+
+/// Path the entry at `path` is extracted to inside `save_dir`. Fails for paths which would
+/// escape it, see `is_safe_path()`.
+fn extraction_path(save_dir: &Path, path: &str) -> io::Result<PathBuf> {
+    if !is_safe_path(path) {
+        return Err(invalid_data(format!(
+            "{} is absolute or leaves its directory, refusing to extract it",
+            path
+        )));
+    }
+    Ok(save_dir.join(path))
+}
+
+/// A file to be written by `create_vpk_from()`
+/// # Properties
+/// - `crc32: u32` = CRC32 checksum of the contents of the file
+/// - `bytes: Vec<u8>` = The contents of the file, or the bytes stored for it in an earlier
+///   VPK if `stored` is set
+/// - `stored: bool` = Whether `bytes` are copied as they are from a VPK written with the same
+///   options, skipping compression
+pub(crate) struct PackedFile {
+    pub(crate) crc32: u32,
+    pub(crate) bytes: Vec<u8>,
+    pub(crate) stored: bool,
+}
+
+impl PackedFile {
+    /// A file with the given contents, which are checksummed here and compressed when written
+    pub(crate) fn new(contents: Vec<u8>) -> PackedFile {
+        PackedFile {
+            crc32: crc32(&contents),
+            bytes: contents,
+            stored: false,
+        }
+    }
+}
+
 /// Create a Vector containing the bytes of a compiled VPK file containing the data given
 /// as `vpk_data` in the form of a HashMap containing the file path and
-/// binary data of each file.
-fn create_vpk(vpk_data: HashMap<String, Vec<u8>>) -> Vec<u8> {
-    let mut tree: HashMap<String, HashMap<String, Vec<String>>> = HashMap::new();
+/// binary data of each file. If `options.compress` is set, each file is stored
+/// LZMA-compressed. The CRC of a file is always calculated over the uncompressed data.
+/// Self hashes are only written if `options.hash_sections` is `HashSections::Rehash`.
+/// Fails for paths which cannot be stored in a tree, see `tree_parts()`.
+pub(crate) fn create_vpk(
+    vpk_data: HashMap<String, Vec<u8>>,
+    options: &VpkOptions,
+) -> io::Result<Vec<u8>> {
+    let vpk_data = vpk_data
+        .into_iter()
+        .map(|(path, contents)| (path, PackedFile::new(contents)))
+        .collect();
+    create_vpk_from(vpk_data, options)
+}
+
+/// Split `path` into the extension, directory and name it is stored under in the tree, the
+/// reverse of `VPK::populate_index()`: files in the root are stored in the directory `" "`,
+/// and files without an extension under the extension `" "`. Fails for paths with an empty
+/// directory or name, or containing a null byte, since the tree could not be read back.
+fn tree_parts(path: &str) -> io::Result<(String, String, String)> {
+    let invalid = || {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("{:?} cannot be stored in a VPK", path),
+        )
+    };
+    if path.contains('\0') {
+        return Err(invalid());
+    }
+    let (dir, file) = match path.rsplit_once('/') {
+        Some((dir, file)) => (dir, file),
+        None => (" ", path),
+    };
+    let (name, ext) = match file.rsplit_once('.') {
+        Some((name, ext)) if !name.is_empty() && !ext.is_empty() => (name, ext),
+        _ => (file, " "),
+    };
+    if dir.is_empty() || name.is_empty() {
+        return Err(invalid());
+    }
+    Ok((ext.to_owned(), dir.to_owned(), name.to_owned()))
+}
+
+/// Names of the files in a tree by extension and directory, along with the path of the file
+/// each stands for
+type Tree<'a> = BTreeMap<String, BTreeMap<String, Vec<(String, &'a String)>>>;
+
+/// Create the bytes of a VPK file like `create_vpk()`, from files which may already be
+/// checksummed and stored, see `PackedFile`
+pub(crate) fn create_vpk_from(
+    vpk_data: HashMap<String, PackedFile>,
+    options: &VpkOptions,
+) -> io::Result<Vec<u8>> {
+    let mut tree: Tree = BTreeMap::new();
 
     // Create Tree using File List
     for file in vpk_data.keys() {
-        let fpath = Path::new(&file);
-        let ext = fpath.extension().unwrap().to_str().unwrap().to_owned();
-        let dir = fpath.parent().unwrap().to_str().unwrap().to_owned();
-        let name = fpath.file_stem().unwrap().to_str().unwrap().to_owned();
-
-        if tree.contains_key(&ext) {
-            if tree.get(&ext).unwrap().contains_key(&dir) {
-                tree.get_mut(&ext)
-                    .unwrap()
-                    .get_mut(&dir)
-                    .unwrap()
-                    .push(name);
-            } else {
-                tree.get_mut(&ext).unwrap().insert(dir, vec![name]);
-            }
-        } else {
-            tree.insert(ext, HashMap::from([(dir, vec![name])]));
-        }
+        let (ext, dir, name) = tree_parts(file)?;
+        tree.entry(ext)
+            .or_default()
+            .entry(dir)
+            .or_default()
+            .push((name, file));
+    }
+    for files in tree.values_mut().flat_map(BTreeMap::values_mut) {
+        files.sort();
     }
 
     // Calculate Tree Length
@@ -278,7 +862,7 @@ fn create_vpk(vpk_data: HashMap<String, Vec<u8>>) -> Vec<u8> {
         for dir in tree.get(ext).unwrap().keys() {
             tree_length += dir.len() as u32 + 2_u32;
 
-            for file in tree.get(ext).unwrap().get(dir).unwrap() {
+            for (file, _) in tree.get(ext).unwrap().get(dir).unwrap() {
                 tree_length += file.len() as u32 + 19_u32;
             }
         }
@@ -291,63 +875,81 @@ fn create_vpk(vpk_data: HashMap<String, Vec<u8>>) -> Vec<u8> {
     let mut embed_chunk_length: u32 = 0;
 
     for (ext, dir) in tree {
-        tree_cursor.write(format!("{ext}\0").as_bytes()).unwrap();
+        tree_cursor
+            .write_all(format!("{ext}\0").as_bytes())
+            .unwrap();
 
         for (dirname, files) in dir {
             tree_cursor
-                .write(format!("{dirname}\0").as_bytes())
+                .write_all(format!("{dirname}\0").as_bytes())
                 .unwrap();
 
-            for file in files {
-                tree_cursor.write(format!("{file}\0").as_bytes()).unwrap();
+            for (file, path) in files {
+                tree_cursor
+                    .write_all(format!("{file}\0").as_bytes())
+                    .unwrap();
 
                 // Write Metadata
                 let file_offset = data_offset;
-                let filename = if ext.len() > 0 {
-                    format!("{file}.{ext}")
+                let packed = &vpk_data[path];
+                let compressed;
+                let filedata = if options.compress && !packed.stored {
+                    // Refused before building without the "lzma" feature, see
+                    // `lzma::check_compress()`
+                    compressed = lzma::compress(&packed.bytes)?;
+                    &compressed
                 } else {
-                    file
+                    &packed.bytes
                 };
-
-                let filedata = vpk_data.get(&format!("{dirname}/{filename}")).unwrap();
                 let file_length = filedata.len() as u32;
-                let mut data_hash = CRC32.digest();
-                data_hash.update(filedata);
 
-                tree_cursor
-                    .write(&data_hash.finalize().to_le_bytes())
-                    .unwrap(); // crc32
-                tree_cursor.write(&0_u16.to_le_bytes()).unwrap(); // preload_length
-                tree_cursor.write(&32767_u16.to_le_bytes()).unwrap(); // archive_index
+                tree_cursor.write_all(&packed.crc32.to_le_bytes()).unwrap(); // crc32
+                tree_cursor.write_all(&0_u16.to_le_bytes()).unwrap(); // preload_length
+                tree_cursor.write_all(&32767_u16.to_le_bytes()).unwrap(); // archive_index
                 let archive_offset: u32 = file_offset - tree_length - HEADER_LENGTH as u32;
-                tree_cursor.write(&archive_offset.to_le_bytes()).unwrap(); // archive_offset
-                tree_cursor.write(&file_length.to_le_bytes()).unwrap(); // file_length
-                tree_cursor.write(&65535_u16.to_le_bytes()).unwrap();
+                tree_cursor
+                    .write_all(&archive_offset.to_le_bytes())
+                    .unwrap(); // archive_offset
+                tree_cursor.write_all(&file_length.to_le_bytes()).unwrap(); // file_length
+                tree_cursor.write_all(&65535_u16.to_le_bytes()).unwrap();
 
                 embed_chunk_length += file_length;
                 data_offset += file_length;
-                data_cursor.write(filedata).unwrap();
+                data_cursor.write_all(filedata).unwrap();
             }
             // Next dir
-            tree_cursor.write("\0".as_bytes()).unwrap();
+            tree_cursor.write_all("\0".as_bytes()).unwrap();
         }
         // Next ext
-        tree_cursor.write("\0".as_bytes()).unwrap();
+        tree_cursor.write_all("\0".as_bytes()).unwrap();
     }
     // End of tree
-    tree_cursor.write("\0".as_bytes()).unwrap();
+    tree_cursor.write_all("\0".as_bytes()).unwrap();
 
     // Create Header
     let mut header_cursor = Cursor::new(Vec::new());
-    header_cursor.write(&0x55aa1234_u32.to_le_bytes()).unwrap(); // signature
-    header_cursor.write(&2_u32.to_le_bytes()).unwrap(); // version
-    header_cursor.write(&tree_length.to_le_bytes()).unwrap(); // tree_length
     header_cursor
-        .write(&embed_chunk_length.to_le_bytes())
+        .write_all(&0x55aa1234_u32.to_le_bytes())
+        .unwrap(); // signature
+    header_cursor.write_all(&2_u32.to_le_bytes()).unwrap(); // version
+    header_cursor.write_all(&tree_length.to_le_bytes()).unwrap(); // tree_length
+    header_cursor
+        .write_all(&embed_chunk_length.to_le_bytes())
         .unwrap(); // embed_chunk_length
-    header_cursor.write(&0_u32.to_le_bytes()).unwrap(); // chunk_hashes_length
-    header_cursor.write(&48_u32.to_le_bytes()).unwrap(); // self_hashes_length
-    header_cursor.write(&0_u32.to_le_bytes()).unwrap(); // signature_length
+    let rehash = matches!(options.hash_sections, HashSections::Rehash);
+    let self_hashes_length: u32 = if rehash { 48 } else { 0 };
+    header_cursor.write_all(&0_u32.to_le_bytes()).unwrap(); // chunk_hashes_length
+    header_cursor
+        .write_all(&self_hashes_length.to_le_bytes())
+        .unwrap(); // self_hashes_length
+    header_cursor.write_all(&0_u32.to_le_bytes()).unwrap(); // signature_length
+
+    let mut file = header_cursor.get_ref().clone();
+    file.extend_from_slice(tree_cursor.get_ref());
+    file.extend_from_slice(data_cursor.get_ref());
+    if !rehash {
+        return Ok(file);
+    }
 
     // Calculate Hashes
     let mut tree_checksum = Md5::new();
@@ -359,73 +961,605 @@ fn create_vpk(vpk_data: HashMap<String, Vec<u8>>) -> Vec<u8> {
     file_checksum.update(data_cursor.get_ref());
     let tree_digest = tree_checksum.finalize();
     let chunk_hashes_checksum_digest = chunk_hashes_checksum.finalize();
-    file_checksum.update(&tree_digest);
-    file_checksum.update(&chunk_hashes_checksum_digest);
+    file_checksum.update(tree_digest);
+    file_checksum.update(chunk_hashes_checksum_digest);
     let mut hashes = tree_digest.to_vec();
     hashes.append(&mut chunk_hashes_checksum_digest.to_vec());
     hashes.append(&mut file_checksum.finalize().to_vec());
 
-    // Combine Cursors to file
-    let mut file = header_cursor.into_inner();
-    file.append(&mut tree_cursor.into_inner());
-    file.append(&mut data_cursor.into_inner());
     file.append(&mut hashes);
 
-    file
+    Ok(file)
+}
+
+/// CRC32 checksum of a file as stored in the tree of a VPK
+pub(crate) fn crc32(data: &[u8]) -> u32 {
+    CRC32.checksum(data)
+}
+
+/// Rewrite the VPK at `in_path` to `out_path` with this program's writer, reading and
+/// writing it according to `options`. Returns the number of files copied and, when
+/// preserving the hash sections, whether the preserved tree hash still matches the
+/// rewritten tree.
+pub(crate) fn copy_vpk(
+    in_path: PathBuf,
+    out_path: &Path,
+    options: &VpkOptions,
+) -> io::Result<(usize, Option<bool>)> {
+    let mut vpk = VPK::new(in_path, *options)?;
+    vpk.read()?;
+    let (lengths, sections) = vpk.trailing_sections()?;
+    let file_count = vpk.files.len();
+
+    let mut file = create_vpk(vpk.files, options)?;
+    let tree_length = u32::from_le_bytes(file[8..12].try_into().unwrap()) as usize;
+    let mut tree_matches = None;
+    if matches!(options.hash_sections, HashSections::Preserve) {
+        file[16..20].copy_from_slice(&lengths[0].to_le_bytes()); // chunk_hashes_length
+        file[20..24].copy_from_slice(&lengths[1].to_le_bytes()); // self_hashes_length
+        file[24..28].copy_from_slice(&lengths[2].to_le_bytes()); // signature_length
+        if lengths[1] == 48 {
+            let self_hashes = &sections[lengths[0] as usize..][..16];
+            let tree_digest = Md5::digest(&file[HEADER_LENGTH..HEADER_LENGTH + tree_length]);
+            tree_matches = Some(tree_digest.as_slice() == self_hashes);
+        }
+        file.extend_from_slice(&sections);
+    }
+
+    if let Some(parent) = out_path.parent() {
+        create_dir_all(parent)?;
+    }
+    std::fs::write(out_path, file)?;
+    Ok((file_count, tree_matches))
 }
 
-/// Patch the target VPK with files from the base VPK. The `vmap_c` file in the target is
-/// renamed to `dota.vmap_c` and retained. Files from the base VPK which are not found in the
-/// target VPK will be added to the target. Returns the patched target VPK as a HashMap
-/// containing the file paths and binary file data for each file within.
+/// How `merge_terrain()` patches a terrain
+/// # Properties
+/// - `overrides: HashMap<String, Vec<u8>>` = Files replacing the patched data
+/// - `rules: &[Rule]` = Rules applied to the entries of the terrain
+/// - `map_aliases: &[String]` = Additional names the renamed map is written under
+/// - `terrain_minimap: bool` = Replace the minimap of the base map with the terrain's own
+/// - `downscale: Option<u16>` = Largest texture size kept from the terrain, larger textures
+///   lose their largest mip levels
+/// - `recolor: Option<Recolor>` = Shift applied to the colors of the terrain's ground textures
+/// - `limits: bool` = Refuse a base map or terrain claiming implausible sizes, see
+///   `VpkOptions`
+pub(crate) struct PatchOptions<'a> {
+    pub(crate) overrides: HashMap<String, Vec<u8>>,
+    pub(crate) rules: &'a [Rule],
+    pub(crate) map_aliases: &'a [String],
+    pub(crate) terrain_minimap: bool,
+    pub(crate) downscale: Option<u16>,
+    pub(crate) recolor: Option<Recolor>,
+    pub(crate) limits: bool,
+}
+
+/// Patch the target VPK with files from the base VPK, the map named `base_name`. The entries
+/// of the target are renamed according to `options.rules`, by default only its `vmap_c` file
+/// is renamed after the base map (`dota.vmap_c`). Files
+/// from the base VPK which are not found in the target VPK will be added to the target. The
+/// renamed map is also written as `maps/<alias>.vmap_c` for every name in
+/// `options.map_aliases`. If `options.terrain_minimap` is set, the minimap files the target
+/// ships for its own map replace the ones of the base map. With `options.downscale`, oversized
+/// textures of the target are downscaled, and with `options.recolor`, its ground textures are
+/// recolored. The source of every file is recorded in `manifest`.
+/// Returns the patched target VPK as a HashMap containing the file paths
+/// and binary file data for each file within.
 fn patch_vpk(
     base: HashMap<String, Vec<u8>>,
     mut target: HashMap<String, Vec<u8>>,
+    base_name: &str,
+    options: &PatchOptions,
+    manifest: &mut Manifest,
 ) -> HashMap<String, Vec<u8>> {
-    // Rename vmap_c in target to dota.vmap_c
-    let mut target_vmap = String::new();
-    for fpath in target.keys() {
-        if fpath.ends_with(".vmap_c") {
-            target_vmap.push_str(fpath);
-            break;
+    if options.terrain_minimap {
+        let map_name = target
+            .keys()
+            .find(|fpath| fpath.ends_with(".vmap_c"))
+            .and_then(|fpath| Path::new(fpath).file_stem())
+            .and_then(|stem| stem.to_str())
+            .map(str::to_owned);
+        if let Some(map_name) = map_name {
+            minimap::inject(&mut target, &map_name, base_name);
         }
     }
-    let vmap_data = target.remove(&target_vmap).unwrap();
-    target.insert(
-        Path::new(&target_vmap)
-            .with_file_name("dota.vmap_c")
-            .to_str()
-            .unwrap()
-            .to_string(),
-        vmap_data,
-    );
+
+    rules::apply(options.rules, &mut target, &base);
+    if let Some(map) = target.get(&map_path(base_name)).cloned() {
+        for alias in options.map_aliases {
+            target.insert(format!("maps/{}.vmap_c", alias), map.clone());
+        }
+    }
+
+    for (fpath, data) in target.iter_mut() {
+        if !fpath.ends_with(".vtex_c") {
+            continue;
+        }
+        if let Some(downscaled) = options
+            .downscale
+            .and_then(|max_size| texture::downscale(data, max_size))
+        {
+            *data = downscaled;
+            manifest.downscaled.push(fpath.clone());
+        }
+        if let Some(recolored) = options
+            .recolor
+            .filter(|_| texture::is_ground_texture(fpath))
+            .and_then(|recolor| texture::recolor(data, &recolor))
+        {
+            *data = recolored;
+            manifest.recolored.push(fpath.clone());
+        }
+    }
+    manifest.downscaled.sort();
+    manifest.recolored.sort();
+
+    for (fpath, data) in &base {
+        manifest.add(fpath, Source::Base);
+        manifest.set_base_crc32(fpath, crc32(data));
+    }
+    for fpath in target.keys() {
+        manifest.add(fpath, Source::Terrain);
+    }
 
     // Add files from base to target
     for (fpath, data) in base {
-        if !target.contains_key(&fpath) {
-            target.insert(fpath, data);
-        }
+        target.entry(fpath).or_insert(data);
     }
 
     target
 }
 
-/// Unpacks the base terrain (`dota.vpk`) given as `base_path` and the custom terrain given
+/// Unpacks the base map (usually `dota.vpk`) given as `base_path` and the custom terrain given
 /// as `target_path`. Unpacking occurs in parallel via multi-processing.
-/// Patches the the target file with the base data in `dota.vpk`
-/// Creates a VPK file using the patched data, and returns the vector containing the binary data
-/// for the resulting VPK.
-pub fn create_terrain(base_path: PathBuf, target_path: PathBuf) -> Vec<u8> {
+/// Patches the the target file with the data of the base map according to `options`, see
+/// `patch_vpk()`. Files in `options.overrides` replace the patched data.
+/// Returns the patched files, keyed by their path, along with the manifest recording where
+/// each file came from. Fails if either VPK cannot be read.
+pub(crate) fn merge_terrain(
+    base_path: PathBuf,
+    target_path: PathBuf,
+    options: PatchOptions,
+) -> io::Result<(HashMap<String, Vec<u8>>, Manifest)> {
+    let mut manifest = Manifest::new(&target_path.file_name().unwrap().to_string_lossy());
+    let base_name = base_path
+        .file_stem()
+        .unwrap()
+        .to_string_lossy()
+        .into_owned();
+    let vpk_options = VpkOptions {
+        limits: options.limits,
+        ..VpkOptions::default()
+    };
     let (tx, rx) = mpsc::channel();
-    let mut base_vpk = VPK::new(base_path);
     thread::spawn(move || {
-        let mut target_vpk = VPK::new(target_path);
-        target_vpk.read();
+        let target_vpk = VPK::new(target_path, vpk_options).and_then(|mut target_vpk| {
+            target_vpk.read()?;
+            Ok(target_vpk)
+        });
         tx.send(target_vpk).unwrap();
     });
-    base_vpk.read();
-    let target_vpk = rx.recv().unwrap();
+    let mut base_vpk = VPK::new(base_path, vpk_options)?;
+    base_vpk.read()?;
+    let target_vpk = rx.recv().unwrap()?;
+
+    let mut out_data = patch_vpk(
+        base_vpk.files,
+        target_vpk.files,
+        &base_name,
+        &options,
+        &mut manifest,
+    );
+    for fpath in options.overrides.keys() {
+        manifest.add(fpath, Source::Overrides);
+    }
+    out_data.extend(options.overrides);
+    Ok((out_data, manifest))
+}
+
+/// Error for a VPK whose contents do not match the format
+fn invalid_data(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
 
-    let out_data = patch_vpk(base_vpk.files, target_vpk.files);
-    create_vpk(out_data)
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Path of a file named `name` in a directory of this test process, removed beforehand
+    fn temp_path(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("dota-terrain-mod-vpk-{}", std::process::id()));
+        create_dir_all(&dir).unwrap();
+        let path = dir.join(name);
+        let _ = std::fs::remove_file(&path);
+        path
+    }
+
+    /// Files keyed by their path, as taken by `create_vpk()`
+    fn files(files: &[(&str, &[u8])]) -> HashMap<String, Vec<u8>> {
+        files
+            .iter()
+            .map(|(path, contents)| (path.to_string(), contents.to_vec()))
+            .collect()
+    }
+
+    /// An entry of a crafted tree, see `raw_vpk()`
+    struct RawEntry {
+        ext: &'static str,
+        dir: &'static str,
+        name: &'static str,
+        offset: u32,
+        length: u32,
+    }
+
+    /// The entry `dir/name.ext` of `length` bytes at `offset` in the embedded data
+    fn raw(path: (&'static str, &'static str, &'static str), offset: u32, length: u32) -> RawEntry {
+        let (dir, name, ext) = path;
+        RawEntry {
+            ext,
+            dir,
+            name,
+            offset,
+            length,
+        }
+    }
+
+    /// The bytes of a VPK whose tree lists `entries` as they are, each in its own extension
+    /// and directory block, followed by `data` as the embedded data. Unlike `create_vpk()`,
+    /// paths may repeat and entries may point anywhere. The header is returned separately as
+    /// its seven fields, to be tampered with before `join()`.
+    fn raw_vpk(entries: &[RawEntry], data: &[u8]) -> ([u32; 7], Vec<u8>) {
+        let mut tree = Vec::new();
+        for entry in entries {
+            for string in [entry.ext, entry.dir, entry.name] {
+                tree.extend_from_slice(string.as_bytes());
+                tree.push(0);
+            }
+            tree.extend_from_slice(&crc32(b"").to_le_bytes());
+            tree.extend_from_slice(&0_u16.to_le_bytes());
+            tree.extend_from_slice(&0x7fff_u16.to_le_bytes());
+            tree.extend_from_slice(&entry.offset.to_le_bytes());
+            tree.extend_from_slice(&entry.length.to_le_bytes());
+            tree.extend_from_slice(&0xffff_u16.to_le_bytes());
+            // End of the names, the directories and the extension
+            tree.extend_from_slice(&[0, 0]);
+        }
+        tree.push(0);
+        let header = [0x55aa1234, 2, tree.len() as u32, data.len() as u32, 0, 0, 0];
+        tree.extend_from_slice(data);
+        (header, tree)
+    }
+
+    /// Write a crafted VPK from the `header` and rest of `raw_vpk()` to a file named `name`
+    fn join(name: &str, header: [u32; 7], rest: &[u8]) -> PathBuf {
+        let mut file: Vec<u8> = header
+            .iter()
+            .flat_map(|field| field.to_le_bytes())
+            .collect();
+        file.extend_from_slice(rest);
+        let path = temp_path(name);
+        std::fs::write(&path, file).unwrap();
+        path
+    }
+
+    #[test]
+    fn crcs_are_the_ones_valve_writes() {
+        // The check value of CRC-32/ISO-HDLC, as computed by zlib's `crc32()`
+        assert_eq!(crc32(b"123456789"), 0xcbf43926);
+        assert_eq!(crc32(b""), 0);
+
+        // An entry as written by Valve's tools, with the zlib CRC of its contents
+        let contents = b"The quick brown fox jumps over the lazy dog";
+        let valve_crc = 0x414fa339_u32;
+        let entries = [raw(("maps", "dota", "txt"), 0, contents.len() as u32)];
+        let (header, mut rest) = raw_vpk(&entries, contents);
+        // The CRC follows the extension, directory and name of the only entry
+        let crc_offset = "txt\0maps\0dota\0".len();
+        rest[crc_offset..crc_offset + 4].copy_from_slice(&valve_crc.to_le_bytes());
+        let path = join("valve_crc.vpk", header, &rest);
+        let options = VpkOptions {
+            verify_crc: true,
+            ..VpkOptions::default()
+        };
+        let mut vpk = VPK::new(path.clone(), options).unwrap();
+        vpk.read().unwrap();
+        assert_eq!(vpk.entry("maps/dota.txt").unwrap().crc32(), valve_crc);
+        let save_dir = temp_path("valve_crc");
+        let (_, intact) = vpk.extract_entry("maps/dota.txt", &save_dir).unwrap();
+        assert!(intact);
+
+        // The files this tool writes carry the same CRC
+        let written = temp_path("written_crc.vpk");
+        let data = files(&[("maps/dota.txt", contents)]);
+        std::fs::write(&written, create_vpk(data, &VpkOptions::default()).unwrap()).unwrap();
+        let vpk = VPK::open(written).unwrap();
+        assert_eq!(vpk.entry("maps/dota.txt").unwrap().crc32(), valve_crc);
+
+        // A checksum of the POSIX variant is a mismatch
+        let cksum = Crc::<u32>::new(&crc::CRC_32_CKSUM).checksum(contents);
+        rest[crc_offset..crc_offset + 4].copy_from_slice(&cksum.to_le_bytes());
+        let path = join("cksum_crc.vpk", header, &rest);
+        let mut vpk = VPK::new(path, options).unwrap();
+        let error = vpk.read().err().unwrap();
+        assert_eq!(error.to_string(), "maps/dota.txt: CRC mismatch");
+    }
+
+    #[cfg(feature = "lzma")]
+    #[test]
+    fn compressed_entries_round_trip() {
+        let text = b"materials/ground.vmat_c ".repeat(200);
+        let data = files(&[("materials/ground.txt", &text), ("maps/dota.txt", b"")]);
+        let options = VpkOptions {
+            compress: true,
+            ..VpkOptions::default()
+        };
+        let path = temp_path("compressed.vpk");
+        std::fs::write(&path, create_vpk(data, &options).unwrap()).unwrap();
+
+        let verified = VpkOptions {
+            verify_crc: true,
+            ..VpkOptions::default()
+        };
+        let mut vpk = VPK::new(path, verified).unwrap();
+        vpk.read().unwrap();
+        let entry = vpk.entry("materials/ground.txt").unwrap();
+        // Stored compressed, while the CRC is the one of the uncompressed file
+        let (length, crc) = (entry.entry_length(), entry.crc32());
+        assert_eq!(crc, crc32(&text));
+        let stored = vpk
+            .read_entry_at("materials/ground.txt", 0, usize::MAX)
+            .unwrap();
+        assert_eq!(stored.len() as u64, length);
+        assert!(lzma::is_compressed(&stored));
+        assert_eq!(vpk.read_entry("materials/ground.txt").unwrap(), text);
+        assert_eq!(vpk.read_entry("maps/dota.txt").unwrap(), b"");
+    }
+
+    #[test]
+    fn duplicate_entries_follow_the_policy() {
+        let entries = [
+            raw(("maps", "dota", "txt"), 0, 5),
+            raw(("maps", "dota", "txt"), 5, 6),
+            raw(("maps", "other", "txt"), 0, 5),
+        ];
+        let (header, rest) = raw_vpk(&entries, b"firstsecond");
+        let path = join("duplicates.vpk", header, &rest);
+        let open = |duplicates| {
+            let options = VpkOptions {
+                duplicates,
+                ..VpkOptions::default()
+            };
+            VPK::open_with(path.clone(), options)
+        };
+
+        let error = open(DuplicatePolicy::Error).err().unwrap();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+        assert_eq!(error.to_string(), "duplicate entries: maps/dota.txt");
+
+        let mut first = open(DuplicatePolicy::KeepFirst).unwrap();
+        assert_eq!(first.duplicates(), ["maps/dota.txt"]);
+        assert_eq!(first.read_entry("maps/dota.txt").unwrap(), b"first");
+        let mut last = open(DuplicatePolicy::KeepLast).unwrap();
+        assert_eq!(last.duplicates(), ["maps/dota.txt"]);
+        assert_eq!(last.read_entry("maps/dota.txt").unwrap(), b"second");
+        assert_eq!(last.entries().count(), 2);
+    }
+
+    #[test]
+    fn inconsistent_trees_are_reported() {
+        let strict = VpkOptions {
+            strict: true,
+            ..VpkOptions::default()
+        };
+        let entries = [raw(("maps", "dota", "txt"), 0, 5)];
+        let (header, rest) = raw_vpk(&entries, b"dota!");
+        let consistent = join("consistent.vpk", header, &rest);
+        assert!(VPK::open_with(consistent, strict)
+            .unwrap()
+            .issues()
+            .is_empty());
+
+        // The tree ends before the header says
+        let mut long_tree = header;
+        long_tree[2] += 2;
+        let path = join("long_tree.vpk", long_tree, &rest);
+        let vpk = VPK::open(path.clone()).unwrap();
+        let tree_end = HEADER_LENGTH as u32 + header[2];
+        // The embedded data, and with it the entry, now appear shifted past the end
+        assert_eq!(
+            vpk.issues()[0],
+            format!(
+                "Tree ends at offset {} but the header declares it ends at {}",
+                tree_end,
+                tree_end + 2
+            )
+        );
+        let error = VPK::open_with(path, strict).err().unwrap();
+        assert!(
+            error.to_string().starts_with("inconsistent tree: "),
+            "{}",
+            error
+        );
+
+        // The embedded data is shorter than the header says
+        let mut long_data = header;
+        long_data[3] += 10;
+        let vpk = VPK::open(join("long_data.vpk", long_data, &rest)).unwrap();
+        assert_eq!(
+            vpk.issues(),
+            ["Header declares 15 bytes of embedded data but the file ends 10 bytes early"]
+        );
+
+        // An entry reaches past the embedded data
+        let entries = [raw(("maps", "dota", "txt"), 2, 5)];
+        let (header, rest) = raw_vpk(&entries, b"dota!");
+        let vpk = VPK::open(join("long_entry.vpk", header, &rest)).unwrap();
+        let data_start = HEADER_LENGTH as u32 + header[2];
+        assert_eq!(
+            vpk.issues(),
+            [format!(
+                "maps/dota.txt spans offsets {} to {}, past the end of the embedded data at {}",
+                data_start + 2,
+                data_start + 7,
+                data_start + 5
+            )]
+        );
+    }
+
+    #[test]
+    fn truncated_trees_are_refused() {
+        let entries = [raw(("maps", "dota", "txt"), 0, 5)];
+        let (header, rest) = raw_vpk(&entries, b"dota!");
+
+        let mut past_the_end = header;
+        past_the_end[2] = 1000;
+        let error = VPK::open(join("tree_past_end.vpk", past_the_end, &rest))
+            .err()
+            .unwrap();
+        assert_eq!(
+            error.to_string(),
+            "header declares a 1000 byte tree, which is longer than the file"
+        );
+
+        // Cut inside the metadata of the entry
+        let mut cut = header;
+        cut[2] = 20;
+        cut[3] = 0;
+        let error = VPK::open(join("tree_cut.vpk", cut, &rest[..20]))
+            .err()
+            .unwrap();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+        assert_eq!(
+            error.to_string(),
+            "tree ends inside the entry for maps/dota.txt"
+        );
+
+        // A name without its terminator
+        let mut unterminated = header;
+        unterminated[2] = 7;
+        unterminated[3] = 0;
+        let error = VPK::open(join("tree_unterminated.vpk", unterminated, &rest[..7]))
+            .err()
+            .unwrap();
+        assert!(
+            error
+                .to_string()
+                .starts_with("tree ends without a terminator"),
+            "{}",
+            error
+        );
+    }
+
+    #[test]
+    fn implausible_sizes_are_refused_unless_limits_are_off() {
+        let entries = [
+            raw(("maps", "first", "bin"), 0, 0xf000_0000),
+            raw(("maps", "second", "bin"), 0, 0xf000_0000),
+            raw(("maps", "third", "bin"), 0, 0xf000_0000),
+        ];
+        let (header, rest) = raw_vpk(&entries, b"");
+        let path = join("implausible.vpk", header, &rest);
+        let error = VPK::open(path.clone()).err().unwrap();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+        assert!(
+            error
+                .to_string()
+                .starts_with("total size of the entries (12079595520) exceeds the limit"),
+            "{}",
+            error
+        );
+
+        let args = ["verify", "implausible.vpk", "--no-limits"].map(String::from);
+        let no_limits = VpkOptions::from_args(&Args::parse_from(args).unwrap());
+        let mut vpk = VPK::open_with(path, no_limits).unwrap();
+        assert_eq!(vpk.entries().count(), 3);
+        assert_eq!(vpk.issues().len(), 3);
+
+        // A single entry over the limit is refused before it is read into memory
+        vpk.options.limits = true;
+        let error = vpk.read_entry("maps/first.bin").unwrap_err();
+        assert!(
+            error
+                .to_string()
+                .starts_with("size of maps/first.bin (4026531840)"),
+            "{}",
+            error
+        );
+    }
+
+    #[test]
+    fn root_and_extensionless_files_round_trip() {
+        let contents: [(&str, &[u8]); 5] = [
+            ("readme.txt", b"root"),
+            ("LICENSE", b"root without extension"),
+            ("bin/run", b"without extension"),
+            ("maps/dota.vmap_c", b"map"),
+            ("maps/.hidden", b"dot file"),
+        ];
+        let source = temp_path("round_trip.vpk");
+        let options = VpkOptions::default();
+        std::fs::write(&source, create_vpk(files(&contents), &options).unwrap()).unwrap();
+        let copy = temp_path("round_trip_copy.vpk");
+        let (count, _) = copy_vpk(source, &copy, &options).unwrap();
+        assert_eq!(count, contents.len());
+
+        let mut vpk = VPK::open(copy).unwrap();
+        assert!(vpk.issues().is_empty(), "{:?}", vpk.issues());
+        assert_eq!(vpk.entries().count(), contents.len());
+        for (path, data) in contents {
+            assert_eq!(vpk.read_entry(path).unwrap(), data, "{}", path);
+        }
+    }
+
+    #[test]
+    fn unstorable_paths_are_refused() {
+        for path in ["/absolute", "dir/", "a\0b"] {
+            let error = create_vpk(files(&[(path, b"")]), &VpkOptions::default()).unwrap_err();
+            assert_eq!(error.kind(), io::ErrorKind::InvalidInput, "{:?}", path);
+        }
+    }
+
+    #[test]
+    fn paths_leaving_the_directory_are_unsafe() {
+        for path in [
+            "maps/dota.vpk",
+            "materials\\ground.vmat_c",
+            "readme.txt",
+            "maps/..hidden/file.txt",
+            "maps/file..txt",
+        ] {
+            assert!(is_safe_path(path), "{:?}", path);
+        }
+        for path in [
+            "",
+            "/etc/passwd",
+            "\\Windows\\win.ini",
+            "../outside.txt",
+            "maps/../../outside.txt",
+            "maps/..",
+            "maps\\..\\..\\outside.txt",
+            "C:/Windows/win.ini",
+            "C:outside.txt",
+            "c:\\outside.txt",
+            "\\\\server\\share\\file.txt",
+            "//server/share/file.txt",
+            "\\\\?\\C:\\outside.txt",
+            "maps/file.txt:stream",
+        ] {
+            assert!(!is_safe_path(path), "{:?}", path);
+        }
+    }
+
+    #[test]
+    fn unsafe_paths_are_not_extracted() {
+        let save_dir = Path::new("out");
+        assert_eq!(
+            extraction_path(save_dir, "maps/dota.vmap_c").unwrap(),
+            save_dir.join("maps/dota.vmap_c")
+        );
+        for path in ["../outside.txt", "/etc/passwd", "C:\\outside.txt"] {
+            let error = extraction_path(save_dir, path).unwrap_err();
+            assert_eq!(error.kind(), io::ErrorKind::InvalidData, "{:?}", path);
+        }
+    }
 }
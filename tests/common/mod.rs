@@ -0,0 +1,213 @@
+// Shared by several test binaries, each using only some of it
+#![allow(dead_code)]
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    process::{Command, Output, Stdio},
+};
+
+/// Files of the synthetic base map, by their path inside the VPK
+const BASE_FILES: [(&str, &[u8]); 3] = [
+    ("maps/dota.vmap_c", b"base map"),
+    ("materials/ground.vmat_c", b"base ground"),
+    ("scripts/items.txt", b"base items"),
+];
+
+/// Files of the synthetic terrain, by their path inside the VPK
+const TERRAIN_FILES: [(&str, &[u8]); 2] = [
+    ("maps/dota_desert.vmap_c", b"desert map"),
+    ("materials/ground.vmat_c", b"desert ground"),
+];
+
+/// A temporary directory with a Steam installation, a Dota 2 game folder holding a synthetic
+/// base map and terrain, packed with the `pack` command, and a configuration directory. The
+/// program is pointed at it by `--steam-path` and `env()`, on every system. Removed when
+/// dropped.
+/// # Properties
+/// - `root: PathBuf` = The temporary directory
+/// - `library: PathBuf` = The Steam library holding the game, listed by Steam as it is
+///   unless `set_library()` lists another path
+pub struct FakeGame {
+    pub root: PathBuf,
+    pub library: PathBuf,
+}
+
+impl FakeGame {
+    pub fn new(name: &str) -> FakeGame {
+        FakeGame::with_library(name, "library")
+    }
+
+    /// A fake game like `new()`, whose Steam library is at `library` inside the temporary
+    /// directory, e.g. on another drive
+    pub fn with_library(name: &str, library: &str) -> FakeGame {
+        let root = std::env::temp_dir().join(format!(
+            "dota-terrain-mod-pipeline-{}-{}",
+            name,
+            std::process::id()
+        ));
+        let _ = fs::remove_dir_all(&root);
+        let game = FakeGame {
+            library: root.join(library),
+            root,
+        };
+        fs::create_dir_all(game.game_dir().join("dota/maps")).unwrap();
+        game.set_library(&game.library);
+        game.set_state_flags(4);
+        fs::write(
+            game.gameinfo(),
+            "\"GameInfo\"\n{\n\tgame\t\t\"Dota 2\"\n\tFileSystem\n\t{\n\t\tSearchPaths\n\t\t{\n\t\t\tGame_Language\t\tdota_*LANGUAGE*\n\t\t\tGame\t\t\t\tdota\n\t\t\tMod\t\t\t\t\tdota\n\t\t}\n\t}\n}\n",
+        )
+        .unwrap();
+        game.pack(
+            &BASE_FILES,
+            "base",
+            &game.game_dir().join("dota/maps/dota.vpk"),
+        );
+        game.pack(
+            &TERRAIN_FILES,
+            "terrain",
+            &game.game_dir().join("dota/maps/dota_desert.vpk"),
+        );
+        game
+    }
+
+    /// The game's `game` folder
+    pub fn game_dir(&self) -> PathBuf {
+        self.library.join("steamapps/common/dota 2 beta/game")
+    }
+
+    /// List `library` as Steam's only library, e.g. a symlink to the real one
+    pub fn set_library(&self, library: &Path) {
+        // Steam keeps the library list in config/ on Linux and in steamapps/ on Windows
+        let libraries = self
+            .steam_path()
+            .join(if cfg!(windows) { "steamapps" } else { "config" });
+        fs::create_dir_all(&libraries).unwrap();
+        fs::write(
+            libraries.join("libraryfolders.vdf"),
+            format!(
+                "\"libraryfolders\"\n{{\n\t\"0\"\n\t{{\n\t\t\"path\"\t\t\"{}\"\n\t\t\"apps\"\n\t\t{{\n\t\t\t\"570\"\t\t\"1000\"\n\t\t}}\n\t}}\n}}\n",
+                escape(library)
+            ),
+        )
+        .unwrap();
+    }
+
+    pub fn gameinfo(&self) -> PathBuf {
+        self.game_dir().join("dota/gameinfo.gi")
+    }
+
+    pub fn out_vpk(&self) -> PathBuf {
+        self.game_dir().join("dota_tempcontent/maps/dota.vpk")
+    }
+
+    /// Pack the terrain again with `files` in addition to its own
+    pub fn add_terrain_files(&self, files: &[(&str, &[u8])]) {
+        let files: Vec<(&str, &[u8])> = TERRAIN_FILES.iter().chain(files).copied().collect();
+        self.pack(
+            &files,
+            "terrain",
+            &self.game_dir().join("dota/maps/dota_desert.vpk"),
+        );
+    }
+
+    /// Write `files` to a folder named `name` and pack it into a VPK at `out`
+    fn pack(&self, files: &[(&str, &[u8])], name: &str, out: &Path) {
+        let dir = self.write_files(files, name);
+        let output = self.run(&[
+            "pack",
+            dir.to_str().unwrap(),
+            out.to_str().unwrap(),
+            "--yes",
+        ]);
+        assert!(output.status.success(), "{}", text(&output));
+    }
+
+    /// Write `files` to a folder named `name`, e.g. for `--overrides`
+    pub fn write_files(&self, files: &[(&str, &[u8])], name: &str) -> PathBuf {
+        let dir = self.root.join("sources").join(name);
+        for (path, data) in files {
+            let file = dir.join(path);
+            fs::create_dir_all(file.parent().unwrap()).unwrap();
+            fs::write(file, data).unwrap();
+        }
+        dir
+    }
+
+    /// Write the app manifest of the game with the `StateFlags` `flags`, 4 being installed
+    pub fn set_state_flags(&self, flags: u64) {
+        fs::write(
+            self.library.join("steamapps/appmanifest_570.acf"),
+            format!(
+                "\"AppState\"\n{{\n\t\"appid\"\t\t\"570\"\n\t\"installdir\"\t\t\"dota 2 beta\"\n\t\"StateFlags\"\t\t\"{}\"\n\t\"buildid\"\t\t\"1000\"\n}}\n",
+                flags
+            ),
+        )
+        .unwrap();
+    }
+
+    /// The fake Steam installation, given to the program with `--steam-path`
+    pub fn steam_path(&self) -> PathBuf {
+        self.root.join("steam")
+    }
+
+    /// Environment variables pointing the program at the fake home and configuration
+    /// directory
+    pub fn env(&self) -> [(&'static str, PathBuf); 3] {
+        [
+            ("HOME", self.root.join("home")),
+            ("XDG_CONFIG_HOME", self.root.join("config")),
+            ("APPDATA", self.root.join("config")),
+        ]
+    }
+
+    /// The configuration directory of the program
+    pub fn config_dir(&self) -> PathBuf {
+        self.root.join("config/dota-terrain-mod")
+    }
+
+    /// Run the program with `args` against the fake installation
+    pub fn run(&self, args: &[&str]) -> Output {
+        Command::new(env!("CARGO_BIN_EXE_dota-terrain-mod"))
+            .arg("--steam-path")
+            .arg(self.steam_path())
+            .args(args)
+            .envs(self.env())
+            .stdin(Stdio::null())
+            .output()
+            .unwrap()
+    }
+
+    /// Run the program with `args`, which has to succeed. Returns everything it printed.
+    pub fn run_ok(&self, args: &[&str]) -> String {
+        let output = self.run(args);
+        assert!(
+            output.status.success(),
+            "{:?} failed:\n{}",
+            args,
+            text(&output)
+        );
+        text(&output)
+    }
+}
+
+impl Drop for FakeGame {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.root);
+    }
+}
+
+/// `path` as written in Steam's VDF files and JSON, with backslashes escaped
+pub fn escape(path: &Path) -> String {
+    path.display().to_string().replace('\\', "\\\\")
+}
+
+/// Everything `output` printed, standard output first
+pub fn text(output: &Output) -> String {
+    format!(
+        "{}{}",
+        String::from_utf8_lossy(&output.stdout),
+        String::from_utf8_lossy(&output.stderr)
+    )
+}
@@ -0,0 +1,224 @@
+mod common;
+
+use std::fs;
+
+use common::{escape, text, FakeGame};
+use dota_terrain_mod::VPK;
+
+#[test]
+fn apply_status_and_panic_button() {
+    let game = FakeGame::new("vpk");
+    assert!(game.run_ok(&["status"]).contains("No terrain applied."));
+
+    game.run_ok(&["apply", "desert"]);
+    let mut output = VPK::open(game.out_vpk()).unwrap();
+    assert_eq!(
+        output.read_entry("maps/dota.vmap_c").unwrap(),
+        b"desert map"
+    );
+    assert_eq!(
+        output.read_entry("materials/ground.vmat_c").unwrap(),
+        b"desert ground"
+    );
+    assert!(output.entry("scripts/items.txt").unwrap().is_embedded());
+    assert!(game
+        .game_dir()
+        .join("dota_tempcontent/maps/dota-terrain-mod.json")
+        .is_file());
+    let status = game.run_ok(&["status"]);
+    assert!(
+        status.contains("Applied terrain: dota_desert.vpk"),
+        "{}",
+        status
+    );
+    assert!(status.contains("with --method vpk"), "{}", status);
+
+    game.run_ok(&["panic-button"]);
+    assert!(!game.out_vpk().exists());
+    assert!(game.run_ok(&["status"]).contains("No terrain applied."));
+}
+
+#[test]
+fn loose_install_is_removed_when_switching_methods() {
+    let game = FakeGame::new("loose");
+    let overrides = game.write_files(&[("materials/tweak.vmat_c", b"tweak")], "overrides");
+    let tempcontent = game.game_dir().join("dota_tempcontent");
+
+    game.run_ok(&[
+        "apply",
+        "desert",
+        "--method",
+        "loose",
+        "--overrides",
+        overrides.to_str().unwrap(),
+    ]);
+    assert!(!game.out_vpk().exists());
+    assert_eq!(
+        fs::read(tempcontent.join("materials/ground.vmat_c")).unwrap(),
+        b"desert ground"
+    );
+    assert!(tempcontent.join("materials/tweak.vmat_c").is_file());
+    assert!(game.run_ok(&["status"]).contains("with --method loose"));
+
+    game.run_ok(&["apply", "desert"]);
+    assert!(game.out_vpk().is_file());
+    assert!(!tempcontent.join("materials").exists());
+}
+
+#[test]
+fn archived_build_replaces_a_loose_install() {
+    let game = FakeGame::new("archive");
+    let archive = game.root.join("desert.tar");
+    game.run_ok(&["build", "desert", "--archive", archive.to_str().unwrap()]);
+    let tempcontent = game.game_dir().join("dota_tempcontent");
+
+    game.run_ok(&["apply", "desert", "--method", "loose"]);
+    assert!(tempcontent.join("materials/ground.vmat_c").is_file());
+
+    let output = game.run_ok(&["install-archive", archive.to_str().unwrap(), "--yes"]);
+    assert!(output.contains("Hashes: OK"), "{}", output);
+    let mut installed = VPK::open(game.out_vpk()).unwrap();
+    assert_eq!(
+        installed.read_entry("materials/ground.vmat_c").unwrap(),
+        b"desert ground"
+    );
+    // The loose files would still be loaded on top of the archived build
+    assert!(!tempcontent.join("materials").exists());
+    let status = game.run_ok(&["status"]);
+    assert!(status.contains("with --method vpk"), "{}", status);
+}
+
+/// A compiled DXT1 texture of 16x16 pixels with mip levels of 4x4, 8x8 and 16x16 pixels,
+/// 8 bytes per block of 4x4 pixels, stored smallest first after the texture data block
+fn texture() -> Vec<u8> {
+    let mut data = Vec::new();
+    data.extend_from_slice(&(16 + 12 + 40 + 168_u32).to_le_bytes());
+    data.extend_from_slice(&[12, 0, 0, 0, 8, 0, 0, 0, 1, 0, 0, 0]);
+    data.extend_from_slice(b"DATA");
+    data.extend_from_slice(&[8, 0, 0, 0, 40, 0, 0, 0]);
+    let mut header = [0; 40];
+    header[0] = 1;
+    header[20..24].copy_from_slice(&[16, 0, 16, 0]);
+    header[24] = 1;
+    header[26] = 1;
+    header[27] = 3;
+    header[32] = 8;
+    data.extend_from_slice(&header);
+    data.extend((0..168).map(|i| i as u8));
+    data
+}
+
+#[test]
+fn downscale_drops_large_mip_levels_of_the_terrain() {
+    let game = FakeGame::new("downscale");
+    let texture = texture();
+    game.add_terrain_files(&[("materials/ground_color.vtex_c", &texture)]);
+
+    let output = game.run_ok(&["apply", "desert", "--downscale", "8"]);
+    assert!(output.contains("1 texture(s) downscaled"), "{}", output);
+    let mut installed = VPK::open(game.out_vpk()).unwrap();
+    let downscaled = installed
+        .read_entry("materials/ground_color.vtex_c")
+        .unwrap();
+    // The 16x16 level is gone, the others are kept
+    assert_eq!(downscaled.len(), texture.len() - 128);
+    assert_eq!(&downscaled[68..], &texture[68..108]);
+    assert_eq!(&downscaled[48..52], &[8, 0, 8, 0]);
+    assert_eq!(downscaled[55], 2);
+}
+
+#[test]
+fn hybrid_install_reuses_the_packed_terrain() {
+    let game = FakeGame::new("hybrid");
+    let overrides = game.write_files(&[("materials/tweak.vmat_c", b"tweak")], "overrides");
+    let args = [
+        "apply",
+        "desert",
+        "--method",
+        "hybrid",
+        "--overrides",
+        overrides.to_str().unwrap(),
+    ];
+
+    game.run_ok(&args);
+    assert!(game.out_vpk().is_file());
+    let loose = game
+        .game_dir()
+        .join("dota_tempcontent/materials/tweak.vmat_c");
+    assert!(loose.is_file());
+
+    fs::write(overrides.join("materials/tweak.vmat_c"), b"tweaked again").unwrap();
+    let output = game.run_ok(&args);
+    assert!(
+        output.contains("The packed terrain is unchanged"),
+        "{}",
+        output
+    );
+    assert_eq!(fs::read(loose).unwrap(), b"tweaked again");
+}
+
+#[test]
+fn gameinfo_search_path_is_added_and_removed() {
+    let game = FakeGame::new("gameinfo");
+    let original = fs::read_to_string(game.gameinfo()).unwrap();
+
+    game.run_ok(&["apply", "desert", "--method", "gameinfo"]);
+    assert!(game
+        .game_dir()
+        .join("dota_terrain_mod/maps/dota.vpk")
+        .is_file());
+    let gameinfo = fs::read_to_string(game.gameinfo()).unwrap();
+    let mod_line = gameinfo.find("dota_terrain_mod").unwrap();
+    let game_line = gameinfo.find("Game\t\t\t\tdota\n").unwrap();
+    assert!(mod_line < game_line, "{}", gameinfo);
+
+    game.run_ok(&["panic-button"]);
+    assert_eq!(fs::read_to_string(game.gameinfo()).unwrap(), original);
+    assert!(!game.game_dir().join("dota_terrain_mod").exists());
+}
+
+#[test]
+fn apply_waits_for_steam_updates() {
+    let game = FakeGame::new("updating");
+    // Downloading an update
+    game.set_state_flags(1 << 20 | 4);
+
+    let output = game.run(&["apply", "desert"]);
+    assert!(!output.status.success());
+    assert!(text(&output).contains("TM-008"), "{}", text(&output));
+    assert!(!game.out_vpk().exists());
+
+    let output = game.run(&["apply", "desert", "--i-know-what-im-doing"]);
+    assert!(text(&output).contains("TM-008"), "{}", text(&output));
+    game.run_ok(&["apply", "desert", "--ignore-steam-state"]);
+    assert!(game.out_vpk().is_file());
+}
+
+#[test]
+fn interrupted_install_is_undone_on_the_next_start() {
+    let game = FakeGame::new("journal");
+    game.run_ok(&["apply", "desert"]);
+    let applied = fs::read(game.out_vpk()).unwrap();
+
+    // What a run killed while writing the output leaves behind
+    let backup = game
+        .game_dir()
+        .join("dota_tempcontent/maps/dota.vpk.journal-backup");
+    fs::rename(game.out_vpk(), &backup).unwrap();
+    fs::write(game.out_vpk(), b"half written").unwrap();
+    fs::write(
+        game.config_dir().join("journal.jsonl"),
+        format!(
+            "{{\"operation\":\"applying dota_desert.vpk\",\"started\":0,\"pid\":{}}}\n{{\"path\":\"{}\",\"backup\":\"{}\"}}\n",
+            u32::MAX,
+            escape(&game.out_vpk()),
+            escape(&backup)
+        ),
+    )
+    .unwrap();
+
+    let output = game.run_ok(&["status"]);
+    assert!(output.contains("were undone"), "{}", output);
+    assert_eq!(fs::read(game.out_vpk()).unwrap(), applied);
+    assert!(!backup.exists());
+}
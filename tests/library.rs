@@ -0,0 +1,67 @@
+//! The library applying terrains in-process. Kept apart from the tests starting the program,
+//! since it has to point this process's environment at the fake home directory.
+
+mod common;
+
+use std::fs;
+
+use common::FakeGame;
+use dota_terrain_mod::{Options, TerrainMod, VPK};
+
+#[test]
+fn library_applies_like_the_command() {
+    let game = FakeGame::new("library");
+    let overrides = game.write_files(&[("materials/tweak.vmat_c", b"tweak")], "overrides");
+    // The only test of this binary, so no other thread reads the environment meanwhile
+    for (name, value) in game.env() {
+        std::env::set_var(name, value);
+    }
+    // Options applying `terrain` in the fake installation
+    let terrain = |terrain: &str| Options {
+        steam_path: Some(game.steam_path()),
+        ..Options::new(terrain)
+    };
+
+    let options = Options {
+        method: Some(String::from("hybrid")),
+        overrides: vec![overrides],
+        ..terrain("desert")
+    };
+    let report = TerrainMod::apply(&options).unwrap();
+    assert_eq!(report.terrain, "dota_desert.vpk");
+    assert_eq!(report.method, "hybrid");
+    assert_eq!(report.output, Some(game.out_vpk()));
+    assert!(report.files.contains(
+        &game
+            .game_dir()
+            .join("dota_tempcontent/materials/tweak.vmat_c")
+    ));
+    assert_eq!(report.build.as_deref(), Some("1000"));
+    assert!(game.run_ok(&["status"]).contains("with --method hybrid"));
+
+    // Not written by the program, so overwriting it is declined without asking
+    let extra = game.root.join("extra.vpk");
+    fs::write(&extra, b"someone else's").unwrap();
+    let options = Options {
+        outputs: vec![extra.clone()],
+        ..terrain("desert")
+    };
+    TerrainMod::apply(&options).unwrap();
+    assert_eq!(fs::read(&extra).unwrap(), b"someone else's");
+    let options = Options {
+        assume_yes: true,
+        ..options
+    };
+    TerrainMod::apply(&options).unwrap();
+    assert!(VPK::open(extra).is_ok());
+
+    let options = Options {
+        method: Some(String::from("nowhere")),
+        ..terrain("desert")
+    };
+    let error = TerrainMod::apply(&options).err().unwrap();
+    assert_eq!(error.code(), "TM-004");
+
+    let error = TerrainMod::apply(&terrain("nowhere")).err().unwrap();
+    assert_eq!(error.code(), "TM-004");
+}
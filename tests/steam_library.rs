@@ -0,0 +1,60 @@
+#![cfg(target_os = "linux")]
+
+mod common;
+
+use std::{fs, path::Path};
+
+use common::{text, FakeGame};
+
+/// The `Dota 2:` line printed by `doctor`
+fn dota_path(game: &FakeGame) -> String {
+    let output = game.run(&["doctor"]);
+    String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .find_map(|line| line.strip_prefix("Dota 2: "))
+        .unwrap_or_else(|| panic!("no game folder found:\n{}", text(&output)))
+        .to_owned()
+}
+
+/// The game folder inside the real library at `library`
+fn game_folder(library: &Path) -> String {
+    fs::canonicalize(library)
+        .unwrap()
+        .join("steamapps/common/dota 2 beta/game")
+        .display()
+        .to_string()
+}
+
+#[test]
+fn symlinked_library_root() {
+    let game = FakeGame::with_library("symlinked-library", "drive/SteamLibrary");
+    let link = game.root.join("SteamLibrary");
+    std::os::unix::fs::symlink(&game.library, &link).unwrap();
+    game.set_library(&link);
+
+    let dota_path = dota_path(&game);
+    assert_eq!(dota_path, game_folder(&game.library));
+    assert!(Path::new(&dota_path).join("dota/maps").is_dir());
+}
+
+#[test]
+fn library_behind_chained_symlinks() {
+    let game = FakeGame::with_library("chained-symlinks", "drive/games/SteamLibrary");
+    let drive = game.root.join("linked-drive");
+    let link = game.root.join("SteamLibrary");
+    std::os::unix::fs::symlink(game.root.join("drive"), &drive).unwrap();
+    std::os::unix::fs::symlink(drive.join("games/SteamLibrary"), &link).unwrap();
+    game.set_library(&link);
+
+    assert_eq!(dota_path(&game), game_folder(&game.library));
+}
+
+#[test]
+fn missing_library_is_reported() {
+    let game = FakeGame::new("missing-library");
+    game.set_library(&game.root.join("gone"));
+
+    let output = game.run(&["doctor"]);
+    assert!(!output.status.success());
+    assert!(text(&output).contains("Dota-Terrain-Mod error"));
+}